@@ -1,3 +1,9 @@
 fn main() {
-  embuild::espidf::sysenv::output();
+  // toml-cfg only notices cfg.toml changes once the file exists
+  println!("cargo:rerun-if-changed=cfg.toml");
+  // host builds such as the simulator and the tests have no ESP-IDF to
+  // link against
+  if std::env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("espidf") {
+    embuild::espidf::sysenv::output();
+  }
 }
@@ -0,0 +1,180 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  events::{Bus, Event},
+  notify::{Priority, SharedNotifications},
+  state::DeviceState,
+};
+
+/// Threshold on a sensor reading, crossed when the reading goes `above` or
+/// `below` the limit (only one of the two) and stays there for `for_min`
+/// minutes. It clears once the reading is back by `hysteresis`. For
+/// example:
+///
+/// ```json
+/// {"name": "damp", "sensor": "humidity", "above": 70, "hysteresis": 5,
+///  "for_min": 10}
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Threshold {
+  pub name: String,
+  pub sensor: Sensor,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub above: Option<f64>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub below: Option<f64>,
+  #[serde(default)]
+  pub hysteresis: f64,
+  #[serde(default)]
+  pub for_min: u32,
+}
+
+/// Readings a threshold can watch
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sensor {
+  /// °C, from the weather service
+  Temperature,
+  /// Percent, from the weather service
+  Humidity,
+  /// WiFi signal in dBm
+  Rssi,
+  /// Bytes
+  FreeHeap,
+}
+
+impl Sensor {
+  fn read(self, state: &DeviceState) -> Option<f64> {
+    match self {
+      Self::Temperature => state.weather.as_ref().map(|weather| weather.temp_c),
+      Self::Humidity => state
+        .weather
+        .as_ref()
+        .map(|weather| weather.humidity as f64),
+      Self::Rssi => state.rssi.map(f64::from),
+      Self::FreeHeap => Some(f64::from(state.free_heap)),
+    }
+  }
+
+  fn label(self) -> &'static str {
+    match self {
+      Self::Temperature => "temperature",
+      Self::Humidity => "humidity",
+      Self::Rssi => "WiFi signal",
+      Self::FreeHeap => "free memory",
+    }
+  }
+}
+
+struct Tracked {
+  threshold: Threshold,
+  /// Since when the reading has been past the limit
+  crossed_at: Option<Instant>,
+  raised: bool,
+}
+
+/// Watches the readings in the device state against the thresholds in the
+/// settings. Raising or clearing one shows a notification and goes on the
+/// event bus, where automation rules can sound the buzzer, blink the LED or
+/// call a webhook.
+#[derive(Default)]
+pub struct Monitor {
+  tracked: Vec<Tracked>,
+}
+
+impl Monitor {
+  /// Called about once a second with the current thresholds. A threshold whose
+  /// sensor has no reading yet keeps its state.
+  pub fn check(
+    &mut self,
+    thresholds: &[Threshold],
+    state: &DeviceState,
+    events: &Bus,
+    notifications: &SharedNotifications,
+    notify_for: Duration,
+  ) {
+    // a threshold changed in the settings starts over
+    self
+      .tracked
+      .retain(|tracked| thresholds.contains(&tracked.threshold));
+    for threshold in thresholds {
+      if !self.tracked.iter().any(|t| t.threshold == *threshold) {
+        self.tracked.push(Tracked {
+          threshold: threshold.clone(),
+          crossed_at: None,
+          raised: false,
+        });
+      }
+    }
+
+    let now = Instant::now();
+    for tracked in &mut self.tracked {
+      let threshold = &tracked.threshold;
+      let Some(value) = threshold.sensor.read(state) else {
+        continue;
+      };
+      let (crossed, back, limit, direction) =
+        match (threshold.above, threshold.below) {
+          (Some(limit), _) => (
+            value > limit,
+            value <= limit - threshold.hysteresis,
+            limit,
+            "above",
+          ),
+          (None, Some(limit)) => (
+            value < limit,
+            value >= limit + threshold.hysteresis,
+            limit,
+            "below",
+          ),
+          (None, None) => continue,
+        };
+      if tracked.raised {
+        if back {
+          tracked.raised = false;
+          tracked.crossed_at = None;
+          log::info!("Threshold {:?} cleared", threshold.name);
+          notifications.lock().unwrap().push(
+            &format!("{} back to normal", threshold.name),
+            notify_for,
+            Priority::Low,
+          );
+          events.publish(Event::Cleared(threshold.name.clone()));
+        }
+        continue;
+      }
+      if !crossed {
+        tracked.crossed_at = None;
+        continue;
+      }
+      let since = *tracked.crossed_at.get_or_insert(now);
+      let hold = Duration::from_secs(u64::from(threshold.for_min) * 60);
+      if now.duration_since(since) >= hold {
+        tracked.raised = true;
+        log::warn!("Threshold {:?} crossed at {}", threshold.name, value);
+        notifications.lock().unwrap().push(
+          &format!(
+            "{}: {} {direction} {limit}",
+            threshold.name,
+            threshold.sensor.label()
+          ),
+          notify_for,
+          Priority::High,
+        );
+        events.publish(Event::Threshold(threshold.name.clone()));
+      }
+    }
+  }
+
+  /// Names of the thresholds currently raised
+  pub fn raised(&self) -> Vec<String> {
+    self
+      .tracked
+      .iter()
+      .filter(|tracked| tracked.raised)
+      .map(|tracked| tracked.threshold.name.clone())
+      .collect()
+  }
+}
@@ -0,0 +1,283 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+/// How long a confirmation token stays valid
+pub const CONFIRMATION_TTL: Duration = Duration::from_secs(30);
+
+/// How long a sign-in to the web pages lasts
+pub const SESSION_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+/// Cookie carrying the session token
+pub const SESSION_COOKIE: &str = "pippo_session";
+/// Sessions kept at most, the oldest is signed out to make room
+const MAX_SESSIONS: usize = 8;
+pub const MIN_PASSWORD_LEN: usize = 8;
+/// PBKDF2 rounds for the login password. Checking it takes a fraction of a
+/// second on the ESP32, guessing it takes as long per attempt.
+const HASH_ROUNDS: u32 = 4096;
+
+/// API tokens kept at most
+pub const MAX_API_TOKENS: usize = 8;
+
+pub type SharedSessions = Arc<Mutex<Sessions>>;
+
+/// What an API token may do. Each scope includes the ones before it.
+#[derive(
+  Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+  /// Only requests that don't change anything
+  Read,
+  /// Also the buzzer, the servo and messages on the display
+  Actuators,
+  /// Everything the admin token can do
+  Admin,
+}
+
+/// Token handed to an integration such as Home Assistant. Only a hash of it
+/// is kept, it is shown once when created.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApiToken {
+  pub name: String,
+  pub scope: Scope,
+  hash: String,
+}
+
+impl ApiToken {
+  /// New token for `name`, and the token itself to give to the integration
+  pub fn new(name: &str, scope: Scope) -> (Self, String) {
+    let token = format!("{:032x}", rand::random::<u128>());
+    let api_token = Self {
+      name: name.to_string(),
+      scope,
+      hash: sha256_hex(&token),
+    };
+    (api_token, token)
+  }
+
+  fn matches(&self, token: &str) -> bool {
+    same(sha256_hex(token).as_bytes(), self.hash.as_bytes())
+  }
+}
+
+/// Whether an `Authorization` header carries the admin token or an API token
+/// with the admin scope
+pub fn is_admin(authorization: Option<&str>, config: &Config) -> bool {
+  scope(authorization, config) == Some(Scope::Admin)
+}
+
+/// Scope granted by the token in an `Authorization` header. The admin token
+/// has every scope.
+pub fn scope(authorization: Option<&str>, config: &Config) -> Option<Scope> {
+  if is_admin_token(authorization, config) {
+    return Some(Scope::Admin);
+  }
+  api_token(authorization, config).map(|token| token.scope)
+}
+
+/// The API token in an `Authorization` header
+pub fn api_token<'a>(
+  authorization: Option<&str>,
+  config: &'a Config,
+) -> Option<&'a ApiToken> {
+  let token = bearer(authorization)?;
+  config
+    .security
+    .api_tokens
+    .iter()
+    .find(|api_token| api_token.matches(token))
+}
+
+/// Always false while no admin token has been set
+fn is_admin_token(authorization: Option<&str>, config: &Config) -> bool {
+  let expected = config.security.admin_token.as_bytes();
+  let Some(token) = bearer(authorization) else {
+    return false;
+  };
+  !expected.is_empty() && same(token.as_bytes(), expected)
+}
+
+fn bearer(authorization: Option<&str>) -> Option<&str> {
+  authorization
+    .and_then(|header| header.strip_prefix("Bearer "))
+    .map(str::trim)
+}
+
+/// Compares every byte so the time taken doesn't reveal the matching prefix
+pub fn same(a: &[u8], b: &[u8]) -> bool {
+  a.len() == b.len()
+    && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Sign-ins to the web pages. They are only needed once a login password has
+/// been set, until then the pages stay open as before.
+#[derive(Default)]
+pub struct Sessions {
+  /// Hash of the login password as kept in the secrets
+  password_hash: Option<String>,
+  /// When each session token was signed in
+  active: HashMap<String, Instant>,
+}
+
+impl Sessions {
+  pub fn new(password_hash: Option<String>) -> Self {
+    Self {
+      password_hash,
+      active: HashMap::new(),
+    }
+  }
+
+  /// Whether the pages that change the device need a sign-in
+  pub fn required(&self) -> bool {
+    self.password_hash.is_some()
+  }
+
+  pub fn verify(&self, password: &str) -> bool {
+    self
+      .password_hash
+      .as_deref()
+      .is_some_and(|hash| verify_password(password, hash))
+  }
+
+  /// Token of a new session when `password` is the login password
+  pub fn sign_in(&mut self, password: &str) -> Option<String> {
+    if !self.verify(password) {
+      return None;
+    }
+    self.active.retain(|_, since| since.elapsed() < SESSION_TTL);
+    if self.active.len() >= MAX_SESSIONS {
+      if let Some(oldest) = self
+        .active
+        .iter()
+        .min_by_key(|(_, since)| **since)
+        .map(|(token, _)| token.clone())
+      {
+        self.active.remove(&oldest);
+      }
+    }
+    let token = format!("{:032x}", rand::random::<u128>());
+    self.active.insert(token.clone(), Instant::now());
+    Some(token)
+  }
+
+  pub fn sign_out(&mut self, cookie: Option<&str>) {
+    if let Some(token) = session_token(cookie) {
+      self.active.remove(token);
+    }
+  }
+
+  /// Whether a `Cookie` header carries a session that hasn't expired
+  pub fn is_signed_in(&self, cookie: Option<&str>) -> bool {
+    session_token(cookie)
+      .and_then(|token| self.active.get(token))
+      .is_some_and(|since| since.elapsed() < SESSION_TTL)
+  }
+
+  /// Replace the login password and sign everyone out. Returns the hash to
+  /// keep in the secrets.
+  pub fn set_password(&mut self, password: &str) -> anyhow::Result<String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+      anyhow::bail!(
+        "password must be at least {MIN_PASSWORD_LEN} characters long"
+      );
+    }
+    let hash = hash_password(password);
+    self.password_hash = Some(hash.clone());
+    self.active.clear();
+    Ok(hash)
+  }
+}
+
+/// Session token in a `Cookie` header
+fn session_token(cookie: Option<&str>) -> Option<&str> {
+  self::cookie(cookie, SESSION_COOKIE)
+}
+
+/// Value of cookie `name` in a `Cookie` header
+pub fn cookie<'a>(header: Option<&'a str>, name: &str) -> Option<&'a str> {
+  header?
+    .split(';')
+    .filter_map(|pair| pair.trim().split_once('='))
+    .find(|(key, _)| *key == name)
+    .map(|(_, value)| value)
+}
+
+/// Salted PBKDF2 hash of `password` as `pbkdf2-sha256$<rounds>$<salt>$<hash>`
+fn hash_password(password: &str) -> String {
+  let salt = format!("{:032x}", rand::random::<u128>());
+  let hash = pbkdf2_hex(password, &salt, HASH_ROUNDS);
+  format!("pbkdf2-sha256${HASH_ROUNDS}${salt}${hash}")
+}
+
+fn verify_password(password: &str, stored: &str) -> bool {
+  let mut parts = stored.split('$');
+  let (Some("pbkdf2-sha256"), Some(rounds), Some(salt), Some(hash), None) = (
+    parts.next(),
+    parts.next().and_then(|rounds| rounds.parse().ok()),
+    parts.next(),
+    parts.next(),
+    parts.next(),
+  ) else {
+    log::warn!("Stored login password hash is malformed");
+    return false;
+  };
+  same(
+    pbkdf2_hex(password, salt, rounds).as_bytes(),
+    hash.as_bytes(),
+  )
+}
+
+fn sha256_hex(token: &str) -> String {
+  Sha256::digest(token.as_bytes())
+    .iter()
+    .map(|byte| format!("{byte:02x}"))
+    .collect()
+}
+
+fn pbkdf2_hex(password: &str, salt: &str, rounds: u32) -> String {
+  let mut key = [0_u8; 32];
+  pbkdf2::pbkdf2_hmac::<Sha256>(
+    password.as_bytes(),
+    salt.as_bytes(),
+    rounds,
+    &mut key,
+  );
+  key.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// One-shot token a destructive action has to be repeated with, so a single
+/// stray request can't reboot or wipe the device
+#[derive(Default)]
+pub struct Confirmations {
+  pending: Option<(&'static str, String, Instant)>,
+}
+
+impl Confirmations {
+  /// New token for `action`, replacing any earlier one
+  pub fn issue(&mut self, action: &'static str) -> String {
+    let token = format!("{:016x}", rand::random::<u64>());
+    self.pending = Some((action, token.clone(), Instant::now()));
+    token
+  }
+
+  /// Consumes the pending token if it was issued for `action`, matches
+  /// `token` and hasn't expired
+  pub fn confirm(&mut self, action: &str, token: &str) -> bool {
+    match self.pending.take() {
+      Some((pending_action, pending_token, issued_at)) => {
+        pending_action == action
+          && same(pending_token.as_bytes(), token.as_bytes())
+          && issued_at.elapsed() < CONFIRMATION_TTL
+      }
+      None => false,
+    }
+  }
+}
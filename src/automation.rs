@@ -0,0 +1,238 @@
+use std::sync::mpsc::Receiver;
+
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "buzzer")]
+use crate::buzzer::Buzzer;
+#[cfg(feature = "relay")]
+use crate::web::Relay;
+#[cfg(feature = "servo")]
+use crate::web::Servo;
+use crate::{
+  buzzer::Pattern,
+  clock,
+  config::{Config, SharedConfig},
+  events::{Bus, Event, Origin},
+  group::Group,
+  http_client,
+  led::{self, SharedLed},
+  notify::{Priority, SharedNotifications},
+  presence::Presence,
+  scene::{self, SharedDisplayMode},
+  state::{DeviceState, SharedState},
+};
+
+/// At most this many rules, webhooks, thresholds and scenes, each
+pub const MAX_RULES: usize = 16;
+pub const MAX_WEBHOOKS: usize = 8;
+pub const MAX_THRESHOLDS: usize = 8;
+pub const MAX_SCENES: usize = 8;
+
+/// When `when` happens and every condition holds, do everything in `then`,
+/// in order. For example:
+///
+/// ```json
+/// {"name": "night-watch", "when": "motion",
+///  "conditions": [{"time_between": ["22:00", "06:00"]}],
+///  "then": [{"buzzer": "alert"}, {"webhook": "security"}]}
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+  pub name: String,
+  pub when: Event,
+  #[serde(default)]
+  pub conditions: Vec<Condition>,
+  pub then: Vec<Action>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+  /// Local time from the first `HH:MM` up to the second, wrapping around
+  /// midnight when the first is later
+  TimeBetween(String, String),
+  /// Someone is home, or everyone is away. Never holds while presence is
+  /// unknown.
+  Presence(Presence),
+  /// The rules are armed, or disarmed by an `arm` action
+  Armed(bool),
+}
+
+impl Condition {
+  /// Whether the condition holds at local time `now` in `state`. A time
+  /// that doesn't parse never holds, though validation keeps those out of
+  /// the settings.
+  pub fn holds(&self, now: NaiveTime, state: &DeviceState) -> bool {
+    match self {
+      Self::Presence(wanted) => state.presence == Some(*wanted),
+      Self::Armed(wanted) => !state.disarmed == *wanted,
+      Self::TimeBetween(start, end) => {
+        let (Some(start), Some(end)) = (parse_time(start), parse_time(end))
+        else {
+          return false;
+        };
+        if start <= end {
+          (start..end).contains(&now)
+        } else {
+          now >= start || now < end
+        }
+      }
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+  /// Sound a pattern, except during quiet hours
+  Buzzer(Pattern),
+  /// POST the event to the webhook with this name
+  Webhook(String),
+  /// Show this text as a notification
+  Notify(String),
+  /// Blink the status LED
+  Led(led::Pattern),
+  /// Set the scene with this name
+  Scene(String),
+  /// Arm or disarm the rules with an `armed` condition
+  Arm(bool),
+}
+
+/// `HH:MM`, as written in a [`Condition`]
+pub fn parse_time(text: &str) -> Option<NaiveTime> {
+  NaiveTime::parse_from_str(text, "%H:%M").ok()
+}
+
+/// What an action needs from the rest of the firmware
+#[derive(Clone)]
+pub struct Outputs {
+  pub notifications: SharedNotifications,
+  pub led: SharedLed,
+  #[cfg(feature = "buzzer")]
+  pub buzzer: Buzzer,
+  #[cfg(feature = "servo")]
+  pub servo: Servo,
+  #[cfg(feature = "relay")]
+  pub relay: Relay,
+  pub display: SharedDisplayMode,
+  pub state: SharedState,
+  /// For the scenes set by a rule
+  pub events: Bus,
+}
+
+/// Run the rules on every event from the bus in a background thread, deep
+/// enough for the TLS handshake of a webhook. Events that happened on this
+/// unit are shared with the `group`, if any, and the rules run on events
+/// from the group the same way, and a scene set on another unit is set on
+/// this one too.
+pub fn spawn(
+  events: Receiver<(Event, Origin)>,
+  config: SharedConfig,
+  outputs: Outputs,
+  group: Option<Group>,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("automation".to_string())
+    .stack_size(12 * 1024)
+    .spawn(move || {
+      for (event, origin) in events {
+        let config = config.lock().unwrap().clone();
+        match (&origin, &group) {
+          (Origin::Local, Some(group)) if event.shared() => group.share(&event),
+          (Origin::Group(device), _) => {
+            log::info!("{:?} on {}", event, device);
+            if let Event::Scene(name) = &event {
+              set_from_group(name, &config, &outputs);
+            }
+          }
+          _ => {}
+        }
+        let now = clock::local_now().time();
+        let state = outputs.state.lock().unwrap().clone();
+        for rule in &config.automation.rules {
+          if rule.when == event
+            && rule
+              .conditions
+              .iter()
+              .all(|condition| condition.holds(now, &state))
+          {
+            log::info!("Rule {:?} fired on {:?}", rule.name, event);
+            run(rule, &event, &config, &outputs);
+          }
+        }
+      }
+    })?;
+  Ok(())
+}
+
+/// Set a scene another unit set, without sharing it again. A unit without
+/// a scene of that name leaves its actuators alone.
+fn set_from_group(name: &str, config: &Config, outputs: &Outputs) {
+  let Some(scene) = config
+    .automation
+    .scenes
+    .iter()
+    .find(|scene| scene.name == name)
+  else {
+    return;
+  };
+  if let Err(error) = scene::apply(scene, outputs) {
+    log::warn!("Scene {:?} failed: {:?}", name, error);
+  }
+}
+
+/// Do everything `rule` says, whatever its conditions. One failed action
+/// doesn't stop the ones after it.
+pub fn run(rule: &Rule, event: &Event, config: &Config, outputs: &Outputs) {
+  for action in &rule.then {
+    if let Err(error) = act(action, rule, event, config, outputs) {
+      log::warn!("Rule {:?}: {:?} failed: {:?}", rule.name, action, error);
+    }
+  }
+}
+
+fn act(
+  action: &Action,
+  rule: &Rule,
+  event: &Event,
+  config: &Config,
+  outputs: &Outputs,
+) -> anyhow::Result<()> {
+  match action {
+    Action::Buzzer(pattern) => {
+      #[cfg(feature = "buzzer")]
+      outputs.buzzer.play(*pattern)?;
+      #[cfg(not(feature = "buzzer"))]
+      log::info!("No buzzer for pattern {:?}", pattern);
+    }
+    Action::Webhook(name) => {
+      let webhook = config
+        .automation
+        .webhooks
+        .iter()
+        .find(|webhook| webhook.name == *name)
+        .ok_or_else(|| anyhow::anyhow!("no webhook named {name:?}"))?;
+      let json = serde_json::json!({
+        "rule": rule.name,
+        "event": event,
+        "time": clock::local_now().to_rfc3339(),
+      });
+      http_client::post_json(&webhook.url, &json.to_string())?;
+    }
+    Action::Led(pattern) => led::play(&outputs.led, *pattern),
+    Action::Notify(text) => {
+      outputs.notifications.lock().unwrap().push(
+        text,
+        config.notifications.duration(),
+        Priority::Normal,
+      );
+    }
+    Action::Scene(name) => scene::activate(name, config, outputs)?,
+    Action::Arm(armed) => {
+      log::info!("Rules {}", if *armed { "armed" } else { "disarmed" });
+      outputs.state.lock().unwrap().disarmed = !armed;
+    }
+  }
+  Ok(())
+}
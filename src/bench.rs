@@ -0,0 +1,119 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+};
+use serde::Serialize;
+
+use crate::{
+  display::Oled,
+  typography::{self, Font},
+};
+
+/// A requested run and the last results, shared by the main loop, which
+/// owns the display, with the console and the web server
+pub type SharedBench = Arc<Mutex<Bench>>;
+
+/// I2C clock rates tried, the SSD1306 is rated for 400 kHz but most panels
+/// keep up with more
+const SPEEDS_KHZ: [u32; 3] = [100, 400, 1000];
+/// Frames flushed for each measurement
+const FRAMES: u32 = 20;
+/// What changes between frames when only part of the panel is sent, the
+/// size of the clock on the status bar
+const DIRTY_AREA: Size = Size::new(25, 8);
+
+#[derive(Debug, Default)]
+pub struct Bench {
+  /// Waiting for the next frame or running
+  pub requested: bool,
+  pub results: Option<Vec<Run>>,
+}
+
+/// Mean flush times at one bus speed, `None` where a flush failed
+#[derive(Clone, Debug, Serialize)]
+pub struct Run {
+  pub speed_khz: u32,
+  /// The whole frame buffer
+  pub full_ms: Option<f32>,
+  /// Only the part that changed, [`DIRTY_AREA`]
+  pub dirty_ms: Option<f32>,
+}
+
+impl Run {
+  pub fn summary(&self) -> String {
+    let ms = |time: Option<f32>| {
+      time.map_or("failed".to_string(), |ms| format!("{ms:.1} ms"))
+    };
+    format!(
+      "{:>4} kHz: full {}, dirty {}",
+      self.speed_khz,
+      ms(self.full_ms),
+      ms(self.dirty_ms)
+    )
+  }
+}
+
+/// Flush frames at each bus speed, the whole frame and only a small part of
+/// it, and go back to the speed in the settings. Takes a few seconds, during
+/// which the panel shows the test frames.
+pub fn run(oled: &mut Oled) -> Vec<Run> {
+  log::info!("Display benchmark started");
+  let configured = oled.bus_khz();
+  let runs: Vec<Run> = SPEEDS_KHZ
+    .iter()
+    .map(|&speed_khz| {
+      oled.set_bus_khz(speed_khz);
+      let full_ms = measure(oled, |display, frame| {
+        let _ = display.clear(BinaryColor::Off);
+        let label = format!("Bench {speed_khz} kHz full");
+        typography::draw_centered(display, &label, 28, Font::Small.style());
+        // moves every frame so no two frames are the same
+        let _ =
+          Rectangle::new(Point::new(frame as i32 * 6, 44), Size::new(6, 6))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(display);
+      });
+      let dirty_ms = measure(oled, |display, frame| {
+        let color = if frame % 2 == 0 {
+          BinaryColor::On
+        } else {
+          BinaryColor::Off
+        };
+        let _ = Rectangle::new(Point::new(1, 1), DIRTY_AREA)
+          .into_styled(PrimitiveStyle::with_fill(color))
+          .draw(display);
+      });
+      let run = Run {
+        speed_khz,
+        full_ms,
+        dirty_ms,
+      };
+      log::info!("Display benchmark {}", run.summary());
+      run
+    })
+    .collect();
+  oled.set_bus_khz(configured);
+  runs
+}
+
+/// Mean flush time of [`FRAMES`] frames drawn with `draw`, `None` if a
+/// flush failed
+fn measure(
+  oled: &mut Oled,
+  mut draw: impl FnMut(&mut crate::display::Display<'static>, u32),
+) -> Option<f32> {
+  let mut total = Duration::ZERO;
+  for frame in 0..FRAMES {
+    if !oled.render(|display| draw(display, frame)) {
+      return None;
+    }
+    total += oled.timings().1;
+  }
+  Some(total.as_secs_f32() * 1000.0 / FRAMES as f32)
+}
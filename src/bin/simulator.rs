@@ -0,0 +1,257 @@
+//! The screens and button navigation in a window on the computer, for UI
+//! work without flashing the board. The space bar is the button, held for a
+//! long press the same as on the device; Enter is an instant long press and
+//! Escape quits. Screens showing data fetched on the device draw a
+//! placeholder.
+//!
+//! ```sh
+//! cargo run --bin simulator --features simulator \
+//!   --target x86_64-unknown-linux-gnu
+//! ```
+//!
+//! `-- --record <file>` writes the presses and screens to `file` on quitting,
+//! `-- --replay <file>` plays a recording back, one from the device's
+//! `record show` as well, and prints whether it went through the same
+//! screens.
+
+// the shared modules have more in them than the simulator uses
+#![allow(dead_code)]
+
+use std::time::Duration;
+
+use chrono::Local;
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use embedded_graphics_simulator::{
+  sdl2::Keycode, BinaryColorTheme, OutputSettingsBuilder, SimulatorEvent,
+  Window,
+};
+
+#[path = "../clock.rs"]
+mod clock;
+#[path = "../pager.rs"]
+mod pager;
+#[path = "../prerender.rs"]
+mod prerender;
+#[path = "../recording.rs"]
+mod recording;
+#[path = "../screens/mod.rs"]
+mod screens;
+#[path = "../snake.rs"]
+mod snake;
+#[path = "../statusbar.rs"]
+mod statusbar;
+#[path = "../titlebar.rs"]
+mod titlebar;
+#[path = "../typography.rs"]
+mod typography;
+#[path = "../ui.rs"]
+mod ui;
+
+/// Stands in for the SSD1306 panel in the shared modules
+mod display {
+  use embedded_graphics::pixelcolor::BinaryColor;
+  use embedded_graphics_simulator::SimulatorDisplay;
+
+  pub type Display<'d> = SimulatorDisplay<BinaryColor>;
+}
+
+use clock::Instant;
+use display::Display;
+use pager::Pager;
+use prerender::Prerendered;
+use recording::Session;
+use statusbar::StatusBar;
+use typography::Font;
+use ui::{ButtonEvent, Redraw, UiState};
+
+/// The main loop on the device sleeps this long between frames
+const FRAME: Duration = Duration::from_millis(20);
+/// Brightness the Settings screen starts from, as in the default settings
+const BRIGHTNESS: u8 = 255;
+
+fn main() {
+  let mut display = Display::new(Size::new(128, 64));
+  let mut window = Window::new(
+    "Pippo",
+    &OutputSettingsBuilder::new()
+      .theme(BinaryColorTheme::OledBlue)
+      .scale(4)
+      .build(),
+  );
+
+  let mut ui_state = UiState::Home;
+  let mut last_ui_state = ui_state;
+  let mut option_index: u8 = 0;
+  let mut list_offset: usize = 0;
+  let mut pager = Pager::default();
+  let mut brightness_draft: Option<u8> = None;
+  let mut snake = snake::Game::new(0);
+  let mut static_screen = Prerendered::<(UiState, Option<u8>)>::default();
+  let mut presses = ui::Button::new(Instant::now());
+  // drawn like on the device, only when something on screen changed
+  let mut redraw = Redraw::default();
+  let mut drawn_at = Instant::now();
+  // the space bar, read like the button pin
+  let mut space_down = false;
+
+  let mut session = Session::default();
+  let mut record_to = None;
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match (arg.as_str(), args.next()) {
+      ("--record", Some(path)) => {
+        session.record(Instant::now());
+        record_to = Some(path);
+      }
+      ("--replay", Some(path)) => {
+        let text = std::fs::read_to_string(&path)
+          .unwrap_or_else(|error| panic!("can't read {path}: {error}"));
+        let entries = recording::parse(&text)
+          .unwrap_or_else(|error| panic!("{path}: {error}"));
+        session.replay(entries, Instant::now());
+      }
+      _ => panic!("usage: simulator [--record <file> | --replay <file>]"),
+    }
+  }
+
+  'running: loop {
+    // shows the last frame, and opens the window on the first one
+    window.update(&display);
+    let now = Instant::now();
+    let mut enter = false;
+    for event in window.events() {
+      match event {
+        SimulatorEvent::Quit => break 'running,
+        SimulatorEvent::KeyDown {
+          keycode: Keycode::Escape,
+          ..
+        } => break 'running,
+        SimulatorEvent::KeyDown {
+          keycode: Keycode::Space,
+          repeat: false,
+          ..
+        } => space_down = true,
+        SimulatorEvent::KeyUp {
+          keycode: Keycode::Space,
+          ..
+        } => space_down = false,
+        SimulatorEvent::KeyDown {
+          keycode: Keycode::Return,
+          repeat: false,
+          ..
+        } => enter = true,
+        _ => {}
+      }
+    }
+    let event = match presses.update(space_down, now) {
+      None if enter => Some(ButtonEvent::Long),
+      event => event,
+    };
+    // starts on Home already
+    session.take_restart();
+    let event = session.press(event, now);
+    if event.is_some() {
+      redraw.mark();
+    }
+    match event {
+      Some(ButtonEvent::Long) => {
+        ui::handle_long_press(&mut ui_state, option_index, list_offset)
+      }
+      Some(ButtonEvent::Short) if ui_state == UiState::Snake => snake.press(),
+      Some(ButtonEvent::Short) => {
+        let list_len = match ui_state {
+          UiState::Games => UiState::Games.menu_len(),
+          _ => 0,
+        };
+        if let Some(paging) = ui::handle_short_press(
+          &mut ui_state,
+          &mut option_index,
+          &mut list_offset,
+          list_len,
+          &mut brightness_draft,
+        ) {
+          pager.turn(paging);
+        }
+      }
+      Some(ButtonEvent::Down) | None => {}
+    }
+
+    if ui_state != last_ui_state {
+      println!("Screen: {:?}", ui_state);
+      last_ui_state = ui_state;
+      session.screen(ui_state, now);
+      pager.reset();
+      redraw.mark();
+      list_offset = 0;
+      brightness_draft = (ui_state == UiState::Settings).then_some(BRIGHTNESS);
+      if ui_state == UiState::Snake {
+        snake = snake::Game::new(snake.high_score());
+      }
+    }
+    match session.finished() {
+      Some(Ok(())) => println!("Replay went through the recorded screens"),
+      Some(Err(mismatch)) => println!("Replay differed: {mismatch}"),
+      None => {}
+    }
+    if ui_state == UiState::Snake {
+      snake.tick(now);
+    }
+
+    // the clock moves on once a second
+    if ui_state.is_animated()
+      || now.duration_since(drawn_at) >= Duration::from_secs(1)
+    {
+      redraw.mark();
+    }
+    if !redraw.take() {
+      std::thread::sleep(FRAME);
+      continue;
+    }
+    drawn_at = now;
+    let local_now = Local::now();
+    let time =
+      typography::line(format_args!("{}", local_now.format("%d/%m %H:%M")));
+    display.clear(BinaryColor::Off).unwrap();
+    match ui_state {
+      UiState::Home => {
+        screens::home_screen(&mut display, Font::Large.style(), &time);
+      }
+      UiState::Menu => {
+        screens::menu_screen(&mut display, option_index as usize)
+      }
+      _ => screens::draw(
+        &mut display,
+        &mut screens::Context {
+          screen: ui_state,
+          time: &time,
+          list_offset,
+          brightness: brightness_draft.unwrap_or(BRIGHTNESS),
+          pager: &mut pager,
+          snake: &mut snake,
+          static_screen: &mut static_screen,
+        },
+      ),
+    }
+    statusbar::draw(
+      &mut display,
+      &StatusBar {
+        time: typography::line(format_args!("{}", local_now.format("%H:%M"))),
+        synced: true,
+        rssi: Some(-60),
+        battery_percent: None,
+        notifications: 0,
+        unread_headlines: 0,
+        title: titlebar::Title::for_screen(ui_state),
+      },
+    );
+
+    std::thread::sleep(FRAME);
+  }
+
+  if let (Some(path), Some(entries)) = (record_to, session.stop()) {
+    match std::fs::write(&path, recording::to_lines(&entries)) {
+      Ok(()) => println!("Recorded {} entries to {path}", entries.len()),
+      Err(error) => println!("Could not write {path}: {error}"),
+    }
+  }
+}
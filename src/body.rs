@@ -0,0 +1,151 @@
+use std::io::{self, Read};
+
+use serde::de::DeserializeOwned;
+
+use crate::clock::Instant;
+
+/// Largest response body read unless a caller asks for another limit, well
+/// above what the APIs used here send
+pub const DEFAULT_LIMIT: usize = 64 * 1024;
+
+/// The response was longer than the limit it was read with
+#[derive(Debug)]
+pub struct TooLarge {
+  pub limit: usize,
+}
+
+impl std::fmt::Display for TooLarge {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "response body over {} bytes", self.limit)
+  }
+}
+
+impl std::error::Error for TooLarge {}
+
+/// A response body read as it arrives, failing with [`TooLarge`] once more
+/// than the limit has come instead of filling the heap
+pub struct Body<R> {
+  reader: R,
+  limit: usize,
+  read: usize,
+  deadline: Option<Instant>,
+}
+
+impl<R: Read> Body<R> {
+  pub fn new(reader: R, limit: usize) -> Self {
+    Self {
+      reader,
+      limit,
+      read: 0,
+      deadline: None,
+    }
+  }
+
+  /// Fail reads with `TimedOut` from `deadline` on, for a server that sends
+  /// its body a trickle at a time
+  pub fn until(mut self, deadline: Instant) -> Self {
+    self.deadline = Some(deadline);
+    self
+  }
+
+  /// The whole body as text, checked to be UTF-8 chunk by chunk
+  pub fn text(mut self) -> io::Result<String> {
+    let mut text = String::new();
+    let mut decoder = Utf8Decoder::default();
+    let mut buf = [0_u8; 512];
+    loop {
+      let size = self.read(&mut buf)?;
+      if size == 0 {
+        decoder.finish()?;
+        return Ok(text);
+      }
+      decoder.push(&buf[..size], &mut text)?;
+    }
+  }
+
+  /// The body parsed as JSON while it is read, without keeping the text
+  pub fn json<T: DeserializeOwned>(self) -> anyhow::Result<T> {
+    Ok(serde_json::from_reader(self)?)
+  }
+}
+
+impl<R: Read> Read for Body<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if self
+      .deadline
+      .is_some_and(|deadline| Instant::now() >= deadline)
+    {
+      return Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "response body took too long",
+      ));
+    }
+    // one byte past the limit tells a body of exactly the limit from a
+    // longer one
+    let allowed = (self.limit + 1).saturating_sub(self.read).min(buf.len());
+    let size = self.reader.read(&mut buf[..allowed])?;
+    self.read += size;
+    if self.read > self.limit {
+      return Err(io::Error::other(TooLarge { limit: self.limit }));
+    }
+    Ok(size)
+  }
+}
+
+/// Text from bytes that arrive in chunks, where a character may be split
+/// between two of them
+#[derive(Default)]
+pub struct Utf8Decoder {
+  /// Start of a character the next chunk finishes
+  pending: [u8; 4],
+  len: usize,
+}
+
+impl Utf8Decoder {
+  /// Append `bytes` to `text`, keeping back a character cut off at the end
+  pub fn push(
+    &mut self,
+    mut bytes: &[u8],
+    text: &mut String,
+  ) -> io::Result<()> {
+    while self.len > 0 {
+      let Some((&byte, rest)) = bytes.split_first() else {
+        return Ok(());
+      };
+      bytes = rest;
+      self.pending[self.len] = byte;
+      self.len += 1;
+      match std::str::from_utf8(&self.pending[..self.len]) {
+        Ok(character) => {
+          text.push_str(character);
+          self.len = 0;
+        }
+        Err(error) if error.error_len().is_some() => return Err(invalid()),
+        Err(_) => {}
+      }
+    }
+    match std::str::from_utf8(bytes) {
+      Ok(chunk) => text.push_str(chunk),
+      Err(error) if error.error_len().is_some() => return Err(invalid()),
+      Err(error) => {
+        let (valid, rest) = bytes.split_at(error.valid_up_to());
+        text.push_str(std::str::from_utf8(valid).map_err(|_| invalid())?);
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.len = rest.len();
+      }
+    }
+    Ok(())
+  }
+
+  /// Fails if the text ended in the middle of a character
+  pub fn finish(&self) -> io::Result<()> {
+    if self.len > 0 {
+      return Err(invalid());
+    }
+    Ok(())
+  }
+}
+
+fn invalid() -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, "response body is not UTF-8")
+}
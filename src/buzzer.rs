@@ -0,0 +1,79 @@
+#[cfg(feature = "buzzer")]
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "buzzer")]
+use esp_idf_hal::{
+  delay::FreeRtos,
+  gpio::{AnyIOPin, Output, PinDriver},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "buzzer")]
+use crate::config::SharedConfig;
+
+/// Buzzer patterns
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+  /// One short beep, as `POST /api/v1/buzz`
+  Beep,
+  /// A short beep, then a longer one
+  Chime,
+  /// Five beeps in a row
+  Alert,
+}
+
+impl Pattern {
+  /// Milliseconds on, then off, for each beep
+  #[cfg(feature = "buzzer")]
+  fn steps(self) -> &'static [(u32, u32)] {
+    match self {
+      Self::Beep => &[(200, 0)],
+      Self::Chime => &[(80, 80), (160, 0)],
+      Self::Alert => &[(150, 100); 5],
+    }
+  }
+}
+
+/// The buzzer, for everything that sounds it. It stays silent during quiet
+/// hours, so callers don't check them themselves.
+#[cfg(feature = "buzzer")]
+#[derive(Clone)]
+pub struct Buzzer {
+  pin: Arc<Mutex<PinDriver<'static, AnyIOPin, Output>>>,
+  config: SharedConfig,
+}
+
+#[cfg(feature = "buzzer")]
+impl Buzzer {
+  pub fn new(
+    pin: PinDriver<'static, AnyIOPin, Output>,
+    config: SharedConfig,
+  ) -> Self {
+    Self {
+      pin: Arc::new(Mutex::new(pin)),
+      config,
+    }
+  }
+
+  /// Sound `pattern`, blocking until it is over. Returns false without a
+  /// sound during quiet hours.
+  pub fn play(&self, pattern: Pattern) -> anyhow::Result<bool> {
+    if self.config.lock().unwrap().quiet_hours.active_now() {
+      log::info!("Buzzer {:?} muted during quiet hours", pattern);
+      return Ok(false);
+    }
+    for &(on_ms, off_ms) in pattern.steps() {
+      self.pin.lock().unwrap().set_high()?;
+      FreeRtos::delay_ms(on_ms);
+      self.pin.lock().unwrap().set_low()?;
+      FreeRtos::delay_ms(off_ms);
+    }
+    Ok(true)
+  }
+
+  /// The pin itself, for the self-test, which sounds regardless of the time
+  pub fn pin(&self) -> &Mutex<PinDriver<'static, AnyIOPin, Output>> {
+    &self.pin
+  }
+}
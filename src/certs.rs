@@ -0,0 +1,85 @@
+use std::ffi::CString;
+
+use esp_idf_svc::{
+  nvs::{EspCustomNvs, EspCustomNvsPartition},
+  tls::X509,
+};
+use serde::Deserialize;
+
+/// Data partition set aside for the certificate in `partitions.csv`. It is
+/// separate from the settings, so a factory reset keeps HTTPS working.
+pub const PARTITION: &str = "certs";
+const NAMESPACE: &str = "tls";
+
+/// NVS strings can't be longer than this
+const MAX_PEM_LEN: usize = 4000;
+
+/// PEM certificate and private key for the HTTPS server, uploaded from the
+/// web. Self-signed ones work once the browser has been told to trust them.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Certificate {
+  pub certificate: String,
+  pub private_key: String,
+}
+
+impl Certificate {
+  pub fn load(
+    partition: EspCustomNvsPartition,
+  ) -> anyhow::Result<Option<Self>> {
+    let nvs = EspCustomNvs::new(partition, NAMESPACE, true)?;
+    let mut certificate_buf = vec![0_u8; MAX_PEM_LEN + 1];
+    let mut key_buf = vec![0_u8; MAX_PEM_LEN + 1];
+    let (Some(certificate), Some(private_key)) = (
+      nvs.get_str("certificate", &mut certificate_buf)?,
+      nvs.get_str("private_key", &mut key_buf)?,
+    ) else {
+      return Ok(None);
+    };
+    Ok(Some(Self {
+      certificate: certificate.to_string(),
+      private_key: private_key.to_string(),
+    }))
+  }
+
+  pub fn save(&self, partition: EspCustomNvsPartition) -> anyhow::Result<()> {
+    let mut nvs = EspCustomNvs::new(partition, NAMESPACE, true)?;
+    nvs.set_str("certificate", &self.certificate)?;
+    nvs.set_str("private_key", &self.private_key)?;
+    Ok(())
+  }
+
+  pub fn erase(partition: EspCustomNvsPartition) -> anyhow::Result<()> {
+    let mut nvs = EspCustomNvs::new(partition, NAMESPACE, true)?;
+    nvs.remove("certificate")?;
+    nvs.remove("private_key")?;
+    Ok(())
+  }
+
+  pub fn validate(&self) -> anyhow::Result<()> {
+    for (name, pem, marker) in [
+      ("certificate", &self.certificate, "CERTIFICATE-----"),
+      ("private_key", &self.private_key, "PRIVATE KEY-----"),
+    ] {
+      if !pem.trim_start().starts_with("-----BEGIN") || !pem.contains(marker) {
+        anyhow::bail!("{name} must be PEM encoded");
+      }
+      if pem.len() > MAX_PEM_LEN || pem.contains('\0') {
+        anyhow::bail!("{name} must be at most {MAX_PEM_LEN} bytes");
+      }
+    }
+    Ok(())
+  }
+
+  /// Certificate and key in the form the server takes. They have to live as
+  /// long as the server, which runs until the device restarts, so they are
+  /// leaked.
+  pub fn x509(&self) -> anyhow::Result<(X509<'static>, X509<'static>)> {
+    let leak = |pem: &str| -> anyhow::Result<&'static std::ffi::CStr> {
+      Ok(Box::leak(CString::new(pem)?.into_boxed_c_str()))
+    };
+    Ok((
+      X509::pem(leak(&self.certificate)?),
+      X509::pem(leak(&self.private_key)?),
+    ))
+  }
+}
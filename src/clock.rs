@@ -0,0 +1,105 @@
+use std::{
+  ops::{Add, AddAssign, Sub},
+  time::Duration,
+};
+
+#[cfg(debug_assertions)]
+use chrono::NaiveDateTime;
+use chrono::{DateTime, Local};
+
+/// A point in time counted from boot by `esp_timer`, for how long things
+/// take: debouncing, long presses, animations. It never jumps, unlike the
+/// wall clock when NTP sets it, which is only for showing the time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(Duration);
+
+impl Instant {
+  pub fn now() -> Self {
+    Self(since_boot())
+  }
+
+  /// Time from `earlier` to this, zero if `earlier` is later
+  pub fn duration_since(self, earlier: Instant) -> Duration {
+    self.0.saturating_sub(earlier.0)
+  }
+
+  /// Same as [`Instant::duration_since`], named as on `std::time::Instant`
+  pub fn saturating_duration_since(self, earlier: Instant) -> Duration {
+    self.duration_since(earlier)
+  }
+
+  pub fn elapsed(self) -> Duration {
+    Self::now().duration_since(self)
+  }
+
+  /// Time from boot to this
+  pub fn since_boot(self) -> Duration {
+    self.0
+  }
+}
+
+impl Add<Duration> for Instant {
+  type Output = Instant;
+
+  fn add(self, duration: Duration) -> Instant {
+    Instant(self.0 + duration)
+  }
+}
+
+impl AddAssign<Duration> for Instant {
+  fn add_assign(&mut self, duration: Duration) {
+    self.0 += duration;
+  }
+}
+
+impl Sub<Instant> for Instant {
+  type Output = Duration;
+
+  fn sub(self, earlier: Instant) -> Duration {
+    self.duration_since(earlier)
+  }
+}
+
+/// Local time an end-to-end test has stopped the wall clock at
+#[cfg(debug_assertions)]
+static FROZEN: std::sync::Mutex<Option<NaiveDateTime>> =
+  std::sync::Mutex::new(None);
+
+/// The wall clock in local time, for showing the time and for everything
+/// that depends on the time of day: rules, quiet hours, the daily quote. In
+/// debug builds a test may have stopped it with [`freeze`].
+pub fn local_now() -> DateTime<Local> {
+  #[cfg(debug_assertions)]
+  if let Some(frozen) =
+    frozen().and_then(|frozen| frozen.and_local_timezone(Local).earliest())
+  {
+    return frozen;
+  }
+  Local::now()
+}
+
+/// Stop the wall clock at `at`, or start it again with `None`
+#[cfg(debug_assertions)]
+pub fn freeze(at: Option<NaiveDateTime>) {
+  *FROZEN.lock().unwrap() = at;
+}
+
+/// The time the wall clock is stopped at, `None` while it runs
+#[cfg(debug_assertions)]
+pub fn frozen() -> Option<NaiveDateTime> {
+  *FROZEN.lock().unwrap()
+}
+
+#[cfg(target_os = "espidf")]
+fn since_boot() -> Duration {
+  let micros = unsafe { esp_idf_svc::sys::esp_timer_get_time() };
+  Duration::from_micros(micros as u64)
+}
+
+/// The simulator and the tests count from the first reading instead
+#[cfg(not(target_os = "espidf"))]
+fn since_boot() -> Duration {
+  static START: std::sync::OnceLock<std::time::Instant> =
+    std::sync::OnceLock::new();
+  START.get_or_init(std::time::Instant::now).elapsed()
+}
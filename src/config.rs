@@ -0,0 +1,1389 @@
+use std::{
+  net::{IpAddr, Ipv4Addr, SocketAddr},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use chrono::Timelike;
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  alerts::Threshold,
+  auth::{self, ApiToken},
+  automation::{self, Action, Condition, Rule},
+  clock,
+  defaults::DEFAULTS,
+  events::Event,
+  profiles::Profiles,
+  scene::Scene,
+  secrets,
+  wifi::Credentials,
+};
+
+pub type SharedConfig = Arc<Mutex<Config>>;
+
+const NAMESPACE: &str = "config";
+const KEY: &str = "config";
+/// Stands in for secrets in settings sent to the browser
+const MASK: &str = "********";
+/// Time servers that can be set, the pool takes the last of the four lwIP
+/// keeps
+const MAX_NTP_SERVERS: usize = 3;
+const NTP_POOL: &str = "pool.ntp.org";
+/// (section, field) of the settings kept in the secrets namespace
+const SECRET_FIELDS: [(&str, &str); 3] = [
+  ("api_keys", "weather"),
+  ("security", "admin_token"),
+  ("proxy", "password"),
+];
+
+/// User settings, edited from the settings page and stored in NVS as JSON.
+/// Fields missing from the stored copy fall back to their defaults, so new
+/// settings can be added without invalidating saved ones. The weather API
+/// key, the admin token and the proxy password are stored separately, see
+/// [`secrets`].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+  pub location: Location,
+  pub api_keys: ApiKeys,
+  pub refresh: RefreshIntervals,
+  pub quiet_hours: QuietHours,
+  pub display: DisplayOptions,
+  pub notifications: NotificationOptions,
+  pub security: Security,
+  pub cors: Cors,
+  pub pins: PinConfig,
+  pub rate_limit: RateLimit,
+  pub server: ServerOptions,
+  pub crypto: Crypto,
+  pub stocks: Stocks,
+  pub feed: Feed,
+  pub spotify: Spotify,
+  pub quote: QuoteOptions,
+  pub automation: Automation,
+  pub presence: PresenceOptions,
+  pub group: GroupOptions,
+  pub door: DoorOptions,
+  pub proxy: Proxy,
+  pub roaming: Roaming,
+  pub static_ip: StaticIp,
+  pub setup_ap: SetupApOptions,
+  pub ntp: Ntp,
+  pub discovery: Discovery,
+  pub ethernet: Ethernet,
+  pub device: DeviceOptions,
+  pub syslog: SyslogOptions,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Location {
+  pub latitude: f64,
+  pub longitude: f64,
+}
+
+impl Default for Location {
+  fn default() -> Self {
+    Self {
+      latitude: DEFAULTS.latitude,
+      longitude: DEFAULTS.longitude,
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKeys {
+  /// weatherapi.com key, weather stays off while it is empty
+  pub weather: String,
+}
+
+impl Default for ApiKeys {
+  fn default() -> Self {
+    Self {
+      weather: DEFAULTS.weather_api_key.to_string(),
+    }
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RefreshIntervals {
+  pub weather_min: u32,
+  pub crypto_min: u32,
+  pub stocks_min: u32,
+  pub feed_min: u32,
+}
+
+impl Default for RefreshIntervals {
+  fn default() -> Self {
+    Self {
+      weather_min: 30,
+      crypto_min: 10,
+      stocks_min: 15,
+      feed_min: 30,
+    }
+  }
+}
+
+/// RSS or Atom feed whose newest titles the Headlines screen shows. Nothing
+/// is fetched while the URL is empty.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feed {
+  pub url: String,
+}
+
+/// Quote of the day, shown on the Home screen in turn with the clock
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuoteOptions {
+  pub enabled: bool,
+}
+
+impl Default for QuoteOptions {
+  fn default() -> Self {
+    Self { enabled: true }
+  }
+}
+
+/// Spotify app the Now Playing screen signs in with. The account itself is
+/// linked from the settings page and its token kept with the secrets.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Spotify {
+  /// Client ID from the Spotify developer dashboard
+  pub client_id: String,
+}
+
+/// Ticker symbols on the stocks screen as Yahoo Finance writes them, e.g.
+/// `AAPL`, `^GSPC` for the S&P 500 or `VOD.L` for London. No quotes are
+/// fetched while the list is empty.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stocks {
+  pub symbols: Vec<String>,
+}
+
+impl Default for Stocks {
+  fn default() -> Self {
+    Self {
+      symbols: vec!["^GSPC".to_string(), "AAPL".to_string()],
+    }
+  }
+}
+
+/// Coins on the ticker screen, by CoinGecko id such as `bitcoin`, priced in
+/// `currency`. No prices are fetched while the list is empty.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Crypto {
+  pub coins: Vec<String>,
+  pub currency: String,
+}
+
+impl Default for Crypto {
+  fn default() -> Self {
+    Self {
+      coins: vec!["bitcoin".to_string(), "ethereum".to_string()],
+      currency: "usd".to_string(),
+    }
+  }
+}
+
+/// Hours of the day during which the buzzer stays silent. The range wraps
+/// around midnight when `start_hour` is after `end_hour`. Notifications
+/// wait until the end unless `hold_notifications` is off, and the display
+/// goes to its lowest brightness unless `dim_display` is off.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuietHours {
+  pub enabled: bool,
+  pub start_hour: u8,
+  pub end_hour: u8,
+  pub dim_display: bool,
+  pub hold_notifications: bool,
+}
+
+impl Default for QuietHours {
+  fn default() -> Self {
+    Self {
+      enabled: false,
+      start_hour: 22,
+      end_hour: 7,
+      dim_display: true,
+      hold_notifications: true,
+    }
+  }
+}
+
+impl QuietHours {
+  pub fn contains(&self, hour: u32) -> bool {
+    let (start, end) = (u32::from(self.start_hour), u32::from(self.end_hour));
+    self.enabled
+      && if start <= end {
+        (start..end).contains(&hour)
+      } else {
+        hour >= start || hour < end
+      }
+  }
+
+  /// Whether it is quiet hours now, in local time
+  pub fn active_now(&self) -> bool {
+    self.contains(clock::local_now().hour())
+  }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayOptions {
+  pub clock_24h: bool,
+  pub invert: bool,
+  /// Panel orientation in degrees, 0 or 180 for a panel mounted upside down
+  pub rotation: u16,
+  /// SSD1306 contrast, 0 (dimmest) to 255 (brightest)
+  pub brightness: u8,
+  /// Frame rate, frame times and free heap in a corner of every screen
+  pub debug_overlay: bool,
+  /// I2C clock of the panel in kHz. The SSD1306 is rated for 400, many
+  /// panels keep up with 1000, `bench display` shows what a panel does.
+  pub bus_khz: u32,
+}
+
+impl Default for DisplayOptions {
+  fn default() -> Self {
+    Self {
+      clock_24h: true,
+      invert: false,
+      rotation: 0,
+      brightness: 0x5F,
+      debug_overlay: false,
+      bus_khz: 100,
+    }
+  }
+}
+
+/// Which events raise a notification on the display, and for how long
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationOptions {
+  pub duration_s: u64,
+  pub motion: bool,
+  pub weather: bool,
+}
+
+impl Default for NotificationOptions {
+  fn default() -> Self {
+    Self {
+      duration_s: 5,
+      motion: true,
+      weather: true,
+    }
+  }
+}
+
+impl NotificationOptions {
+  pub fn duration(&self) -> Duration {
+    Duration::from_secs(self.duration_s)
+  }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Security {
+  /// Bearer token for admin endpoints such as reboot and factory reset.
+  /// Those endpoints stay disabled while it is empty.
+  pub admin_token: String,
+  /// Send plain HTTP requests over to HTTPS, once a certificate has been
+  /// uploaded
+  pub https_redirect: bool,
+  /// Kept with the secrets and managed on their own routes, never part of
+  /// the settings sent to or from a browser
+  #[serde(skip)]
+  pub api_tokens: Vec<ApiToken>,
+}
+
+/// Origins of web apps hosted elsewhere that may call the API, e.g.
+/// `https://dash.example.com`. `*` allows any origin.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Cors {
+  pub allowed_origins: Vec<String>,
+}
+
+impl Cors {
+  pub fn allows(&self, origin: &str) -> bool {
+    self
+      .allowed_origins
+      .iter()
+      .any(|allowed| allowed == "*" || allowed.eq_ignore_ascii_case(origin))
+  }
+}
+
+/// How often one client may call the routes that move or sound something,
+/// such as the buzzer and the servo
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimit {
+  /// Requests in a row before the limit kicks in
+  pub burst: u32,
+  /// Requests allowed again per minute after that
+  pub per_minute: u32,
+}
+
+impl Default for RateLimit {
+  fn default() -> Self {
+    Self {
+      burst: 3,
+      per_minute: 6,
+    }
+  }
+}
+
+/// Phones whose answers to pings tell whether anyone is home. Presence is
+/// unknown while the list is empty.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PresenceOptions {
+  /// IPv4 addresses, best reserved for the phones on the router
+  pub phones: Vec<String>,
+  /// Minutes without an answer before everyone counts as away
+  pub away_after_min: u32,
+}
+
+impl Default for PresenceOptions {
+  fn default() -> Self {
+    Self {
+      phones: Vec::new(),
+      away_after_min: 15,
+    }
+  }
+}
+
+/// What the door's reed switch sets off. `alarm_after_s` of 0 turns the
+/// alarm off.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DoorOptions {
+  /// Chime when the door opens
+  pub chime: bool,
+  /// Seconds the door may stay open before the alarm goes off
+  pub alarm_after_s: u32,
+  /// Seconds between alarms while the door stays open
+  pub alarm_every_s: u32,
+}
+
+impl Default for DoorOptions {
+  fn default() -> Self {
+    Self {
+      chime: true,
+      alarm_after_s: 120,
+      alarm_every_s: 10,
+    }
+  }
+}
+
+/// HTTP proxy outgoing requests go through, firmware downloads included.
+/// Requests go straight out while `host` is empty. Applies after a reboot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Proxy {
+  pub host: String,
+  pub port: u16,
+  /// Sent as Basic `Proxy-Authorization` unless empty
+  pub username: String,
+  pub password: String,
+}
+
+impl Default for Proxy {
+  fn default() -> Self {
+    Self {
+      host: String::new(),
+      port: 8080,
+      username: String::new(),
+      password: String::new(),
+    }
+  }
+}
+
+/// Moving to a stronger access point of the same network, for mesh
+/// networks and repeaters the station would otherwise stay stuck to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Roaming {
+  pub enabled: bool,
+  /// Signal below which stronger access points are looked for
+  pub below_dbm: i8,
+  /// How much stronger one has to be to move to it
+  pub margin_db: u8,
+}
+
+impl Default for Roaming {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      below_dbm: -72,
+      margin_db: 8,
+    }
+  }
+}
+
+/// Fixed IPv4 settings for a network without DHCP, or to keep the address
+/// a router forwards a port to. DHCP sets everything while `address` is
+/// empty. Applies after a reboot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StaticIp {
+  pub address: String,
+  pub netmask: String,
+  pub gateway: String,
+  /// Up to two name servers, the gateway when none are set
+  pub dns: Vec<String>,
+}
+
+impl Default for StaticIp {
+  fn default() -> Self {
+    Self {
+      address: String::new(),
+      netmask: "255.255.255.0".to_string(),
+      gateway: String::new(),
+      dns: Vec::new(),
+    }
+  }
+}
+
+impl StaticIp {
+  /// Length of the network prefix `netmask` stands for, `None` when it
+  /// isn't a netmask
+  pub fn prefix_len(&self) -> Option<u8> {
+    let mask = u32::from(self.netmask.parse::<Ipv4Addr>().ok()?);
+    let len = mask.leading_ones();
+    (mask.count_ones() == len && (1..=30).contains(&len)).then_some(len as u8)
+  }
+}
+
+/// The device's own network, opened when the saved ones can't be joined
+/// for `after_min` minutes, so another one can be chosen on the WiFi page
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SetupApOptions {
+  pub enabled: bool,
+  pub after_min: u32,
+}
+
+impl Default for SetupApOptions {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      after_min: 5,
+    }
+  }
+}
+
+/// Answering other units and apps looking for pippos on the LAN, see
+/// [`discovery`](crate::discovery), and `<name>.local` over mDNS
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Discovery {
+  pub enabled: bool,
+  /// Also show up in network browsers that use UPnP, see
+  /// [`ssdp`](crate::ssdp)
+  pub ssdp: bool,
+}
+
+impl Default for Discovery {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      ssdp: true,
+    }
+  }
+}
+
+/// Wired network through a W5500 on the SPI bus, for builds with the
+/// `ethernet` feature. Used instead of WiFi while the cable is in. Applies
+/// after a reboot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ethernet {
+  pub enabled: bool,
+  pub sclk: u8,
+  pub mosi: u8,
+  pub miso: u8,
+  pub cs: u8,
+  /// The chip's interrupt line, can be an input-only pin
+  pub int: u8,
+  /// The chip's reset line, when it is wired
+  pub rst: Option<u8>,
+  pub spi_mhz: u32,
+}
+
+impl Default for Ethernet {
+  fn default() -> Self {
+    // the ESP32's VSPI pins, but for the ones the other parts use
+    Self {
+      enabled: false,
+      sclk: 18,
+      mosi: 13,
+      miso: 19,
+      cs: 14,
+      int: 35,
+      rst: None,
+      spi_mhz: 20,
+    }
+  }
+}
+
+impl Ethernet {
+  /// (role, pin, drives the pin), as checked with the other pins
+  pub fn roles(&self) -> Vec<(&'static str, u8, bool)> {
+    let mut roles = vec![
+      ("ethernet sclk", self.sclk, true),
+      ("ethernet mosi", self.mosi, true),
+      ("ethernet miso", self.miso, false),
+      ("ethernet cs", self.cs, true),
+      ("ethernet int", self.int, false),
+    ];
+    roles.extend(self.rst.map(|rst| ("ethernet rst", rst, true)));
+    roles
+  }
+}
+
+/// What the unit calls itself on the network, see
+/// [`hostname`](crate::hostname). Applies after a reboot.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeviceOptions {
+  /// Empty for `pippo-` and the end of the MAC address
+  pub name: String,
+}
+
+impl DeviceOptions {
+  /// Up to 30 letters, digits or `-`, not at either end, which DHCP and the
+  /// web pages take as is
+  pub fn valid_name(name: &str) -> bool {
+    name.is_empty()
+      || (name.len() <= 30
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+  }
+}
+
+/// A copy of the logs sent to a collector on the LAN, see
+/// [`syslog`](crate::syslog). Applies after a reboot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyslogOptions {
+  /// `ip:port` of the collector, empty to keep the logs on the device
+  pub collector: String,
+  /// Most verbose level forwarded: error, warn, info, debug or trace
+  pub level: String,
+}
+
+impl Default for SyslogOptions {
+  fn default() -> Self {
+    Self {
+      collector: String::new(),
+      level: "warn".to_string(),
+    }
+  }
+}
+
+impl SyslogOptions {
+  /// The level to forward at, `None` if it isn't one
+  pub fn level_filter(&self) -> Option<LevelFilter> {
+    self
+      .level
+      .parse::<LevelFilter>()
+      .ok()
+      .filter(|level| *level != LevelFilter::Off)
+  }
+}
+
+/// Time servers, tried in order, such as one on the LAN where a firewall
+/// keeps the public ones out. Applies after a reboot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ntp {
+  pub servers: Vec<String>,
+  /// Try `pool.ntp.org` after them
+  pub fallback_pool: bool,
+}
+
+impl Default for Ntp {
+  fn default() -> Self {
+    Self {
+      servers: Vec::new(),
+      fallback_pool: true,
+    }
+  }
+}
+
+impl Ntp {
+  /// The servers in the order they are tried
+  pub fn all_servers(&self) -> Vec<&str> {
+    let mut servers: Vec<&str> =
+      self.servers.iter().map(String::as_str).collect();
+    if self.fallback_pool || servers.is_empty() {
+      servers.push(NTP_POOL);
+    }
+    servers
+  }
+}
+
+/// Units sharing their events over MQTT: motion on one unit, for example,
+/// fires the motion rules on all of them. Joining or leaving applies after
+/// a reboot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupOptions {
+  /// `mqtt://host:1883` or `mqtts://host:8883`, off while empty
+  pub broker: String,
+  /// Units with the same name are in the same group
+  pub name: String,
+  /// Seconds an event from another unit is still acted on
+  pub ttl_s: u32,
+}
+
+impl Default for GroupOptions {
+  fn default() -> Self {
+    Self {
+      broker: String::new(),
+      name: "home".to_string(),
+      ttl_s: 30,
+    }
+  }
+}
+
+/// Rules that act on events such as motion, the webhooks they can call and
+/// the thresholds on sensor readings that raise events of their own
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Automation {
+  pub rules: Vec<Rule>,
+  pub webhooks: Vec<Webhook>,
+  pub thresholds: Vec<Threshold>,
+  pub scenes: Vec<Scene>,
+}
+
+/// Address a rule's `webhook` action POSTs the event to, as JSON
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+  pub name: String,
+  pub url: String,
+}
+
+impl Automation {
+  pub fn validate(&self) -> anyhow::Result<()> {
+    if self.rules.len() > automation::MAX_RULES {
+      anyhow::bail!("at most {} rules", automation::MAX_RULES);
+    }
+    if self.webhooks.len() > automation::MAX_WEBHOOKS {
+      anyhow::bail!("at most {} webhooks", automation::MAX_WEBHOOKS);
+    }
+    for (i, webhook) in self.webhooks.iter().enumerate() {
+      if !valid_name(&webhook.name) {
+        anyhow::bail!("webhook name must be 1-32 letters, digits, - or _");
+      }
+      if self.webhooks[..i]
+        .iter()
+        .any(|other| other.name == webhook.name)
+      {
+        anyhow::bail!("there is already a webhook named {:?}", webhook.name);
+      }
+      let url = &webhook.url;
+      if url.len() > 256
+        || !(url.starts_with("http://") || url.starts_with("https://"))
+        || url.contains(char::is_whitespace)
+      {
+        anyhow::bail!("webhook {:?} needs an http(s) URL", webhook.name);
+      }
+    }
+    if self.thresholds.len() > automation::MAX_THRESHOLDS {
+      anyhow::bail!("at most {} thresholds", automation::MAX_THRESHOLDS);
+    }
+    for (i, threshold) in self.thresholds.iter().enumerate() {
+      if !valid_name(&threshold.name) {
+        anyhow::bail!("threshold name must be 1-32 letters, digits, - or _");
+      }
+      if self.thresholds[..i]
+        .iter()
+        .any(|other| other.name == threshold.name)
+      {
+        anyhow::bail!(
+          "there is already a threshold named {:?}",
+          threshold.name
+        );
+      }
+      if threshold.above.is_some() == threshold.below.is_some() {
+        anyhow::bail!(
+          "threshold {:?} needs either above or below",
+          threshold.name
+        );
+      }
+      if !(0.0..=1000.0).contains(&threshold.hysteresis)
+        || threshold.for_min > 24 * 60
+      {
+        anyhow::bail!(
+          "threshold {:?}: hysteresis must be 0-1000, for_min 0-1440",
+          threshold.name
+        );
+      }
+    }
+    if self.scenes.len() > automation::MAX_SCENES {
+      anyhow::bail!("at most {} scenes", automation::MAX_SCENES);
+    }
+    for (i, scene) in self.scenes.iter().enumerate() {
+      if !valid_name(&scene.name) {
+        anyhow::bail!("scene name must be 1-32 letters, digits, - or _");
+      }
+      if self.scenes[..i]
+        .iter()
+        .any(|other| other.name == scene.name)
+      {
+        anyhow::bail!("there is already a scene named {:?}", scene.name);
+      }
+      if scene.servo.is_some_and(|angle| angle > 180) {
+        anyhow::bail!("scene {:?}: servo angle must be 0-180", scene.name);
+      }
+    }
+    for (i, rule) in self.rules.iter().enumerate() {
+      if !valid_name(&rule.name) {
+        anyhow::bail!("rule name must be 1-32 letters, digits, - or _");
+      }
+      if self.rules[..i].iter().any(|other| other.name == rule.name) {
+        anyhow::bail!("there is already a rule named {:?}", rule.name);
+      }
+      if let Event::Threshold(name) | Event::Cleared(name) = &rule.when {
+        if !self
+          .thresholds
+          .iter()
+          .any(|threshold| threshold.name == *name)
+        {
+          anyhow::bail!("rule {:?}: no threshold named {name:?}", rule.name);
+        }
+      }
+      if let Event::Trigger(name) = &rule.when {
+        if !valid_name(name) {
+          anyhow::bail!(
+            "rule {:?}: trigger must be 1-32 letters, digits, - or _",
+            rule.name
+          );
+        }
+      }
+      if let Event::At(time) = &rule.when {
+        // matched as text against the clock, so it has to be written the
+        // same way
+        let canonical = automation::parse_time(time)
+          .map(|time| time.format("%H:%M").to_string());
+        if canonical.as_ref() != Some(time) {
+          anyhow::bail!("rule {:?}: times must be HH:MM", rule.name);
+        }
+      }
+      if rule.then.is_empty() || rule.then.len() > 8 {
+        anyhow::bail!("rule {:?} needs 1-8 actions", rule.name);
+      }
+      for condition in &rule.conditions {
+        let Condition::TimeBetween(start, end) = condition else {
+          continue;
+        };
+        if automation::parse_time(start).is_none()
+          || automation::parse_time(end).is_none()
+        {
+          anyhow::bail!("rule {:?}: times must be HH:MM", rule.name);
+        }
+      }
+      for action in &rule.then {
+        match action {
+          Action::Webhook(name)
+            if !self.webhooks.iter().any(|webhook| webhook.name == *name) =>
+          {
+            anyhow::bail!("rule {:?}: no webhook named {name:?}", rule.name);
+          }
+          Action::Notify(text) if text.is_empty() || text.len() > 64 => {
+            anyhow::bail!(
+              "rule {:?}: notification must be 1-64 bytes",
+              rule.name
+            );
+          }
+          Action::Scene(name)
+            if !self.scenes.iter().any(|scene| scene.name == *name) =>
+          {
+            anyhow::bail!("rule {:?}: no scene named {name:?}", rule.name);
+          }
+          // setting a scene raises an event, which could set it again
+          Action::Scene(_) if matches!(rule.when, Event::Scene(_)) => {
+            anyhow::bail!(
+              "rule {:?}: a rule on a scene can't set a scene",
+              rule.name
+            );
+          }
+          _ => {}
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Rule and webhook names: short, and safe in a query string
+pub fn valid_name(name: &str) -> bool {
+  (1..=32).contains(&name.len())
+    && name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || "-_".contains(c))
+}
+
+/// Which clients the web server answers. With the server disabled it is not
+/// started at all; holding the button at power-on turns it back on.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerOptions {
+  pub enabled: bool,
+  /// Client addresses or subnets, e.g. `192.168.1.20` or `192.168.1.0/24`.
+  /// Everyone is allowed while it is empty.
+  pub allowlist: Vec<String>,
+}
+
+impl Default for ServerOptions {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      allowlist: Vec::new(),
+    }
+  }
+}
+
+impl ServerOptions {
+  pub fn allows(&self, client: Option<IpAddr>) -> bool {
+    if self.allowlist.is_empty() {
+      return true;
+    }
+    let Some(client) = client else {
+      return false;
+    };
+    self.allowlist.iter().any(|entry| {
+      parse_subnet(entry)
+        .map(|(network, bits)| in_subnet(client, network, bits))
+        .unwrap_or(false)
+    })
+  }
+}
+
+/// `a.b.c.d` or `a.b.c.d/bits`, and the same for IPv6
+fn parse_subnet(entry: &str) -> anyhow::Result<(IpAddr, u8)> {
+  let (address, bits) = match entry.split_once('/') {
+    Some((address, bits)) => (address, Some(bits)),
+    None => (entry, None),
+  };
+  let address: IpAddr = address.trim().parse()?;
+  let max = if address.is_ipv4() { 32 } else { 128 };
+  let bits = match bits {
+    Some(bits) => bits.trim().parse()?,
+    None => max,
+  };
+  if bits > max {
+    anyhow::bail!("prefix length {bits} is longer than {max}");
+  }
+  Ok((address, bits))
+}
+
+fn in_subnet(client: IpAddr, network: IpAddr, bits: u8) -> bool {
+  match (client, network) {
+    (IpAddr::V4(client), IpAddr::V4(network)) => {
+      let mask = u32::MAX.checked_shl(32 - bits as u32).unwrap_or(0);
+      u32::from(client) & mask == u32::from(network) & mask
+    }
+    (IpAddr::V6(client), IpAddr::V6(network)) => {
+      let mask = u128::MAX.checked_shl(128 - bits as u32).unwrap_or(0);
+      u128::from(client) & mask == u128::from(network) & mask
+    }
+    _ => false,
+  }
+}
+
+/// GPIO number for each peripheral. Read once at startup, so a change takes
+/// effect after the next reboot.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PinConfig {
+  pub button: u8,
+  pub led: u8,
+  pub buzzer: u8,
+  pub sda: u8,
+  pub scl: u8,
+  pub pir: u8,
+  pub servo: u8,
+  pub relay: u8,
+  pub door: u8,
+}
+
+impl Default for PinConfig {
+  fn default() -> Self {
+    Self {
+      button: DEFAULTS.pin_button,
+      led: DEFAULTS.pin_led,
+      buzzer: DEFAULTS.pin_buzzer,
+      sda: DEFAULTS.pin_sda,
+      scl: DEFAULTS.pin_scl,
+      pir: DEFAULTS.pin_pir,
+      servo: DEFAULTS.pin_servo,
+      relay: DEFAULTS.pin_relay,
+      door: DEFAULTS.pin_door,
+    }
+  }
+}
+
+impl PinConfig {
+  fn roles(&self) -> [(&'static str, u8, bool); 9] {
+    // (role, pin, drives the pin)
+    [
+      ("button", self.button, false),
+      ("led", self.led, true),
+      ("buzzer", self.buzzer, true),
+      ("sda", self.sda, true),
+      ("scl", self.scl, true),
+      ("pir", self.pir, false),
+      ("servo", self.servo, true),
+      ("relay", self.relay, true),
+      ("door", self.door, false),
+    ]
+  }
+
+  pub fn validate(&self) -> anyhow::Result<()> {
+    self.validate_with(&[])
+  }
+
+  /// Same as [`validate`](Self::validate) with the pins of `more` parts,
+  /// such as an Ethernet chip
+  pub fn validate_with(
+    &self,
+    more: &[(&'static str, u8, bool)],
+  ) -> anyhow::Result<()> {
+    let roles: Vec<_> = self.roles().into_iter().chain(more.to_vec()).collect();
+    for (index, &(role, pin, output)) in roles.iter().enumerate() {
+      match pin {
+        // not bonded out on the ESP32
+        20 | 24 | 28..=31 | 40.. => {
+          anyhow::bail!("{role} pin {pin} is not a GPIO")
+        }
+        // wired to the SPI flash
+        6..=11 => anyhow::bail!("{role} pin {pin} is used by the flash"),
+        // the USB serial port, for the logs and the console
+        1 | 3 => anyhow::bail!("{role} pin {pin} is used by the serial port"),
+        34..=39 if output => {
+          anyhow::bail!("{role} pin {pin} is input-only")
+        }
+        _ => {}
+      }
+      if let Some((other, ..)) =
+        roles[..index].iter().find(|(_, other, _)| *other == pin)
+      {
+        anyhow::bail!("{role} and {other} can't share pin {pin}");
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Config {
+  /// Saved settings, or the defaults when nothing has been saved yet
+  pub fn load(partition: EspDefaultNvsPartition) -> anyhow::Result<Self> {
+    let nvs = EspDefaultNvs::new(partition.clone(), NAMESPACE, true)?;
+    let mut config = match nvs.str_len(KEY)? {
+      Some(len) => {
+        let mut buf = vec![0_u8; len];
+        match nvs.get_str(KEY, &mut buf)? {
+          Some(json) => serde_json::from_str(json)?,
+          None => Self::default(),
+        }
+      }
+      None => Self::default(),
+    };
+    // settings saved before secrets had their own namespace still carry
+    // them in the JSON, they move over on the next save
+    if let Some(key) =
+      secrets::get(partition.clone(), secrets::WEATHER_API_KEY)?
+    {
+      config.api_keys.weather = key;
+    }
+    if let Some(token) = secrets::get(partition.clone(), secrets::ADMIN_TOKEN)?
+    {
+      config.security.admin_token = token;
+    }
+    if let Some(password) =
+      secrets::get(partition.clone(), secrets::PROXY_PASSWORD)?
+    {
+      config.proxy.password = password;
+    }
+    if let Some(tokens) = secrets::get(partition, secrets::API_TOKENS)? {
+      config.security.api_tokens = serde_json::from_str(&tokens)?;
+    }
+    Ok(config)
+  }
+
+  pub fn save(&self, partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+    secrets::set(
+      partition.clone(),
+      secrets::WEATHER_API_KEY,
+      &self.api_keys.weather,
+    )?;
+    secrets::set(
+      partition.clone(),
+      secrets::ADMIN_TOKEN,
+      &self.security.admin_token,
+    )?;
+    secrets::set(
+      partition.clone(),
+      secrets::PROXY_PASSWORD,
+      &self.proxy.password,
+    )?;
+    secrets::set(
+      partition.clone(),
+      secrets::API_TOKENS,
+      &serde_json::to_string(&self.security.api_tokens)?,
+    )?;
+    let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    nvs.set_str(KEY, &self.without_secrets()?.to_string())?;
+    Ok(())
+  }
+
+  /// Settings as JSON with the secret fields left out
+  pub fn without_secrets(&self) -> anyhow::Result<serde_json::Value> {
+    let mut json = serde_json::to_value(self)?;
+    for (section, field) in SECRET_FIELDS {
+      if let Some(section) = json[section].as_object_mut() {
+        section.remove(field);
+      }
+    }
+    Ok(json)
+  }
+
+  /// Settings from JSON that may leave out the secret fields, as exported by
+  /// [`Config::without_secrets`]. Missing secrets are taken from `current`.
+  pub fn with_secrets_of(
+    mut json: serde_json::Value,
+    current: &Self,
+  ) -> anyhow::Result<Self> {
+    let current_json = serde_json::to_value(current)?;
+    if let Some(object) = json.as_object_mut() {
+      for (section, field) in SECRET_FIELDS {
+        let section_json = object
+          .entry(section)
+          .or_insert_with(|| serde_json::json!({}));
+        if let Some(section_json) = section_json.as_object_mut() {
+          section_json
+            .entry(field)
+            .or_insert_with(|| current_json[section][field].clone());
+        }
+      }
+    }
+    Ok(serde_json::from_value::<Self>(json)?.unmasked(current))
+  }
+
+  /// Copy safe to send to a browser, with the secrets hidden
+  pub fn masked(&self) -> Self {
+    let mut config = self.clone();
+    for secret in [
+      &mut config.api_keys.weather,
+      &mut config.security.admin_token,
+      &mut config.proxy.password,
+    ] {
+      if !secret.is_empty() {
+        *secret = MASK.to_string();
+      }
+    }
+    config
+  }
+
+  /// Settings posted back from a browser, keeping the secrets of `current`
+  /// that were sent out masked
+  pub fn unmasked(mut self, current: &Self) -> Self {
+    if self.api_keys.weather == MASK {
+      self.api_keys.weather = current.api_keys.weather.clone();
+    }
+    if self.security.admin_token == MASK {
+      self.security.admin_token = current.security.admin_token.clone();
+    }
+    if self.proxy.password == MASK {
+      self.proxy.password = current.proxy.password.clone();
+    }
+    self.security.api_tokens = current.security.api_tokens.clone();
+    self
+  }
+
+  /// Forget the saved settings, the defaults apply after the next boot
+  pub fn erase(partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+    let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    nvs.remove(KEY)?;
+    Ok(())
+  }
+
+  pub fn validate(&self) -> anyhow::Result<()> {
+    if !(-90.0..=90.0).contains(&self.location.latitude) {
+      anyhow::bail!("latitude must be between -90 and 90");
+    }
+    if !(-180.0..=180.0).contains(&self.location.longitude) {
+      anyhow::bail!("longitude must be between -180 and 180");
+    }
+    if !self
+      .api_keys
+      .weather
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric())
+    {
+      anyhow::bail!("weather API key must be letters and digits");
+    }
+    if !(5..=24 * 60).contains(&self.refresh.weather_min) {
+      anyhow::bail!("weather refresh must be 5-1440 minutes");
+    }
+    if !(1..=24 * 60).contains(&self.refresh.crypto_min) {
+      anyhow::bail!("crypto refresh must be 1-1440 minutes");
+    }
+    if self.crypto.coins.len() > 12
+      || self.crypto.coins.iter().any(|coin| {
+        coin.is_empty()
+          || !coin
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+      })
+    {
+      anyhow::bail!("coins must be up to 12 CoinGecko ids such as bitcoin");
+    }
+    if !(3..=5).contains(&self.crypto.currency.len())
+      || !self.crypto.currency.chars().all(|c| c.is_ascii_lowercase())
+    {
+      anyhow::bail!("currency must be a code such as usd or eur");
+    }
+    if !(5..=24 * 60).contains(&self.refresh.stocks_min) {
+      anyhow::bail!("stocks refresh must be 5-1440 minutes");
+    }
+    if self.stocks.symbols.len() > 8
+      || self.stocks.symbols.iter().any(|symbol| {
+        !(1..=12).contains(&symbol.len())
+          || !symbol.chars().all(|c| {
+            c.is_ascii_uppercase() || c.is_ascii_digit() || ".-^=".contains(c)
+          })
+      })
+    {
+      anyhow::bail!("symbols must be up to 8 tickers such as AAPL or ^GSPC");
+    }
+    if !(5..=24 * 60).contains(&self.refresh.feed_min) {
+      anyhow::bail!("feed refresh must be 5-1440 minutes");
+    }
+    let url = &self.feed.url;
+    if !url.is_empty()
+      && (url.len() > 256
+        || !(url.starts_with("http://") || url.starts_with("https://"))
+        || url.contains(char::is_whitespace))
+    {
+      anyhow::bail!("feed URL must be empty or an http(s) URL");
+    }
+    let client_id = &self.spotify.client_id;
+    if client_id.len() > 64 || !client_id.chars().all(|c| c.is_ascii_hexdigit())
+    {
+      anyhow::bail!("Spotify client ID must be empty or a hex string");
+    }
+    if self.quiet_hours.start_hour > 23 || self.quiet_hours.end_hour > 23 {
+      anyhow::bail!("quiet hours must be 0-23");
+    }
+    if !(1..=60).contains(&self.notifications.duration_s) {
+      anyhow::bail!("notification duration must be 1-60 seconds");
+    }
+    if ![0, 180].contains(&self.display.rotation) {
+      anyhow::bail!("display rotation must be 0 or 180");
+    }
+    if !(100..=1000).contains(&self.display.bus_khz) {
+      anyhow::bail!("display bus speed must be 100-1000 kHz");
+    }
+    for origin in &self.cors.allowed_origins {
+      let scheme_ok =
+        origin.starts_with("http://") || origin.starts_with("https://");
+      if origin != "*" && (!scheme_ok || origin.ends_with('/')) {
+        anyhow::bail!("allowed origin {origin:?} must be * or scheme://host");
+      }
+    }
+    let token = &self.security.admin_token;
+    if !token.is_empty()
+      && (token.len() < 16 || !token.chars().all(|c| c.is_ascii_graphic()))
+    {
+      anyhow::bail!("admin token must be empty or 16+ printable characters");
+    }
+    if !(1..=60).contains(&self.rate_limit.burst)
+      || !(1..=600).contains(&self.rate_limit.per_minute)
+    {
+      anyhow::bail!("rate limit must be a burst of 1-60 and 1-600 per minute");
+    }
+    if self.server.allowlist.len() > 16 {
+      anyhow::bail!("the allowlist holds at most 16 entries");
+    }
+    for entry in &self.server.allowlist {
+      if let Err(e) = parse_subnet(entry) {
+        anyhow::bail!("allowlist entry {entry:?} is invalid: {e}");
+      }
+    }
+    if self.security.api_tokens.len() > auth::MAX_API_TOKENS {
+      anyhow::bail!("at most {} API tokens", auth::MAX_API_TOKENS);
+    }
+    for (i, token) in self.security.api_tokens.iter().enumerate() {
+      let name_ok = token
+        .name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || " -_".contains(c));
+      if !(1..=32).contains(&token.name.len()) || !name_ok {
+        anyhow::bail!(
+          "token name must be 1-32 letters, digits, spaces, - or _"
+        );
+      }
+      if self.security.api_tokens[..i]
+        .iter()
+        .any(|other| other.name == token.name)
+      {
+        anyhow::bail!("there is already a token named {:?}", token.name);
+      }
+    }
+    if self.presence.phones.len() > 4
+      || self
+        .presence
+        .phones
+        .iter()
+        .any(|phone| phone.parse::<Ipv4Addr>().is_err())
+    {
+      anyhow::bail!("phones must be up to 4 IPv4 addresses");
+    }
+    if !(1..=240).contains(&self.presence.away_after_min) {
+      anyhow::bail!("away after must be 1-240 minutes");
+    }
+    let broker = &self.group.broker;
+    if broker.len() > 128
+      || !(broker.is_empty()
+        || broker.starts_with("mqtt://")
+        || broker.starts_with("mqtts://"))
+    {
+      anyhow::bail!("broker must be an mqtt:// or mqtts:// URL");
+    }
+    if !valid_name(&self.group.name) {
+      anyhow::bail!("group name must be 1-32 letters, digits, - or _");
+    }
+    if !(1..=3600).contains(&self.group.ttl_s) {
+      anyhow::bail!("group TTL must be 1-3600 seconds");
+    }
+    if self.door.alarm_after_s > 3600
+      || !(5..=600).contains(&self.door.alarm_every_s)
+    {
+      anyhow::bail!(
+        "door alarm must be 0-3600 seconds after opening, every 5-600 seconds"
+      );
+    }
+    let proxy = &self.proxy;
+    if proxy.host.len() > 64
+      || !proxy
+        .host
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || ".-".contains(c))
+    {
+      anyhow::bail!("proxy host must be empty or a host name or address");
+    }
+    if proxy.port == 0 {
+      anyhow::bail!("proxy port must be 1-65535");
+    }
+    if proxy.username.len() > 64
+      || proxy.password.len() > 64
+      || proxy.username.contains(':')
+      || !proxy
+        .username
+        .chars()
+        .chain(proxy.password.chars())
+        .all(|c| c.is_ascii_graphic())
+    {
+      anyhow::bail!(
+        "proxy username and password must be up to 64 printable characters"
+      );
+    }
+    if !(-90..=-50).contains(&self.roaming.below_dbm) {
+      anyhow::bail!("roaming threshold must be -90 to -50 dBm");
+    }
+    if !(3..=30).contains(&self.roaming.margin_db) {
+      anyhow::bail!("roaming margin must be 3-30 dB");
+    }
+    let static_ip = &self.static_ip;
+    if !static_ip.address.is_empty() {
+      let Some(prefix_len) = static_ip.prefix_len() else {
+        anyhow::bail!("netmask must be a netmask such as 255.255.255.0");
+      };
+      let (Ok(address), Ok(gateway)) = (
+        static_ip.address.parse::<Ipv4Addr>(),
+        static_ip.gateway.parse::<Ipv4Addr>(),
+      ) else {
+        anyhow::bail!("address and gateway must be IPv4 addresses");
+      };
+      let network = |ip: Ipv4Addr| u32::from(ip) >> (32 - prefix_len);
+      if network(address) != network(gateway) || address == gateway {
+        anyhow::bail!("gateway must be another address of the same network");
+      }
+    }
+    if static_ip.dns.len() > 2
+      || static_ip
+        .dns
+        .iter()
+        .any(|server| server.parse::<Ipv4Addr>().is_err())
+    {
+      anyhow::bail!("dns must be up to 2 IPv4 addresses");
+    }
+    if self.ntp.servers.len() > MAX_NTP_SERVERS
+      || self.ntp.servers.iter().any(|server| {
+        server.is_empty()
+          || server.len() > 64
+          || !server
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".-".contains(c))
+      })
+    {
+      anyhow::bail!(
+        "NTP servers must be up to {MAX_NTP_SERVERS} host names or addresses"
+      );
+    }
+    if !(1..=60).contains(&self.setup_ap.after_min) {
+      anyhow::bail!("setup network delay must be 1-60 minutes");
+    }
+    if !(1..=40).contains(&self.ethernet.spi_mhz) {
+      anyhow::bail!("Ethernet SPI speed must be 1-40 MHz");
+    }
+    if !DeviceOptions::valid_name(&self.device.name) {
+      anyhow::bail!(
+        "device name must be up to 30 letters, digits or -, not starting or \
+         ending with -"
+      );
+    }
+    let syslog = &self.syslog;
+    if !syslog.collector.is_empty()
+      && syslog.collector.parse::<SocketAddr>().is_err()
+    {
+      anyhow::bail!("syslog collector must be empty or ip:port");
+    }
+    if syslog.level_filter().is_none() {
+      anyhow::bail!("syslog level must be error, warn, info, debug or trace");
+    }
+    self.automation.validate()?;
+    if self.ethernet.enabled {
+      self.pins.validate_with(&self.ethernet.roles())?;
+    } else {
+      self.pins.validate()?;
+    }
+    Ok(())
+  }
+}
+
+/// Erase the settings, WiFi credentials, profiles and secrets. The
+/// build-time defaults apply after the next boot.
+pub fn factory_reset(partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+  Config::erase(partition.clone())?;
+  Credentials::erase(partition.clone())?;
+  Profiles::erase(partition.clone())?;
+  secrets::erase(partition)?;
+  Ok(())
+}
@@ -0,0 +1,365 @@
+use std::{io::Write, time::Duration};
+
+use esp_idf_hal::{delay::BLOCK, uart::UartDriver};
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition};
+use log::LevelFilter;
+
+use crate::{
+  bench::SharedBench,
+  clock::Instant,
+  config::{Config, SharedConfig},
+  http_client::Validators,
+  logger,
+  recording::{self, SharedSession},
+  state::SharedState,
+  weather,
+  wifi::SharedWifi,
+};
+#[cfg(feature = "servo")]
+use crate::{servo, web::Servo};
+
+/// Longest line kept, the rest of a longer one is dropped
+const MAX_LINE_LEN: usize = 128;
+/// Where `record stop` keeps the recording for `replay`
+const RECORDING_NAMESPACE: &str = "recording";
+const RECORDING_KEY: &str = "last";
+
+const HELP: &str = "\
+Commands:
+  help                    this list
+  wifi status             connection, address and signal
+  weather fetch           fetch the weather now
+  servo <0-180>           turn the servo
+  config get <key>        a setting, e.g. display.brightness
+  config set <key> <val>  change and save a setting, val as JSON or text
+  log level <level> [mod] off, error, warn, info, debug or trace, for
+                          everything or one module, e.g. weather
+  log level default <mod> the module back to the level of everything
+  log levels              the levels set
+  overlay <on|off>        frame times and free heap on the display
+  record start            record presses, motion and screens from Home
+  record stop             stop and save the recording to flash
+  record show             the saved recording, one entry per line
+  replay                  play the saved recording back from Home
+  bench display           flush times at each I2C speed
+  reboot                  restart";
+
+/// What the console commands act on
+pub struct Context {
+  pub config: SharedConfig,
+  pub state: SharedState,
+  pub wifi: SharedWifi,
+  pub nvs: EspDefaultNvsPartition,
+  pub session: SharedSession,
+  pub bench: SharedBench,
+  #[cfg(feature = "servo")]
+  pub servo: Servo,
+}
+
+/// A line typed on the console
+#[derive(Debug)]
+enum Command<'a> {
+  Help,
+  WifiStatus,
+  WeatherFetch,
+  Servo(u32),
+  ConfigGet(&'a str),
+  ConfigSet(&'a str, &'a str),
+  LogLevel(LevelFilter),
+  /// A module's own level, `None` to clear it
+  ModuleLevel(&'a str, Option<LevelFilter>),
+  LogLevels,
+  Overlay(bool),
+  RecordStart,
+  RecordStop,
+  RecordShow,
+  Replay,
+  BenchDisplay,
+  Reboot,
+}
+
+impl<'a> Command<'a> {
+  fn parse(line: &'a str) -> Result<Self, String> {
+    let mut words = line.split_whitespace();
+    let command = match (words.next(), words.next()) {
+      (Some("help" | "?"), None) => Self::Help,
+      (Some("wifi"), Some("status")) => Self::WifiStatus,
+      (Some("weather"), Some("fetch")) => Self::WeatherFetch,
+      (Some("servo"), Some(angle)) => match angle.parse() {
+        Ok(angle) if angle <= 180 => Self::Servo(angle),
+        _ => return Err("servo takes an angle, 0-180".to_string()),
+      },
+      (Some("config"), Some("get")) => match words.next() {
+        Some(key) => Self::ConfigGet(key),
+        None => return Err("config get <key>".to_string()),
+      },
+      (Some("config"), Some("set")) => {
+        let key = words.next();
+        // the value is the rest of the line after the key, spaces and all
+        let value = key
+          .map(|key| {
+            let end =
+              key.as_ptr() as usize - line.as_ptr() as usize + key.len();
+            line[end..].trim()
+          })
+          .filter(|value| !value.is_empty());
+        match (key, value) {
+          (Some(key), Some(value)) => return Ok(Self::ConfigSet(key, value)),
+          _ => return Err("config set <key> <value>".to_string()),
+        }
+      }
+      (Some("log"), Some("level")) => {
+        let level = words.next();
+        let module = words.next();
+        match (level, module) {
+          (Some("default"), Some(module)) => Self::ModuleLevel(module, None),
+          (Some(level), module) => match (level.parse(), module) {
+            (Ok(level), None) => Self::LogLevel(level),
+            (Ok(level), Some(module)) => Self::ModuleLevel(module, Some(level)),
+            (Err(_), _) => {
+              return Err(
+                "log level off, error, warn, info, debug or trace".to_string(),
+              )
+            }
+          },
+          (None, _) => return Err("log level <level> [module]".to_string()),
+        }
+      }
+      (Some("log"), Some("levels")) => Self::LogLevels,
+      (Some("overlay"), Some("on")) => Self::Overlay(true),
+      (Some("overlay"), Some("off")) => Self::Overlay(false),
+      (Some("record"), Some("start")) => Self::RecordStart,
+      (Some("record"), Some("stop")) => Self::RecordStop,
+      (Some("record"), Some("show")) => Self::RecordShow,
+      (Some("replay"), None) => Self::Replay,
+      (Some("bench"), Some("display")) => Self::BenchDisplay,
+      (Some("reboot"), None) => Self::Reboot,
+      _ => {
+        return Err(format!("unknown command {line:?}, try help"));
+      }
+    };
+    match words.next() {
+      Some(extra) => Err(format!("unexpected {extra:?}")),
+      None => Ok(command),
+    }
+  }
+}
+
+/// Read commands from the USB serial port in a background thread, for
+/// bench debugging without a network. Log lines keep coming out between
+/// them.
+pub fn spawn(
+  uart: UartDriver<'static>,
+  context: Context,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("console".to_string())
+    // weather fetch does a TLS handshake
+    .stack_size(12 * 1024)
+    .spawn(move || {
+      let mut line = String::new();
+      let mut byte = [0_u8; 1];
+      loop {
+        if !matches!(uart.read(&mut byte, BLOCK), Ok(1)) {
+          continue;
+        }
+        match byte[0] {
+          b'\r' | b'\n' => {
+            println!();
+            if !line.trim().is_empty() {
+              run(&line, &context);
+            }
+            line.clear();
+          }
+          // backspace and delete
+          0x08 | 0x7f => {
+            if line.pop().is_some() {
+              print!("\x08 \x08");
+              let _ = std::io::stdout().flush();
+            }
+          }
+          byte @ 0x20..=0x7e if line.len() < MAX_LINE_LEN => {
+            line.push(char::from(byte));
+            print!("{}", char::from(byte));
+            let _ = std::io::stdout().flush();
+          }
+          _ => {}
+        }
+      }
+    })?;
+  log::info!("Serial console ready, type help");
+  Ok(())
+}
+
+fn run(line: &str, context: &Context) {
+  let result = match Command::parse(line) {
+    Ok(command) => execute(command, context),
+    Err(usage) => Err(anyhow::anyhow!(usage)),
+  };
+  match result {
+    Ok(output) => println!("{output}"),
+    Err(error) => println!("error: {error}"),
+  }
+}
+
+fn execute(command: Command<'_>, context: &Context) -> anyhow::Result<String> {
+  match command {
+    Command::Help => Ok(HELP.to_string()),
+    Command::WifiStatus => {
+      // the WiFi page may be switching networks
+      let Ok(wifi) = context.wifi.try_lock() else {
+        return Ok("busy, connecting".to_string());
+      };
+      if !wifi.is_connected()? {
+        return Ok("not connected".to_string());
+      }
+      let ip = wifi.wifi().sta_netif().get_ip_info()?.ip;
+      let rssi = wifi.wifi().get_rssi()?;
+      Ok(format!("connected, {ip}, {rssi} dBm"))
+    }
+    Command::WeatherFetch => {
+      let config = context.config.lock().unwrap().clone();
+      let Some(key) = weather::api_key(context.nvs.clone())? else {
+        return Ok("no API key set".to_string());
+      };
+      let mut validators = Validators::default();
+      let Some(weather) = weather::fetch(&config, &key, &mut validators)?
+      else {
+        return Ok("unchanged".to_string());
+      };
+      let summary = format!(
+        "{:.1}°C {}, {}% humidity",
+        weather.temp_c, weather.condition, weather.humidity
+      );
+      context.state.lock().unwrap().weather = Some(weather);
+      Ok(summary)
+    }
+    #[cfg(feature = "servo")]
+    Command::Servo(angle) => {
+      servo::set_angle(&mut context.servo.lock().unwrap(), angle)?;
+      Ok(format!("servo at {angle}"))
+    }
+    #[cfg(not(feature = "servo"))]
+    Command::Servo(_) => anyhow::bail!("built without the servo feature"),
+    Command::ConfigGet(key) => {
+      let json = context.config.lock().unwrap().without_secrets()?;
+      match json.pointer(&pointer(key)) {
+        Some(value) => Ok(value.to_string()),
+        None => anyhow::bail!("no setting {key:?}"),
+      }
+    }
+    Command::ConfigSet(key, value) => {
+      let mut json = serde_json::to_value(&*context.config.lock().unwrap())?;
+      let Some(slot) = json.pointer_mut(&pointer(key)) else {
+        anyhow::bail!("no setting {key:?}");
+      };
+      // text that isn't JSON is taken as a string, so names need no quotes
+      *slot = serde_json::from_str(value)
+        .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+      let config: Config = serde_json::from_value(json)?;
+      config.validate()?;
+      config.save(context.nvs.clone())?;
+      *context.config.lock().unwrap() = config;
+      log::info!("Setting {} changed from the console", key);
+      Ok(format!("{key} saved"))
+    }
+    Command::LogLevel(level) => {
+      logger::set_level(level)?;
+      Ok(format!("log level {level}"))
+    }
+    Command::ModuleLevel(module, level) => {
+      logger::set_module_level(module, level)?;
+      match level {
+        Some(level) => Ok(format!("log level {level} for {module}")),
+        None => Ok(format!("{module} logs at the default level")),
+      }
+    }
+    Command::LogLevels => {
+      let levels = logger::levels();
+      let mut output = format!("default {}", levels.default);
+      for (module, level) in &levels.modules {
+        output.push_str(&format!("\n{module} {level}"));
+      }
+      Ok(output)
+    }
+    Command::Overlay(on) => {
+      let mut config = context.config.lock().unwrap();
+      config.display.debug_overlay = on;
+      config.save(context.nvs.clone())?;
+      Ok(format!("overlay {}", if on { "on" } else { "off" }))
+    }
+    Command::RecordStart => {
+      context.session.lock().unwrap().record(Instant::now());
+      Ok("recording from Home, record stop to save".to_string())
+    }
+    Command::RecordStop => {
+      let Some(entries) = context.session.lock().unwrap().stop() else {
+        anyhow::bail!("not recording");
+      };
+      let mut nvs =
+        EspDefaultNvs::new(context.nvs.clone(), RECORDING_NAMESPACE, true)?;
+      nvs.set_blob(RECORDING_KEY, recording::to_lines(&entries).as_bytes())?;
+      Ok(format!("{} entries saved", entries.len()))
+    }
+    Command::RecordShow => match saved_recording(context)? {
+      Some(text) => Ok(text.trim_end().to_string()),
+      None => Ok("nothing recorded".to_string()),
+    },
+    Command::Replay => {
+      let Some(text) = saved_recording(context)? else {
+        anyhow::bail!("nothing recorded");
+      };
+      let entries = recording::parse(&text).map_err(anyhow::Error::msg)?;
+      let len = entries.len();
+      context
+        .session
+        .lock()
+        .unwrap()
+        .replay(entries, Instant::now());
+      Ok(format!(
+        "replaying {len} entries, the result goes to the log"
+      ))
+    }
+    Command::BenchDisplay => {
+      {
+        let mut bench = context.bench.lock().unwrap();
+        bench.requested = true;
+        bench.results = None;
+      }
+      println!("running, the panel shows test frames");
+      // the main loop picks it up on its next frame
+      for _ in 0..60 {
+        std::thread::sleep(Duration::from_millis(500));
+        if let Some(runs) = &context.bench.lock().unwrap().results {
+          let lines: Vec<String> =
+            runs.iter().map(|run| run.summary()).collect();
+          return Ok(lines.join("\n"));
+        }
+      }
+      anyhow::bail!("no results after 30 s")
+    }
+    Command::Reboot => {
+      log::warn!("Restart requested from the console");
+      esp_idf_hal::reset::restart();
+    }
+  }
+}
+
+/// The recording saved by `record stop`
+fn saved_recording(context: &Context) -> anyhow::Result<Option<String>> {
+  let nvs = EspDefaultNvs::new(context.nvs.clone(), RECORDING_NAMESPACE, true)?;
+  let Some(len) = nvs.blob_len(RECORDING_KEY)? else {
+    return Ok(None);
+  };
+  let mut buf = vec![0_u8; len];
+  Ok(
+    nvs
+      .get_blob(RECORDING_KEY, &mut buf)?
+      .map(|blob| String::from_utf8_lossy(blob).into_owned()),
+  )
+}
+
+/// JSON pointer to the setting written `section.field`
+fn pointer(key: &str) -> String {
+  format!("/{}", key.replace('.', "/"))
+}
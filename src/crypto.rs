@@ -0,0 +1,151 @@
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+
+use crate::{
+  config::{Config, Crypto},
+  display::Display,
+  fetch, http_client,
+  pager::{self, Pager},
+  state::{Price, SharedState},
+  statusbar,
+  typography::{self, Align, Font, Line},
+  url::Url,
+};
+
+/// Coins on one page of the ticker screen
+const ROWS_PER_PAGE: usize = 3;
+
+/// Keeps the prices of the coins in the crypto settings fresh in `state`.
+/// A change to the list or the currency fetches straight away.
+pub struct Source {
+  state: SharedState,
+  fetched: Option<(Instant, Crypto)>,
+}
+
+impl Source {
+  pub fn new(state: SharedState) -> Self {
+    Self {
+      state,
+      fetched: None,
+    }
+  }
+}
+
+impl fetch::Source for Source {
+  fn poll(&mut self, config: &Config) {
+    let interval =
+      Duration::from_secs(u64::from(config.refresh.crypto_min) * 60);
+    let due = match &self.fetched {
+      None => true,
+      Some((at, used)) => at.elapsed() >= interval || *used != config.crypto,
+    };
+    if !due {
+      return;
+    }
+    self.fetched = Some((Instant::now(), config.crypto.clone()));
+    if config.crypto.coins.is_empty() {
+      self.state.lock().unwrap().prices.clear();
+      return;
+    }
+    match fetch(&config.crypto) {
+      Ok(prices) => self.state.lock().unwrap().prices = prices,
+      Err(error) => log::warn!("Price update failed: {:?}", error),
+    }
+  }
+}
+
+/// Current price and 24 h change of every coin in `crypto` from CoinGecko,
+/// which needs no API key. Coins it doesn't know are left out.
+pub fn fetch(crypto: &Crypto) -> anyhow::Result<Vec<Price>> {
+  log::info!("Fetching prices of {}", crypto.coins.join(", "));
+  let url = Url::new("https://api.coingecko.com/api/v3/simple/price")
+    .query("ids", crypto.coins.join(","))
+    .query("vs_currencies", &crypto.currency)
+    .query("include_24hr_change", true);
+  let parsed: serde_json::Value = http_client::get_json(url.as_str())?;
+  let change_key = format!("{}_24h_change", crypto.currency);
+  let prices = crypto
+    .coins
+    .iter()
+    .filter_map(|coin| {
+      let Some(price) = parsed[coin][&crypto.currency].as_f64() else {
+        log::warn!("No price for {}", coin);
+        return None;
+      };
+      Some(Price {
+        coin: coin.clone(),
+        price,
+        change_24h: parsed[coin][&change_key].as_f64(),
+      })
+    })
+    .collect();
+  Ok(prices)
+}
+
+/// Ticker screen: a few coins per page with their price and an arrow for
+/// the 24 h change. Pages turn by themselves or with a short press.
+pub fn draw(display: &mut Display<'_>, prices: &[Price], pager: &mut Pager) {
+  let small_style = Font::Small.style();
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Crypto",
+    Point::new(1, top),
+    Align::Left,
+    small_style,
+  );
+  if prices.is_empty() {
+    typography::draw_centered(display, "No prices yet", 28, small_style);
+    typography::draw_centered(
+      display,
+      "Pick coins in settings",
+      40,
+      small_style,
+    );
+    return;
+  }
+
+  let pages = prices.len().div_ceil(ROWS_PER_PAGE);
+  let page = pager.page(pages);
+  let style = Font::Medium.style();
+  for (row, price) in prices
+    .iter()
+    .skip(page * ROWS_PER_PAGE)
+    .take(ROWS_PER_PAGE)
+    .enumerate()
+  {
+    let y = top + 12 + 11 * row as i32;
+    let name: String = price.coin.chars().take(7).collect();
+    typography::draw(display, &name, Point::new(1, y), Align::Left, style);
+    typography::draw(
+      display,
+      &format_price(price.price),
+      Point::new(84, y),
+      Align::Right,
+      style,
+    );
+    if let Some(change) = price.change_24h {
+      typography::draw_arrow(display, Point::new(87, y + 1), change >= 0.0);
+      typography::draw(
+        display,
+        &typography::line(format_args!("{:.1}%", change.abs())),
+        Point::new(95, y + 1),
+        Align::Left,
+        small_style,
+      );
+    }
+  }
+  pager::draw_dots(display, page, pages);
+}
+
+/// Fewer decimals the larger the price, so every price fits its column
+fn format_price(price: f64) -> Line {
+  if price >= 1000.0 {
+    typography::line(format_args!("{price:.0}"))
+  } else if price >= 1.0 {
+    typography::line(format_args!("{price:.2}"))
+  } else {
+    typography::line(format_args!("{price:.4}"))
+  }
+}
@@ -0,0 +1,39 @@
+/// Defaults baked into the firmware from `cfg.toml` at build time, see
+/// `cfg.toml.example`. Settings saved on the device take precedence, so these
+/// only apply to a fresh or factory-reset device.
+#[toml_cfg::toml_config]
+pub struct Defaults {
+  #[default("")]
+  wifi_ssid: &'static str,
+  #[default("")]
+  wifi_password: &'static str,
+  /// weatherapi.com key, weather stays off while it is empty
+  #[default("")]
+  weather_api_key: &'static str,
+  #[default(18.555917)]
+  latitude: f64,
+  #[default(73.764256)]
+  longitude: f64,
+  #[default(23)]
+  pin_button: u8,
+  #[default(2)]
+  pin_led: u8,
+  #[default(5)]
+  pin_buzzer: u8,
+  #[default(21)]
+  pin_sda: u8,
+  #[default(22)]
+  pin_scl: u8,
+  #[default(15)]
+  pin_pir: u8,
+  #[default(4)]
+  pin_servo: u8,
+  #[default(26)]
+  pin_relay: u8,
+  #[default(27)]
+  pin_door: u8,
+  /// Ed25519 public key firmware updates must be signed with, as 64 hex
+  /// digits. Updates aren't checked while it is empty.
+  #[default("")]
+  ota_public_key: &'static str,
+}
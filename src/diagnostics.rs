@@ -0,0 +1,255 @@
+#[cfg(feature = "buzzer")]
+use std::sync::Mutex;
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  text::{Baseline, Text},
+};
+#[cfg(feature = "pir")]
+use esp_idf_hal::gpio::Input;
+#[cfg(feature = "servo")]
+use esp_idf_hal::ledc::LedcDriver;
+use esp_idf_hal::{
+  delay::{FreeRtos, TickType},
+  gpio::{Output, Pin, PinDriver},
+  i2c::I2cDriver,
+};
+use esp_idf_svc::wifi::{BlockingWifi, EspWifi};
+use serde::Serialize;
+
+use crate::{
+  display::{Display, Oled},
+  typography::Font,
+};
+
+/// Result of one self-test step
+pub struct Check {
+  pub name: &'static str,
+  pub passed: bool,
+  pub detail: String,
+}
+
+impl Check {
+  fn new(name: &'static str, passed: bool, detail: impl Into<String>) -> Self {
+    let check = Self {
+      name,
+      passed,
+      detail: detail.into(),
+    };
+    if check.passed {
+      log::info!("Self-test {}: PASS {}", check.name, check.detail);
+    } else {
+      log::error!("Self-test {}: FAIL {}", check.name, check.detail);
+    }
+    check
+  }
+}
+
+/// Addresses answering on the bus. Has to run before the display driver takes
+/// ownership of the I2C peripheral.
+pub fn scan_i2c(i2c: &mut I2cDriver<'_>) -> Vec<u8> {
+  let timeout = TickType::new_millis(10).ticks();
+  (0x08..0x78)
+    .filter(|&address| i2c.write(address, &[], timeout).is_ok())
+    .collect()
+}
+
+/// Which of the parts the firmware knows about answered on the I2C bus
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct I2cDevices {
+  pub addresses: Vec<u8>,
+  /// SSD1306 panel, on 0x3C or 0x3D
+  pub display: bool,
+  /// BME280 temperature, humidity and pressure sensor, on 0x76 or 0x77
+  pub bme280: bool,
+  /// DS3231/DS1307 on 0x68 or PCF8563 on 0x51
+  pub rtc: bool,
+}
+
+impl I2cDevices {
+  pub fn detect(addresses: Vec<u8>) -> Self {
+    let found = |candidates: &[u8]| {
+      addresses.iter().any(|address| candidates.contains(address))
+    };
+    Self {
+      display: found(&[0x3C, 0x3D]),
+      bme280: found(&[0x76, 0x77]),
+      rtc: found(&[0x68, 0x51]),
+      addresses,
+    }
+  }
+
+  /// Warn about every missing part, the features using it stay off
+  pub fn log(&self) {
+    log::info!("I2C devices: {}", self.summary());
+    if !self.display {
+      log::warn!("No display found, screens are drawn blind until it answers");
+    }
+    if !self.bme280 {
+      log::warn!("No BME280 found, indoor readings are off");
+    }
+    if !self.rtc {
+      log::warn!("No RTC found, the clock waits for NTP after a power loss");
+    }
+  }
+
+  /// Known parts by name, anything else by address
+  pub fn summary(&self) -> String {
+    let known: [(&[u8], &str); 3] = [
+      (&[0x3C, 0x3D], "OLED"),
+      (&[0x76, 0x77], "BME280"),
+      (&[0x68, 0x51], "RTC"),
+    ];
+    let names: Vec<String> = self
+      .addresses
+      .iter()
+      .map(|address| {
+        known
+          .iter()
+          .find(|(addresses, _)| addresses.contains(address))
+          .map_or_else(
+            || format!("{address:02X}"),
+            |(_, name)| name.to_string(),
+          )
+      })
+      .collect();
+    if names.is_empty() {
+      "none".to_string()
+    } else {
+      names.join(" ")
+    }
+  }
+}
+
+pub fn check_i2c(devices: &I2cDevices) -> Check {
+  if devices.addresses.is_empty() {
+    Check::new("I2C", false, "no devices")
+  } else {
+    Check::new("I2C", devices.display, devices.summary())
+  }
+}
+
+/// Lights every pixel, then a checkerboard, so dead pixels stand out
+pub fn check_display(oled: &mut Oled) -> Check {
+  let all_on = oled.render(|display| {
+    let _ = display.clear(BinaryColor::On);
+  });
+  FreeRtos::delay_ms(700);
+
+  let checkerboard = (0..64).flat_map(|y| {
+    (0..128).map(move |x| {
+      Pixel(
+        Point::new(x, y),
+        BinaryColor::from((x / 8 + y / 8) % 2 == 0),
+      )
+    })
+  });
+  let pattern = oled.render(|display| {
+    let _ = display.draw_iter(checkerboard);
+  });
+  FreeRtos::delay_ms(700);
+
+  if all_on && pattern {
+    Check::new("Display", true, "pattern ok")
+  } else {
+    Check::new("Display", false, "flush failed")
+  }
+}
+
+pub fn check_led<T: Pin>(led: &mut PinDriver<'_, T, Output>) -> Check {
+  for _ in 0..3 {
+    if led.set_high().is_err() || !led.is_set_high() {
+      return Check::new("LED", false, "set high failed");
+    }
+    FreeRtos::delay_ms(150);
+    if led.set_low().is_err() || led.is_set_high() {
+      return Check::new("LED", false, "set low failed");
+    }
+    FreeRtos::delay_ms(150);
+  }
+  Check::new("LED", true, "blinked")
+}
+
+#[cfg(feature = "buzzer")]
+pub fn check_buzzer<T: Pin>(buzzer: &Mutex<PinDriver<'_, T, Output>>) -> Check {
+  let mut buzzer = buzzer.lock().unwrap();
+  if buzzer.set_high().is_err() {
+    return Check::new("Buzzer", false, "set high failed");
+  }
+  FreeRtos::delay_ms(100);
+  if buzzer.set_low().is_err() {
+    return Check::new("Buzzer", false, "set low failed");
+  }
+  Check::new("Buzzer", true, "chirped")
+}
+
+/// Sweeps 0° -> 180° and parks the servo at 90°
+#[cfg(feature = "servo")]
+pub fn check_servo(driver: &mut LedcDriver<'_>) -> Check {
+  use crate::servo;
+
+  for angle in (0..=servo::MAX_ANGLE).step_by(10).chain([90]) {
+    if let Err(error) = servo::set_angle(driver, angle) {
+      return Check::new("Servo", false, format!("{error:?}"));
+    }
+    FreeRtos::delay_ms(60);
+  }
+  Check::new("Servo", true, "swept")
+}
+
+#[cfg(feature = "pir")]
+pub fn check_pir<T: Pin>(pir: &PinDriver<'_, T, Input>) -> Check {
+  let level = if pir.is_high() { "HIGH" } else { "LOW" };
+  Check::new("PIR", true, level)
+}
+
+pub fn check_wifi(wifi: &mut BlockingWifi<EspWifi<'_>>) -> Check {
+  match wifi.scan() {
+    Ok(access_points) if !access_points.is_empty() => {
+      Check::new("WiFi", true, format!("{} APs", access_points.len()))
+    }
+    Ok(_) => Check::new("WiFi", false, "no APs"),
+    Err(error) => Check::new("WiFi", false, format!("{error:?}")),
+  }
+}
+
+/// One line per finished check, plus the check currently running
+pub fn draw_report(oled: &mut Oled, checks: &[Check], running: Option<&str>) {
+  oled.render(|display| draw_checks(display, checks, running));
+}
+
+fn draw_checks(
+  display: &mut Display<'_>,
+  checks: &[Check],
+  running: Option<&str>,
+) {
+  let style = Font::Small.style();
+  let _ = display.clear(BinaryColor::Off);
+
+  let failed = checks.iter().filter(|check| !check.passed).count();
+  let title = match running {
+    Some(name) => format!("Self-test: {name}..."),
+    None if failed == 0 => "Self-test passed".to_string(),
+    None => format!("Self-test: {failed} failed"),
+  };
+  let _ = Text::with_baseline(&title, Point::new(1, 1), style, Baseline::Top)
+    .draw(display);
+
+  for (row, check) in checks.iter().enumerate() {
+    let verdict = if check.passed { "OK  " } else { "FAIL" };
+    // 25 columns of FONT_5X8 fit on the 128 px wide panel
+    let line: String =
+      format!("{:<7} {} {}", check.name, verdict, check.detail)
+        .chars()
+        .take(25)
+        .collect();
+    let _ = Text::with_baseline(
+      &line,
+      Point::new(1, 14 + 7 * row as i32),
+      style,
+      Baseline::Top,
+    )
+    .draw(display);
+  }
+}
@@ -0,0 +1,143 @@
+use std::{
+  net::{Ipv4Addr, UdpSocket},
+  time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// UDP port the units listen on for [`QUERY`]
+pub const PORT: u16 = 47_474;
+/// Broadcast by a unit, or anything else on the LAN, looking for units
+pub const QUERY: &[u8] = b"PIPPO?";
+/// Longest reply read during a scan, a unit's is well under it
+const REPLY_LEN: usize = 512;
+
+/// What a unit answers [`QUERY`] with, as JSON:
+///
+/// ```json
+/// {"name": "pippo-a1b2c3", "version": "0.4.0", "ip": "192.168.1.23",
+///  "capabilities": ["web", "buzzer", "servo"]}
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Announcement {
+  pub name: String,
+  pub version: String,
+  pub ip: String,
+  /// Optional hardware built in and services running, e.g. `servo`, `web`
+  #[serde(default)]
+  pub capabilities: Vec<String>,
+}
+
+/// Whether `datagram` asks for units, a trailing newline allowed for
+/// `echo PIPPO? | nc -ub`
+pub fn is_query(datagram: &[u8]) -> bool {
+  let end = datagram
+    .iter()
+    .rposition(|byte| !matches!(byte, b'\r' | b'\n'))
+    .map_or(0, |last| last + 1);
+  &datagram[..end] == QUERY
+}
+
+/// A unit's reply, `None` for anything else sent to the scanning socket
+pub fn parse_reply(datagram: &[u8]) -> Option<Announcement> {
+  serde_json::from_slice(datagram).ok()
+}
+
+/// Adds `found` to `units`, in place of an earlier reply from the same
+/// address, keeping them ordered by name
+pub fn merge(units: &mut Vec<Announcement>, found: Announcement) {
+  units.retain(|unit| unit.ip != found.ip);
+  let at = units.partition_point(|unit| unit.name <= found.name);
+  units.insert(at, found);
+}
+
+/// Answer [`QUERY`] on [`PORT`] with this unit's [`Announcement`]. The
+/// address comes from `address` on each query, so a new lease is picked
+/// up, and nothing is sent while there is none.
+pub fn spawn(
+  name: String,
+  capabilities: Vec<String>,
+  address: impl Fn() -> Option<Ipv4Addr> + Send + 'static,
+) -> anyhow::Result<()> {
+  let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, PORT))?;
+  std::thread::Builder::new()
+    .name("discovery".to_string())
+    .stack_size(4 * 1024)
+    .spawn(move || {
+      let mut buf = [0_u8; 64];
+      loop {
+        let (len, sender) = match socket.recv_from(&mut buf) {
+          Ok(received) => received,
+          Err(error) => {
+            log::warn!("Discovery receive failed: {:?}", error);
+            std::thread::sleep(Duration::from_secs(1));
+            continue;
+          }
+        };
+        if !is_query(&buf[..len]) {
+          continue;
+        }
+        let Some(ip) = address() else {
+          continue;
+        };
+        let announcement = Announcement {
+          name: name.clone(),
+          version: env!("CARGO_PKG_VERSION").to_string(),
+          ip: ip.to_string(),
+          capabilities: capabilities.clone(),
+        };
+        let reply = match serde_json::to_vec(&announcement) {
+          Ok(reply) => reply,
+          Err(error) => {
+            log::warn!("Discovery reply not encoded: {:?}", error);
+            continue;
+          }
+        };
+        if let Err(error) = socket.send_to(&reply, sender) {
+          log::warn!("Discovery reply to {} failed: {:?}", sender, error);
+        }
+      }
+    })?;
+  log::info!("Answering discovery on UDP port {}", PORT);
+  Ok(())
+}
+
+/// Broadcast [`QUERY`] and collect the replies that arrive within `wait`,
+/// leaving out the one from `own` address
+pub fn scan(
+  own: Option<Ipv4Addr>,
+  wait: Duration,
+) -> anyhow::Result<Vec<Announcement>> {
+  let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+  socket.set_broadcast(true)?;
+  socket.send_to(QUERY, (Ipv4Addr::BROADCAST, PORT))?;
+  let deadline = Instant::now() + wait;
+  let mut units = Vec::new();
+  let mut buf = [0_u8; REPLY_LEN];
+  loop {
+    let left = deadline.saturating_duration_since(Instant::now());
+    if left.is_zero() {
+      break;
+    }
+    socket.set_read_timeout(Some(left))?;
+    let (len, _) = match socket.recv_from(&mut buf) {
+      Ok(received) => received,
+      Err(error)
+        if matches!(
+          error.kind(),
+          std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ) =>
+      {
+        break;
+      }
+      Err(error) => return Err(error.into()),
+    };
+    if let Some(found) = parse_reply(&buf[..len]) {
+      if own.is_some_and(|own| found.ip == own.to_string()) {
+        continue;
+      }
+      merge(&mut units, found);
+    }
+  }
+  Ok(units)
+}
@@ -0,0 +1,332 @@
+use std::time::{Duration, Instant};
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+  text::{Baseline, Text},
+};
+use esp_idf_hal::{
+  delay::Ets,
+  gpio::{AnyIOPin, PinDriver, Pull},
+  i2c::{I2cConfig, I2cDriver, I2C0},
+  peripheral::Peripheral,
+  units::*,
+};
+use ssd1306::{prelude::*, I2CDisplayInterface, Ssd1306};
+
+use crate::{
+  perf::{self, Span},
+  typography::Font,
+};
+
+/// SSD1306 panel in buffered graphics mode on the I2C bus
+pub type Display<'d> = Ssd1306<
+  I2CInterface<I2cDriver<'d>>,
+  DisplaySize128x64,
+  ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
+>;
+
+/// I2C clock the panel starts at, and goes back to when it can't keep up
+/// with a faster one
+pub const DEFAULT_BUS_KHZ: u32 = 100;
+/// Longest wait between two attempts to bring a lost display back
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+/// Failed attempts at a faster clock before going back to the default
+const FALLBACK_AFTER: u32 = 3;
+
+/// Owns the display together with the I2C peripheral and pins it runs on, so
+/// that a glitched bus can be recovered and the panel re-initialized instead
+/// of panicking on a failed flush.
+pub struct Oled {
+  i2c: I2C0,
+  sda: AnyIOPin,
+  scl: AnyIOPin,
+  display: Option<Display<'static>>,
+  // errors since the display was last working
+  failures: u32,
+  retry_at: Instant,
+  // the display has been lost and re-initialized at least once
+  recovered: bool,
+  inverted: bool,
+  rotation: DisplayRotation,
+  brightness: u8,
+  on: bool,
+  /// Drawing and flushing the last frame took this long
+  timings: (Duration, Duration),
+  /// Clock asked for with `set_bus_khz`
+  requested_khz: u32,
+  /// Clock the bus runs at, the default after a fallback
+  bus_khz: u32,
+}
+
+impl Oled {
+  pub fn new(i2c: I2C0, sda: AnyIOPin, scl: AnyIOPin) -> Self {
+    let mut oled = Self {
+      i2c,
+      sda,
+      scl,
+      display: None,
+      failures: 0,
+      retry_at: Instant::now(),
+      recovered: false,
+      inverted: false,
+      rotation: DisplayRotation::Rotate0,
+      brightness: 0x5F,
+      on: true,
+      timings: (Duration::ZERO, Duration::ZERO),
+      requested_khz: DEFAULT_BUS_KHZ,
+      bus_khz: DEFAULT_BUS_KHZ,
+    };
+    oled.reconnect();
+    oled
+  }
+
+  /// Draw a frame with `draw` and flush it. Drawing is skipped while the
+  /// display is lost. Returns whether the frame reached the panel.
+  pub fn render(&mut self, draw: impl FnOnce(&mut Display<'static>)) -> bool {
+    let started = Instant::now();
+    if let Some(display) = self.display.as_mut() {
+      draw(display);
+    }
+    let drawn = Instant::now();
+    let flushed = self.flush();
+    self.timings = (drawn - started, drawn.elapsed());
+    perf::record(Span::Render, self.timings.0);
+    perf::record(Span::Flush, self.timings.1);
+    flushed
+  }
+
+  /// How long drawing and flushing the last rendered frame took
+  pub fn timings(&self) -> (Duration, Duration) {
+    self.timings
+  }
+
+  /// Send the frame buffer to the panel. On a bus error the driver is torn
+  /// down, the bus recovered and the panel re-initialized, retrying with
+  /// backoff. Returns whether the frame reached the panel.
+  pub fn flush(&mut self) -> bool {
+    if let Some(display) = self.display.as_mut() {
+      if self.recovered {
+        draw_degraded_marker(display);
+      }
+      match display.flush() {
+        Ok(()) => return true,
+        Err(error) => {
+          log::warn!("Display flush failed: {:?}, recovering I2C bus", error);
+          // dropping the display uninstalls the I2C driver
+          self.display = None;
+          self.failures += 1;
+          self.recover_bus();
+          self.retry_at = Instant::now();
+        }
+      }
+    }
+
+    if Instant::now() >= self.retry_at {
+      self.reconnect();
+    }
+    false
+  }
+
+  /// Swap lit and unlit pixels. Kept across re-initializations.
+  pub fn set_inverted(&mut self, inverted: bool) {
+    if self.inverted == inverted {
+      return;
+    }
+    self.inverted = inverted;
+    if let Some(display) = self.display.as_mut() {
+      if let Err(error) = display.set_invert(inverted) {
+        log::warn!("Could not set display inversion: {:?}", error);
+      }
+    }
+  }
+
+  /// Turn the picture around for panels mounted the other way up. Screens
+  /// keep drawing in the same coordinates. Kept across re-initializations.
+  pub fn set_rotation(&mut self, rotation: DisplayRotation) {
+    if self.rotation == rotation {
+      return;
+    }
+    self.rotation = rotation;
+    if let Some(display) = self.display.as_mut() {
+      if let Err(error) = display.set_rotation(rotation) {
+        log::warn!("Could not set display rotation: {:?}", error);
+      }
+    }
+  }
+
+  /// Set the contrast register, 0 (dimmest) to 255 (brightest). Kept across
+  /// re-initializations.
+  pub fn set_brightness(&mut self, brightness: u8) {
+    if self.brightness == brightness {
+      return;
+    }
+    self.brightness = brightness;
+    if let Some(display) = self.display.as_mut() {
+      if let Err(error) = display.set_brightness(contrast(brightness)) {
+        log::warn!("Could not set display brightness: {:?}", error);
+      }
+    }
+  }
+
+  /// Blank the panel, or light it up again. Drawing carries on meanwhile.
+  /// Kept across re-initializations.
+  pub fn set_display_on(&mut self, on: bool) {
+    if self.on == on {
+      return;
+    }
+    self.on = on;
+    if let Some(display) = self.display.as_mut() {
+      if let Err(error) = display.set_display_on(on) {
+        log::warn!("Could not switch the display: {:?}", error);
+      }
+    }
+  }
+
+  /// Run the I2C bus at `khz`, re-initializing the panel. A panel that
+  /// fails to come up at a faster clock goes back to [`DEFAULT_BUS_KHZ`]
+  /// until another clock is asked for.
+  pub fn set_bus_khz(&mut self, khz: u32) {
+    if self.requested_khz == khz {
+      return;
+    }
+    self.requested_khz = khz;
+    self.bus_khz = khz;
+    self.display = None;
+    self.failures = 0;
+    self.reconnect();
+  }
+
+  /// Clock asked for last, which the bus runs at unless it fell back
+  pub fn bus_khz(&self) -> u32 {
+    self.requested_khz
+  }
+
+  /// Addresses answering on the I2C bus. The display driver is released for
+  /// the duration of the scan.
+  pub fn scan(&mut self) -> Vec<u8> {
+    self.display = None;
+    let devices = match self.driver() {
+      Ok(mut i2c) => crate::diagnostics::scan_i2c(&mut i2c),
+      Err(error) => {
+        log::error!("Could not start I2C driver for scan: {:?}", error);
+        Vec::new()
+      }
+    };
+    self.reconnect();
+    devices
+  }
+
+  fn driver(&mut self) -> anyhow::Result<I2cDriver<'static>> {
+    let config = I2cConfig::new().baudrate(self.bus_khz.kHz().into());
+    // Safety: at most one driver exists at a time, the previous one is always
+    // dropped before a new one is created
+    let (i2c, sda, scl) = unsafe {
+      (
+        self.i2c.clone_unchecked(),
+        self.sda.clone_unchecked(),
+        self.scl.clone_unchecked(),
+      )
+    };
+    Ok(I2cDriver::new(i2c, sda, scl, &config)?)
+  }
+
+  fn connect(&mut self) -> anyhow::Result<Display<'static>> {
+    let interface = I2CDisplayInterface::new(self.driver()?);
+    let mut display = Ssd1306::new(interface, DisplaySize128x64, self.rotation)
+      .into_buffered_graphics_mode();
+    display
+      .init()
+      .and_then(|()| display.set_invert(self.inverted))
+      .and_then(|()| display.set_brightness(contrast(self.brightness)))
+      .and_then(|()| display.set_display_on(self.on))
+      .map_err(|error| anyhow::anyhow!("display init failed: {:?}", error))?;
+    Ok(display)
+  }
+
+  fn reconnect(&mut self) {
+    match self.connect() {
+      Ok(display) => {
+        if self.failures > 0 {
+          log::info!("Display re-initialized");
+          self.recovered = true;
+        }
+        self.failures = 0;
+        self.display = Some(display);
+      }
+      Err(error) => {
+        self.failures += 1;
+        if self.bus_khz > DEFAULT_BUS_KHZ && self.failures >= FALLBACK_AFTER {
+          log::warn!(
+            "Display not answering at {} kHz, back to {} kHz",
+            self.bus_khz,
+            DEFAULT_BUS_KHZ
+          );
+          self.bus_khz = DEFAULT_BUS_KHZ;
+        }
+        let delay = Duration::from_secs(1_u64 << self.failures.min(5))
+          .min(MAX_RETRY_DELAY);
+        log::warn!("{:?}, retrying in {}s", error, delay.as_secs());
+        self.retry_at = Instant::now() + delay;
+        self.recover_bus();
+      }
+    }
+  }
+
+  /// Clock out a slave stuck in the middle of a byte and end with a STOP
+  /// condition, so the bus is idle for the next transfer
+  fn recover_bus(&mut self) {
+    let (sda, scl) =
+      unsafe { (self.sda.clone_unchecked(), self.scl.clone_unchecked()) };
+    let (Ok(mut sda), Ok(mut scl)) = (
+      PinDriver::input_output_od(sda),
+      PinDriver::input_output_od(scl),
+    ) else {
+      log::error!("Could not take I2C pins for bus recovery");
+      return;
+    };
+    let _ = sda.set_pull(Pull::Up);
+    let _ = scl.set_pull(Pull::Up);
+    let _ = sda.set_high();
+    let _ = scl.set_high();
+    Ets::delay_us(5);
+
+    // a slave holding SDA low lets go after at most 9 clock pulses
+    for _ in 0..9 {
+      if sda.is_high() {
+        break;
+      }
+      let _ = scl.set_low();
+      Ets::delay_us(5);
+      let _ = scl.set_high();
+      Ets::delay_us(5);
+    }
+
+    // STOP: SDA rises while SCL is high
+    let _ = scl.set_low();
+    Ets::delay_us(5);
+    let _ = sda.set_low();
+    Ets::delay_us(5);
+    let _ = scl.set_high();
+    Ets::delay_us(5);
+    let _ = sda.set_high();
+    Ets::delay_us(5);
+  }
+}
+
+fn contrast(brightness: u8) -> Brightness {
+  // the pre-charge period used by the driver's own levels
+  Brightness::custom(2, brightness)
+}
+
+/// Inverted "!" in the bottom-right corner, shown for the rest of the session
+/// once the display had to be recovered
+fn draw_degraded_marker(display: &mut Display<'_>) {
+  let style = Font::Small.inverted();
+  let _ = Rectangle::new(Point::new(121, 55), Size::new(7, 9))
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    .draw(display);
+  let _ = Text::with_baseline("!", Point::new(122, 56), style, Baseline::Top)
+    .draw(display);
+}
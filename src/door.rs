@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+
+use esp_idf_hal::{
+  delay::FreeRtos,
+  gpio::{AnyIOPin, Input, PinDriver},
+};
+
+use crate::{
+  automation::Outputs, buzzer::Pattern, config::SharedConfig, events::Event,
+  notify::Priority,
+};
+
+/// How often the reed switch is read
+const POLL_MS: u32 = 50;
+/// A reading has to hold this long to count, the contacts bounce
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watch the door's reed switch in a background thread. The switch closes
+/// while the magnet on the door is near, pulling the pin low, so an open door
+/// reads high. Opening chimes, and a door left open past `alarm_after_s`
+/// sounds the alarm every `alarm_every_s` until it is closed. Opening,
+/// closing and being left open go on the event bus, for rules that call a
+/// webhook.
+pub fn spawn(
+  pin: PinDriver<'static, AnyIOPin, Input>,
+  config: SharedConfig,
+  outputs: Outputs,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("door".to_string())
+    .stack_size(4 * 1024)
+    .spawn(move || {
+      let mut open = pin.is_high();
+      let mut changed_at = Instant::now();
+      let mut opened_at = Instant::now();
+      let mut alarm_at: Option<Instant> = None;
+      outputs.state.lock().unwrap().door_open = Some(open);
+      loop {
+        FreeRtos::delay_ms(POLL_MS);
+        let now = Instant::now();
+        let options = config.lock().unwrap().door.clone();
+        if pin.is_high() == open {
+          changed_at = now;
+        } else if now.duration_since(changed_at) >= DEBOUNCE {
+          open = !open;
+          changed_at = now;
+          outputs.state.lock().unwrap().door_open = Some(open);
+          if open {
+            log::info!("Door opened");
+            opened_at = now;
+            outputs.events.publish(Event::DoorOpened);
+            if options.chime {
+              sound(&outputs, Pattern::Chime);
+            }
+          } else {
+            log::info!("Door closed");
+            outputs.events.publish(Event::DoorClosed);
+            if alarm_at.take().is_some() {
+              outputs.notifications.lock().unwrap().push(
+                "Door closed",
+                config.lock().unwrap().notifications.duration(),
+                Priority::Low,
+              );
+            }
+          }
+        }
+
+        let alarm_after = Duration::from_secs(u64::from(options.alarm_after_s));
+        if !open
+          || options.alarm_after_s == 0
+          || now.duration_since(opened_at) < alarm_after
+        {
+          continue;
+        }
+        let due = match alarm_at {
+          None => {
+            log::warn!("Door left open");
+            outputs.events.publish(Event::DoorLeftOpen);
+            outputs.notifications.lock().unwrap().push(
+              "Door left open",
+              config.lock().unwrap().notifications.duration(),
+              Priority::High,
+            );
+            true
+          }
+          Some(at) => {
+            now.duration_since(at)
+              >= Duration::from_secs(u64::from(options.alarm_every_s))
+          }
+        };
+        if due {
+          alarm_at = Some(now);
+          sound(&outputs, Pattern::Alert);
+        }
+      }
+    })?;
+  Ok(())
+}
+
+fn sound(outputs: &Outputs, pattern: Pattern) {
+  #[cfg(feature = "buzzer")]
+  if let Err(error) = outputs.buzzer.play(pattern) {
+    log::warn!("Door buzzer failed: {:?}", error);
+  }
+  #[cfg(not(feature = "buzzer"))]
+  {
+    let _ = outputs;
+    log::info!("No buzzer for pattern {:?}", pattern);
+  }
+}
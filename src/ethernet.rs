@@ -0,0 +1,91 @@
+use std::{
+  net::Ipv4Addr,
+  sync::{Arc, Mutex},
+};
+
+use esp_idf_hal::{
+  gpio::AnyIOPin,
+  peripheral::Peripheral,
+  spi::{Dma, SpiDriver, SpiDriverConfig, SPI2},
+  units::FromValueType,
+};
+use esp_idf_svc::{
+  eth::{BlockingEth, EspEth, EthDriver, SpiEth, SpiEthChipset},
+  eventloop::EspSystemEventLoop,
+  netif::{EspNetif, NetifConfiguration},
+};
+
+use crate::{
+  config::{Ethernet, PinConfig},
+  hostname, network,
+};
+
+pub type SharedEth =
+  Arc<Mutex<BlockingEth<EspEth<'static, SpiEth<SpiDriver<'static>>>>>>;
+
+/// Above the WiFi station's, so traffic goes out over the cable while both
+/// are up
+const ROUTE_PRIORITY: u32 = 150;
+
+/// Start the W5500 on `spi`, `None` while `ethernet.enabled` is off. Its
+/// address comes over DHCP once a cable is in, which
+/// [`Uplink`](crate::network::Uplink) looks for.
+pub fn start(
+  spi: impl Peripheral<P = SPI2> + 'static,
+  options: &Ethernet,
+  pins: &PinConfig,
+  system_event_loop: EspSystemEventLoop,
+) -> anyhow::Result<Option<SharedEth>> {
+  if !options.enabled {
+    return Ok(None);
+  }
+  pins.validate_with(&options.roles())?;
+  let bus = SpiDriver::new(
+    spi,
+    gpio(options.sclk),
+    gpio(options.mosi),
+    Some(gpio(options.miso)),
+    &SpiDriverConfig::new().dma(Dma::Auto(4096)),
+  )?;
+  let driver = EthDriver::new_spi(
+    bus,
+    gpio(options.int),
+    Some(gpio(options.cs)),
+    options.rst.map(gpio),
+    SpiEthChipset::W5500,
+    options.spi_mhz.MHz().into(),
+    // the chip has none of its own, the driver uses the one set aside for
+    // Ethernet in eFuse
+    None,
+    None,
+    system_event_loop.clone(),
+  )?;
+  let netif = EspNetif::new_with_conf(&NetifConfiguration {
+    route_priority: ROUTE_PRIORITY,
+    ip_configuration: Some(network::dhcp_client(hostname::get())?),
+    ..NetifConfiguration::eth_default_client()
+  })?;
+  let mut eth =
+    BlockingEth::wrap(EspEth::wrap_all(driver, netif)?, system_event_loop)?;
+  eth.start()?;
+  log::info!("Ethernet started, waiting for a cable");
+  Ok(Some(Arc::new(Mutex::new(eth))))
+}
+
+/// Whether the cable is in and has an address
+pub fn is_up(eth: &SharedEth) -> bool {
+  eth.lock().unwrap().is_up().unwrap_or(false)
+}
+
+/// The wired address, `None` without a cable or a lease
+pub fn ip(eth: &SharedEth) -> Option<Ipv4Addr> {
+  let eth = eth.lock().unwrap();
+  let ip = eth.eth().netif().get_ip_info().ok()?.ip;
+  (!ip.is_unspecified()).then_some(ip)
+}
+
+fn gpio(number: u8) -> AnyIOPin {
+  // Safety: the pins are checked against the others in `start`, so each has
+  // a single owner
+  unsafe { AnyIOPin::new(i32::from(number)) }
+}
@@ -0,0 +1,88 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+
+use serde::{Deserialize, Serialize};
+
+/// Events waiting for the automation rules. Any more are dropped, so a slow
+/// webhook can't make them pile up.
+const QUEUE_LEN: usize = 16;
+
+/// Something that happened on the device, for the automation rules to act
+/// on
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+  /// The PIR saw motion after a quiet spell
+  Motion,
+  /// The threshold with this name was crossed and held long enough
+  Threshold(String),
+  /// The reading went back past the threshold with this name, by its
+  /// hysteresis
+  Cleared(String),
+  /// A phone in the presence settings showed up after everyone was away
+  Arrived,
+  /// None of the phones have been seen for a while
+  Left,
+  /// The scene with this name was set
+  Scene(String),
+  /// The clock reached this `HH:MM`, every minute
+  At(String),
+  /// The door's reed switch opened
+  DoorOpened,
+  DoorClosed,
+  /// The door has been open longer than the door settings allow
+  DoorLeftOpen,
+  /// Sent in from outside with `POST /api/v1/trigger/<name>`, e.g. by a
+  /// phone arriving home
+  Trigger(String),
+}
+
+impl Event {
+  /// Whether the other units in a group hear about it. Each unit keeps its
+  /// own time.
+  pub fn shared(&self) -> bool {
+    !matches!(self, Self::At(_))
+  }
+}
+
+/// Where an event happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Origin {
+  /// On this device
+  Local,
+  /// On the unit in the group with this id
+  Group(String),
+}
+
+/// Sending end of the event bus, cloned into everything that raises events
+#[derive(Clone)]
+pub struct Bus(SyncSender<(Event, Origin)>);
+
+impl Bus {
+  /// Raise an event that happened on this device
+  pub fn publish(&self, event: Event) {
+    self.send(event, Origin::Local);
+  }
+
+  /// Pass on an event from another unit in the group. It isn't shared with
+  /// the group again.
+  pub fn relay(&self, event: Event, device: String) {
+    self.send(event, Origin::Group(device));
+  }
+
+  fn send(&self, event: Event, origin: Origin) {
+    match self.0.try_send((event, origin)) {
+      Ok(()) => {}
+      Err(TrySendError::Full((event, _))) => {
+        log::warn!("Event {:?} dropped, automation is behind", event)
+      }
+      // nothing is listening, e.g. automation failed to start
+      Err(TrySendError::Disconnected(_)) => {}
+    }
+  }
+}
+
+/// The bus and the receiving end for the automation rules
+pub fn bus() -> (Bus, Receiver<(Event, Origin)>) {
+  let (sender, receiver) = mpsc::sync_channel(QUEUE_LEN);
+  (Bus(sender), receiver)
+}
@@ -0,0 +1,343 @@
+use std::time::{Duration, Instant};
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+
+use crate::{
+  config::Config,
+  display::Display,
+  fetch,
+  http_client::{self, RequestOptions, Validators},
+  marquee,
+  state::SharedState,
+  statusbar,
+  typography::{self, Align, Font},
+};
+
+/// Headlines kept from the top of the feed, the rest is not even downloaded
+pub const MAX_HEADLINES: usize = 10;
+/// Feeds are read as they arrive and only up to the headlines kept, but
+/// one that goes on longer than this before they are all in is given up
+const MAX_FEED_BYTES: usize = 512 * 1024;
+/// Longer titles are cut off
+const MAX_TITLE_BYTES: usize = 256;
+/// Tag names are only ever compared against a few short ones
+const MAX_TAG_BYTES: usize = 32;
+/// Headlines on the screen at once
+const ROWS: usize = 4;
+
+/// Keeps the headlines of the feed in the settings fresh in `state`. Titles
+/// that weren't there on the previous fetch count as unread until the
+/// Headlines screen is opened. A feed the server says is unchanged isn't
+/// downloaded again.
+pub struct Source {
+  state: SharedState,
+  fetched: Option<(Instant, String)>,
+  validators: Validators,
+}
+
+impl Source {
+  pub fn new(state: SharedState) -> Self {
+    Self {
+      state,
+      fetched: None,
+      validators: Validators::default(),
+    }
+  }
+}
+
+impl fetch::Source for Source {
+  fn poll(&mut self, config: &Config) {
+    let interval = Duration::from_secs(u64::from(config.refresh.feed_min) * 60);
+    let url = &config.feed.url;
+    let (due, first) = match &self.fetched {
+      None => (true, true),
+      Some((at, used)) => {
+        (at.elapsed() >= interval || used != url, used != url)
+      }
+    };
+    if !due {
+      return;
+    }
+    self.fetched = Some((Instant::now(), url.clone()));
+    if url.is_empty() {
+      let mut state = self.state.lock().unwrap();
+      state.headlines.clear();
+      state.unread_headlines = 0;
+      return;
+    }
+    match fetch(url, &mut self.validators) {
+      Ok(None) => log::info!("Headlines unchanged"),
+      Ok(Some(headlines)) => {
+        let mut state = self.state.lock().unwrap();
+        // a new feed starts out read, only later arrivals are news
+        if !first {
+          let new = headlines
+            .iter()
+            .filter(|title| !state.headlines.contains(title))
+            .count();
+          state.unread_headlines =
+            (state.unread_headlines + new).min(MAX_HEADLINES);
+        }
+        state.headlines = headlines;
+      }
+      Err(error) => log::warn!("Feed update failed: {:?}", error),
+    }
+  }
+}
+
+/// Titles of the newest items of the RSS or Atom feed at `url`, `None` if
+/// it hasn't changed since `validators` were taken
+pub fn fetch(
+  url: &str,
+  validators: &mut Validators,
+) -> anyhow::Result<Option<Vec<String>>> {
+  log::info!("Fetching headlines from {}", url);
+  let mut parser = Parser::default();
+  let modified = http_client::get_streamed(
+    url,
+    "application/rss+xml, application/atom+xml, text/xml",
+    validators,
+    &RequestOptions {
+      max_body_bytes: MAX_FEED_BYTES,
+      ..Default::default()
+    },
+    |chunk| {
+      parser.feed(chunk);
+      parser.titles.len() < MAX_HEADLINES
+    },
+  )?;
+  Ok(modified.then_some(parser.titles))
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Markup {
+  /// Outside any markup
+  #[default]
+  Text,
+  /// Between `<` and `>`
+  Tag,
+  CData,
+  Comment,
+}
+
+/// Picks the item titles out of an RSS or Atom document fed to it in
+/// pieces of any size. It only knows as much XML as that takes: tags,
+/// CDATA sections, comments and the common entities.
+#[derive(Default)]
+struct Parser {
+  markup: Markup,
+  /// Name of the tag being read, or the last bytes of a CDATA section or
+  /// comment to spot its end
+  tag: Vec<u8>,
+  /// Text since the last tag, entities not yet decoded
+  text: Vec<u8>,
+  in_item: bool,
+  in_title: bool,
+  title: String,
+  titles: Vec<String>,
+}
+
+impl Parser {
+  fn feed(&mut self, bytes: &[u8]) {
+    for &byte in bytes {
+      match self.markup {
+        Markup::Text if byte == b'<' => {
+          if self.in_title {
+            let text = String::from_utf8_lossy(&self.text);
+            self.title.push_str(&decode_entities(&text));
+          }
+          self.text.clear();
+          self.tag.clear();
+          self.markup = Markup::Tag;
+        }
+        Markup::Text => {
+          if self.in_title && self.text.len() < MAX_TITLE_BYTES {
+            self.text.push(byte);
+          }
+        }
+        Markup::Tag if byte == b'>' => {
+          self.handle_tag();
+          self.markup = Markup::Text;
+        }
+        Markup::Tag => {
+          if self.tag.len() < MAX_TAG_BYTES {
+            self.tag.push(byte);
+          }
+          if self.tag == b"![CDATA[" {
+            self.tag.clear();
+            self.markup = Markup::CData;
+          } else if self.tag == b"!--" {
+            self.tag.clear();
+            self.markup = Markup::Comment;
+          }
+        }
+        Markup::CData | Markup::Comment => {
+          if self.markup == Markup::CData
+            && self.in_title
+            && self.text.len() < MAX_TITLE_BYTES
+          {
+            self.text.push(byte);
+          }
+          if self.tag.len() == 3 {
+            self.tag.remove(0);
+          }
+          self.tag.push(byte);
+          let end: &[u8] = if self.markup == Markup::CData {
+            b"]]>"
+          } else {
+            b"-->"
+          };
+          if self.tag == end {
+            if self.markup == Markup::CData && self.in_title {
+              // CDATA is taken as it is, without decoding entities
+              if self.text.ends_with(b"]]>") {
+                self.text.truncate(self.text.len() - 3);
+              }
+              self.title.push_str(&String::from_utf8_lossy(&self.text));
+              self.text.clear();
+            }
+            self.markup = Markup::Text;
+          }
+        }
+      }
+    }
+  }
+
+  /// Handles the tag just read, such as `item`, `/title` or `link href=..`
+  fn handle_tag(&mut self) {
+    let tag = String::from_utf8_lossy(&self.tag);
+    let (closing, tag) = match tag.strip_prefix('/') {
+      Some(tag) => (true, tag),
+      None => (false, tag.as_ref()),
+    };
+    let name = tag
+      .split(|c: char| c.is_whitespace() || c == '/')
+      .next()
+      .unwrap_or("");
+    // namespaced names such as atom:title count as their local name
+    let name = name.rsplit(':').next().unwrap_or(name);
+    let self_closing = tag.ends_with('/');
+    match (name, closing) {
+      ("item" | "entry", false) => self.in_item = !self_closing,
+      ("item" | "entry", true) => self.in_item = false,
+      ("title", false) if self.in_item && !self_closing => {
+        self.in_title = true;
+        self.title.clear();
+      }
+      ("title", true) if self.in_title => {
+        self.in_title = false;
+        let title = self.title.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !title.is_empty() && self.titles.len() < MAX_HEADLINES {
+          self.titles.push(title);
+        }
+      }
+      _ => {}
+    }
+  }
+}
+
+/// Replaces the predefined XML entities and numeric character references
+fn decode_entities(text: &str) -> String {
+  let mut decoded = String::with_capacity(text.len());
+  let mut rest = text;
+  while let Some(start) = rest.find('&') {
+    decoded.push_str(&rest[..start]);
+    rest = &rest[start..];
+    let Some(end) = rest.find(';').filter(|&end| end <= 10) else {
+      decoded.push('&');
+      rest = &rest[1..];
+      continue;
+    };
+    let entity = &rest[1..end];
+    let character = match entity {
+      "amp" => Some('&'),
+      "lt" => Some('<'),
+      "gt" => Some('>'),
+      "quot" => Some('"'),
+      "apos" => Some('\''),
+      _ => entity
+        .strip_prefix("#x")
+        .map(|hex| u32::from_str_radix(hex, 16))
+        .or_else(|| entity.strip_prefix('#').map(str::parse))
+        .and_then(Result::ok)
+        .and_then(char::from_u32),
+    };
+    match character {
+      Some(character) => {
+        decoded.push(character);
+        rest = &rest[end + 1..];
+      }
+      None => {
+        decoded.push('&');
+        rest = &rest[1..];
+      }
+    }
+  }
+  decoded.push_str(rest);
+  decoded
+}
+
+/// Headlines screen: the titles around the selected one, which scrolls when
+/// it is too long to fit. `elapsed` is the time since it was selected.
+pub fn draw(
+  display: &mut Display<'_>,
+  headlines: &[String],
+  selected: usize,
+  elapsed: Duration,
+) {
+  let small_style = Font::Small.style();
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Headlines",
+    Point::new(1, top),
+    Align::Left,
+    small_style,
+  );
+  if headlines.is_empty() {
+    typography::draw_centered(display, "No headlines yet", 28, small_style);
+    typography::draw_centered(
+      display,
+      "Set a feed in settings",
+      40,
+      small_style,
+    );
+    return;
+  }
+  typography::draw(
+    display,
+    &typography::line(format_args!("{}/{}", selected + 1, headlines.len())),
+    Point::new(typography::PANEL_WIDTH - 1, top),
+    Align::Right,
+    small_style,
+  );
+
+  // keep the selected headline on screen
+  let first = (selected + 1).saturating_sub(ROWS);
+  for (row, (index, title)) in headlines
+    .iter()
+    .enumerate()
+    .skip(first)
+    .take(ROWS)
+    .enumerate()
+  {
+    let y = top + 11 + 10 * row as i32;
+    let is_selected = index == selected;
+    if is_selected {
+      typography::draw(
+        display,
+        ">",
+        Point::new(1, y),
+        Align::Left,
+        small_style,
+      );
+    }
+    marquee::draw(
+      display,
+      title,
+      Rectangle::new(Point::new(8, y), Size::new(120, 8)),
+      small_style,
+      if is_selected { elapsed } else { Duration::ZERO },
+    );
+  }
+}
@@ -0,0 +1,40 @@
+use std::sync::atomic::Ordering;
+
+use esp_idf_hal::delay::FreeRtos;
+
+use crate::{
+  config::{Config, SharedConfig},
+  network::Online,
+};
+
+/// Data kept fresh from the internet, such as the weather. Every source
+/// decides for itself when it is due and what to do when a fetch fails.
+pub trait Source: Send {
+  /// Called about once a second with the current settings
+  fn poll(&mut self, config: &Config);
+}
+
+/// Poll every source in one background thread, so the sources share a
+/// stack deep enough for a TLS handshake and fetch one at a time. Nothing
+/// is polled until the device is `online`, so the first fetches don't fail.
+pub fn spawn(
+  mut sources: Vec<Box<dyn Source>>,
+  config: SharedConfig,
+  online: Online,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("fetch".to_string())
+    // TLS handshakes need a deep stack
+    .stack_size(12 * 1024)
+    .spawn(move || loop {
+      FreeRtos::delay_ms(1000);
+      if !online.load(Ordering::Relaxed) {
+        continue;
+      }
+      let config = config.lock().unwrap().clone();
+      for source in &mut sources {
+        source.poll(&config);
+      }
+    })?;
+  Ok(())
+}
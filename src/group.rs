@@ -0,0 +1,217 @@
+use std::{
+  collections::VecDeque,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    Arc, Mutex,
+  },
+};
+
+use chrono::Utc;
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_svc::mqtt::client::{
+  EspMqttClient, EspMqttConnection, EventPayload, MqttClientConfiguration, QoS,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  config::GroupOptions,
+  events::{Bus, Event},
+  hostname,
+};
+
+/// Messages already seen, by sender and number, so a message delivered twice
+/// is acted on once
+const SEEN_LEN: usize = 32;
+/// Before this, the sender's clock wasn't set and the age of a message is
+/// unknown
+const CLOCK_SET_AFTER: i64 = 1_704_067_200; // 2024-01-01
+
+/// An event shared with the other units in the group, as JSON on the group
+/// topic:
+///
+/// ```json
+/// {"device": "pippo-a1b2c3", "id": 17, "event": "motion",
+///  "sent": 1760600000, "ttl": 30}
+/// ```
+#[derive(Debug, Serialize, Deserialize)]
+struct Message {
+  /// Unit the event happened on
+  device: String,
+  /// Counts up on each unit, from a random start at boot so a restarted
+  /// unit isn't mistaken for repeating itself
+  id: u32,
+  event: Event,
+  /// Unix time it was sent
+  sent: i64,
+  /// Seconds after `sent` the event is no longer worth acting on, e.g.
+  /// when the broker delivers it after a reconnect
+  ttl: u32,
+}
+
+impl Message {
+  fn expired(&self, now: i64) -> bool {
+    self.sent > CLOCK_SET_AFTER
+      && now > CLOCK_SET_AFTER
+      && now - self.sent > i64::from(self.ttl)
+  }
+}
+
+/// This unit's place in the group, for sharing the events that happen on
+/// it
+#[derive(Clone)]
+pub struct Group {
+  client: Arc<Mutex<EspMqttClient<'static>>>,
+  topic: String,
+  device: String,
+  ttl: u32,
+  next_id: Arc<AtomicU32>,
+}
+
+impl Group {
+  /// Tell the other units about an event that happened on this one
+  pub fn share(&self, event: &Event) {
+    let message = Message {
+      device: self.device.clone(),
+      id: self.next_id.fetch_add(1, Ordering::Relaxed),
+      event: event.clone(),
+      sent: Utc::now().timestamp(),
+      ttl: self.ttl,
+    };
+    let Ok(json) = serde_json::to_vec(&message) else {
+      return;
+    };
+    if let Err(error) = self.client.lock().unwrap().enqueue(
+      &self.topic,
+      QoS::AtLeastOnce,
+      false,
+      &json,
+    ) {
+      log::warn!("Could not share {:?} with the group: {:?}", event, error);
+    }
+  }
+}
+
+/// Join the group in the settings, or `None` while no broker is set. Events
+/// from the other units go on `events` in the background. The broker keeps
+/// the subscription across reconnects, so events sent meanwhile arrive late
+/// and the TTL drops the stale ones.
+pub fn start(
+  options: &GroupOptions,
+  events: Bus,
+) -> anyhow::Result<Option<Group>> {
+  if options.broker.is_empty() {
+    return Ok(None);
+  }
+  let device = hostname::get().to_string();
+  let topic = format!("pippo/group/{}", options.name);
+  let (client, connection) = EspMqttClient::new(
+    &options.broker,
+    &MqttClientConfiguration {
+      client_id: Some(&device),
+      disable_clean_session: true,
+      crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+      ..Default::default()
+    },
+  )?;
+  let client = Arc::new(Mutex::new(client));
+  let subscribed = Arc::new(AtomicBool::new(false));
+
+  let own_device = device.clone();
+  let own_topic = topic.clone();
+  let session_lost = subscribed.clone();
+  std::thread::Builder::new()
+    .name("group".to_string())
+    .stack_size(6 * 1024)
+    .spawn(move || {
+      receive(connection, &own_topic, &own_device, &session_lost, &events)
+    })?;
+
+  // subscribing while the connection thread is busy with an event can block
+  // it, so it happens here instead
+  let subscriber = client.clone();
+  let group_topic = topic.clone();
+  std::thread::Builder::new()
+    .name("group-sub".to_string())
+    .stack_size(3 * 1024)
+    .spawn(move || loop {
+      if !subscribed.load(Ordering::Relaxed) {
+        let result = subscriber
+          .lock()
+          .unwrap()
+          .subscribe(&group_topic, QoS::AtLeastOnce);
+        match result {
+          Ok(_) => {
+            log::info!("Joined group {:?}", group_topic);
+            subscribed.store(true, Ordering::Relaxed);
+          }
+          Err(error) => log::debug!("Group subscribe failed: {:?}", error),
+        }
+      }
+      FreeRtos::delay_ms(5000);
+    })?;
+
+  log::info!("Group member {} on {}", device, options.broker);
+  Ok(Some(Group {
+    client,
+    topic,
+    device,
+    ttl: options.ttl_s,
+    next_id: Arc::new(AtomicU32::new(rand::random())),
+  }))
+}
+
+/// Pass the events on the group topic to the local bus, leaving out this
+/// unit's own, ones already seen and stale ones
+fn receive(
+  mut connection: EspMqttConnection,
+  topic: &str,
+  device: &str,
+  subscribed: &AtomicBool,
+  events: &Bus,
+) {
+  let mut seen: VecDeque<(String, u32)> = VecDeque::with_capacity(SEEN_LEN);
+  while let Ok(event) = connection.next() {
+    let data = match event.payload() {
+      EventPayload::Connected(session_present) => {
+        if !session_present {
+          subscribed.store(false, Ordering::Relaxed);
+        }
+        continue;
+      }
+      EventPayload::Disconnected => {
+        log::warn!("Group broker disconnected");
+        continue;
+      }
+      EventPayload::Received {
+        topic: Some(received),
+        data,
+        ..
+      } if received == topic => data,
+      _ => continue,
+    };
+    let message: Message = match serde_json::from_slice(data) {
+      Ok(message) => message,
+      Err(error) => {
+        log::warn!("Bad group message: {:?}", error);
+        continue;
+      }
+    };
+    if message.device == device {
+      continue;
+    }
+    let key = (message.device.clone(), message.id);
+    if seen.contains(&key) {
+      continue;
+    }
+    if seen.len() >= SEEN_LEN {
+      seen.pop_front();
+    }
+    seen.push_back(key);
+    if message.expired(Utc::now().timestamp()) {
+      log::info!("Stale {:?} from {} dropped", message.event, message.device);
+      continue;
+    }
+    events.relay(message.event, message.device);
+  }
+  log::warn!("Group connection closed");
+}
@@ -0,0 +1,322 @@
+use std::{
+  collections::VecDeque,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{Line, PrimitiveStyle, Rectangle},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  clock::Instant,
+  display::Display,
+  psram,
+  state::Weather,
+  statusbar,
+  typography::{self, Align, Font},
+};
+
+pub type SharedHistory = Arc<Mutex<History>>;
+
+/// How far back the history goes
+pub const WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+/// One column per pixel of the panel
+pub const POINTS: usize = 128;
+/// Readings in `WINDOW` at the shortest weather refresh, 5 minutes
+const MAX_SAMPLES: usize = 24 * 60 / 5 + 1;
+
+/// Top and height of the plot area in pixels, below the status bar and the
+/// header
+const PLOT_TOP: i32 = statusbar::HEIGHT + 10;
+const PLOT_HEIGHT: i32 = 34;
+/// Height of a full hour on the activity screen, leaving room for the hour
+/// marker and labels
+const BAR_HEIGHT: i32 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+  Temperature,
+  Humidity,
+}
+
+impl Metric {
+  /// The other metric, for flipping between graphs
+  pub fn next(self) -> Self {
+    match self {
+      Metric::Temperature => Metric::Humidity,
+      Metric::Humidity => Metric::Temperature,
+    }
+  }
+
+  fn label(self) -> &'static str {
+    match self {
+      Metric::Temperature => "Temp",
+      Metric::Humidity => "Humidity",
+    }
+  }
+
+  fn format(self, value: f32) -> typography::Line {
+    match self {
+      Metric::Temperature => typography::line(format_args!("{value:.1}°C")),
+      Metric::Humidity => typography::line(format_args!("{value:.0}%")),
+    }
+  }
+
+  fn value(self, sample: &Sample) -> f32 {
+    match self {
+      Metric::Temperature => sample.temp_c,
+      Metric::Humidity => sample.humidity,
+    }
+  }
+}
+
+struct Sample {
+  at: Instant,
+  temp_c: f32,
+  humidity: f32,
+}
+
+/// Minutes with motion in one hour of the day
+#[derive(Clone, Copy, Default)]
+struct OccupiedHour {
+  /// Days since the common era of the hour counted, 0 for never
+  day: i32,
+  minutes: u8,
+}
+
+/// Readings from the last 24 hours, oldest first, and when the PIR saw
+/// motion in them
+pub struct History {
+  samples: VecDeque<Sample>,
+  /// By local hour of the day
+  occupancy: [OccupiedHour; 24],
+  /// Minute last counted as occupied
+  occupied_minute: Option<NaiveDateTime>,
+}
+
+impl Default for History {
+  fn default() -> Self {
+    Self {
+      samples: psram::vec_deque(MAX_SAMPLES),
+      occupancy: Default::default(),
+      occupied_minute: None,
+    }
+  }
+}
+
+impl History {
+  pub fn record(&mut self, weather: &Weather) {
+    let now = Instant::now();
+    self.samples.push_back(Sample {
+      at: now,
+      temp_c: weather.temp_c as f32,
+      humidity: weather.humidity as f32,
+    });
+    while self
+      .samples
+      .front()
+      .is_some_and(|sample| now.duration_since(sample.at) > WINDOW)
+    {
+      self.samples.pop_front();
+    }
+  }
+
+  /// `metric` over the last 24 hours in `POINTS` columns, oldest first.
+  /// Samples falling in the same column are averaged, columns without any
+  /// sample are `None`.
+  pub fn series(&self, metric: Metric, now: Instant) -> Vec<Option<f32>> {
+    let column_span = WINDOW.as_secs_f32() / POINTS as f32;
+    let mut sums = vec![(0.0_f32, 0_u32); POINTS];
+    for sample in &self.samples {
+      let age = now.duration_since(sample.at).as_secs_f32();
+      let from_end = (age / column_span) as usize;
+      if from_end < POINTS {
+        let (sum, count) = &mut sums[POINTS - 1 - from_end];
+        *sum += metric.value(sample);
+        *count += 1;
+      }
+    }
+    sums
+      .into_iter()
+      .map(|(sum, count)| (count > 0).then(|| sum / count as f32))
+      .collect()
+  }
+
+  pub fn latest(&self, metric: Metric) -> Option<f32> {
+    self.samples.back().map(|sample| metric.value(sample))
+  }
+
+  /// Count the local minute `at` as occupied, once however often the PIR
+  /// fires in it
+  pub fn record_motion(&mut self, at: NaiveDateTime) {
+    let minute = at
+      .with_second(0)
+      .and_then(|at| at.with_nanosecond(0))
+      .unwrap_or(at);
+    if self.occupied_minute == Some(minute) {
+      return;
+    }
+    self.occupied_minute = Some(minute);
+    let day = at.date().num_days_from_ce();
+    let hour = &mut self.occupancy[at.hour() as usize];
+    if hour.day != day {
+      *hour = OccupiedHour { day, minutes: 0 };
+    }
+    hour.minutes = hour.minutes.saturating_add(1).min(60);
+  }
+
+  /// Minutes with motion in each hour of the day over the last 24 hours,
+  /// midnight first. The current hour is the one in progress, the hours
+  /// after it are from yesterday.
+  pub fn occupancy(&self, now: NaiveDateTime) -> [u8; 24] {
+    let today = now.date().num_days_from_ce();
+    let mut minutes = [0; 24];
+    for (hour, occupied) in self.occupancy.iter().enumerate() {
+      let day = if hour as u32 <= now.hour() {
+        today
+      } else {
+        today - 1
+      };
+      if occupied.day == day {
+        minutes[hour] = occupied.minutes;
+      }
+    }
+    minutes
+  }
+}
+
+/// Sparkline of `series` across the panel with its minimum and maximum on
+/// the left and `current` in the top-right corner of the screen
+pub fn draw_graph(
+  display: &mut Display<'_>,
+  metric: Metric,
+  series: &[Option<f32>],
+  current: Option<f32>,
+) {
+  let style = Font::Small.style();
+  typography::draw(
+    display,
+    &typography::line(format_args!("{} 24h", metric.label())),
+    Point::new(1, statusbar::HEIGHT + 1),
+    Align::Left,
+    style,
+  );
+  let current = current.map(|value| metric.format(value));
+  typography::draw(
+    display,
+    current.as_deref().unwrap_or("--"),
+    Point::new(typography::PANEL_WIDTH - 1, statusbar::HEIGHT + 1),
+    Align::Right,
+    style,
+  );
+
+  let known = series.iter().flatten();
+  let (Some(min), Some(max)) = (
+    known.clone().copied().reduce(f32::min),
+    known.copied().reduce(f32::max),
+  ) else {
+    typography::draw_centered(display, "No data yet", 32, style);
+    return;
+  };
+  typography::draw(
+    display,
+    &metric.format(max),
+    Point::new(1, PLOT_TOP),
+    Align::Left,
+    style,
+  );
+  typography::draw(
+    display,
+    &metric.format(min),
+    Point::new(1, 56),
+    Align::Left,
+    style,
+  );
+
+  // a flat series sits in the middle of the plot
+  let range = max - min;
+  let y = |value: f32| {
+    let bottom = PLOT_TOP + PLOT_HEIGHT;
+    if range <= f32::EPSILON {
+      return bottom - PLOT_HEIGHT / 2;
+    }
+    bottom - ((value - min) / range * PLOT_HEIGHT as f32).round() as i32
+  };
+
+  // gaps between samples are bridged with a straight line
+  let line = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+  let mut previous: Option<Point> = None;
+  for (x, value) in series.iter().enumerate() {
+    let Some(value) = value else {
+      continue;
+    };
+    let point = Point::new(x as i32, y(*value));
+    let _ = Line::new(previous.unwrap_or(point), point)
+      .into_styled(line)
+      .draw(display);
+    previous = Some(point);
+  }
+}
+
+/// 24 bars of the minutes with motion in each hour, midnight on the left,
+/// with the hour in progress marked below its bar
+pub fn draw_occupancy(
+  display: &mut Display<'_>,
+  minutes: &[u8; 24],
+  current_hour: u32,
+) {
+  let style = Font::Small.style();
+  typography::draw(
+    display,
+    "Activity 24h",
+    Point::new(1, statusbar::HEIGHT + 1),
+    Align::Left,
+    style,
+  );
+  let total: u32 = minutes.iter().map(|&minutes| u32::from(minutes)).sum();
+  typography::draw(
+    display,
+    &typography::line(format_args!("{}h{:02}m", total / 60, total % 60)),
+    Point::new(typography::PANEL_WIDTH - 1, statusbar::HEIGHT + 1),
+    Align::Right,
+    style,
+  );
+
+  // 5 px per hour, centred on the panel
+  let left = (typography::PANEL_WIDTH - 24 * 5) / 2;
+  let bottom = PLOT_TOP + BAR_HEIGHT;
+  let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+  for (hour, &occupied) in minutes.iter().enumerate() {
+    let x = left + 5 * hour as i32;
+    // any motion at all shows
+    let height = (i32::from(occupied) * BAR_HEIGHT + 59) / 60;
+    if height > 0 {
+      let _ = Rectangle::new(
+        Point::new(x, bottom - height),
+        Size::new(4, height as u32),
+      )
+      .into_styled(fill)
+      .draw(display);
+    }
+    if hour as u32 == current_hour {
+      let _ =
+        Line::new(Point::new(x, bottom + 2), Point::new(x + 3, bottom + 2))
+          .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+          .draw(display);
+    }
+  }
+  for hour in [0, 6, 12, 18] {
+    typography::draw(
+      display,
+      &typography::line(format_args!("{hour}")),
+      Point::new(left + 5 * hour, 56),
+      Align::Left,
+      style,
+    );
+  }
+}
@@ -0,0 +1,49 @@
+use std::sync::OnceLock;
+
+use esp_idf_svc::mdns::EspMdns;
+
+/// Set once at boot by [`init`]
+static NAME: OnceLock<String> = OnceLock::new();
+
+/// Take the name from `device.name`, before anything goes on the network.
+/// A changed name applies after a restart.
+pub fn init(configured: &str) {
+  let name = if configured.is_empty() {
+    let mac = mac();
+    format!("pippo-{:02x}{:02x}{:02x}", mac[3], mac[4], mac[5])
+  } else {
+    configured.to_string()
+  };
+  log::info!("Device name {}", name);
+  let _ = NAME.set(name);
+}
+
+/// The unit's name: `device.name`, or `pippo-` and the end of the MAC
+/// address while that is empty. Used as the DHCP and mDNS host name, the
+/// MQTT client id and on the web pages, so several units on one LAN can be
+/// told apart.
+pub fn get() -> &'static str {
+  NAME.get().map_or("pippo", String::as_str)
+}
+
+/// Answer for `<name>.local` under the unit's name, and list the web
+/// server served over `scheme` in network browsers that look for mDNS
+/// services. Answering stops when the returned responder is dropped.
+pub fn advertise(scheme: Option<&str>) -> anyhow::Result<EspMdns> {
+  let mut mdns = EspMdns::take()?;
+  mdns.set_hostname(get())?;
+  mdns.set_instance_name(get())?;
+  match scheme {
+    Some("https") => mdns.add_service(None, "_https", "_tcp", 443, &[])?,
+    Some(_) => mdns.add_service(None, "_http", "_tcp", 80, &[])?,
+    None => {}
+  }
+  Ok(mdns)
+}
+
+/// The MAC address burnt in at the factory
+pub fn mac() -> [u8; 6] {
+  let mut mac = [0u8; 6];
+  unsafe { esp_idf_svc::sys::esp_efuse_mac_get_default(mac.as_mut_ptr()) };
+  mac
+}
@@ -0,0 +1,252 @@
+use std::io::{self, Read};
+
+/// Longest status line or header line read, and most header lines
+const MAX_LINE_LEN: usize = 1024;
+const MAX_HEADERS: usize = 64;
+
+/// Head of an HTTP/1.1 request for `target`, which is the path or, to a
+/// proxy, the whole URL. The connection is closed after the response, so
+/// a body without a length simply ends with it.
+pub fn request_head(
+  method: &str,
+  target: &str,
+  host: &str,
+  headers: &[(&str, &str)],
+) -> String {
+  let mut head = format!("{method} {target} HTTP/1.1\r\nhost: {host}\r\n");
+  for (name, value) in headers {
+    head.push_str(&format!("{name}: {value}\r\n"));
+  }
+  head.push_str("connection: close\r\n\r\n");
+  head
+}
+
+/// `Proxy-Authorization` value for `username` and `password`
+pub fn basic_auth(username: &str, password: &str) -> String {
+  const ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  let bytes = format!("{username}:{password}").into_bytes();
+  let mut encoded = String::from("Basic ");
+  for group in bytes.chunks(3) {
+    let bits = group.iter().enumerate().fold(0_u32, |bits, (i, &byte)| {
+      bits | u32::from(byte) << (16 - 8 * i)
+    });
+    for i in 0..4 {
+      if i <= group.len() {
+        encoded
+          .push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+  encoded
+}
+
+/// Status and headers of a response, read up to the blank line after them
+#[derive(Debug)]
+pub struct Head {
+  pub status: u16,
+  headers: Vec<(String, String)>,
+}
+
+impl Head {
+  pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+    let status_line = read_line(reader)?;
+    // HTTP/1.1 200 OK
+    let status = status_line
+      .strip_prefix("HTTP/1.")
+      .and_then(|rest| rest.get(2..5))
+      .and_then(|code| code.parse().ok())
+      .ok_or_else(|| invalid("not an HTTP response"))?;
+    let mut headers = Vec::new();
+    loop {
+      let line = read_line(reader)?;
+      if line.is_empty() {
+        return Ok(Self { status, headers });
+      }
+      if headers.len() == MAX_HEADERS {
+        return Err(invalid("too many response headers"));
+      }
+      let (name, value) = line
+        .split_once(':')
+        .ok_or_else(|| invalid("malformed response header"))?;
+      headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+  }
+
+  /// Value of the header `name`, whatever its case
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .find(|(header, _)| header.eq_ignore_ascii_case(name))
+      .map(|(_, value)| value.as_str())
+  }
+}
+
+/// A response read from `reader`, its body ending where the head says:
+/// after its length, after the last chunk, or when the server closes the
+/// connection
+pub struct Response<R> {
+  pub head: Head,
+  reader: R,
+  framing: Framing,
+}
+
+enum Framing {
+  /// Bytes left
+  Length(usize),
+  /// Bytes left of the current chunk, `None` before the first one
+  Chunked(Option<usize>),
+  Close,
+  Done,
+}
+
+impl<R: Read> Response<R> {
+  /// Reads the head, leaving the body to [`Read`]
+  pub fn read(mut reader: R) -> io::Result<Self> {
+    let head = Head::read(&mut reader)?;
+    let chunked = head
+      .header("transfer-encoding")
+      .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"));
+    let length = head
+      .header("content-length")
+      .and_then(|length| length.parse().ok());
+    let framing = match (head.status, chunked, length) {
+      (204 | 304, _, _) => Framing::Done,
+      (_, true, _) => Framing::Chunked(None),
+      (_, false, Some(length)) => Framing::Length(length),
+      (_, false, None) => Framing::Close,
+    };
+    Ok(Self {
+      head,
+      reader,
+      framing,
+    })
+  }
+}
+
+impl<R: Read> Read for Response<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    loop {
+      match self.framing {
+        Framing::Done => return Ok(0),
+        Framing::Close => return self.reader.read(buf),
+        Framing::Length(0) => self.framing = Framing::Done,
+        Framing::Length(left) => {
+          let size = read_up_to(&mut self.reader, buf, left)?;
+          self.framing = Framing::Length(left - size);
+          return Ok(size);
+        }
+        Framing::Chunked(Some(0)) => {
+          // the line break after the data of a chunk
+          if !read_line(&mut self.reader)?.is_empty() {
+            return Err(invalid("chunk longer than its size"));
+          }
+          self.framing = Framing::Chunked(None);
+        }
+        Framing::Chunked(None) => {
+          let line = read_line(&mut self.reader)?;
+          // extensions after the size are of no interest
+          let size = line.split(';').next().unwrap_or("").trim();
+          let size = usize::from_str_radix(size, 16)
+            .map_err(|_| invalid("malformed chunk size"))?;
+          if size == 0 {
+            // trailers, up to the blank line that ends the body
+            while !read_line(&mut self.reader)?.is_empty() {}
+            self.framing = Framing::Done;
+          } else {
+            self.framing = Framing::Chunked(Some(size));
+          }
+        }
+        Framing::Chunked(Some(left)) => {
+          let size = read_up_to(&mut self.reader, buf, left)?;
+          self.framing = Framing::Chunked(Some(left - size));
+          return Ok(size);
+        }
+      }
+    }
+  }
+}
+
+/// Reads at most `left` bytes of a body that has that many still to come
+fn read_up_to(
+  reader: &mut impl Read,
+  buf: &mut [u8],
+  left: usize,
+) -> io::Result<usize> {
+  let len = left.min(buf.len());
+  let size = reader.read(&mut buf[..len])?;
+  if size == 0 && len > 0 {
+    return Err(io::ErrorKind::UnexpectedEof.into());
+  }
+  Ok(size)
+}
+
+/// A line without its line break, read a byte at a time so that nothing
+/// after it is taken from `reader`
+fn read_line(reader: &mut impl Read) -> io::Result<String> {
+  let mut line = Vec::new();
+  let mut byte = [0_u8];
+  loop {
+    if reader.read(&mut byte)? == 0 {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    match byte[0] {
+      b'\n' => break,
+      byte if line.len() < MAX_LINE_LEN => line.push(byte),
+      _ => return Err(invalid("response line too long")),
+    }
+  }
+  if line.last() == Some(&b'\r') {
+    line.pop();
+  }
+  String::from_utf8(line).map_err(|_| invalid("response line is not UTF-8"))
+}
+
+/// Scheme, host, port and the path with its query of `url`, `None` unless it
+/// is an `http://` or `https://` URL
+pub fn split_url(url: &str) -> Option<Url<'_>> {
+  let (https, rest) = if let Some(rest) = url.strip_prefix("https://") {
+    (true, rest)
+  } else {
+    (false, url.strip_prefix("http://")?)
+  };
+  let (authority, path) = match rest.find(['/', '?']) {
+    Some(start) => rest.split_at(start),
+    None => (rest, ""),
+  };
+  let (host, port) = match authority.rsplit_once(':') {
+    Some((host, port)) => (host, port.parse().ok()?),
+    None => (authority, if https { 443 } else { 80 }),
+  };
+  if host.is_empty() || host.contains('@') {
+    return None;
+  }
+  let path = match path {
+    "" => "/".to_string(),
+    path if path.starts_with('?') => format!("/{path}"),
+    path => path.to_string(),
+  };
+  Some(Url {
+    https,
+    host,
+    port,
+    path,
+  })
+}
+
+/// Parts of a URL, see [`split_url`]
+#[derive(Debug, PartialEq, Eq)]
+pub struct Url<'a> {
+  pub https: bool,
+  pub host: &'a str,
+  pub port: u16,
+  /// Path and query, at least `/`
+  pub path: String,
+}
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
@@ -0,0 +1,517 @@
+use std::{io::Read, time::Duration};
+
+use esp_idf_hal::io::Write;
+use esp_idf_svc::http::{
+  client::{Configuration, EspHttpConnection, FollowRedirectsPolicy},
+  Method,
+};
+use serde::de::DeserializeOwned;
+
+use crate::{
+  body::{self, Body},
+  clock::Instant,
+  perf::{self, Span},
+  proxy,
+  request_queue::{self, Priority},
+  tls_pins::{self, Endpoint},
+};
+
+/// The API turned the key or token down, it has to be replaced
+#[derive(Debug)]
+pub struct KeyRejected(pub u16);
+
+impl std::fmt::Display for KeyRejected {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "API key rejected with status {}", self.0)
+  }
+}
+
+impl std::error::Error for KeyRejected {}
+
+/// The server answered with a status outside 2xx that isn't a
+/// [`KeyRejected`]
+#[derive(Debug)]
+pub struct BadStatus(pub u16);
+
+impl std::fmt::Display for BadStatus {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Request failed with status: {}", self.0)
+  }
+}
+
+impl std::error::Error for BadStatus {}
+
+/// No connection came free before the deadline of the request
+#[derive(Debug)]
+pub struct QueueFull;
+
+impl std::fmt::Display for QueueFull {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Timed out waiting for a free connection")
+  }
+}
+
+impl std::error::Error for QueueFull {}
+
+/// Limits every request is held to, whichever of the functions here sends
+/// it
+#[derive(Clone, Copy, Debug)]
+pub struct RequestOptions {
+  /// For connecting, and for each read or write after that
+  pub timeout: Duration,
+  /// For the whole request, from connecting to the end of the body
+  pub deadline: Duration,
+  /// Longer response bodies fail with [`body::TooLarge`]
+  pub max_body_bytes: usize,
+  /// Tries after the first one when connecting fails or the server answers
+  /// 5xx, as long as the deadline leaves time for them
+  pub retries: u32,
+  /// Wait before the first retry, doubled before each one after it
+  pub retry_delay: Duration,
+  /// Place in line when more requests want a connection than there are,
+  /// see [`request_queue`]
+  pub priority: Priority,
+}
+
+impl Default for RequestOptions {
+  fn default() -> Self {
+    Self {
+      timeout: Duration::from_secs(10),
+      deadline: Duration::from_secs(30),
+      max_body_bytes: body::DEFAULT_LIMIT,
+      retries: 1,
+      retry_delay: Duration::from_secs(2),
+      priority: Priority::Refresh,
+    }
+  }
+}
+
+/// Connection settings every outgoing request starts from: TLS checked
+/// against the built in CA bundle
+fn configuration() -> Configuration {
+  Configuration {
+    use_global_ca_store: true,
+    crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
+    ..Default::default()
+  }
+}
+
+/// Connects and sends the `method` request line and `headers` for `url`,
+/// leaving the connection ready for a request body. With an `endpoint`
+/// whose certificate is pinned, only that certificate is trusted and
+/// redirects are not followed, since they would need a new handshake. With
+/// a proxy set up, the request goes through it, see [`proxy::Connection`].
+/// It waits its turn for a connection first, for up to the deadline in
+/// `options`. Of the rest only the timeout applies, retries and the
+/// deadline of the response are up to the caller.
+pub fn open(
+  endpoint: Option<Endpoint>,
+  method: Method,
+  url: &str,
+  headers: &[(&str, &str)],
+  buffer_size: Option<usize>,
+  options: &RequestOptions,
+) -> anyhow::Result<Connection> {
+  let slot = request_queue::acquire(options.priority, options.deadline)
+    .ok_or(QueueFull)?;
+  let transport = if let Some(proxy) = proxy::get() {
+    let connection =
+      proxy::Connection::open(proxy, endpoint, method, url, headers, options)?;
+    Transport::Proxied(connection)
+  } else {
+    connect_directly(endpoint, method, url, headers, buffer_size, options)?
+  };
+  Ok(Connection {
+    transport,
+    _slot: slot,
+  })
+}
+
+fn connect_directly(
+  endpoint: Option<Endpoint>,
+  method: Method,
+  url: &str,
+  headers: &[(&str, &str)],
+  buffer_size: Option<usize>,
+  options: &RequestOptions,
+) -> anyhow::Result<Transport> {
+  let connect = |configuration: Configuration| -> anyhow::Result<_> {
+    let mut connection = EspHttpConnection::new(&Configuration {
+      buffer_size,
+      timeout: Some(options.timeout),
+      ..configuration
+    })?;
+    connection.initiate_request(method, url, headers)?;
+    Ok(Transport::Direct(connection))
+  };
+  let Some(endpoint) = endpoint else {
+    return connect(configuration());
+  };
+  tls_pins::with(endpoint, |pinned| {
+    if !pinned {
+      return connect(configuration());
+    }
+    connect(Configuration {
+      use_global_ca_store: true,
+      follow_redirects_policy: FollowRedirectsPolicy::FollowNone,
+      ..Default::default()
+    })
+  })
+}
+
+/// A request on its way out, holding one of the connections requests take
+/// turns on until it is dropped
+pub struct Connection {
+  transport: Transport,
+  _slot: request_queue::Slot,
+}
+
+/// Straight to the server or through the proxy
+enum Transport {
+  Direct(EspHttpConnection),
+  Proxied(proxy::Connection),
+}
+
+impl Connection {
+  pub fn write_all(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+    match &mut self.transport {
+      Transport::Direct(connection) => connection.write_all(buf)?,
+      Transport::Proxied(connection) => connection.write_all(buf)?,
+    }
+    Ok(())
+  }
+
+  /// Sends what is left of the request and reads the status and headers
+  pub fn initiate_response(&mut self) -> anyhow::Result<()> {
+    match &mut self.transport {
+      Transport::Direct(connection) => connection.initiate_response()?,
+      Transport::Proxied(connection) => connection.initiate_response()?,
+    }
+    Ok(())
+  }
+
+  pub fn status(&self) -> u16 {
+    match &self.transport {
+      Transport::Direct(connection) => connection.status(),
+      Transport::Proxied(connection) => connection.status(),
+    }
+  }
+
+  pub fn header(&self, name: &str) -> Option<&str> {
+    match &self.transport {
+      Transport::Direct(connection) => connection.header(name),
+      Transport::Proxied(connection) => connection.header(name),
+    }
+  }
+
+  pub fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    match &mut self.transport {
+      Transport::Direct(connection) => connection.read(buf).map_err(|error| {
+        std::io::Error::other(format!("read failed: {:?}", error))
+      }),
+      Transport::Proxied(connection) => connection.read(buf),
+    }
+  }
+}
+
+/// A response whose status and headers are in, its body still to read
+pub struct Response {
+  connection: Connection,
+  deadline: Instant,
+  max_body_bytes: usize,
+}
+
+impl Response {
+  pub fn status(&self) -> u16 {
+    self.connection.status()
+  }
+
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.connection.header(name)
+  }
+
+  /// The body, held to the size and deadline of the request
+  pub fn body(&mut self) -> Body<Reader<'_>> {
+    Body::new(Reader(&mut self.connection), self.max_body_bytes)
+      .until(self.deadline)
+  }
+}
+
+/// Sends the `method` request with `headers` and `payload` to `url`, trying
+/// again as `options` allow
+fn send(
+  endpoint: Option<Endpoint>,
+  method: Method,
+  url: &str,
+  headers: &[(&str, &str)],
+  payload: &[u8],
+  options: &RequestOptions,
+) -> anyhow::Result<Response> {
+  let deadline = Instant::now() + options.deadline;
+  let mut delay = options.retry_delay;
+  let mut retries = options.retries;
+  loop {
+    let sent = open(endpoint, method, url, headers, None, options).and_then(
+      |mut connection| -> anyhow::Result<_> {
+        connection.write_all(payload)?;
+        connection.initiate_response()?;
+        Ok(connection)
+      },
+    );
+    let failed = match &sent {
+      Ok(connection) => connection.status() >= 500,
+      Err(_) => true,
+    };
+    if !failed || retries == 0 || Instant::now() + delay >= deadline {
+      let connection = sent?;
+      log::debug!("Response code: {}", connection.status());
+      return Ok(Response {
+        connection,
+        deadline,
+        max_body_bytes: options.max_body_bytes,
+      });
+    }
+    // the URL is left out of the log, it may carry an API key
+    match &sent {
+      Ok(connection) => log::warn!(
+        "Request failed with status: {}, trying again in {:?}",
+        connection.status(),
+        delay
+      ),
+      Err(error) => {
+        log::warn!("Request failed, trying again in {:?}: {:?}", delay, error)
+      }
+    }
+    drop(sent);
+    std::thread::sleep(delay);
+    delay *= 2;
+    retries -= 1;
+  }
+}
+
+/// Body of a GET request to `url` parsed as JSON as it arrives
+pub fn get_json<T: DeserializeOwned>(url: &str) -> anyhow::Result<T> {
+  get_body(None, url, &[], &RequestOptions::default(), |body| {
+    body.json()
+  })
+}
+
+/// Body of a GET request to `url` with more request headers, such as
+/// `Authorization`. A 401 or 403 is a [`KeyRejected`] error.
+pub fn get_with_headers(
+  url: &str,
+  extra_headers: &[(&str, &str)],
+) -> anyhow::Result<String> {
+  let options = RequestOptions::default();
+  get_body(None, url, extra_headers, &options, |body| Ok(body.text()?))
+}
+
+/// GET `url` and hand the body to `read` if the request succeeded. A 401 or
+/// 403 is a [`KeyRejected`] error.
+pub fn get_body<T>(
+  endpoint: Option<Endpoint>,
+  url: &str,
+  extra_headers: &[(&str, &str)],
+  options: &RequestOptions,
+  read: impl FnOnce(Body<Reader<'_>>) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+  let _span = perf::span(Span::HttpFetch);
+  let mut response =
+    get(endpoint, url, "application/json", extra_headers, options)?;
+  let status = response.status();
+  match status {
+    200..=299 => read(response.body()),
+    401 | 403 => Err(KeyRejected(status).into()),
+    _ => Err(BadStatus(status).into()),
+  }
+}
+
+/// [`get_json`] that sends `validators` along, `None` if the server says
+/// the body is the one they were taken from. They are replaced with those
+/// of a new body once it has been parsed.
+pub fn get_json_if_modified<T: DeserializeOwned>(
+  endpoint: Option<Endpoint>,
+  url: &str,
+  validators: &mut Validators,
+  options: &RequestOptions,
+) -> anyhow::Result<Option<T>> {
+  let _span = perf::span(Span::HttpFetch);
+  let headers = validators.headers(url);
+  let mut response = get(endpoint, url, "application/json", &headers, options)?;
+  let status = response.status();
+  match status {
+    304 => Ok(None),
+    200..=299 => {
+      let fresh = Validators::of(url, &response);
+      let parsed = response.body().json()?;
+      *validators = fresh;
+      Ok(Some(parsed))
+    }
+    401 | 403 => Err(KeyRejected(status).into()),
+    _ => Err(BadStatus(status).into()),
+  }
+}
+
+fn get(
+  endpoint: Option<Endpoint>,
+  url: &str,
+  accept: &str,
+  extra_headers: &[(&str, &str)],
+  options: &RequestOptions,
+) -> anyhow::Result<Response> {
+  // some APIs turn down requests without a user agent
+  let mut headers = vec![("accept", accept), ("user-agent", "pippo")];
+  headers.extend_from_slice(extra_headers);
+  send(endpoint, Method::Get, url, &headers, &[], options)
+}
+
+/// What the server said identifies the last body it sent for a URL. Sent
+/// back with the next request to the same URL, so that an unchanged body
+/// can be answered with 304 Not Modified instead of coming again.
+#[derive(Clone, Debug, Default)]
+pub struct Validators {
+  url: String,
+  etag: Option<String>,
+  last_modified: Option<String>,
+}
+
+impl Validators {
+  fn of(url: &str, response: &Response) -> Self {
+    Self {
+      url: url.to_string(),
+      etag: response.header("ETag").map(str::to_string),
+      last_modified: response.header("Last-Modified").map(str::to_string),
+    }
+  }
+
+  /// `If-None-Match` and `If-Modified-Since` for a request to `url`, none
+  /// if the last body came from another one
+  fn headers(&self, url: &str) -> Vec<(&'static str, &str)> {
+    if self.url != url {
+      return Vec::new();
+    }
+    let etag = self.etag.as_deref().map(|etag| ("if-none-match", etag));
+    let date = self.last_modified.as_deref();
+    let last_modified = date.map(|date| ("if-modified-since", date));
+    etag.into_iter().chain(last_modified).collect()
+  }
+}
+
+/// A response as `std::io::Read`, for [`Body`]
+pub struct Reader<'a>(&'a mut Connection);
+
+impl Read for Reader<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    self.0.read(buf)
+  }
+}
+
+/// Body of a GET request to `url`, handed to `chunk` piece by piece as it
+/// arrives, for responses too large to keep in memory. Reading stops early
+/// once `chunk` returns false. Like [`get_json_if_modified`], `validators`
+/// are sent along and replaced, and false means nothing came since they
+/// were taken.
+pub fn get_streamed(
+  url: &str,
+  accept: &str,
+  validators: &mut Validators,
+  options: &RequestOptions,
+  mut chunk: impl FnMut(&[u8]) -> bool,
+) -> anyhow::Result<bool> {
+  let _span = perf::span(Span::HttpFetch);
+  let headers = validators.headers(url);
+  let mut response = get(None, url, accept, &headers, options)?;
+  let status = response.status();
+  if status == 304 {
+    return Ok(false);
+  }
+  if !(200..=299).contains(&status) {
+    return Err(BadStatus(status).into());
+  }
+  let fresh = Validators::of(url, &response);
+  let mut body = response.body();
+  let mut buf = [0_u8; 512];
+  loop {
+    let size = body.read(&mut buf)?;
+    if size == 0 || !chunk(&buf[..size]) {
+      *validators = fresh;
+      return Ok(true);
+    }
+  }
+}
+
+/// Body of a POST request. It goes out with its length and a matching
+/// `content-type`.
+#[derive(Clone, Copy, Debug)]
+pub enum Payload<'a> {
+  /// Serialized JSON
+  Json(&'a str),
+  /// `application/x-www-form-urlencoded`, see [`crate::url::form_encode`]
+  Form(&'a str),
+}
+
+impl<'a> Payload<'a> {
+  fn content_type(self) -> &'static str {
+    match self {
+      Payload::Json(_) => "application/json",
+      Payload::Form(_) => "application/x-www-form-urlencoded",
+    }
+  }
+
+  fn bytes(self) -> &'a [u8] {
+    match self {
+      Payload::Json(text) | Payload::Form(text) => text.as_bytes(),
+    }
+  }
+}
+
+/// POST `payload` to `url` with more request headers, such as
+/// `Authorization`. What counts as a failure is up to the API, so the
+/// response is returned whatever its status.
+pub fn post(
+  url: &str,
+  payload: Payload<'_>,
+  extra_headers: &[(&str, &str)],
+  options: &RequestOptions,
+) -> anyhow::Result<Response> {
+  let length = payload.bytes().len().to_string();
+  let mut headers = vec![
+    ("accept", "application/json"),
+    ("user-agent", "pippo"),
+    ("content-type", payload.content_type()),
+    ("content-length", length.as_str()),
+  ];
+  headers.extend_from_slice(extra_headers);
+  send(None, Method::Post, url, &headers, payload.bytes(), options)
+}
+
+/// Body of the response to POSTing the form-encoded `form` to `url`, such
+/// as a token request. A 400 or 401 is a [`KeyRejected`] error.
+pub fn post_form(url: &str, form: &str) -> anyhow::Result<String> {
+  let _span = perf::span(Span::HttpFetch);
+  let options = RequestOptions::default();
+  let mut response = post(url, Payload::Form(form), &[], &options)?;
+  let status = response.status();
+  let body = response.body().text()?;
+  match status {
+    200..=299 => Ok(body),
+    // OAuth servers answer a bad grant with 400
+    400 | 401 => Err(KeyRejected(status).into()),
+    _ => Err(BadStatus(status).into()),
+  }
+}
+
+/// POST `json` to `url`, such as a webhook, ignoring what comes back.
+/// Someone is waiting for it, so it goes ahead of data refreshes.
+pub fn post_json(url: &str, json: &str) -> anyhow::Result<()> {
+  let _span = perf::span(Span::HttpFetch);
+  let options = RequestOptions {
+    priority: Priority::Notification,
+    ..Default::default()
+  };
+  let status = post(url, Payload::Json(json), &[], &options)?.status();
+  if !(200..=299).contains(&status) {
+    return Err(BadStatus(status).into());
+  }
+  Ok(())
+}
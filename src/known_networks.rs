@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+
+/// Networks remembered at most, their passwords share one NVS string
+pub const MAX_KNOWN: usize = 8;
+
+/// A network the device joins when it is in range, such as home, the
+/// office or a phone's hotspot
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownNetwork {
+  pub ssid: String,
+  /// Kept with the secrets, never listed
+  #[serde(default, skip_serializing)]
+  pub password: String,
+  /// Higher is joined first when several are in range
+  #[serde(default)]
+  pub priority: u8,
+}
+
+impl KnownNetwork {
+  pub fn validate(&self) -> anyhow::Result<()> {
+    if self.ssid.is_empty() || self.ssid.len() > 32 {
+      anyhow::bail!("SSID must be 1-32 bytes");
+    }
+    if !self.password.is_empty() && !(8..=64).contains(&self.password.len()) {
+      anyhow::bail!("password must be empty or 8-64 bytes");
+    }
+    Ok(())
+  }
+}
+
+/// The known network to join out of the `visible` ones, as SSID and signal
+/// in dBm. The highest priority wins, the stronger signal between two of
+/// the same priority.
+pub fn pick<'a>(
+  known: &'a [KnownNetwork],
+  visible: &[(&str, i8)],
+) -> Option<&'a KnownNetwork> {
+  known
+    .iter()
+    .filter_map(|network| {
+      visible
+        .iter()
+        .filter(|(ssid, _)| *ssid == network.ssid)
+        .map(|(_, rssi)| *rssi)
+        .max()
+        .map(|rssi| (network, rssi))
+    })
+    .max_by_key(|(network, rssi)| (network.priority, *rssi))
+    .map(|(network, _)| network)
+}
+
+/// Adds `network` to `known`, in place of one with the same SSID
+pub fn remember(
+  known: &mut Vec<KnownNetwork>,
+  network: KnownNetwork,
+) -> anyhow::Result<()> {
+  known.retain(|other| other.ssid != network.ssid);
+  if known.len() >= MAX_KNOWN {
+    anyhow::bail!("at most {MAX_KNOWN} networks can be saved");
+  }
+  known.push(network);
+  Ok(())
+}
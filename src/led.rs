@@ -0,0 +1,63 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Instant;
+
+/// Pattern the status LED is playing and when it started
+pub type SharedLed = Arc<Mutex<Option<(Pattern, Instant)>>>;
+
+/// Blink patterns for the status LED, played for a while and then off
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+  /// Once a second, for 30 s
+  Blink,
+  /// Five times a second, for 10 s
+  Flash,
+}
+
+impl Pattern {
+  fn half_period(self) -> Duration {
+    match self {
+      Self::Blink => Duration::from_millis(500),
+      Self::Flash => Duration::from_millis(100),
+    }
+  }
+
+  fn length(self) -> Duration {
+    match self {
+      Self::Blink => Duration::from_secs(30),
+      Self::Flash => Duration::from_secs(10),
+    }
+  }
+
+  /// Whether the LED is lit `elapsed` into the pattern, `None` once it is
+  /// over
+  pub fn lit(self, elapsed: Duration) -> Option<bool> {
+    if elapsed >= self.length() {
+      return None;
+    }
+    let half_periods = elapsed.as_millis() / self.half_period().as_millis();
+    Some(half_periods % 2 == 0)
+  }
+
+  /// How long after `elapsed` the LED next turns on or off, `None` once the
+  /// pattern is over
+  pub fn next_change(self, elapsed: Duration) -> Option<Duration> {
+    if elapsed >= self.length() {
+      return None;
+    }
+    let half_period = self.half_period().as_millis();
+    let into = elapsed.as_millis() % half_period;
+    Some(Duration::from_millis((half_period - into) as u64))
+  }
+}
+
+/// Start playing `pattern`, in place of any other
+pub fn play(led: &SharedLed, pattern: Pattern) {
+  *led.lock().unwrap() = Some((pattern, Instant::now()));
+}
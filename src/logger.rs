@@ -0,0 +1,517 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use esp_idf_svc::log::EspLogger;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::psram;
+
+/// Number of records kept in the RAM ring buffer
+pub const CAPACITY: usize = 64;
+/// Messages longer than this are truncated before being buffered
+const MAX_MESSAGE_LEN: usize = 96;
+/// Target of the records noting who changed something on the device
+pub const AUDIT: &str = "audit";
+/// Audit records kept apart, so a busy log doesn't push them out
+const AUDIT_CAPACITY: usize = 32;
+
+static LOGGER: RingLogger = RingLogger::new();
+/// Targets of this crate's modules start with this, it is left out when
+/// naming a module's level
+const CRATE_PREFIX: &str = concat!(env!("CARGO_CRATE_NAME"), "::");
+/// Secondary sink receiving every record, e.g. a syslog collector
+static REMOTE: OnceLock<Box<dyn Log>> = OnceLock::new();
+/// Values masked wherever they show up in a record, see [`redact`]
+static REDACTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Most values masked, the oldest is dropped for a new one
+const MAX_REDACTED: usize = 16;
+/// Shorter values would mask ordinary words
+const MIN_REDACTED_LEN: usize = 6;
+/// Stands in for a redacted value
+const MASK: &str = "********";
+
+#[derive(Clone, Debug)]
+pub struct LogEntry {
+  /// Position in the order records were buffered, starting at 1, so clients
+  /// can ask for what they haven't seen yet
+  pub seq: u32,
+  /// Milliseconds since boot, same clock as the serial log prefix
+  pub timestamp_ms: u32,
+  pub level: Level,
+  pub target: String,
+  pub message: String,
+}
+
+impl LogEntry {
+  pub fn marker(&self) -> char {
+    match self.level {
+      Level::Error => 'E',
+      Level::Warn => 'W',
+      Level::Info => 'I',
+      Level::Debug => 'D',
+      Level::Trace => 'V',
+    }
+  }
+}
+
+impl fmt::Display for LogEntry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "{} ({}) {}: {}",
+      self.marker(),
+      self.timestamp_ms,
+      self.target,
+      self.message
+    )
+  }
+}
+
+/// Forwards every record to the ESP-IDF console logger and keeps a copy of
+/// the most recent ones in RAM for the Logs screen and the `/logs` endpoint.
+pub struct RingLogger {
+  console: EspLogger,
+  entries: Mutex<VecDeque<LogEntry>>,
+  /// Copy of the newest `AUDIT` records
+  audit: Mutex<VecDeque<LogEntry>>,
+  // set when a warning, error or audit record arrives that isn't in flash yet
+  unsaved: AtomicBool,
+  last_seq: AtomicU32,
+  levels: Mutex<Levels>,
+}
+
+/// Level of every record, and of the modules set apart from it
+#[derive(Clone, Debug)]
+pub struct Levels {
+  pub default: LevelFilter,
+  /// Module, e.g. `weather` or `esp_idf_svc::wifi`, and its level, which
+  /// also covers the modules inside it
+  pub modules: Vec<(String, LevelFilter)>,
+}
+
+impl Levels {
+  /// Level for records with `target`, from the longest module naming it
+  fn for_target(&self, target: &str) -> LevelFilter {
+    let target = target.strip_prefix(CRATE_PREFIX).unwrap_or(target);
+    self
+      .modules
+      .iter()
+      .filter(|(module, _)| {
+        target
+          .strip_prefix(module.as_str())
+          .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+      })
+      .max_by_key(|(module, _)| module.len())
+      .map_or(self.default, |(_, level)| *level)
+  }
+
+  /// The most verbose of the levels
+  fn max(&self) -> LevelFilter {
+    self
+      .modules
+      .iter()
+      .map(|(_, level)| *level)
+      .fold(self.default, LevelFilter::max)
+  }
+}
+
+impl RingLogger {
+  const fn new() -> Self {
+    Self {
+      console: EspLogger::new(),
+      entries: Mutex::new(VecDeque::new()),
+      audit: Mutex::new(VecDeque::new()),
+      unsaved: AtomicBool::new(false),
+      last_seq: AtomicU32::new(0),
+      levels: Mutex::new(Levels {
+        default: LevelFilter::Info,
+        modules: Vec::new(),
+      }),
+    }
+  }
+
+  fn push(&self, mut entry: LogEntry) {
+    // never block or panic inside the logger
+    if let Ok(mut entries) = self.entries.try_lock() {
+      // a const static can't allocate, the buffers come with the first record
+      if entries.capacity() == 0 {
+        *entries = psram::vec_deque(CAPACITY);
+      }
+      if entries.len() == CAPACITY {
+        entries.pop_front();
+      }
+      entry.seq = self.last_seq.fetch_add(1, Ordering::Relaxed) + 1;
+      if entry.target == AUDIT {
+        if let Ok(mut audit) = self.audit.try_lock() {
+          if audit.capacity() == 0 {
+            *audit = psram::vec_deque(AUDIT_CAPACITY);
+          }
+          if audit.len() == AUDIT_CAPACITY {
+            audit.pop_front();
+          }
+          audit.push_back(entry.clone());
+        }
+      }
+      entries.push_back(entry);
+    }
+  }
+
+  /// Hands `record` to the console, the remote sink and the ring buffer
+  fn write(&self, record: &Record) {
+    self.console.log(record);
+    if let Some(remote) = REMOTE.get() {
+      remote.log(record);
+    }
+
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+
+    let mut message = psram::string(MAX_MESSAGE_LEN);
+    let _ = fmt::Write::write_fmt(&mut Capped(&mut message), *record.args());
+
+    if record.level() <= Level::Warn || record.target() == AUDIT {
+      self.unsaved.store(true, Ordering::Relaxed);
+    }
+    self.push(LogEntry {
+      seq: 0,
+      timestamp_ms: unsafe { esp_idf_svc::sys::esp_log_timestamp() },
+      level: record.level(),
+      target: record.target().to_string(),
+      message,
+    });
+  }
+}
+
+impl Log for RingLogger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    self.console.enabled(metadata)
+  }
+
+  fn log(&self, record: &Record) {
+    let level = match self.levels.lock() {
+      Ok(levels) => levels.for_target(record.target()),
+      Err(_) => LevelFilter::Trace,
+    };
+    if record.level() > level {
+      return;
+    }
+    match redacted(record) {
+      None => self.write(record),
+      Some(text) => self.write(
+        &Record::builder()
+          .args(format_args!("{text}"))
+          .metadata(record.metadata().clone())
+          .module_path(record.module_path())
+          .file(record.file())
+          .line(record.line())
+          .build(),
+      ),
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+/// Install the ring logger as the global `log` backend
+pub fn initialize() {
+  let max_level = LOGGER.console.get_max_level();
+  LOGGER.levels.lock().unwrap().default = max_level;
+  log::set_logger(&LOGGER)
+    .map(|()| log::set_max_level(max_level))
+    .unwrap();
+}
+
+/// Log `level` and above from now on, as far as the firmware was built with,
+/// except in the modules given their own level
+pub fn set_level(level: LevelFilter) -> anyhow::Result<()> {
+  LOGGER.console.set_target_level("*", level)?;
+  let mut levels = LOGGER.levels.lock().unwrap();
+  levels.default = level;
+  log::set_max_level(levels.max().min(LOGGER.console.get_max_level()));
+  Ok(())
+}
+
+/// Log `level` and above from `module` and the modules inside it, or the
+/// same as everything else again with `None`. Modules of the firmware are
+/// named without the crate, e.g. `weather`.
+pub fn set_module_level(
+  module: &str,
+  level: Option<LevelFilter>,
+) -> anyhow::Result<()> {
+  let module = module.strip_prefix(CRATE_PREFIX).unwrap_or(module);
+  if module.is_empty() || module.contains(char::is_whitespace) {
+    anyhow::bail!("no module {module:?}");
+  }
+  let mut levels = LOGGER.levels.lock().unwrap();
+  levels.modules.retain(|(name, _)| name != module);
+  if let Some(level) = level {
+    levels.modules.push((module.to_string(), level));
+  }
+  // the console has its own level for each target, the default for the rest
+  let target = if module.contains("::") {
+    module.to_string()
+  } else {
+    format!("{CRATE_PREFIX}{module}")
+  };
+  LOGGER
+    .console
+    .set_target_level(target, level.unwrap_or(levels.default))?;
+  log::set_max_level(levels.max().min(LOGGER.console.get_max_level()));
+  Ok(())
+}
+
+/// The level for everything and the modules set apart
+pub fn levels() -> Levels {
+  LOGGER.levels.lock().unwrap().clone()
+}
+
+/// Mask `secret` in every record from now on, such as an API key that may
+/// end up in an error message. Values too short to tell apart from other
+/// text are left alone.
+pub fn redact(secret: &str) {
+  if secret.len() < MIN_REDACTED_LEN {
+    return;
+  }
+  let mut redacted = REDACTED.lock().unwrap();
+  if redacted.iter().any(|known| known == secret) {
+    return;
+  }
+  if redacted.len() == MAX_REDACTED {
+    redacted.remove(0);
+  }
+  redacted.push(secret.to_string());
+}
+
+/// The message of `record` with the redacted values masked, `None` if it
+/// has none of them
+fn redacted(record: &Record) -> Option<String> {
+  // only held briefly, and `redact` doesn't log while holding it
+  let redacted = REDACTED.lock().ok()?;
+  if redacted.is_empty() {
+    return None;
+  }
+  let text = record.args().to_string();
+  let mut masked = Cow::Borrowed(text.as_str());
+  for secret in redacted.iter() {
+    if masked.contains(secret.as_str()) {
+      masked = Cow::Owned(masked.replace(secret.as_str(), MASK));
+    }
+  }
+  match masked {
+    Cow::Borrowed(_) => None,
+    Cow::Owned(masked) => Some(masked),
+  }
+}
+
+/// Forward records to `sink` as well. Only one sink can be attached, later
+/// calls are ignored.
+pub fn attach(sink: Box<dyn Log>) {
+  if REMOTE.set(sink).is_err() {
+    log::warn!("A remote log sink is already attached");
+  }
+}
+
+/// Up to `count` entries, newest first, skipping the `skip` newest ones
+pub fn recent(skip: usize, count: usize) -> Vec<LogEntry> {
+  match LOGGER.entries.lock() {
+    Ok(entries) => entries
+      .iter()
+      .rev()
+      .skip(skip)
+      .take(count)
+      .cloned()
+      .collect(),
+    Err(_) => Vec::new(),
+  }
+}
+
+/// Entries buffered after the one numbered `seq`, oldest first. A `seq`
+/// from before a reboot returns everything.
+pub fn since(seq: u32) -> Vec<LogEntry> {
+  let seq = if seq > LOGGER.last_seq.load(Ordering::Relaxed) {
+    0
+  } else {
+    seq
+  };
+  match LOGGER.entries.lock() {
+    Ok(entries) => entries
+      .iter()
+      .filter(|entry| entry.seq > seq)
+      .cloned()
+      .collect(),
+    Err(_) => Vec::new(),
+  }
+}
+
+/// Audit records after the one numbered `seq`, oldest first, like `since`
+pub fn audit_since(seq: u32) -> Vec<LogEntry> {
+  let seq = if seq > LOGGER.last_seq.load(Ordering::Relaxed) {
+    0
+  } else {
+    seq
+  };
+  match LOGGER.audit.lock() {
+    Ok(audit) => audit
+      .iter()
+      .filter(|entry| entry.seq > seq)
+      .cloned()
+      .collect(),
+    Err(_) => Vec::new(),
+  }
+}
+
+/// Number of entries currently buffered
+pub fn len() -> usize {
+  LOGGER
+    .entries
+    .lock()
+    .map(|entries| entries.len())
+    .unwrap_or(0)
+}
+
+/// Whole buffer as plain text, oldest first, one record per line
+pub fn dump() -> String {
+  let mut text = String::new();
+  if let Ok(entries) = LOGGER.entries.lock() {
+    for entry in entries.iter() {
+      text.push_str(&entry.to_string());
+      text.push('\n');
+    }
+  }
+  text
+}
+
+/// Writes up to `MAX_MESSAGE_LEN` bytes, then stops the formatting, so the
+/// message never grows out of the buffer it was given
+struct Capped<'a>(&'a mut String);
+
+impl fmt::Write for Capped<'_> {
+  fn write_str(&mut self, text: &str) -> fmt::Result {
+    for c in text.chars() {
+      if self.0.len() + c.len_utf8() > MAX_MESSAGE_LEN {
+        return Err(fmt::Error);
+      }
+      self.0.push(c);
+    }
+    Ok(())
+  }
+}
+
+/// Flash-backed copy of the recent warnings, errors and audit records, so
+/// they survive a crash or watchdog reset. Nothing else is written to keep
+/// flash wear low.
+#[cfg(feature = "log-flash")]
+pub struct FlashLog {
+  nvs: esp_idf_svc::nvs::EspDefaultNvs,
+  last_saved: std::time::Instant,
+}
+
+#[cfg(feature = "log-flash")]
+impl FlashLog {
+  /// Warnings and errors kept in flash, besides the audit records
+  const KEPT: usize = 16;
+  /// Minimum time between two flash writes
+  const MIN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+  const KEY: &'static str = "recent";
+
+  pub fn new(
+    partition: esp_idf_svc::nvs::EspDefaultNvsPartition,
+  ) -> anyhow::Result<Self> {
+    let nvs = esp_idf_svc::nvs::EspDefaultNvs::new(partition, "logs", true)?;
+    Ok(Self {
+      nvs,
+      last_saved: std::time::Instant::now(),
+    })
+  }
+
+  /// Load the records saved by the previous boot into the ring buffer
+  pub fn restore(&self) -> anyhow::Result<()> {
+    let Some(len) = self.nvs.blob_len(Self::KEY)? else {
+      return Ok(());
+    };
+    let mut buf = vec![0_u8; len];
+    let Some(blob) = self.nvs.get_blob(Self::KEY, &mut buf)? else {
+      return Ok(());
+    };
+
+    LOGGER.push(separator("previous boot"));
+    for line in String::from_utf8_lossy(blob).lines() {
+      if let Some(entry) = parse_line(line) {
+        LOGGER.push(entry);
+      }
+    }
+    LOGGER.push(separator("current boot"));
+    Ok(())
+  }
+
+  /// Write the recent warnings, errors and audit records to flash if new
+  /// ones arrived
+  pub fn sync(&mut self) -> anyhow::Result<()> {
+    if self.last_saved.elapsed() < Self::MIN_INTERVAL
+      || !LOGGER.unsaved.swap(false, Ordering::Relaxed)
+    {
+      return Ok(());
+    }
+    self.last_saved = std::time::Instant::now();
+
+    let mut text = String::new();
+    let mut write = |entry: &LogEntry| {
+      text.push_str(&format!(
+        "{}|{}|{}|{}\n",
+        entry.marker(),
+        entry.timestamp_ms,
+        entry.target,
+        entry.message.replace('\n', " ")
+      ));
+    };
+    // the audit trail is kept whole, it has its own limit
+    if let Ok(audit) = LOGGER.audit.lock() {
+      audit.iter().for_each(&mut write);
+    }
+    if let Ok(entries) = LOGGER.entries.lock() {
+      let important: Vec<&LogEntry> = entries
+        .iter()
+        .filter(|entry| entry.level <= Level::Warn && entry.target != AUDIT)
+        .collect();
+      for entry in important.iter().rev().take(Self::KEPT).rev() {
+        write(*entry);
+      }
+    }
+    self.nvs.set_blob(Self::KEY, text.as_bytes())?;
+    Ok(())
+  }
+}
+
+#[cfg(feature = "log-flash")]
+fn separator(label: &str) -> LogEntry {
+  LogEntry {
+    seq: 0,
+    timestamp_ms: 0,
+    level: Level::Info,
+    target: "logger".to_string(),
+    message: format!("--- {label} ---"),
+  }
+}
+
+#[cfg(feature = "log-flash")]
+fn parse_line(line: &str) -> Option<LogEntry> {
+  let mut parts = line.splitn(4, '|');
+  let level = match parts.next()? {
+    "E" => Level::Error,
+    "W" => Level::Warn,
+    "I" => Level::Info,
+    "D" => Level::Debug,
+    _ => Level::Trace,
+  };
+  Some(LogEntry {
+    seq: 0,
+    timestamp_ms: parts.next()?.parse().ok()?,
+    level,
+    target: parts.next()?.to_string(),
+    message: parts.next()?.to_string(),
+  })
+}
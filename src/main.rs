@@ -1,89 +1,284 @@
 use anyhow::{self};
-use chrono::{DateTime, Local, Utc};
-use embedded_graphics::{
-  mono_font::{
-    MonoTextStyleBuilder,
-  },
-  pixelcolor::BinaryColor,
-  prelude::*,
-  primitives::{
-    Arc as GraphicsArc, CornerRadii, Line, PrimitiveStyle, Rectangle,
-    RoundedRectangle,
-  },
-  text::{Baseline, Text},
+use chrono::{Datelike, Timelike};
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+use esp_idf_hal::delay::TickType;
+use esp_idf_hal::gpio::{AnyIOPin, InterruptType, PinDriver};
+#[cfg(feature = "servo")]
+use esp_idf_hal::ledc::{
+  config::TimerConfig, LedcDriver, LedcTimerDriver, Resolution,
 };
-use embedded_svc::{
-  http::client::Client,
-  wifi::{AuthMethod, ClientConfiguration, Configuration},
-};
-use esp_idf_hal::{
-  delay::FreeRtos,
-  ledc::{config::TimerConfig, LedcDriver, LedcTimerDriver, Resolution},
-  peripherals::Peripherals,
-};
-use esp_idf_hal::{gpio::PinDriver, i2c::*};
-use esp_idf_hal::{io::Read, units::*};
-use esp_idf_svc::http::server::{
-  Configuration as HttpServerConfig, EspHttpServer,
-};
-use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use esp_idf_hal::task::notification::Notification;
+use esp_idf_hal::uart::{config::Config as UartConfig, UartDriver};
+use esp_idf_hal::units::Hertz;
+#[cfg(feature = "servo")]
+use esp_idf_hal::units::*;
+use esp_idf_hal::{delay::FreeRtos, peripherals::Peripherals};
+use esp_idf_svc::eventloop::EspSystemEventLoop;
+use esp_idf_svc::nvs::{EspCustomNvsPartition, EspDefaultNvsPartition};
 use esp_idf_svc::wifi::{BlockingWifi, EspWifi};
-use esp_idf_svc::{
-  eventloop::EspSystemEventLoop, http::client::EspHttpConnection,
-};
-use esp_idf_svc::{
-  http::{client::Configuration as HttpClientConfiguration, Method},
-  sntp::EspSntp,
+use serde::{Deserialize, Serialize};
+use ssd1306::prelude::DisplayRotation;
+use std::num::NonZeroU32;
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Arc, Mutex,
 };
-use ssd1306::{prelude::*, I2CDisplayInterface, Ssd1306};
-use std::sync::{Arc, Mutex};
-use std::{time::Duration, time::Instant};
+use std::time::Duration;
+mod alerts;
+mod auth;
+mod automation;
+mod bench;
+mod body;
+mod buzzer;
+mod certs;
+mod clock;
+mod config;
+mod console;
+mod crypto;
+mod defaults;
+mod diagnostics;
+mod discovery;
+mod display;
+#[cfg(feature = "door")]
+mod door;
+#[cfg(feature = "ethernet")]
+mod ethernet;
+mod events;
+mod feed;
+mod fetch;
+mod group;
+mod history;
+mod hostname;
+mod http1;
+mod http_client;
+mod known_networks;
+mod led;
+mod logger;
+mod marquee;
+mod network;
+mod notify;
+mod ota;
+mod overlay;
+mod pager;
+mod perf;
+mod persist;
+mod prerender;
+mod presence;
+mod profiles;
+mod proxy;
+mod psram;
+mod quote;
+mod ratelimit;
+mod recording;
+mod request_queue;
+mod roaming;
+mod scene;
+mod screens;
+mod secrets;
+#[cfg(feature = "servo")]
+mod servo;
+mod snake;
+mod splash;
+mod spotify;
+mod ssdp;
+mod state;
+mod statusbar;
+mod stocks;
+mod syslog;
+#[cfg(debug_assertions)]
+mod testing;
+mod tick;
+mod titlebar;
+mod tls_pins;
+mod typography;
+mod ui;
+mod url;
+#[cfg(feature = "servo")]
 mod utils;
+mod weather;
+mod web;
+mod wifi;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
-enum UiState {
-  Home,
-  Menu,
-  Settings,
-  Status,
-  Exit,
+use bench::SharedBench;
+use clock::Instant;
+use config::{Config, DisplayOptions, PinConfig, SharedConfig};
+use diagnostics::I2cDevices;
+use display::Oled;
+use events::Event;
+use history::{Metric, SharedHistory};
+use led::SharedLed;
+use network::Online;
+use notify::{Notifications, SharedNotifications};
+use ota::SharedProgress;
+use pager::Pager;
+use persist::Persisted;
+use prerender::Prerendered;
+use profiles::Profiles;
+use recording::SharedSession;
+use scene::{DisplayMode, SharedDisplayMode};
+use splash::{Splash, Stage};
+use state::{DeviceState, SharedState};
+use statusbar::StatusBar;
+use typography::{Font, Line};
+use ui::{ButtonEvent, Redraw, UiState};
+use wifi::{Credentials, SharedWifi};
+
+/// Where the user was, restored after a restart such as a watchdog reset or
+/// a firmware update
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct UiMemory {
+  screen: UiState,
+  option_index: u8,
+  history_metric: Metric,
 }
 
+/// Motion is announced again only after this long without any
+const MOTION_NOTIFY_GAP: Duration = Duration::from_secs(60);
+
+/// Holding the button this long at power-on erases all settings
+const FACTORY_RESET_HOLD: Duration = Duration::from_secs(10);
+
 // PINS
-// LED: GPIO2
-// BUTTON: GPIO23
-// I2C SDA: GPIO21
-// I2C SCL: GPIO22
+// Set in the `pins` settings, see `config::PinConfig`
 fn main() -> anyhow::Result<()> {
+  let boot_started = Instant::now();
   initialize();
+  #[cfg(feature = "psram")]
+  psram::log_size();
 
   let peripherals = Peripherals::take().unwrap();
 
   let system_event_loop = EspSystemEventLoop::take()?;
   let non_volatile_storage = EspDefaultNvsPartition::take()?;
+  secrets::init(non_volatile_storage.clone());
 
-  let mut button = PinDriver::input(peripherals.pins.gpio23)?;
+  #[cfg(feature = "log-flash")]
+  let mut flash_log = {
+    let flash_log = logger::FlashLog::new(non_volatile_storage.clone())?;
+    if let Err(error) = flash_log.restore() {
+      log::warn!("Could not restore saved logs: {:?}", error);
+    }
+    flash_log
+  };
+
+  let config: SharedConfig = Arc::new(Mutex::new(
+    Config::load(non_volatile_storage.clone()).unwrap_or_else(|error| {
+      log::warn!("Could not load settings, using defaults: {:?}", error);
+      Config::default()
+    }),
+  ));
+
+  let pins = config.lock().unwrap().pins.clone();
+  let pins = match pins.validate() {
+    Ok(()) => pins,
+    Err(error) => {
+      log::warn!("Invalid pin settings, using defaults: {:?}", error);
+      PinConfig::default()
+    }
+  };
+  log::info!("Pins: {:?}", pins);
+  hostname::init(&config.lock().unwrap().device.name);
+  // a cable that gets an address while WiFi is still starting is used
+  // instead
+  #[cfg(feature = "ethernet")]
+  let ethernet = ethernet::start(
+    peripherals.spi2,
+    &config.lock().unwrap().ethernet,
+    &pins,
+    system_event_loop.clone(),
+  )
+  .unwrap_or_else(|error| {
+    log::warn!("Ethernet not started: {:?}", error);
+    None
+  });
+
+  // Starting the WiFi driver is the slowest part of boot and needs nothing
+  // but the radio, so it runs while the display and the rest come up
+  let splash = Splash::default();
+  let wifi_task = {
+    let non_volatile_storage = non_volatile_storage.clone();
+    let static_ip = config.lock().unwrap().static_ip.clone();
+    splash.spawn(Stage::Wifi, move || {
+      let mut driver = EspWifi::new(
+        peripherals.modem,
+        system_event_loop.clone(),
+        Some(non_volatile_storage.clone()),
+      )?;
+      let netif = match network::static_netif(&static_ip) {
+        Ok(Some(netif)) => netif,
+        Ok(None) => network::dhcp_netif(hostname::get())?,
+        Err(error) => {
+          log::warn!("Invalid fixed address, using DHCP: {:?}", error);
+          network::dhcp_netif(hostname::get())?
+        }
+      };
+      driver.swap_netif_sta(netif)?;
+      let mut wifi = BlockingWifi::wrap(driver, system_event_loop)?;
+      // Credentials saved from the WiFi page win over the ones built in from
+      // cfg.toml
+      let credentials =
+        Credentials::load_or_default(non_volatile_storage.clone());
+      if credentials.ssid.is_empty() {
+        log::warn!("No WiFi network configured, set one in cfg.toml");
+      }
+      credentials.configure(&mut wifi)?;
+
+      wifi.start()?;
+      Ok(wifi)
+    })?
+  };
+
+  let mut button = PinDriver::input(gpio(pins.button))?;
 
   // Enable internal pull-up resistor on button pin (Thanks Google)
   button.set_pull(esp_idf_hal::gpio::Pull::Up)?;
+  // Holding the button while powering on runs the hardware self-test, or
+  // resets the device when held for FACTORY_RESET_HOLD
+  FreeRtos::delay_ms(10);
+  let diagnostics_requested = button.is_low();
   // Initialize I2C SSD1306 Display (Yellow and Blue Pixels)
-  let mut display = {
-    let config = I2cConfig::new().baudrate(100.kHz().into());
-    let sda = peripherals.pins.gpio21;
-    let scl = peripherals.pins.gpio22;
-    let i2c =
-      esp_idf_hal::i2c::I2cDriver::new(peripherals.i2c0, sda, scl, &config)?;
-    let interface = I2CDisplayInterface::new(i2c);
-    Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
-      .into_buffered_graphics_mode()
-  };
+  let mut oled = Oled::new(peripherals.i2c0, gpio(pins.sda), gpio(pins.scl));
+  apply_display_options(&mut oled, &config.lock().unwrap().display);
+  // Optional parts that don't answer are left out instead of failing boot
+  let i2c_devices = I2cDevices::detect(oled.scan());
+  i2c_devices.log();
 
-  let mut led = PinDriver::output(peripherals.pins.gpio2)?;
-  let buzzer = Arc::new(Mutex::new(PinDriver::output(peripherals.pins.gpio5)?));
+  if diagnostics_requested && held_for_factory_reset(&button, &mut oled) {
+    log::warn!("Button held at power-on, erasing all settings");
+    // the WiFi driver reads its settings from the storage being erased
+    let _ = splash.wait(&mut oled, wifi_task);
+    config::factory_reset(non_volatile_storage.clone())?;
+    oled.render(|display| {
+      display.clear(BinaryColor::Off).unwrap();
+      typography::draw_centered(
+        display,
+        "Settings erased",
+        24,
+        Font::Medium.style(),
+      );
+      typography::draw_centered(
+        display,
+        "Restarting...",
+        40,
+        Font::Small.style(),
+      );
+    });
+    FreeRtos::delay_ms(1500);
+    esp_idf_hal::reset::restart();
+  }
+
+  let mut led = PinDriver::output(gpio(pins.led))?;
+  #[cfg(feature = "buzzer")]
+  let buzzer = buzzer::Buzzer::new(
+    PinDriver::output(gpio(pins.buzzer))?,
+    Arc::clone(&config),
+  );
 
-  let mut motion_sensor = PinDriver::input(peripherals.pins.gpio15)?;
-  motion_sensor
-    .set_interrupt_type(esp_idf_hal::gpio::InterruptType::AnyEdge)?;
+  #[cfg(feature = "pir")]
+  let mut motion_sensor = PinDriver::input(gpio(pins.pir))?;
+  #[cfg(feature = "pir")]
+  motion_sensor.set_interrupt_type(InterruptType::AnyEdge)?;
+  #[cfg(feature = "servo")]
   let timer_driver = LedcTimerDriver::new(
     peripherals.ledc.timer0,
     &TimerConfig::default()
@@ -93,264 +288,903 @@ fn main() -> anyhow::Result<()> {
   .unwrap();
 
   // Configure and Initialize LEDC Driver
-  let mut driver = LedcDriver::new(
-    peripherals.ledc.channel0,
-    timer_driver,
-    peripherals.pins.gpio4,
-  )
-  .unwrap();
-  let text_style_settings = MonoTextStyleBuilder::new()
-    .font(&embedded_graphics::mono_font::ascii::FONT_7X13)
-    .text_color(BinaryColor::On)
-    .build();
-
-  display.init().unwrap();
-  boot_screen(&mut display, text_style_settings);
-  let mut wifi = BlockingWifi::wrap(
-    EspWifi::new(
-      peripherals.modem,
-      system_event_loop.clone(),
-      Some(non_volatile_storage),
-    )?,
-    system_event_loop,
-  )?;
-  wifi.set_configuration(&Configuration::Client(ClientConfiguration {
-    ssid: "A 403".try_into().unwrap(),
-    bssid: None,
-    auth_method: AuthMethod::None,
-    password: "38YZ5VQF".try_into().unwrap(),
-    channel: None,
+  #[cfg(feature = "servo")]
+  let servo = Arc::new(Mutex::new(
+    LedcDriver::new(peripherals.ledc.channel0, timer_driver, gpio(pins.servo))
+      .unwrap(),
+  ));
+  #[cfg(feature = "relay")]
+  let relay = Arc::new(Mutex::new(PinDriver::output(gpio(pins.relay))?));
+  let text_style_settings = Font::Large.style();
+
+  splash.start(Stage::Display);
+  let display_ok = splash.show(&mut oled);
+  splash.finish(Stage::Display, display_ok);
+  let mut wifi = splash.wait(&mut oled, wifi_task)?;
+
+  if diagnostics_requested {
+    log::info!("Button held at power-on, running self-test");
+    let mut checks = Vec::new();
+    diagnostics::draw_report(&mut oled, &checks, Some("I2C"));
+    checks.push(diagnostics::check_i2c(&I2cDevices::detect(oled.scan())));
+    checks.push(diagnostics::check_display(&mut oled));
+    diagnostics::draw_report(&mut oled, &checks, Some("LED"));
+    checks.push(diagnostics::check_led(&mut led));
+    #[cfg(feature = "buzzer")]
+    {
+      diagnostics::draw_report(&mut oled, &checks, Some("Buzzer"));
+      checks.push(diagnostics::check_buzzer(buzzer.pin()));
+    }
+    #[cfg(feature = "servo")]
+    {
+      diagnostics::draw_report(&mut oled, &checks, Some("Servo"));
+      checks.push(diagnostics::check_servo(&mut servo.lock().unwrap()));
+    }
+    #[cfg(feature = "pir")]
+    {
+      diagnostics::draw_report(&mut oled, &checks, Some("PIR"));
+      checks.push(diagnostics::check_pir(&motion_sensor));
+    }
+    diagnostics::draw_report(&mut oled, &checks, Some("WiFi"));
+    checks.push(diagnostics::check_wifi(&mut wifi));
+    diagnostics::draw_report(&mut oled, &checks, None);
+    // the only way back in once the web server has been switched off
+    let mut settings = config.lock().unwrap();
+    if !settings.server.enabled {
+      settings.server.enabled = true;
+      match settings.save(non_volatile_storage.clone()) {
+        Ok(()) => log::warn!("Web server re-enabled"),
+        Err(error) => log::warn!("Could not re-enable the server: {:?}", error),
+      }
+    }
+    drop(settings);
+
+    // Keep the report on screen until the button held at power-on has been
+    // released and then pressed again
+    while button.is_low() {
+      FreeRtos::delay_ms(20);
+    }
+    while button.is_high() {
+      FreeRtos::delay_ms(20);
+    }
+    while button.is_low() {
+      FreeRtos::delay_ms(20);
+    }
+    splash.show(&mut oled);
+  }
+
+  let wifi: SharedWifi = Arc::new(Mutex::new(wifi));
+  let uplink = network::Uplink {
+    wifi: Arc::clone(&wifi),
+    #[cfg(feature = "ethernet")]
+    ethernet,
+  };
+  let state: SharedState = Arc::new(Mutex::new(DeviceState {
+    i2c: i2c_devices,
     ..Default::default()
-  }))?;
-
-  wifi.start()?;
-  wifi.connect()?;
-
-  wifi.wait_netif_up()?;
-
-  log::info!("Connected to WiFi!");
-
-  // get weather from API
-  let weather_json = get_weather("https://api.weatherapi.com/v1/current.json?key=2b6e79acb58f407bba4125239250411&q=18.555917,73.764256")?;
-  let parsed: serde_json::Value = serde_json::from_str(&weather_json)?;
-  let temp = parsed["current"]["temp_c"].as_f64().unwrap();
-  let weather_condition = parsed["current"]["condition"]["text"]
-    .as_str()
-    .unwrap_or("Unknown");
-  let humidity = parsed["current"]["humidity"].as_u64().unwrap_or(0);
-
-  let ntp = EspSntp::new_default().unwrap();
-
-  println!("Synchronizing with NTP Server");
-  while ntp.get_sync_status() != esp_idf_svc::sntp::SyncStatus::Completed {}
-
-  let mut http_server = EspHttpServer::new(&HttpServerConfig::default())?;
-  http_server.fn_handler(
-    "/",
-    Method::Get,
-    |request| -> Result<(), anyhow::Error> {
-      let html = index_html();
-      let mut response = request.into_ok_response()?;
-      response.write(html.as_bytes())?;
-      Ok(())
+  }));
+  // started before joining the network, for when that is what fails
+  let uart = UartDriver::new(
+    peripherals.uart0,
+    gpio(1),
+    gpio(3),
+    Option::<AnyIOPin>::None,
+    Option::<AnyIOPin>::None,
+    &UartConfig::default().baudrate(Hertz(115_200)),
+  )?;
+  let session: SharedSession = Arc::default();
+  let display_bench: SharedBench = Arc::default();
+  console::spawn(
+    uart,
+    console::Context {
+      config: Arc::clone(&config),
+      state: Arc::clone(&state),
+      wifi: Arc::clone(&wifi),
+      nvs: non_volatile_storage.clone(),
+      session: Arc::clone(&session),
+      bench: Arc::clone(&display_bench),
+      #[cfg(feature = "servo")]
+      servo: Arc::clone(&servo),
     },
   )?;
-  let buzzer_clone = Arc::clone(&buzzer);
-  http_server.fn_handler(
-    "/buzz",
-    Method::Get,
-    move |request| -> Result<(), anyhow::Error> {
-      let html = buzz_html();
-      let mut response = request.into_ok_response()?;
-      {
-        let mut buzzer_lock = buzzer_clone.lock().unwrap();
-        buzzer_lock.set_high().unwrap();
-      }
-      FreeRtos::delay_ms(200);
+
+  let syslog = config.lock().unwrap().syslog.clone();
+  if !syslog.collector.is_empty() {
+    let level = syslog.level_filter().unwrap_or(log::LevelFilter::Warn);
+    match syslog::Syslog::new(&syslog.collector, level, hostname::get()) {
+      Ok(syslog) => logger::attach(Box::new(syslog)),
+      Err(error) => log::warn!("Syslog forwarding disabled: {:?}", error),
+    }
+  }
+  // the screens come up without waiting for the network
+  let online: Online = Arc::default();
+  network::spawn(
+    uplink.clone(),
+    Arc::clone(&online),
+    Arc::clone(&config),
+    Arc::clone(&state),
+    non_volatile_storage.clone(),
+  )?;
+  network::spawn_roaming(
+    uplink.clone(),
+    Arc::clone(&config),
+    Arc::clone(&online),
+    non_volatile_storage.clone(),
+  )?;
+  network::spawn_setup_ap(
+    uplink.clone(),
+    Arc::clone(&config),
+    Arc::clone(&state),
+  )?;
+
+  let notifications: SharedNotifications =
+    Arc::new(Mutex::new(Notifications::default()));
+  let ota_progress: SharedProgress = Arc::default();
+  let history: SharedHistory = Arc::default();
+  #[cfg(debug_assertions)]
+  let overrides: testing::SharedOverrides = Arc::default();
+  let spotify_link: spotify::SharedLink = Arc::default();
+  // the track is only polled while someone can see it
+  let now_playing_open = Arc::new(AtomicBool::new(false));
+  // addresses are read and the checks run only while the screen is open
+  let network_open = Arc::new(AtomicBool::new(false));
+  network::spawn_checks(
+    uplink.clone(),
+    Arc::clone(&state),
+    Arc::clone(&network_open),
+  )?;
+  let certs = EspCustomNvsPartition::take(certs::PARTITION)
+    .map_err(|error| {
+      log::warn!(
+        "No certificate partition, HTTPS and pinning are off: {:?}",
+        error
+      )
+    })
+    .ok();
+  if let Some(certs) = &certs {
+    if let Err(error) = tls_pins::load(certs.clone()) {
+      log::warn!("Pinned certificates not loaded: {:?}", error);
+    }
+  }
+  proxy::init(&config.lock().unwrap().proxy);
+  let sources: Vec<Box<dyn fetch::Source>> = vec![
+    Box::new(weather::Source::new(
+      Arc::clone(&state),
+      Arc::clone(&history),
+      Arc::clone(&notifications),
+      non_volatile_storage.clone(),
+    )),
+    Box::new(crypto::Source::new(Arc::clone(&state))),
+    Box::new(stocks::Source::new(Arc::clone(&state))),
+    Box::new(feed::Source::new(Arc::clone(&state))),
+    Box::new(quote::Source::new(
+      Arc::clone(&state),
+      non_volatile_storage.clone(),
+    )),
+    Box::new(spotify::Source::new(
+      Arc::clone(&state),
+      Arc::clone(&spotify_link),
+      Arc::clone(&now_playing_open),
+      non_volatile_storage.clone(),
+    )),
+  ];
+  fetch::spawn(sources, Arc::clone(&config), Arc::clone(&online))?;
+  let (events, event_receiver) = events::bus();
+  let led_pattern: SharedLed = Arc::default();
+  let display_mode: SharedDisplayMode = Arc::default();
+  let outputs = automation::Outputs {
+    notifications: Arc::clone(&notifications),
+    led: Arc::clone(&led_pattern),
+    #[cfg(feature = "buzzer")]
+    buzzer: buzzer.clone(),
+    #[cfg(feature = "servo")]
+    servo: Arc::clone(&servo),
+    #[cfg(feature = "relay")]
+    relay: Arc::clone(&relay),
+    display: Arc::clone(&display_mode),
+    state: Arc::clone(&state),
+    events: events.clone(),
+  };
+  let group_options = config.lock().unwrap().group.clone();
+  let group = group::start(&group_options, events.clone())
+    .map_err(|error| log::warn!("Could not join the group: {:?}", error))
+    .ok()
+    .flatten();
+  automation::spawn(
+    event_receiver,
+    Arc::clone(&config),
+    outputs.clone(),
+    group,
+  )?;
+  presence::spawn(Arc::clone(&config), Arc::clone(&state), events.clone())?;
+  #[cfg(feature = "door")]
+  {
+    let mut door_switch = PinDriver::input(gpio(pins.door))?;
+    door_switch.set_pull(esp_idf_hal::gpio::Pull::Up)?;
+    door::spawn(door_switch, Arc::clone(&config), outputs.clone())?;
+  }
+
+  splash.start(Stage::Server);
+  splash.show(&mut oled);
+  let server_enabled = config.lock().unwrap().server.enabled;
+  let http_server = server_enabled.then(|| {
+    web::start(web::Context {
+      state: Arc::clone(&state),
+      #[cfg(feature = "buzzer")]
+      buzzer: buzzer.clone(),
+      #[cfg(feature = "servo")]
+      servo: Arc::clone(&servo),
+      wifi: Arc::clone(&wifi),
+      uplink: uplink.clone(),
+      config: Arc::clone(&config),
+      notifications: Arc::clone(&notifications),
+      ota: Arc::clone(&ota_progress),
+      nvs: non_volatile_storage.clone(),
+      certs,
+      spotify: Arc::clone(&spotify_link),
+      led: Arc::clone(&led_pattern),
+      #[cfg(feature = "relay")]
+      relay: Arc::clone(&relay),
+      display_mode: Arc::clone(&display_mode),
+      events: events.clone(),
+      history: Arc::clone(&history),
+      bench: Arc::clone(&display_bench),
+      #[cfg(debug_assertions)]
+      overrides: Arc::clone(&overrides),
+    })
+  });
+  if !server_enabled {
+    log::warn!("Web server disabled in the settings");
+  }
+  if config.lock().unwrap().discovery.enabled {
+    let discovery_uplink = uplink.clone();
+    if let Err(error) = discovery::spawn(
+      hostname::get().to_string(),
+      capabilities(server_enabled),
+      move || discovery_uplink.ip(),
+    ) {
+      log::warn!("Discovery not answered: {:?}", error);
+    }
+  }
+  splash.finish(
+    Stage::Server,
+    http_server.as_ref().map_or(true, Result::is_ok),
+  );
+  splash.show(&mut oled);
+  log::info!("Booted in {} ms", boot_started.elapsed().as_millis());
+  let mut http_server = http_server.transpose()?;
+  // the description is on the web server, no use announcing without it
+  if let Some(server) = http_server.as_ref() {
+    if config.lock().unwrap().discovery.ssdp {
+      let device = ssdp::Device::new(
+        hostname::get().to_string(),
+        env!("CARGO_PKG_VERSION"),
+        hostname::mac(),
+      );
+      let ssdp_uplink = uplink.clone();
+      if let Err(error) =
+        ssdp::spawn(device, server.scheme(), move || ssdp_uplink.ip())
       {
-        let mut buzzer_lock = buzzer_clone.lock().unwrap();
-        buzzer_lock.set_low().unwrap();
+        log::warn!("Not announced over SSDP: {:?}", error);
       }
-      response.write(html.as_bytes())?;
-      Ok(())
-    },
-  )?;
+    }
+  }
+  // kept for as long as the main loop runs, dropping it stops answering
+  let _mdns = if config.lock().unwrap().discovery.enabled {
+    hostname::advertise(http_server.as_ref().map(|server| server.scheme()))
+      .map_err(|error| log::warn!("Not announced over mDNS: {:?}", error))
+      .ok()
+  } else {
+    None
+  };
   // Give servo some time to update
+  #[cfg(feature = "servo")]
   FreeRtos::delay_ms(500);
   // Loop to Avoid Program Termination
   let mut ui_state = UiState::Home;
 
   // Button handling states
   let mut option_index: u8 = 0;
-  let mut btn_down = false; // debounced current state
-  let mut btn_raw_last = false; // last raw read
-  let mut btn_changed_at = Instant::now(); // debounce timer
-  let mut btn_pressed_at = Instant::now(); // press start time
-  let mut long_fired = false; // long press fired once
-  let mut motion_detected = false;
+  // entries skipped on Logs/Notifications, selected entry on Profiles,
+  // Games and Headlines
+  let mut list_offset: usize = 0;
+  let mut saved_profiles = Profiles::default(); // read on entering Profiles
+  let mut history_metric = Metric::Temperature; // graph on the History screen
+  let mut presses = ui::Button::new(Instant::now()); // debounced button
+  let mut last_motion_at: Option<Instant> = None;
+  let mut monitor = alerts::Monitor::default(); // thresholds in the settings
+  let mut last_ota_stage = ota::Stage::Idle;
+  let mut last_minute = Line::new(); // for the rules on the time of day
+  let mut state_updated_at = Instant::now();
+  let mut frame_started_at = Instant::now(); // for the debug overlay
+  let mut last_ui_state = ui_state;
+  let mut pager = Pager::default(); // page of Home, Status and the tickers
+  let mut brightness_draft: Option<u8> = None; // Settings edit, not yet saved
+  let (mut snake_best, saved_best) =
+    Persisted::<u32>::load(non_volatile_storage.clone(), "snake_best");
+  let mut snake = snake::Game::new(saved_best.unwrap_or(0)); // new on entering
+  let mut redraw = Redraw::default(); // the panel keeps the last frame
+  let mut shown_options = DisplayOptions::default();
+  let (mut banner_shown, mut shown_pending) = (false, 0);
+  // Settings and Exit, drawn once for the brightness shown
+  let mut static_screen = Prerendered::<(UiState, Option<u8>)>::default();
 
-  const DEBOUNCE_MS: u64 = 30;
-  const LONG_PRESS_MS: u64 = 1600;
+  let (mut ui_memory, restored) =
+    Persisted::<UiMemory>::load(non_volatile_storage.clone(), "ui");
+  if let Some(restored) = restored {
+    log::info!("Restoring {:?}", restored);
+    // differs from last_ui_state, so entering the screen runs as usual
+    ui_state = restored.screen;
+    option_index = restored.option_index % UiState::Menu.menu_len() as u8;
+    history_metric = restored.history_metric;
+  }
+
+  // Presses and motion wake the loop from its sleep
+  let wake = Notification::new();
+  button.set_interrupt_type(InterruptType::AnyEdge)?;
+  let notifier = wake.notifier();
+  unsafe {
+    button.subscribe(move || {
+      notifier.notify_and_yield(NonZeroU32::MIN);
+    })?;
+  }
+  #[cfg(feature = "pir")]
+  {
+    let notifier = wake.notifier();
+    unsafe {
+      motion_sensor.subscribe(move || {
+        notifier.notify_and_yield(NonZeroU32::MIN);
+      })?;
+    }
+  }
 
   loop {
-    let st_now = std::time::SystemTime::now();
-    // Convert to IST
-    let local_date_now: DateTime<Local> = st_now.into();
+    // the benchmark has the display to itself for a few seconds
+    let bench_requested = display_bench.lock().unwrap().requested;
+    if bench_requested {
+      let runs = bench::run(&mut oled);
+      let mut bench = display_bench.lock().unwrap();
+      bench.results = Some(runs);
+      bench.requested = false;
+      redraw.mark();
+    }
+    let frame_span = perf::span(perf::Span::Loop);
+    // Switching the server off from its own settings page stops it here,
+    // switching it back on takes the self-test at power-on
+    if http_server.is_some() && !config.lock().unwrap().server.enabled {
+      http_server = None;
+      log::warn!("Web server stopped");
+    }
+    // Settings saved from the web page take effect on the next frame
+    let (mut display_options, quiet_hours) = {
+      let config = config.lock().unwrap();
+      (config.display.clone(), config.quiet_hours.clone())
+    };
+    redraw.watch(&mut shown_options, &display_options);
+    let quiet = quiet_hours.active_now();
+    notifications
+      .lock()
+      .unwrap()
+      .set_quiet(quiet && quiet_hours.hold_notifications);
+    let mode = *display_mode.lock().unwrap();
+    if let Some(brightness) = brightness_draft {
+      display_options.brightness = brightness;
+    } else if mode == DisplayMode::Dim || (quiet && quiet_hours.dim_display) {
+      display_options.brightness = 0;
+    }
+    apply_display_options(&mut oled, &display_options);
+    oled.set_display_on(mode != DisplayMode::Off);
+
+    // an end-to-end test may have stopped the clock
+    let local_date_now = clock::local_now();
     // Format Time String having date and time
-    let formatted_time = local_date_now.format("%d/%m %H:%M").to_string();
+    let (time_format, clock_format) = if display_options.clock_24h {
+      ("%d/%m %H:%M", "%H:%M")
+    } else {
+      ("%d/%m %I:%M%p", "%I:%M%p")
+    };
+    let formatted_time =
+      typography::line(format_args!("{}", local_date_now.format(time_format)));
+    let minute =
+      typography::line(format_args!("{}", local_date_now.format("%H:%M")));
+    if minute != last_minute && local_date_now.year() >= 2024 {
+      events.publish(Event::At(minute.to_string()));
+      last_minute = minute;
+    }
 
-    // Read raw button
-    let raw = button.is_low();
+    // Debounced presses from the raw button
     let now = Instant::now();
-
-    // Debounce
-    if raw != btn_raw_last {
-      btn_raw_last = raw;
-      btn_changed_at = now;
-    }
-    let stable =
-      now.duration_since(btn_changed_at) >= Duration::from_millis(DEBOUNCE_MS);
-
-    // Edge detection on stable transitions
-    if stable {
-      // Rising edge (pressed)
-      if raw && !btn_down {
-        btn_down = true;
-        btn_pressed_at = now;
-        long_fired = false;
+    let pressed = presses.update(button.is_low(), now);
+    #[cfg(debug_assertions)]
+    let pressed = pressed.or_else(|| overrides.lock().unwrap().take_press());
+    let mut inputs = session.lock().unwrap();
+    if inputs.take_restart() {
+      // recordings start from Home, so a replay goes the same way
+      ui_state = UiState::Home;
+      last_ui_state = ui_state;
+      option_index = 0;
+      list_offset = 0;
+      brightness_draft = None;
+      pager.reset();
+      redraw.mark();
+    }
+    let pressed = inputs.press(pressed, now);
+    drop(inputs);
+    if pressed.is_some() {
+      redraw.mark();
+    }
+    match pressed {
+      Some(ButtonEvent::Down) => {
+        // a press on a display a scene turned off only wakes it
+        let mut mode = display_mode.lock().unwrap();
+        if *mode == DisplayMode::Off {
+          *mode = DisplayMode::Normal;
+          presses.ignore_press();
+        }
       }
-
-      // Long press while held
-      if btn_down
-        && !long_fired
-        && now.duration_since(btn_pressed_at)
-          >= Duration::from_millis(LONG_PRESS_MS)
-      {
-        long_fired = true;
+      Some(ButtonEvent::Long) => {
+        // Long press on Settings keeps the edited brightness
+        if ui_state == UiState::Settings {
+          if let Some(brightness) = brightness_draft {
+            save_brightness(&config, &non_volatile_storage, brightness);
+          }
+        }
+        // Long press on Profiles switches to the selected one
+        if ui_state == UiState::Profiles {
+          if let Some(profile) = saved_profiles.list.get(list_offset) {
+            activate_profile(
+              &profile.name,
+              &config,
+              &wifi,
+              &notifications,
+              &non_volatile_storage,
+            );
+          }
+        }
+        // Long press on Scenes sets the selected one
+        if ui_state == UiState::Scenes {
+          let config = config.lock().unwrap().clone();
+          if let Some(scene) = config.automation.scenes.get(list_offset) {
+            if let Err(error) = scene::activate(&scene.name, &config, &outputs)
+            {
+              log::error!("Could not set scene: {:?}", error);
+            }
+          }
+        }
         // Selection or navigation on long press
-        handle_long_press(&mut ui_state, option_index);
+        ui::handle_long_press(&mut ui_state, option_index, list_offset);
       }
-
-      // Falling edge (released)
-      if !raw && btn_down {
-        btn_down = false;
-        // Short press actions (only if long didn't fire)
-        if !long_fired {
-          handle_short_press(&mut ui_state, &mut option_index);
+      Some(ButtonEvent::Short) if ui_state == UiState::Snake => snake.press(),
+      // short press on History flips between the graphs
+      Some(ButtonEvent::Short) if ui_state == UiState::History => {
+        history_metric = history_metric.next();
+      }
+      Some(ButtonEvent::Short) => {
+        let list_len = match ui_state {
+          UiState::Logs => logger::len(),
+          UiState::Notifications => {
+            notifications.lock().unwrap().history().count()
+          }
+          UiState::Profiles => saved_profiles.list.len(),
+          UiState::Scenes => config.lock().unwrap().automation.scenes.len(),
+          UiState::Games => UiState::Games.menu_len(),
+          UiState::Headlines => state.lock().unwrap().headlines.len(),
+          _ => 0,
+        };
+        if let Some(paging) = ui::handle_short_press(
+          &mut ui_state,
+          &mut option_index,
+          &mut list_offset,
+          list_len,
+          &mut brightness_draft,
+        ) {
+          pager.turn(paging);
         }
       }
+      None => {}
     }
 
-    // LED reflects button state (pressed -> low)
-    handle_led(&mut led, btn_down);
-    // Render by state
-
-    match ui_state {
-      UiState::Home => {
-        display.clear(BinaryColor::Off).unwrap();
-        home_screen(&mut display, text_style_settings, formatted_time.as_str());
+    // PIR output is high while it sees motion
+    let sensor_span = perf::span(perf::Span::SensorPoll);
+    #[cfg(feature = "pir")]
+    let motion_detected = motion_sensor.is_high();
+    #[cfg(not(feature = "pir"))]
+    let motion_detected = false;
+    drop(sensor_span);
+    #[cfg(debug_assertions)]
+    let motion_detected = overrides
+      .lock()
+      .unwrap()
+      .sensors
+      .motion
+      .unwrap_or(motion_detected);
+    let motion_detected = session.lock().unwrap().motion(motion_detected, now);
+    if motion_detected {
+      let burst_start = !matches!(
+        last_motion_at,
+        Some(at) if now.duration_since(at) < MOTION_NOTIFY_GAP
+      );
+      if burst_start {
+        events.publish(Event::Motion);
       }
-      UiState::Menu => {
-        // Avoid flicker: only redraw when not holding the button
-        if !btn_down {
-          display.clear(BinaryColor::Off).unwrap();
-          match option_index {
-            0 => {
-              menu_screen(&mut display, text_style_settings, true, false, false)
-            }
-            1 => {
-              menu_screen(&mut display, text_style_settings, false, true, false)
-            }
-            2 => {
-              menu_screen(&mut display, text_style_settings, false, false, true)
-            }
-            _ => unreachable!(),
-          }
-          display.flush().unwrap();
-        }
+      let options = config.lock().unwrap().notifications.clone();
+      if burst_start && options.motion {
+        notifications.lock().unwrap().push(
+          "Motion detected",
+          options.duration(),
+          notify::Priority::Normal,
+        );
       }
-      UiState::Settings => {
-        display.clear(BinaryColor::Off).unwrap();
-        draw_settings_screen(&mut display, text_style_settings);
+      last_motion_at = Some(now);
+      if local_date_now.year() >= 2024 {
+        history
+          .lock()
+          .unwrap()
+          .record_motion(local_date_now.naive_local());
       }
-      UiState::Status => {
-        display.clear(BinaryColor::Off).unwrap();
-        draw_status_screen(
-          &mut display,
-          text_style_settings,
-          temp,
-          weather_condition,
-          humidity,
-          formatted_time.as_str(),
+    }
+
+    if ui_state != last_ui_state {
+      last_ui_state = ui_state;
+      session.lock().unwrap().screen(ui_state, now);
+      pager.reset();
+      redraw.mark();
+      list_offset = 0;
+      // leaving Settings without a long press drops the edit
+      brightness_draft =
+        (ui_state == UiState::Settings).then_some(display_options.brightness);
+      if ui_state == UiState::Profiles {
+        saved_profiles = Profiles::load(non_volatile_storage.clone())
+          .unwrap_or_else(|error| {
+            log::warn!("Could not load profiles: {:?}", error);
+            Profiles::default()
+          });
+      }
+      if ui_state == UiState::Snake {
+        snake = snake::Game::new(snake.high_score());
+      }
+    }
+    match session.lock().unwrap().finished() {
+      Some(Ok(())) => log::info!("Replay went through the recorded screens"),
+      Some(Err(mismatch)) => log::warn!("Replay differed: {}", mismatch),
+      None => {}
+    }
+    if ui_state == UiState::Snake {
+      snake.tick(now);
+    }
+    snake_best.update(&snake.high_score());
+
+    ui_memory.update(&UiMemory {
+      screen: ui_state,
+      option_index,
+      history_metric,
+    });
+
+    // LED reflects button state (pressed -> low)
+    handle_led(&mut led, presses.is_down(), &led_pattern);
+    if ui_state == UiState::Headlines {
+      state.lock().unwrap().unread_headlines = 0;
+    }
+    now_playing_open.store(ui_state == UiState::NowPlaying, Ordering::Relaxed);
+    network_open.store(ui_state == UiState::Network, Ordering::Relaxed);
+    #[cfg(debug_assertions)]
+    {
+      let mut overrides = overrides.lock().unwrap();
+      overrides.screen = Some(ui_state);
+      overrides.apply(&mut state.lock().unwrap());
+    }
+    let device_state = state.lock().unwrap().clone();
+    let (message, pending) = {
+      let mut notifications = notifications.lock().unwrap();
+      (notifications.current().cloned(), notifications.count())
+    };
+    let status_bar = StatusBar {
+      time: typography::line(format_args!(
+        "{}",
+        local_date_now.format(clock_format)
+      )),
+      synced: local_date_now.year() >= 2024,
+      rssi: device_state.rssi,
+      // no battery monitor on this board yet
+      battery_percent: None,
+      notifications: pending,
+      unread_headlines: device_state.unread_headlines,
+      title: titlebar::Title::for_screen(ui_state),
+    };
+    let update = ota_progress.lock().unwrap().clone();
+    if update.stage != last_ota_stage {
+      last_ota_stage = update.stage;
+      redraw.mark();
+      let failure = match update.stage {
+        ota::Stage::Failed => Some("Firmware update failed".to_string()),
+        ota::Stage::Rejected => Some(format!(
+          "Update rejected, {}",
+          update.error.as_deref().unwrap_or("bad signature")
+        )),
+        _ => None,
+      };
+      if let Some(failure) = failure {
+        notifications.lock().unwrap().push(
+          &failure,
+          config.lock().unwrap().notifications.duration(),
+          notify::Priority::High,
         );
       }
-      UiState::Exit => {
+    }
+    // the clock and the data behind the screens move on once a second
+    let second_passed =
+      now.duration_since(state_updated_at) >= Duration::from_secs(1);
+    let moving = ui_state.is_animated()
+      // a banner may scroll, a debug overlay counts frames
+      || message.is_some()
+      || display_options.debug_overlay
+      || update.in_progress();
+    if second_passed || moving || (ui_state.has_pages() && pager.is_due()) {
+      redraw.mark();
+    }
+    redraw.watch(&mut banner_shown, &message.is_some());
+    redraw.watch(&mut shown_pending, &pending);
+    // Render by state, only when something on screen changed
+    let (draw_time, flush_time) = oled.timings();
+    let frame_stats = overlay::FrameStats {
+      frame: now.duration_since(frame_started_at),
+      draw: draw_time,
+      flush: flush_time,
+      free_heap: state::free_heap(),
+    };
+    frame_started_at = now;
+    if redraw.take() {
+      let flushed = oled.render(|display| {
+        // A firmware update takes over the display until the device restarts
+        if update.in_progress() || update.stage == ota::Stage::Done {
+          ota::draw_progress(display, &update);
+          return;
+        }
         display.clear(BinaryColor::Off).unwrap();
-        draw_exit_screen(&mut display, text_style_settings);
+        match ui_state {
+          UiState::Home => {
+            if let Some(setup) = &device_state.setup_ap {
+              screens::setup_ap_screen(
+                display,
+                &setup.ssid,
+                &setup.password,
+                &setup.address,
+              );
+            } else {
+              // the clock, and the quote of the day in turn with it
+              let pages = 1 + usize::from(device_state.quote.is_some());
+              let page = pager.page(pages);
+              match &device_state.quote {
+                Some(quote) if page == 1 => quote::draw(display, quote),
+                _ => screens::home_screen(
+                  display,
+                  text_style_settings,
+                  formatted_time.as_str(),
+                ),
+              }
+              pager::draw_dots(display, page, pages);
+            }
+          }
+          UiState::Menu => {
+            screens::menu_screen(display, option_index as usize);
+          }
+          _ => screens::draw(
+            display,
+            &mut screens::Context {
+              screen: ui_state,
+              time: formatted_time.as_str(),
+              list_offset,
+              brightness: display_options.brightness,
+              pager: &mut pager,
+              snake: &mut snake,
+              static_screen: &mut static_screen,
+              device: screens::Device {
+                state: &device_state,
+                config: &config,
+                history: &history,
+                history_metric,
+                notifications: &notifications,
+                profiles: &saved_profiles,
+                now,
+                local_now: local_date_now,
+                synced: status_bar.synced,
+              },
+            },
+          ),
+        }
+        statusbar::draw(display, &status_bar);
+        // Messages from the web API overlay whatever screen is active
+        if let Some(message) = &message {
+          notify::draw_banner(display, message);
+        }
+        if display_options.debug_overlay {
+          overlay::draw(display, &frame_stats);
+        }
+      });
+      if !flushed {
+        // the frame didn't reach the panel, tried again on the next one
+        redraw.mark();
       }
     }
 
-    FreeRtos::delay_ms(20);
-  }
-}
+    // Refresh the snapshot served to the web dashboard
+    if second_passed {
+      state_updated_at = now;
+      let (thresholds, notify_for) = {
+        let config = config.lock().unwrap();
+        (
+          config.automation.thresholds.clone(),
+          config.notifications.duration(),
+        )
+      };
+      monitor.check(
+        &thresholds,
+        &device_state,
+        &events,
+        &notifications,
+        notify_for,
+      );
+      let mut snapshot = state.lock().unwrap();
+      snapshot.alerts = monitor.raised();
+      snapshot.time = formatted_time.to_string();
+      snapshot.motion = motion_detected;
+      snapshot.last_motion_s =
+        last_motion_at.map(|at| now.duration_since(at).as_secs());
+      // the WiFi page may be switching networks, keep the old value then
+      if let Ok(mut wifi) = wifi.try_lock() {
+        let info = wifi.wifi_mut().get_ap_info().ok();
+        snapshot.rssi = info.as_ref().map(|info| info.signal_strength.into());
+        snapshot.access_point = info.map(|info| state::AccessPoint {
+          ssid: info.ssid.to_string(),
+          bssid: roaming::format_bssid(&info.bssid),
+          channel: info.channel,
+        });
+      }
+      snapshot.free_heap = state::free_heap();
+      snapshot.uptime_s = state::uptime_s();
+    }
 
-fn boot_screen(
-  display: &mut Ssd1306<
-    I2CInterface<I2cDriver<'_>>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
-  >,
-  text_style_settings: embedded_graphics::mono_font::MonoTextStyle<
-    '_,
-    BinaryColor,
-  >,
-) {
-  display.clear(BinaryColor::Off).unwrap();
+    #[cfg(feature = "log-flash")]
+    if let Err(error) = flash_log.sync() {
+      log::error!("Could not save logs to flash: {:?}", error);
+    }
 
-  Text::with_baseline(
-    "pippo is booting...",
-    Point::new(30, 3),
-    text_style_settings,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
+    drop(frame_span);
+    // Sleep until the soonest of what is pending, a press or motion wakes
+    // the loop before that
+    let mut schedule = tick::Schedule::new(now);
+    if moving || redraw.is_pending() || session.lock().unwrap().is_replaying() {
+      schedule.frame();
+    }
+    if let Some(deadline) = presses.deadline() {
+      schedule.at(deadline);
+    }
+    if ui_state.has_pages() {
+      schedule.at(pager.due_at());
+    }
+    if let Some((pattern, at)) = *led_pattern.lock().unwrap() {
+      if let Some(after) = pattern.next_change(at.elapsed()) {
+        schedule.within(after);
+      }
+    }
+    schedule.at(state_updated_at + Duration::from_secs(1));
+    // the clock on screen turns over on the minute
+    schedule.within(Duration::from_secs(60).saturating_sub(Duration::new(
+      local_date_now.second().into(),
+      local_date_now.nanosecond(),
+    )));
+    // interrupts are switched off each time one fires
+    button.enable_interrupt()?;
+    #[cfg(feature = "pir")]
+    motion_sensor.enable_interrupt()?;
+    wake.wait(TickType::from(schedule.sleep(Instant::now())).ticks());
+  }
+}
 
-  display.flush().unwrap();
+fn apply_display_options(oled: &mut Oled, options: &DisplayOptions) {
+  oled.set_bus_khz(options.bus_khz);
+  oled.set_inverted(options.invert);
+  oled.set_brightness(options.brightness);
+  oled.set_rotation(if options.rotation == 180 {
+    DisplayRotation::Rotate180
+  } else {
+    DisplayRotation::Rotate0
+  });
 }
 
-fn handle_long_press(ui_state: &mut UiState, option_index: u8) {
-  match *ui_state {
-    UiState::Home => *ui_state = UiState::Menu, // long press from home opens menu
-    UiState::Menu => match option_index {
-      0 => *ui_state = UiState::Settings,
-      1 => *ui_state = UiState::Status,
-      2 => *ui_state = UiState::Exit,
-      _ => *ui_state = UiState::Menu,
-    },
-    // long press on any sub-screen returns to home
-    _ => *ui_state = UiState::Home,
-  };
+fn save_brightness(
+  config: &SharedConfig,
+  nvs: &EspDefaultNvsPartition,
+  brightness: u8,
+) {
+  let mut config = config.lock().unwrap();
+  config.display.brightness = brightness;
+  match config.save(nvs.clone()) {
+    Ok(()) => log::info!("Brightness set to {:?}", brightness),
+    Err(error) => log::error!("Could not save brightness: {:?}", error),
+  }
 }
 
-fn handle_short_press(ui_state: &mut UiState, option_index: &mut u8) {
-  match *ui_state {
-    UiState::Menu => {
-      *option_index = (*option_index + 1) % 3;
+fn activate_profile(
+  name: &str,
+  config: &SharedConfig,
+  wifi: &SharedWifi,
+  notifications: &SharedNotifications,
+  nvs: &EspDefaultNvsPartition,
+) {
+  match Profiles::activate(nvs.clone(), name, config, wifi) {
+    Ok(_) => {
+      notifications.lock().unwrap().push(
+        &format!("Profile {name}"),
+        config.lock().unwrap().notifications.duration(),
+        notify::Priority::Normal,
+      );
     }
-    UiState::Settings | UiState::Status | UiState::Exit => {
-      *option_index = 0;
-      *ui_state = UiState::Menu; // now actually updates
+    Err(error) => log::error!("Could not switch to profile: {:?}", error),
+  }
+}
+
+/// Counts down while the button stays held at power-on. True once it has
+/// been held for `FACTORY_RESET_HOLD`, false if it is let go before.
+fn held_for_factory_reset(
+  button: &PinDriver<'_, AnyIOPin, esp_idf_hal::gpio::Input>,
+  oled: &mut Oled,
+) -> bool {
+  let pressed_at = Instant::now();
+  while button.is_low() {
+    let held = pressed_at.elapsed();
+    if held >= FACTORY_RESET_HOLD {
+      return true;
     }
-    UiState::Home => {}
-  };
+    let remaining = (FACTORY_RESET_HOLD - held).as_secs() + 1;
+    oled.render(|display| {
+      display.clear(BinaryColor::Off).unwrap();
+      typography::draw_centered(
+        display,
+        "Factory reset in",
+        14,
+        Font::Medium.style(),
+      );
+      typography::draw_centered(
+        display,
+        &typography::line(format_args!("{remaining} s")),
+        28,
+        Font::Large.style(),
+      );
+      typography::draw_centered(
+        display,
+        "Release for self-test",
+        50,
+        Font::Small.style(),
+      );
+    });
+    FreeRtos::delay_ms(100);
+  }
+  false
+}
+
+/// Pin `number` from the pin settings
+fn gpio(number: u8) -> AnyIOPin {
+  // Safety: `peripherals.pins` is never used and the pin settings are
+  // checked to name each pin once, so every pin has a single owner
+  unsafe { AnyIOPin::new(i32::from(number)) }
 }
 
 fn handle_led(
-  led: &mut PinDriver<'_, esp_idf_hal::gpio::Gpio2, esp_idf_hal::gpio::Output>,
+  led: &mut PinDriver<'_, AnyIOPin, esp_idf_hal::gpio::Output>,
   btn_down: bool,
+  pattern: &SharedLed,
 ) {
-  if btn_down {
+  // a pattern from an automation rule plays until it is over
+  let lit = {
+    let mut pattern = pattern.lock().unwrap();
+    let lit = pattern.and_then(|(pattern, at)| pattern.lit(at.elapsed()));
+    if lit.is_none() {
+      *pattern = None;
+    }
+    lit.unwrap_or(false)
+  };
+  if btn_down || lit {
     led.set_high().unwrap();
   } else {
     led.set_low().unwrap();
@@ -359,291 +1193,25 @@ fn handle_led(
 
 fn initialize() {
   esp_idf_svc::sys::link_patches();
-  esp_idf_svc::log::EspLogger::initialize_default();
+  logger::initialize();
   log::info!("Initialization complete!");
 }
-fn home_screen(
-  display: &mut Ssd1306<
-    I2CInterface<I2cDriver<'_>>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
-  >,
-  text_style: embedded_graphics::mono_font::MonoTextStyle<'_, BinaryColor>,
-  formatted_time: &str,
-) {
-  Text::with_baseline(
-    formatted_time,
-    Point::new(1, 1),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  draw_wifi_icon(display);
-
-  // centered "Welcome!" text
-  let welcome_text = "Welcome!";
-  let text_width = welcome_text.len() as i32 * 6; // Approximate width per character
-  let x_position = (128 - text_width) / 2; // Center horizontally
-  let y_position = (64 - 8) / 2; // Center vertically (assuming 8px height)
-  Text::with_baseline(
-    welcome_text,
-    Point::new(x_position, y_position),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  display.flush().unwrap();
-}
-fn menu_screen(
-  display: &mut Ssd1306<
-    I2CInterface<I2cDriver<'_>>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
-  >,
-  text_style: embedded_graphics::mono_font::MonoTextStyle<'_, BinaryColor>,
-  settings_selected: bool,
-  status_selected: bool,
-  exit_selected: bool,
-) {
-  let settings_indicator = if settings_selected { "> " } else { " " };
-  let status_indicator = if status_selected { "> " } else { " " };
-  let exit_indicator = if exit_selected { "> " } else { " " };
-  let y_level = 15;
-  Text::with_baseline(
-    format!("{settings_indicator}Settings").as_str(),
-    Point::new(10, y_level),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  Text::with_baseline(
-    format!("{status_indicator}Status").as_str(),
-    Point::new(10, y_level + 8),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  Text::with_baseline(
-    format!("{exit_indicator}Exit").as_str(),
-    Point::new(10, y_level + 16),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  display.flush().unwrap();
-}
 
-fn draw_settings_screen(
-  display: &mut Ssd1306<
-    I2CInterface<I2cDriver<'_>>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
-  >,
-  text_style: embedded_graphics::mono_font::MonoTextStyle<'_, BinaryColor>,
-) {
-  Text::with_baseline(
-    "Settings",
-    Point::new(10, 10),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  Text::with_baseline(
-    "Short: Back",
-    Point::new(10, 26),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  Text::with_baseline(
-    "Long: Face",
-    Point::new(10, 34),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  display.flush().unwrap();
-}
-
-fn draw_status_screen(
-  display: &mut Ssd1306<
-    I2CInterface<I2cDriver<'_>>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
-  >,
-  text_style: embedded_graphics::mono_font::MonoTextStyle<'_, BinaryColor>,
-  temp: f64,
-  weather_condition: &str,
-  humidity: u64,
-  formatted: &str,
-) {
-  Text::with_baseline("Status", Point::new(10, 7), text_style, Baseline::Top)
-    .draw(display)
-    .unwrap();
-
-  Text::with_baseline(
-    format!("Temperature: {}°C", temp).as_str(),
-    Point::new(10, 26),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  Text::with_baseline(
-    format!("Condition: {}", weather_condition).as_str(),
-    Point::new(10, 34),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-
-  Text::with_baseline(
-    format!("Humidity: {}%", humidity).as_str(),
-    Point::new(10, 42),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  Text::with_baseline(
-    format!("Time: {}", formatted).as_str(),
-    Point::new(10, 50),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  display.flush().unwrap();
-}
-
-fn draw_exit_screen(
-  display: &mut Ssd1306<
-    I2CInterface<I2cDriver<'_>>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
-  >,
-  text_style: embedded_graphics::mono_font::MonoTextStyle<'_, BinaryColor>,
-) {
-  Text::with_baseline("Exit", Point::new(10, 10), text_style, Baseline::Top)
-    .draw(display)
-    .unwrap();
-  Text::with_baseline(
-    "Short: Back",
-    Point::new(10, 26),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  Text::with_baseline(
-    "Long: Face",
-    Point::new(10, 34),
-    text_style,
-    Baseline::Top,
-  )
-  .draw(display)
-  .unwrap();
-  display.flush().unwrap();
-}
-
-fn get_weather(api_url: &str) -> anyhow::Result<String> {
-  log::info!("Fetching weather data from API: {}", api_url);
-
-  let connection = EspHttpConnection::new(&HttpClientConfiguration {
-    use_global_ca_store: true,
-    crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
-    ..Default::default()
-  })?;
-  let mut client = Client::wrap(connection);
-
-  let headers = [("accept", "application/json")];
-  let request = client.request(Method::Get, api_url.as_ref(), &headers)?;
-
-  let response = request.submit()?;
-  let status = response.status();
-
-  println!("Response code: {}\n", status);
-  match status {
-    200..=299 => {
-      let mut buf = [0_u8; 512]; // Increased for larger JSON
-      let mut offset = 0;
-      let mut total = 0;
-      let mut reader = response;
-      let mut json_response = String::new(); // Accumulate response here
-
-      loop {
-        if let Ok(size) = Read::read(&mut reader, &mut buf[offset..]) {
-          if size == 0 {
-            break;
-          }
-          total += size;
-          let size_plus_offset = size + offset;
-          match str::from_utf8(&buf[..size_plus_offset]) {
-            Ok(text) => {
-              json_response.push_str(text); // Append to string
-              offset = 0;
-            }
-            Err(error) => {
-              let valid_up_to = error.valid_up_to();
-              unsafe {
-                json_response
-                  .push_str(str::from_utf8_unchecked(&buf[..valid_up_to]));
-              }
-              buf.copy_within(valid_up_to.., 0);
-              offset = size_plus_offset - valid_up_to;
-            }
-          }
-        }
-      }
-      log::info!("Total: {} bytes", total);
-      Ok(json_response) // Return the accumulated JSON
-    }
-    _ => {
-      anyhow::bail!("Request failed with status: {}", status)
-    }
+/// What this unit tells the others it can do when they look for it
+fn capabilities(server_enabled: bool) -> Vec<String> {
+  let mut capabilities = Vec::new();
+  if server_enabled {
+    capabilities.push("web");
   }
-}
-
-fn draw_wifi_icon(
-  display: &mut Ssd1306<
-    I2CInterface<I2cDriver<'_>>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsMode<DisplaySize128x64>,
-  >,
-) {
-  let style = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
-
-  // First line: (125, 0) to (120, 5)
-  Line::new(Point::new(125, 0), Point::new(120, 5))
-    .into_styled(style)
-    .draw(display)
-    .unwrap();
-
-  // Second line: (120, 5) to (125, 10)
-  Line::new(Point::new(120, 5), Point::new(125, 10))
-    .into_styled(style)
-    .draw(display)
-    .unwrap();
-
-  // Third line: (122, 0) to (122, 10)
-  Line::new(Point::new(122, 0), Point::new(122, 10))
-    .into_styled(style)
-    .draw(display)
-    .unwrap();
-}
-
-fn index_html() -> String {
-  include_str!("../web/index.html").to_string()
-}
-fn buzz_html() -> String {
-  include_str!("../web/buzz.html").to_string()
+  #[cfg(feature = "buzzer")]
+  capabilities.push("buzzer");
+  #[cfg(feature = "servo")]
+  capabilities.push("servo");
+  #[cfg(feature = "pir")]
+  capabilities.push("pir");
+  #[cfg(feature = "relay")]
+  capabilities.push("relay");
+  #[cfg(feature = "door")]
+  capabilities.push("door");
+  capabilities.into_iter().map(str::to_string).collect()
 }
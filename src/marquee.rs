@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+use embedded_graphics::{
+  mono_font::MonoTextStyle,
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::Rectangle,
+  text::{Baseline, Text},
+};
+
+use crate::{display::Display, typography};
+
+/// Scroll speed in pixels per second
+const SPEED: u32 = 30;
+/// Time the start of the text stays put before each pass
+const PAUSE: Duration = Duration::from_millis(1500);
+/// Blank space between the end of the text and its next copy
+const GAP: u32 = 24;
+
+/// Draw `text` inside `area`. Text that fits is drawn as is; longer text
+/// scrolls left, pausing at the start of every pass. `elapsed` is the time
+/// since the text first appeared, so every pass starts from the beginning.
+pub fn draw(
+  display: &mut Display<'_>,
+  text: &str,
+  area: Rectangle,
+  style: MonoTextStyle<'_, BinaryColor>,
+  elapsed: Duration,
+) {
+  let text = typography::displayable(text);
+  let width = typography::width(&text, &style);
+  let mut clipped = display.clipped(&area);
+  if width <= area.size.width {
+    let _ = Text::with_baseline(&text, area.top_left, style, Baseline::Top)
+      .draw(&mut clipped);
+    return;
+  }
+
+  let distance = width + GAP;
+  let pass = PAUSE
+    + Duration::from_millis(u64::from(distance) * 1000 / u64::from(SPEED));
+  let into_pass =
+    Duration::from_nanos((elapsed.as_nanos() % pass.as_nanos()) as u64);
+  let offset =
+    into_pass.saturating_sub(PAUSE).as_millis() as u32 * SPEED / 1000;
+
+  // a second copy follows the first so the text wraps around seamlessly
+  for start in [0, distance] {
+    let position = area.top_left + Point::new(start as i32 - offset as i32, 0);
+    let _ = Text::with_baseline(&text, position, style, Baseline::Top)
+      .draw(&mut clipped);
+  }
+}
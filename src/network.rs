@@ -0,0 +1,700 @@
+use std::{
+  cell::Cell,
+  ffi::{c_char, c_int, CStr},
+  net::{IpAddr, Ipv4Addr, TcpStream, ToSocketAddrs},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+
+use embedded_svc::wifi::Configuration;
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_svc::{
+  ipv4,
+  netif::{EspNetif, NetifConfiguration},
+  nvs::EspDefaultNvsPartition,
+  ping::{self, EspPing},
+  sntp::{EspSntp, SntpConf, SyncStatus},
+  sys::{err_t, esp_sntp_getreachability, ip_addr_t, SNTP_MAX_SERVERS},
+  wifi::{BlockingWifi, EspWifi},
+};
+
+#[cfg(feature = "ethernet")]
+use crate::ethernet::{self, SharedEth};
+use crate::{
+  clock::Instant,
+  config::{Roaming, SharedConfig, StaticIp},
+  ota,
+  roaming::{self, AccessPoint},
+  state::{NetworkInfo, Reach, SharedState},
+  wifi::{self, SharedWifi},
+};
+
+/// Joined the network, set by [`spawn`]. Until then fetching from the
+/// internet waits.
+pub type Online = Arc<AtomicBool>;
+
+/// Longest wait between two attempts to join the network
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// How often the signal is checked for roaming
+const ROAM_CHECK: Duration = Duration::from_secs(30);
+/// Least time between two scans for a stronger access point. A scan holds
+/// up traffic for a few seconds.
+const ROAM_SCAN_EVERY: Duration = Duration::from_secs(3 * 60);
+/// How often the connection is checked for opening or closing the setup
+/// network
+const SETUP_AP_CHECK: Duration = Duration::from_secs(10);
+/// Least time between two attempts to join while the setup network is
+/// open. Each one scans, which drops whoever is on the setup network for a
+/// moment.
+const SETUP_AP_RETRY: Duration = Duration::from_secs(2 * 60);
+/// How long joining WiFi waits at boot for a cable to get an address
+const ETHERNET_WAIT: Duration = Duration::from_secs(10);
+/// How long the cable's link takes to come up, after which no link means
+/// no cable
+#[cfg(feature = "ethernet")]
+const LINK_WAIT: Duration = Duration::from_secs(3);
+/// How often the Network screen's checks run while it is open
+const CHECK_EVERY: Duration = Duration::from_secs(5);
+/// Reached for the internet check, a site made for telling whether a
+/// network lets traffic out
+const INTERNET_CHECK: (&str, u16) = ("connectivitycheck.gstatic.com", 80);
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long a looked up address is used before it is looked up again. The
+/// resolver doesn't pass on the TTL of the record, this is well within what
+/// the APIs used here give theirs.
+const DNS_TTL: Duration = Duration::from_secs(10 * 60);
+/// Hosts whose address is kept, the one looked up longest ago is dropped
+const MAX_HOSTS: usize = 8;
+// from lwIP's err.h and api.h
+const ERR_OK: err_t = 0;
+const ERR_VAL: err_t = -6;
+const NETCONN_DNS_IPV6: u8 = 1;
+
+/// The way the device is online: over WiFi, or with the `ethernet` feature
+/// over a cable, which is used instead while it has an address. Only the
+/// threads here and the services announcing the device need to know which,
+/// everything else just opens sockets.
+#[derive(Clone)]
+pub struct Uplink {
+  pub wifi: SharedWifi,
+  /// `None` while `ethernet.enabled` is off
+  #[cfg(feature = "ethernet")]
+  pub ethernet: Option<SharedEth>,
+}
+
+impl Uplink {
+  pub fn on_ethernet(&self) -> bool {
+    #[cfg(feature = "ethernet")]
+    if let Some(eth) = &self.ethernet {
+      return ethernet::is_up(eth);
+    }
+    false
+  }
+
+  /// The address the device is reached at, `None` without one or while
+  /// the WiFi is busy joining
+  pub fn ip(&self) -> Option<Ipv4Addr> {
+    #[cfg(feature = "ethernet")]
+    if let Some(ip) = self.ethernet.as_ref().and_then(ethernet::ip) {
+      return Some(ip);
+    }
+    wifi::station_ip(&self.wifi)
+  }
+
+  /// Whether a cable may be about to get an address: its link is up, or
+  /// still being negotiated `waited` after start
+  fn ethernet_pending(&self, waited: Duration) -> bool {
+    #[cfg(feature = "ethernet")]
+    if let Some(eth) = &self.ethernet {
+      let eth = eth.lock().unwrap();
+      let link = eth.is_connected().unwrap_or(false) || waited < LINK_WAIT;
+      return link && !eth.is_up().unwrap_or(false);
+    }
+    let _ = waited;
+    false
+  }
+
+  /// `read` on the interface in use
+  fn with_netif<T>(&self, read: impl FnOnce(&EspNetif) -> T) -> T {
+    #[cfg(feature = "ethernet")]
+    if let Some(eth) = self.ethernet.as_ref().filter(|eth| ethernet::is_up(eth))
+    {
+      return read(eth.lock().unwrap().eth().netif());
+    }
+    read(self.wifi.lock().unwrap().wifi().sta_netif())
+  }
+}
+
+/// Addresses looked up before, see [`resolve`]
+static HOSTS: Mutex<Vec<Host>> = Mutex::new(Vec::new());
+
+thread_local! {
+  /// Set while [`resolve`] asks lwIP, so the hook lets that lookup through
+  static RESOLVING: Cell<bool> = const { Cell::new(false) };
+}
+
+struct Host {
+  name: String,
+  address: Ipv4Addr,
+  resolved: Instant,
+}
+
+/// Asking DHCP for an address under `hostname`, the name routers list the
+/// device under
+pub fn dhcp_client(hostname: &str) -> anyhow::Result<ipv4::Configuration> {
+  let hostname = hostname
+    .try_into()
+    .map_err(|()| anyhow::anyhow!("host name {hostname} is too long"))?;
+  Ok(ipv4::Configuration::Client(
+    ipv4::ClientConfiguration::DHCP(ipv4::DHCPClientSettings {
+      hostname: Some(hostname),
+    }),
+  ))
+}
+
+/// Station interface getting its address from DHCP under `hostname`.
+/// Swapped in before the driver starts.
+pub fn dhcp_netif(hostname: &str) -> anyhow::Result<EspNetif> {
+  Ok(EspNetif::new_with_conf(&NetifConfiguration {
+    ip_configuration: Some(dhcp_client(hostname)?),
+    ..NetifConfiguration::wifi_default_client()
+  })?)
+}
+
+/// Station interface with the fixed address of `static_ip`, `None` while
+/// DHCP is to set it. Swapped in before the driver starts.
+pub fn static_netif(static_ip: &StaticIp) -> anyhow::Result<Option<EspNetif>> {
+  if static_ip.address.is_empty() {
+    return Ok(None);
+  }
+  let prefix_len = static_ip
+    .prefix_len()
+    .ok_or_else(|| anyhow::anyhow!("invalid netmask {}", static_ip.netmask))?;
+  let gateway: Ipv4Addr = static_ip.gateway.parse()?;
+  let mut dns = static_ip.dns.iter().map(|server| server.parse());
+  let settings = ipv4::ClientSettings {
+    ip: static_ip.address.parse()?,
+    subnet: ipv4::Subnet {
+      gateway,
+      mask: ipv4::Mask(prefix_len),
+    },
+    // without DHCP nothing else names a server
+    dns: Some(dns.next().transpose()?.unwrap_or(gateway)),
+    secondary_dns: dns.next().transpose()?,
+  };
+  let netif = EspNetif::new_with_conf(&NetifConfiguration {
+    ip_configuration: Some(ipv4::Configuration::Client(
+      ipv4::ClientConfiguration::Fixed(settings),
+    )),
+    ..NetifConfiguration::wifi_default_client()
+  })?;
+  log::info!(
+    "Fixed address {}/{} via {}",
+    static_ip.address,
+    prefix_len,
+    gateway
+  );
+  Ok(Some(netif))
+}
+
+/// Join the WiFi network and set the clock from NTP in a background thread,
+/// so the screens are up straight away rather than after the network. The
+/// status bar shows the signal and the clock once they are there. A failed
+/// attempt to join is retried with backoff. With known networks saved, each
+/// attempt goes to the one of the highest priority in range. The time
+/// servers are tried in the order of `ntp.servers`. A cable with an address
+/// within [`ETHERNET_WAIT`], or by the next attempt, wins over WiFi.
+pub fn spawn(
+  uplink: Uplink,
+  online: Online,
+  config: SharedConfig,
+  state: SharedState,
+  nvs: EspDefaultNvsPartition,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("network".to_string())
+    .stack_size(6 * 1024)
+    .spawn(move || {
+      let wifi = &uplink.wifi;
+      let mut failures = 0;
+      let mut waited = Duration::ZERO;
+      while uplink.ethernet_pending(waited) && waited < ETHERNET_WAIT {
+        FreeRtos::delay_ms(100);
+        waited += Duration::from_millis(100);
+      }
+      loop {
+        if uplink.on_ethernet() {
+          log::info!("Connected over Ethernet");
+          break;
+        }
+        let connected = {
+          let mut wifi = wifi.lock().unwrap();
+          wifi::choose_known(&mut wifi, nvs.clone()).and_then(|()| {
+            wifi.connect()?;
+            wifi.wait_netif_up()?;
+            Ok(())
+          })
+        };
+        match connected {
+          Ok(()) => break,
+          Err(error) => {
+            failures += 1;
+            let mut delay =
+              Duration::from_secs(5 << failures.min(4)).min(MAX_RETRY_DELAY);
+            if wifi::setup_ap_open(&wifi.lock().unwrap()) {
+              delay = SETUP_AP_RETRY;
+            }
+            log::warn!(
+              "Could not join WiFi: {:?}, retrying in {}s",
+              error,
+              delay.as_secs()
+            );
+            FreeRtos::delay_ms(delay.as_millis() as u32);
+          }
+        }
+      }
+      if !uplink.on_ethernet() {
+        log::info!("Connected to WiFi!");
+      }
+      online.store(true, Ordering::Relaxed);
+      // Reaching the network means an image flashed over the air works,
+      // keep it
+      if let Err(error) = esp_idf_svc::ota::EspOta::new()
+        .and_then(|mut ota| ota.mark_running_slot_valid())
+      {
+        log::warn!("Could not mark firmware as valid: {:?}", error);
+      }
+      ota::log_signing();
+
+      let ntp_options = config.lock().unwrap().ntp.clone();
+      let servers = ntp_options.all_servers();
+      log::info!("Synchronizing with NTP servers {:?}", servers);
+      // lwIP goes on to the next one when a server doesn't answer, slots
+      // left empty fail their lookup and are passed over the same way
+      let mut slots = [""; SNTP_MAX_SERVERS as usize];
+      for (slot, server) in slots.iter_mut().zip(&servers) {
+        *slot = server;
+      }
+      let ntp = match EspSntp::new(&SntpConf {
+        servers: slots,
+        ..Default::default()
+      }) {
+        Ok(ntp) => ntp,
+        Err(error) => {
+          log::error!("Could not start NTP: {:?}", error);
+          return;
+        }
+      };
+      while ntp.get_sync_status() != SyncStatus::Completed {
+        FreeRtos::delay_ms(100);
+      }
+      // Safety: only reads the counters of the servers configured above
+      let synced = (0..servers.len())
+        .find(|&i| unsafe { esp_sntp_getreachability(i as u8) } != 0)
+        .map(|i| servers[i].to_string());
+      log::info!(
+        "Clock set from NTP server {}",
+        synced.as_deref().unwrap_or("?")
+      );
+      state.lock().unwrap().ntp_server = synced;
+      // kept for as long as the device runs, dropping it stops the clock
+      // being kept in sync
+      Box::leak(Box::new(ntp));
+    })?;
+  Ok(())
+}
+
+/// Once online, check the signal every [`ROAM_CHECK`]. When it is below
+/// `roaming.below_dbm`, scan for an access point of the same network that
+/// is stronger by `roaming.margin_db` and move to it. The station is held
+/// to that access point, so when the connection drops it joins whichever
+/// is strongest again, or the known network of the highest priority in
+/// range. While the setup network is open that is tried every
+/// [`SETUP_AP_RETRY`] only.
+pub fn spawn_roaming(
+  uplink: Uplink,
+  config: SharedConfig,
+  online: Online,
+  nvs: EspDefaultNvsPartition,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("roaming".to_string())
+    .stack_size(6 * 1024)
+    .spawn(move || {
+      let mut scanned: Option<Instant> = None;
+      let mut rejoined: Option<Instant> = None;
+      loop {
+        FreeRtos::delay_ms(ROAM_CHECK.as_millis() as u32);
+        // still joining for the first time, or on the cable
+        if !online.load(Ordering::Relaxed) || uplink.on_ethernet() {
+          continue;
+        }
+        let roaming = config.lock().unwrap().roaming.clone();
+        let mut wifi = uplink.wifi.lock().unwrap();
+        if !wifi.is_connected().unwrap_or(false) {
+          if wifi::setup_ap_open(&wifi)
+            && rejoined.is_some_and(|at| at.elapsed() < SETUP_AP_RETRY)
+          {
+            continue;
+          }
+          rejoined = Some(Instant::now());
+          if let Err(error) = rejoin(&mut wifi, &nvs) {
+            log::warn!("Could not join WiFi again: {:?}", error);
+          }
+        } else if let Err(error) = roam(&mut wifi, &roaming, &mut scanned) {
+          log::warn!("Roaming failed: {:?}", error);
+        }
+      }
+    })?;
+  Ok(())
+}
+
+fn rejoin(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+  nvs: &EspDefaultNvsPartition,
+) -> anyhow::Result<()> {
+  // the access point roamed to, or the whole network, may be gone
+  log::info!("WiFi connection lost, joining again");
+  hold_to(wifi, None)?;
+  wifi::choose_known(wifi, nvs.clone())?;
+  wifi.connect()?;
+  wifi.wait_netif_up()?;
+  Ok(())
+}
+
+fn roam(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+  roaming: &Roaming,
+  scanned: &mut Option<Instant>,
+) -> anyhow::Result<()> {
+  if !roaming.enabled {
+    return Ok(());
+  }
+  let info = wifi.wifi_mut().get_ap_info()?;
+  if info.signal_strength >= roaming.below_dbm
+    || scanned.is_some_and(|at| at.elapsed() < ROAM_SCAN_EVERY)
+  {
+    return Ok(());
+  }
+  *scanned = Some(Instant::now());
+  let current = AccessPoint {
+    bssid: info.bssid,
+    channel: info.channel,
+    rssi: info.signal_strength,
+  };
+  let candidates: Vec<_> = wifi
+    .scan()?
+    .into_iter()
+    .filter(|access_point| access_point.ssid == info.ssid)
+    .map(|access_point| AccessPoint {
+      bssid: access_point.bssid,
+      channel: access_point.channel,
+      rssi: access_point.signal_strength,
+    })
+    .collect();
+  let Some(target) = roaming::better(&current, &candidates, roaming.margin_db)
+  else {
+    return Ok(());
+  };
+  log::info!(
+    "Roaming from {} ({} dBm) to {} ({} dBm)",
+    roaming::format_bssid(&current.bssid),
+    current.rssi,
+    roaming::format_bssid(&target.bssid),
+    target.rssi
+  );
+  hold_to(wifi, Some(&target))?;
+  wifi.disconnect()?;
+  if let Err(error) = wifi.connect().and_then(|()| wifi.wait_netif_up()) {
+    log::warn!("Could not roam: {:?}, joining the strongest", error);
+    hold_to(wifi, None)?;
+    wifi.connect()?;
+    wifi.wait_netif_up()?;
+  }
+  Ok(())
+}
+
+/// Holds the station to `access_point` from the next connect on, or lets
+/// it pick the strongest with `None`. Returns whether that changed
+/// anything.
+fn hold_to(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+  access_point: Option<&AccessPoint>,
+) -> anyhow::Result<bool> {
+  let (mut client, setup) = match wifi.get_configuration()? {
+    Configuration::Client(client) => (client, None),
+    Configuration::Mixed(client, setup) => (client, Some(setup)),
+    _ => return Ok(false),
+  };
+  let bssid = access_point.map(|access_point| access_point.bssid);
+  if client.bssid == bssid {
+    return Ok(false);
+  }
+  client.bssid = bssid;
+  client.channel = access_point.map(|access_point| access_point.channel);
+  wifi.set_configuration(&match setup {
+    Some(setup) => Configuration::Mixed(client, setup),
+    None => Configuration::Client(client),
+  })?;
+  Ok(true)
+}
+
+/// Open the device's own network once no network could be joined for
+/// `setup_ap.after_min`, so another one can be chosen on the WiFi page,
+/// and close it again once one is, or a cable is in. Its name and password
+/// are on the display meanwhile.
+pub fn spawn_setup_ap(
+  uplink: Uplink,
+  config: SharedConfig,
+  state: SharedState,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("setup_ap".to_string())
+    .stack_size(4 * 1024)
+    .spawn(move || {
+      let mut offline_since: Option<Instant> = None;
+      loop {
+        FreeRtos::delay_ms(SETUP_AP_CHECK.as_millis() as u32);
+        let options = config.lock().unwrap().setup_ap.clone();
+        let on_ethernet = uplink.on_ethernet();
+        let mut wifi = uplink.wifi.lock().unwrap();
+        let open = wifi::setup_ap_open(&wifi);
+        if on_ethernet || wifi.is_connected().unwrap_or(false) {
+          offline_since = None;
+          if open {
+            match wifi::stop_setup_ap(&mut wifi) {
+              Ok(()) => {
+                log::info!("Back online, setup network closed");
+                state.lock().unwrap().setup_ap = None;
+              }
+              Err(error) => {
+                log::warn!("Could not close the setup network: {:?}", error);
+              }
+            }
+          }
+          continue;
+        }
+        let offline = offline_since.get_or_insert_with(Instant::now).elapsed();
+        let after = Duration::from_secs(u64::from(options.after_min) * 60);
+        if open || !options.enabled || offline < after {
+          continue;
+        }
+        match wifi::start_setup_ap(&mut wifi) {
+          Ok(setup) => {
+            log::warn!(
+              "No WiFi for {} min, opened setup network {} at {}",
+              options.after_min,
+              setup.ssid,
+              setup.address
+            );
+            state.lock().unwrap().setup_ap = Some(setup);
+          }
+          Err(error) => {
+            log::error!("Could not open the setup network: {:?}", error);
+          }
+        }
+      }
+    })?;
+  Ok(())
+}
+
+/// While the Network screen is open, read the addresses of the interface in
+/// use and ping the gateway and reach a site on the internet every
+/// [`CHECK_EVERY`], so it shows how far packets get.
+pub fn spawn_checks(
+  uplink: Uplink,
+  state: SharedState,
+  screen_open: Arc<AtomicBool>,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("netcheck".to_string())
+    .stack_size(6 * 1024)
+    .spawn(move || {
+      let mut pinger = EspPing::default();
+      loop {
+        if !screen_open.load(Ordering::Relaxed) {
+          state.lock().unwrap().network = None;
+          FreeRtos::delay_ms(500);
+          continue;
+        }
+        match uplink.with_netif(addresses) {
+          Ok(Some((mut info, gateway))) => {
+            // what was found last stays on screen while checking again
+            if let Some(last) = &state.lock().unwrap().network {
+              info.gateway_ping = last.gateway_ping;
+              info.internet = last.internet;
+            }
+            state.lock().unwrap().network = Some(info);
+            let gateway_ping = ping_once(&mut pinger, gateway);
+            let internet = reach_internet();
+            if let Some(info) = &mut state.lock().unwrap().network {
+              info.gateway_ping = gateway_ping;
+              info.internet = internet;
+            }
+          }
+          Ok(None) => state.lock().unwrap().network = None,
+          Err(error) => log::warn!("Could not read addresses: {:?}", error),
+        }
+        FreeRtos::delay_ms(CHECK_EVERY.as_millis() as u32);
+      }
+    })?;
+  Ok(())
+}
+
+/// The interface's addresses and its gateway, `None` without an address
+fn addresses(
+  netif: &EspNetif,
+) -> anyhow::Result<Option<(NetworkInfo, Ipv4Addr)>> {
+  let ip_info = netif.get_ip_info()?;
+  if ip_info.ip.is_unspecified() {
+    return Ok(None);
+  }
+  let mac = netif.get_mac()?;
+  let gateway = ip_info.subnet.gateway;
+  let info = NetworkInfo {
+    address: format!("{}/{}", ip_info.ip, ip_info.subnet.mask),
+    gateway: gateway.to_string(),
+    dns: ip_info.dns.map(|dns| dns.to_string()),
+    mac: roaming::format_bssid(&mac),
+    ..Default::default()
+  };
+  Ok(Some((info, gateway)))
+}
+
+fn ping_once(pinger: &mut EspPing, address: Ipv4Addr) -> Reach {
+  let configuration = ping::Configuration {
+    count: 1,
+    timeout: CHECK_TIMEOUT,
+    ..Default::default()
+  };
+  match pinger.ping(address, &configuration) {
+    Ok(summary) if summary.received > 0 => Reach::Answered {
+      ms: summary.time.as_millis() as u32,
+    },
+    Ok(_) => Reach::NoAnswer,
+    Err(error) => {
+      log::warn!("Could not ping {}: {:?}", address, error);
+      Reach::NoAnswer
+    }
+  }
+}
+
+/// Time to look up and connect to [`INTERNET_CHECK`]
+fn reach_internet() -> Reach {
+  let started = Instant::now();
+  let connected = INTERNET_CHECK
+    .to_socket_addrs()
+    .ok()
+    .and_then(|mut addresses| addresses.next())
+    .and_then(|address| {
+      TcpStream::connect_timeout(&address, CHECK_TIMEOUT).ok()
+    });
+  match connected {
+    Some(_) => Reach::Answered {
+      ms: started.elapsed().as_millis() as u32,
+    },
+    None => Reach::NoAnswer,
+  }
+}
+
+/// IPv4 address of `name`. One looked up less than [`DNS_TTL`] ago is used
+/// as it is. After that it is looked up again, but if that fails the last
+/// address that worked is used rather than failing the request.
+fn resolve(name: &str) -> Option<Ipv4Addr> {
+  let cached = HOSTS
+    .lock()
+    .unwrap()
+    .iter()
+    .find(|host| host.name == name)
+    .map(|host| (host.address, host.resolved));
+  if let Some((address, resolved)) = cached {
+    if resolved.elapsed() < DNS_TTL {
+      return Some(address);
+    }
+  }
+  RESOLVING.with(|resolving| resolving.set(true));
+  let looked_up = (name, 0).to_socket_addrs().map(|mut addresses| {
+    addresses.find_map(|address| match address.ip() {
+      IpAddr::V4(address) => Some(address),
+      IpAddr::V6(_) => None,
+    })
+  });
+  RESOLVING.with(|resolving| resolving.set(false));
+  match (looked_up, cached) {
+    (Ok(Some(address)), _) => {
+      remember(name, address);
+      Some(address)
+    }
+    (Ok(None), Some((address, _))) => {
+      log::warn!("{} has no IPv4 address now, using {}", name, address);
+      Some(address)
+    }
+    (Err(error), Some((address, _))) => {
+      log::warn!("Could not look up {}, using {}: {}", name, address, error);
+      Some(address)
+    }
+    (_, None) => None,
+  }
+}
+
+fn remember(name: &str, address: Ipv4Addr) {
+  let mut hosts = HOSTS.lock().unwrap();
+  hosts.retain(|host| host.name != name);
+  if hosts.len() >= MAX_HOSTS {
+    hosts.sort_by_key(|host| host.resolved);
+    hosts.remove(0);
+  }
+  hosts.push(Host {
+    name: name.to_string(),
+    address,
+    resolved: Instant::now(),
+  });
+}
+
+/// Called by lwIP for every name a socket looks up, such as the host of an
+/// outgoing HTTP request, so they all go through [`resolve`]. Linked in with
+/// `CONFIG_LWIP_HOOK_NETCONN_EXT_RESOLVE_CUSTOM`. Returning 0 leaves the
+/// lookup to lwIP.
+///
+/// # Safety
+///
+/// lwIP passes a NUL-terminated `name` and valid `addr` and `err`.
+#[no_mangle]
+pub unsafe extern "C" fn lwip_hook_netconn_external_resolve(
+  name: *const c_char,
+  addr: *mut ip_addr_t,
+  addrtype: u8,
+  err: *mut err_t,
+) -> c_int {
+  if RESOLVING.with(Cell::get) || addrtype == NETCONN_DNS_IPV6 {
+    return 0;
+  }
+  let Ok(name) = CStr::from_ptr(name).to_str() else {
+    return 0;
+  };
+  if name.parse::<IpAddr>().is_ok() {
+    return 0;
+  }
+  match resolve(name) {
+    Some(address) => {
+      // lwIP keeps addresses in network order
+      let raw = u32::from_ne_bytes(address.octets());
+      #[cfg(esp_idf_lwip_ipv6)]
+      {
+        (*addr).u_addr.ip4.addr = raw;
+        // IPADDR_TYPE_V4
+        (*addr).type_ = 0;
+      }
+      #[cfg(not(esp_idf_lwip_ipv6))]
+      {
+        (*addr).addr = raw;
+      }
+      *err = ERR_OK;
+    }
+    None => *err = ERR_VAL,
+  }
+  1
+}
@@ -0,0 +1,303 @@
+use std::{
+  collections::VecDeque,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+  text::{Baseline, Text},
+};
+use serde::Deserialize;
+
+use crate::{
+  clock::Instant,
+  display::Display,
+  marquee, statusbar,
+  typography::{self, Align, Font, Line},
+};
+
+pub type SharedNotifications = Arc<Mutex<Notifications>>;
+
+/// 21 columns of FONT_6X10 fit on the 128 px wide panel
+const BANNER_COLUMNS: usize = 21;
+const BANNER_LINES: usize = 2;
+
+#[derive(
+  Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+  Low,
+  #[default]
+  Normal,
+  High,
+}
+
+/// Notifications waiting for their turn on screen
+const QUEUE_LEN: usize = 8;
+/// Notifications kept for the history screen
+const HISTORY_LEN: usize = 20;
+
+/// A notification, shown over the active screen for `duration`
+#[derive(Clone, Debug)]
+pub struct Message {
+  pub text: String,
+  pub priority: Priority,
+  pub duration: Duration,
+  /// When it reached the screen, only meaningful once it is current
+  pub shown_at: Instant,
+}
+
+/// A past notification on the history screen
+#[derive(Clone, Debug)]
+pub struct Entry {
+  pub text: String,
+  pub priority: Priority,
+  pub at: Instant,
+}
+
+/// The notification on screen, the ones waiting behind it in priority order
+/// and a history of recent ones
+#[derive(Default)]
+pub struct Notifications {
+  current: Option<Message>,
+  queue: VecDeque<Message>,
+  history: VecDeque<Entry>,
+  /// Held back during quiet hours
+  quiet: bool,
+}
+
+impl Notifications {
+  /// Queue `text` to be shown for `duration`. Higher priorities go first and
+  /// replace a lower priority notification on screen, which is shown again
+  /// afterwards. Returns false when the queue is full of notifications of
+  /// the same or higher priority and `text` was dropped.
+  pub fn push(
+    &mut self,
+    text: &str,
+    duration: Duration,
+    priority: Priority,
+  ) -> bool {
+    let now = Instant::now();
+    self.advance(now);
+    if self.queue.len() >= QUEUE_LEN
+      && self
+        .queue
+        .back()
+        .is_some_and(|last| last.priority >= priority)
+    {
+      return false;
+    }
+    let message = Message {
+      text: text.to_string(),
+      priority,
+      duration,
+      shown_at: now,
+    };
+    if self
+      .current
+      .as_ref()
+      .is_some_and(|current| current.priority < priority)
+    {
+      if let Some(preempted) = self.current.take() {
+        self.enqueue(preempted, true);
+      }
+    }
+    self.enqueue(message, false);
+    // the lowest priority notifications make room
+    self.queue.truncate(QUEUE_LEN);
+    if self.history.len() >= HISTORY_LEN {
+      self.history.pop_back();
+    }
+    self.history.push_front(Entry {
+      text: text.to_string(),
+      priority,
+      at: now,
+    });
+    self.advance(now);
+    true
+  }
+
+  /// Notification to draw this frame, moving on to the next one once it
+  /// has been shown for its duration
+  pub fn current(&mut self) -> Option<&Message> {
+    self.advance(Instant::now());
+    self.current.as_ref()
+  }
+
+  /// Hold notifications back, e.g. during quiet hours. They still go into
+  /// the history, and wait in the queue to be shown once `quiet` is off.
+  pub fn set_quiet(&mut self, quiet: bool) {
+    if quiet && !self.quiet {
+      // the one on screen is shown again, in full, afterwards
+      if let Some(current) = self.current.take() {
+        self.enqueue(current, true);
+      }
+    }
+    self.quiet = quiet;
+  }
+
+  /// Notifications on screen or still waiting
+  pub fn count(&mut self) -> usize {
+    usize::from(self.current().is_some()) + self.queue.len()
+  }
+
+  /// Recent notifications, newest first
+  pub fn history(&self) -> impl Iterator<Item = &Entry> {
+    self.history.iter()
+  }
+
+  /// Insert behind everything of the same or higher priority, or in front
+  /// of its own priority when `first`
+  fn enqueue(&mut self, message: Message, first: bool) {
+    let index = self
+      .queue
+      .iter()
+      .position(|queued| {
+        queued.priority < message.priority
+          || (first && queued.priority == message.priority)
+      })
+      .unwrap_or(self.queue.len());
+    self.queue.insert(index, message);
+  }
+
+  fn advance(&mut self, now: Instant) {
+    if self.current.as_ref().is_some_and(|current| {
+      now.duration_since(current.shown_at) >= current.duration
+    }) {
+      self.current = None;
+    }
+    if self.current.is_none() && !self.quiet {
+      self.current = self.queue.pop_front().map(|mut message| {
+        message.shown_at = now;
+        message
+      });
+    }
+  }
+}
+
+/// Inverted banner across the bottom of the panel, word-wrapped to two lines.
+/// Text too long for two lines scrolls on a single line instead.
+pub fn draw_banner(display: &mut Display<'_>, message: &Message) {
+  let style = Font::Medium.inverted();
+  let _ = Rectangle::new(Point::new(0, 40), Size::new(128, 24))
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    .draw(display);
+  let Some(lines) = wrap(&message.text) else {
+    marquee::draw(
+      display,
+      &message.text,
+      Rectangle::new(Point::new(1, 47), Size::new(126, 10)),
+      style,
+      message.shown_at.elapsed(),
+    );
+    return;
+  };
+  for (row, line) in lines.iter().enumerate() {
+    let _ = Text::with_baseline(
+      &typography::displayable(line),
+      Point::new(1, 42 + 11 * row as i32),
+      style,
+      Baseline::Top,
+    )
+    .draw(display);
+  }
+}
+
+/// Page of the notification history below the status bar, newest first,
+/// each line starting with how long ago it arrived
+pub fn draw_history(
+  display: &mut Display<'_>,
+  entries: &[Entry],
+  offset: usize,
+  total: usize,
+) {
+  let style = Font::Small.style();
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Notifications",
+    Point::new(1, top),
+    Align::Left,
+    style,
+  );
+  if entries.is_empty() {
+    typography::draw_centered(display, "Nothing yet", 32, style);
+    return;
+  }
+  typography::draw(
+    display,
+    &typography::line(format_args!(
+      "{}-{}/{}",
+      offset + 1,
+      offset + entries.len(),
+      total
+    )),
+    Point::new(typography::PANEL_WIDTH - 1, top),
+    Align::Right,
+    style,
+  );
+
+  // 25 columns of FONT_5X8 fit on the 128 px wide panel
+  for (row, entry) in entries.iter().enumerate() {
+    let marker = if entry.priority == Priority::High {
+      "!"
+    } else {
+      " "
+    };
+    let line = typography::first_chars(
+      typography::line(format_args!(
+        "{:>3}{} {}",
+        age(entry.at.elapsed()),
+        marker,
+        entry.text
+      )),
+      25,
+    );
+    typography::draw(
+      display,
+      &line,
+      Point::new(1, top + 12 + 8 * row as i32),
+      Align::Left,
+      style,
+    );
+  }
+}
+
+/// Compact age such as `45s`, `12m` or `3h`
+fn age(elapsed: Duration) -> Line {
+  match elapsed.as_secs() {
+    seconds @ 0..=59 => typography::line(format_args!("{seconds}s")),
+    seconds @ 60..=3599 => typography::line(format_args!("{}m", seconds / 60)),
+    seconds @ 3600..=86399 => {
+      typography::line(format_args!("{}h", seconds / 3600))
+    }
+    seconds => typography::line(format_args!("{}d", seconds / 86400)),
+  }
+}
+
+/// Split on spaces into lines of `BANNER_COLUMNS`, `None` when that takes
+/// more than `BANNER_LINES` lines
+fn wrap(text: &str) -> Option<Vec<String>> {
+  let mut lines: Vec<String> = vec![String::new()];
+  for word in text.split_whitespace() {
+    let line = lines.last_mut().unwrap();
+    let needed = if line.is_empty() { 0 } else { 1 } + word.chars().count();
+    if line.chars().count() + needed > BANNER_COLUMNS && !line.is_empty() {
+      lines.push(String::new());
+    }
+    let line = lines.last_mut().unwrap();
+    if !line.is_empty() {
+      line.push(' ');
+    }
+    line.push_str(word);
+  }
+  let fits = lines.len() <= BANNER_LINES
+    && lines
+      .iter()
+      .all(|line| line.chars().count() <= BANNER_COLUMNS);
+  fits.then_some(lines)
+}
@@ -0,0 +1,267 @@
+use std::sync::{Arc, Mutex};
+
+use ed25519_dalek::{Signature, VerifyingKey, SIGNATURE_LENGTH};
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+};
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_svc::{http::Method, ota::EspOta};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+  defaults::DEFAULTS,
+  display::Display,
+  http_client::{self, RequestOptions},
+  request_queue::Priority,
+  tls_pins::Endpoint,
+  typography::{self, Font},
+};
+
+pub type SharedProgress = Arc<Mutex<Progress>>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+  #[default]
+  Idle,
+  Downloading,
+  Verifying,
+  Done,
+  Failed,
+  /// The image wasn't signed with the release key and was thrown away
+  Rejected,
+}
+
+/// The downloaded image failed the signature check, or there is no key to
+/// check it with
+#[derive(Debug)]
+struct Rejected(&'static str);
+
+impl std::fmt::Display for Rejected {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.0)
+  }
+}
+
+impl std::error::Error for Rejected {}
+
+/// Where a firmware update stands, polled by the OTA page and drawn on the
+/// display while flashing
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Progress {
+  pub stage: Stage,
+  pub written: usize,
+  /// Image size, when the server sent a Content-Length
+  pub total: Option<usize>,
+  pub error: Option<String>,
+}
+
+impl Progress {
+  /// Flash is being written, other changes to the device have to wait
+  pub fn in_progress(&self) -> bool {
+    matches!(self.stage, Stage::Downloading | Stage::Verifying)
+  }
+
+  fn percent(&self) -> Option<usize> {
+    self
+      .total
+      .filter(|total| *total > 0)
+      .map(|total| self.written * 100 / total)
+  }
+}
+
+/// Download the image at `url` into the next OTA slot in a background thread
+/// and reboot into it. Refused while another update is running, and without
+/// a key to check the image with.
+pub fn start(url: String, progress: SharedProgress) -> anyhow::Result<()> {
+  {
+    let mut progress = progress.lock().unwrap();
+    if progress.in_progress() {
+      anyhow::bail!("an update is already running");
+    }
+    // refused before downloading, the display and the caller say why
+    if let Err(error) = public_key() {
+      *progress = Progress {
+        stage: stage_of(&error),
+        error: Some(error.to_string()),
+        ..Default::default()
+      };
+      return Err(error);
+    }
+    *progress = Progress {
+      stage: Stage::Downloading,
+      ..Default::default()
+    };
+  }
+
+  std::thread::Builder::new()
+    .name("ota".to_string())
+    .stack_size(12 * 1024)
+    .spawn(move || match update(&url, &progress) {
+      Ok(()) => {
+        log::warn!("Firmware update complete, restarting");
+        progress.lock().unwrap().stage = Stage::Done;
+        FreeRtos::delay_ms(2000);
+        esp_idf_hal::reset::restart();
+      }
+      Err(error) => {
+        log::error!("Firmware update failed: {:?}", error);
+        let mut progress = progress.lock().unwrap();
+        progress.stage = stage_of(&error);
+        progress.error = Some(error.to_string());
+      }
+    })?;
+  Ok(())
+}
+
+/// Where an update that went wrong with `error` stopped
+fn stage_of(error: &anyhow::Error) -> Stage {
+  if error.is::<Rejected>() {
+    Stage::Rejected
+  } else {
+    Stage::Failed
+  }
+}
+
+/// Key release images are signed with, `ota_public_key` in `cfg.toml`.
+/// Without one every update is rejected, unless the firmware was built with
+/// the `ota-unsigned` feature: then this is `None` and images are flashed
+/// unchecked.
+fn public_key() -> anyhow::Result<Option<VerifyingKey>> {
+  let hex = DEFAULTS.ota_public_key.trim();
+  if hex.is_empty() {
+    if cfg!(feature = "ota-unsigned") {
+      return Ok(None);
+    }
+    return Err(Rejected("no ota_public_key built in").into());
+  }
+  let mut key = [0_u8; 32];
+  if hex.len() != key.len() * 2 || !hex.is_ascii() {
+    anyhow::bail!("ota_public_key must be 64 hex digits");
+  }
+  for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+    *byte = u8::from_str_radix(std::str::from_utf8(digits)?, 16)?;
+  }
+  Ok(Some(VerifyingKey::from_bytes(&key)?))
+}
+
+/// Log whether updates have to be signed, once at boot
+pub fn log_signing() {
+  match public_key() {
+    Ok(Some(_)) => log::info!("Firmware updates must be signed"),
+    Ok(None) => {
+      log::warn!("Built with ota-unsigned, updates are not verified")
+    }
+    Err(error) => {
+      log::error!("Updates will be refused: {:?}", error)
+    }
+  }
+}
+
+/// Flash the image at `url`. Unless built with `ota-unsigned`, the image has
+/// to end in the Ed25519 signature of the SHA-256 digest of the rest of it.
+///
+/// The image is written to flash as it arrives, holding back the last
+/// `SIGNATURE_LENGTH` bytes since they may be the signature. The update is
+/// only finished, and the image only booted, once the signature checks out.
+fn update(url: &str, progress: &SharedProgress) -> anyhow::Result<()> {
+  // a missing or bad key fails every update rather than letting unsigned
+  // images in
+  let key = public_key()?;
+  log::info!("Downloading firmware from {}", url);
+  let mut connection = http_client::open(
+    Some(Endpoint::Ota),
+    Method::Get,
+    url,
+    &[],
+    Some(4096),
+    &RequestOptions {
+      priority: Priority::Update,
+      ..Default::default()
+    },
+  )?;
+  connection.initiate_response()?;
+  if !(200..=299).contains(&connection.status()) {
+    anyhow::bail!("download failed with status {}", connection.status());
+  }
+  let total = connection
+    .header("Content-Length")
+    .and_then(|len| len.parse::<usize>().ok());
+  progress.lock().unwrap().total = total;
+
+  let mut ota = EspOta::new()?;
+  // dropping the update without finishing it aborts it
+  let mut update = ota.initiate_update()?;
+  let mut buf = vec![0_u8; 4096];
+  let mut held = Vec::with_capacity(buf.len() + SIGNATURE_LENGTH);
+  let mut digest = Sha256::new();
+  let mut written = 0;
+  loop {
+    let size = connection.read(&mut buf)?;
+    if size == 0 {
+      break;
+    }
+    held.extend_from_slice(&buf[..size]);
+    let ready = if key.is_some() {
+      held.len().saturating_sub(SIGNATURE_LENGTH)
+    } else {
+      held.len()
+    };
+    update.write(&held[..ready])?;
+    digest.update(&held[..ready]);
+    held.drain(..ready);
+    written += size;
+    progress.lock().unwrap().written = written;
+  }
+  if total.is_some_and(|total| total != written) {
+    anyhow::bail!("download ended after {} bytes", written);
+  }
+
+  progress.lock().unwrap().stage = Stage::Verifying;
+  if let Some(key) = key {
+    let signature = <[u8; SIGNATURE_LENGTH]>::try_from(held.as_slice())
+      .map_err(|_| Rejected("no signature, image too short"))?;
+    key
+      .verify_strict(&digest.finalize(), &Signature::from_bytes(&signature))
+      .map_err(|_| Rejected("signature invalid or unsigned image"))?;
+    log::info!("Firmware signature verified");
+  }
+  // esp_ota_end checks the image before it can be booted
+  update.finish()?.activate()?;
+  Ok(())
+}
+
+/// Full-screen progress bar shown instead of the UI while flashing
+pub fn draw_progress(display: &mut Display<'_>, progress: &Progress) {
+  let style = Font::Medium.style();
+  let _ = display.clear(BinaryColor::Off);
+
+  let title = match progress.stage {
+    Stage::Verifying => "Verifying update",
+    Stage::Done => "Update done",
+    Stage::Failed => "Update failed",
+    Stage::Rejected => "Update rejected",
+    _ => "Updating firmware",
+  };
+  typography::draw_centered(display, title, 1, style);
+
+  let _ = Rectangle::new(Point::new(4, 26), Size::new(120, 12))
+    .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+    .draw(display);
+  let filled = progress.percent().unwrap_or(0).min(100) as u32 * 116 / 100;
+  let _ = Rectangle::new(Point::new(6, 28), Size::new(filled, 8))
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    .draw(display);
+
+  let detail = match progress.percent() {
+    Some(percent) => typography::line(format_args!(
+      "{percent}%  {} KB",
+      progress.written / 1024
+    )),
+    None => typography::line(format_args!("{} KB", progress.written / 1024)),
+  };
+  typography::draw_centered(display, &detail, 44, style);
+}
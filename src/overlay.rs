@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+  text::{Baseline, Text},
+};
+
+use crate::{
+  display::Display,
+  typography::{self, Font},
+};
+
+/// Two rows of the small font, above the bottom edge
+const HEIGHT: u32 = 17;
+/// Twelve columns of the small font
+const WIDTH: u32 = 61;
+
+/// How long the last frame took, for the debug overlay
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+  /// From the start of one frame to the start of the next
+  pub frame: Duration,
+  /// Drawing into the frame buffer
+  pub draw: Duration,
+  /// Sending the frame buffer to the panel over I2C
+  pub flush: Duration,
+  pub free_heap: u32,
+}
+
+impl FrameStats {
+  fn fps(&self) -> u128 {
+    match self.frame.as_millis() {
+      0 => 0,
+      ms => 1000 / ms,
+    }
+  }
+}
+
+/// Frame rate and times and the free heap in the bottom-left corner, over
+/// whatever the screen drew there. Turned on with `display.debug_overlay`.
+pub fn draw(display: &mut Display<'_>, stats: &FrameStats) {
+  let top = 64 - HEIGHT as i32;
+  let _ = Rectangle::new(Point::new(0, top), Size::new(WIDTH, HEIGHT))
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    .draw(display);
+  let lines = [
+    typography::line(format_args!(
+      "{}fps {}ms",
+      stats.fps(),
+      stats.frame.as_millis()
+    )),
+    typography::line(format_args!(
+      "d{} f{} {}K",
+      stats.draw.as_millis(),
+      stats.flush.as_millis(),
+      stats.free_heap / 1024
+    )),
+  ];
+  for (row, line) in lines.iter().enumerate() {
+    let _ = Text::with_baseline(
+      line,
+      Point::new(1, top + 1 + 8 * row as i32),
+      Font::Small.inverted(),
+      Baseline::Top,
+    )
+    .draw(display);
+  }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{Circle, PrimitiveStyle},
+};
+
+use crate::{
+  clock::Instant, display::Display, typography::PANEL_WIDTH, ui::Paging,
+};
+
+/// How long a page stays up before the next one comes on by itself
+const AUTO_ADVANCE: Duration = Duration::from_secs(5);
+/// Space between the centres of two indicator dots
+const DOT_PITCH: i32 = 6;
+
+/// Page of a screen whose content is split over several pages. Pages move
+/// on by themselves every few seconds, or straight away on `next`.
+pub struct Pager {
+  page: usize,
+  shown_at: Instant,
+}
+
+impl Default for Pager {
+  fn default() -> Self {
+    Self {
+      page: 0,
+      shown_at: Instant::now(),
+    }
+  }
+}
+
+impl Pager {
+  /// Back to the first page, for a screen that was just entered
+  pub fn reset(&mut self) {
+    *self = Self::default();
+  }
+
+  pub fn next(&mut self) {
+    self.page += 1;
+    self.shown_at = Instant::now();
+  }
+
+  /// Turn as a short press asked, see `ui::handle_short_press`
+  pub fn turn(&mut self, paging: Paging) {
+    match paging {
+      Paging::Next => self.next(),
+      Paging::Restart => self.reset(),
+    }
+  }
+
+  /// The next page comes on when the screen is next drawn
+  pub fn is_due(&self) -> bool {
+    self.shown_at.elapsed() >= AUTO_ADVANCE
+  }
+
+  /// When the next page comes on by itself
+  pub fn due_at(&self) -> Instant {
+    self.shown_at + AUTO_ADVANCE
+  }
+
+  /// Page to draw out of `count`, advancing once it has been up long enough
+  pub fn page(&mut self, count: usize) -> usize {
+    if self.shown_at.elapsed() >= AUTO_ADVANCE {
+      self.next();
+    }
+    if self.page >= count.max(1) {
+      self.page = 0;
+    }
+    self.page
+  }
+
+  /// Time the current page has been up, so scrolling text on it starts
+  /// from the beginning
+  pub fn elapsed(&self) -> Duration {
+    self.shown_at.elapsed()
+  }
+}
+
+/// Row of dots along the bottom of the panel with the current page filled
+pub fn draw_dots(display: &mut Display<'_>, page: usize, count: usize) {
+  if count < 2 {
+    return;
+  }
+  let first = PANEL_WIDTH / 2 - DOT_PITCH * (count as i32 - 1) / 2;
+  for index in 0..count {
+    let style = if index == page {
+      PrimitiveStyle::with_fill(BinaryColor::On)
+    } else {
+      PrimitiveStyle::with_stroke(BinaryColor::On, 1)
+    };
+    let _ =
+      Circle::new(Point::new(first + DOT_PITCH * index as i32 - 2, 59), 4)
+        .into_styled(style)
+        .draw(display);
+  }
+}
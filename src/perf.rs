@@ -0,0 +1,185 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Samples kept for each span, the oldest dropped first
+const SAMPLES: usize = 64;
+/// Upper bounds of the histogram buckets in milliseconds, the last bucket
+/// takes everything above
+const BUCKETS_MS: [u32; 8] = [1, 2, 5, 10, 20, 50, 100, 1000];
+
+static SPANS: Mutex<[Samples; Span::ALL.len()]> =
+  Mutex::new([Samples::new(); Span::ALL.len()]);
+
+/// Parts of the firmware that are timed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Span {
+  /// One pass of the main loop, without the delay at its end
+  Loop,
+  /// Drawing a frame into the frame buffer
+  Render,
+  /// Sending the frame buffer to the panel over I2C
+  Flush,
+  /// An outgoing HTTP request, from connecting to the end of the body
+  HttpFetch,
+  /// Reading the motion sensor
+  SensorPoll,
+}
+
+impl Span {
+  /// In the order of the variants, which index the samples
+  const ALL: [Span; 5] = [
+    Span::Loop,
+    Span::Render,
+    Span::Flush,
+    Span::HttpFetch,
+    Span::SensorPoll,
+  ];
+
+  pub fn name(self) -> &'static str {
+    match self {
+      Span::Loop => "loop",
+      Span::Render => "render",
+      Span::Flush => "flush",
+      Span::HttpFetch => "http_fetch",
+      Span::SensorPoll => "sensor_poll",
+    }
+  }
+}
+
+/// The last [`SAMPLES`] durations of a span, in microseconds
+#[derive(Clone, Copy)]
+struct Samples {
+  micros: [u32; SAMPLES],
+  len: usize,
+  next: usize,
+  /// Recorded since boot, including the dropped ones
+  total: u32,
+}
+
+impl Samples {
+  const fn new() -> Self {
+    Self {
+      micros: [0; SAMPLES],
+      len: 0,
+      next: 0,
+      total: 0,
+    }
+  }
+
+  fn push(&mut self, duration: Duration) {
+    self.micros[self.next] =
+      u32::try_from(duration.as_micros()).unwrap_or(u32::MAX);
+    self.next = (self.next + 1) % SAMPLES;
+    self.len = (self.len + 1).min(SAMPLES);
+    self.total = self.total.saturating_add(1);
+  }
+
+  fn summary(&self) -> Summary {
+    let mut sorted = self.micros[..self.len].to_vec();
+    sorted.sort_unstable();
+    let percentile = |p: usize| match sorted.len() {
+      0 => 0,
+      len => sorted[(len - 1) * p / 100],
+    };
+    let mut histogram: Vec<Bucket> = BUCKETS_MS
+      .iter()
+      .map(|&le_ms| Bucket {
+        le_ms: Some(le_ms),
+        count: 0,
+      })
+      .chain([Bucket {
+        le_ms: None,
+        count: 0,
+      }])
+      .collect();
+    for &micros in &sorted {
+      let bucket = BUCKETS_MS
+        .iter()
+        .position(|&le_ms| micros <= le_ms * 1000)
+        .unwrap_or(BUCKETS_MS.len());
+      histogram[bucket].count += 1;
+    }
+    let sum: u64 = sorted.iter().map(|&micros| u64::from(micros)).sum();
+    Summary {
+      count: self.len,
+      total: self.total,
+      mean_us: sum.checked_div(self.len as u64).unwrap_or(0) as u32,
+      p50_us: percentile(50),
+      p95_us: percentile(95),
+      max_us: sorted.last().copied().unwrap_or(0),
+      histogram,
+    }
+  }
+}
+
+/// Durations of one span over its recent samples
+#[derive(Clone, Debug, Serialize)]
+pub struct Summary {
+  /// Samples the figures are over
+  pub count: usize,
+  /// Recorded since boot
+  pub total: u32,
+  pub mean_us: u32,
+  pub p50_us: u32,
+  pub p95_us: u32,
+  pub max_us: u32,
+  pub histogram: Vec<Bucket>,
+}
+
+/// Samples that took at most `le_ms`, and more than the bucket before.
+/// The last bucket has no bound.
+#[derive(Clone, Debug, Serialize)]
+pub struct Bucket {
+  pub le_ms: Option<u32>,
+  pub count: u32,
+}
+
+/// Times a span until dropped, from [`span`]
+pub struct Guard {
+  span: Span,
+  started: Instant,
+}
+
+impl Drop for Guard {
+  fn drop(&mut self) {
+    record(self.span, self.started.elapsed());
+  }
+}
+
+/// Start timing `span`, recorded when the returned guard is dropped
+pub fn span(span: Span) -> Guard {
+  Guard {
+    span,
+    started: Instant::now(),
+  }
+}
+
+/// Record a duration measured elsewhere
+pub fn record(span: Span, duration: Duration) {
+  SPANS.lock().unwrap()[span as usize].push(duration);
+}
+
+/// Figures for `span`
+pub fn summary(span: Span) -> Summary {
+  SPANS.lock().unwrap()[span as usize].summary()
+}
+
+/// Every span by name, for `/api/v1/perf`
+pub fn to_json() -> serde_json::Value {
+  let spans = SPANS.lock().unwrap();
+  Span::ALL
+    .iter()
+    .zip(spans.iter())
+    .map(|(span, samples)| {
+      (
+        span.name().to_string(),
+        serde_json::to_value(samples.summary()).unwrap_or_default(),
+      )
+    })
+    .collect::<serde_json::Map<_, _>>()
+    .into()
+}
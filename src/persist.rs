@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition};
+use serde::{de::DeserializeOwned, Serialize};
+
+const NAMESPACE: &str = "persist";
+
+/// A value has to stay unchanged this long before it is written, so paging
+/// through screens doesn't wear out the flash
+const SETTLE: Duration = Duration::from_secs(5);
+
+/// Value kept in NVS across restarts. Updates are written once the value
+/// has settled.
+pub struct Persisted<T> {
+  partition: EspDefaultNvsPartition,
+  key: &'static str,
+  saved: Option<T>,
+  pending: Option<(T, Instant)>,
+}
+
+impl<T: Clone + PartialEq + Serialize + DeserializeOwned> Persisted<T> {
+  /// Handle for `key` together with the value saved under it, if any
+  pub fn load(
+    partition: EspDefaultNvsPartition,
+    key: &'static str,
+  ) -> (Self, Option<T>) {
+    let saved = read(partition.clone(), key).unwrap_or_else(|error| {
+      log::warn!("Could not read saved {}: {:?}", key, error);
+      None
+    });
+    let persisted = Self {
+      partition,
+      key,
+      saved: saved.clone(),
+      pending: None,
+    };
+    (persisted, saved)
+  }
+
+  /// Called with the current value on every frame
+  pub fn update(&mut self, value: &T) {
+    if self.saved.as_ref() == Some(value) {
+      self.pending = None;
+      return;
+    }
+    match &self.pending {
+      Some((pending, since)) if pending == value => {
+        if since.elapsed() < SETTLE {
+          return;
+        }
+      }
+      _ => {
+        self.pending = Some((value.clone(), Instant::now()));
+        return;
+      }
+    }
+    match write(self.partition.clone(), self.key, value) {
+      Ok(()) => {
+        self.saved = Some(value.clone());
+        self.pending = None;
+      }
+      Err(error) => {
+        log::warn!("Could not save {}: {:?}", self.key, error);
+        // try again once the value has settled for another while
+        self.pending = Some((value.clone(), Instant::now()));
+      }
+    }
+  }
+}
+
+fn read<T: DeserializeOwned>(
+  partition: EspDefaultNvsPartition,
+  key: &str,
+) -> anyhow::Result<Option<T>> {
+  let nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+  let Some(len) = nvs.str_len(key)? else {
+    return Ok(None);
+  };
+  let mut buf = vec![0_u8; len];
+  match nvs.get_str(key, &mut buf)? {
+    Some(json) => Ok(Some(serde_json::from_str(json)?)),
+    None => Ok(None),
+  }
+}
+
+fn write<T: Serialize>(
+  partition: EspDefaultNvsPartition,
+  key: &str,
+  value: &T,
+) -> anyhow::Result<()> {
+  let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+  nvs.set_str(key, &serde_json::to_string(value)?)?;
+  Ok(())
+}
@@ -0,0 +1,83 @@
+use embedded_graphics::{
+  framebuffer::{buffer_size, Framebuffer},
+  pixelcolor::{raw::BigEndian, raw::RawU1, BinaryColor},
+  prelude::*,
+};
+
+use crate::display::Display;
+
+const WIDTH: usize = 128;
+const HEIGHT: usize = 64;
+
+/// A whole screen, one bit per pixel
+pub type Frame = Framebuffer<
+  BinaryColor,
+  RawU1,
+  BigEndian,
+  WIDTH,
+  HEIGHT,
+  { buffer_size::<BinaryColor>(WIDTH, HEIGHT) },
+>;
+
+/// A screen that only changes with a few values, e.g. Settings with the
+/// brightness. It is drawn into a frame of its own once, and only its lit
+/// pixels are copied to the panel after that, until the values differ.
+pub struct Prerendered<K> {
+  /// What the frame was drawn for
+  key: Option<K>,
+  frame: Box<Frame>,
+}
+
+impl<K> Default for Prerendered<K> {
+  fn default() -> Self {
+    Self {
+      key: None,
+      frame: Box::new(Frame::new()),
+    }
+  }
+}
+
+impl<K: PartialEq> Prerendered<K> {
+  /// Put the screen for `key` on `display`, calling `draw` only when the
+  /// frame was drawn for something else. `display` is expected to be
+  /// cleared.
+  pub fn draw(
+    &mut self,
+    display: &mut Display<'_>,
+    key: K,
+    draw: impl FnOnce(&mut Frame),
+  ) {
+    if self.key.as_ref() != Some(&key) {
+      let _ = self.frame.clear(BinaryColor::Off);
+      draw(&mut self.frame);
+      self.key = Some(key);
+    }
+    let _ = display.draw_iter(lit(&self.frame));
+  }
+}
+
+/// Pixels that are on in `frame`, skipping the dark bytes
+fn lit(frame: &Frame) -> impl Iterator<Item = Pixel<BinaryColor>> + '_ {
+  const BYTES_PER_ROW: usize = WIDTH / 8;
+  frame
+    .data()
+    .chunks(BYTES_PER_ROW)
+    .enumerate()
+    .flat_map(|(y, row)| {
+      row
+        .iter()
+        .enumerate()
+        .filter(|(_, byte)| **byte != 0)
+        .flat_map(move |(column, &byte)| {
+          // the leftmost pixel is the top bit
+          (0..8)
+            .filter(move |bit| byte & (0x80 >> bit) != 0)
+            .map(move |bit| {
+              Pixel(
+                Point::new((column * 8 + bit) as i32, y as i32),
+                BinaryColor::On,
+              )
+            })
+        })
+    })
+}
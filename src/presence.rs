@@ -0,0 +1,107 @@
+use std::{
+  net::Ipv4Addr,
+  time::{Duration, Instant},
+};
+
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_svc::ping::{self, EspPing};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  config::SharedConfig,
+  events::{Bus, Event},
+  state::SharedState,
+};
+
+/// How often the phones are looked for
+const SCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Whether anyone is home, going by their phones
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Presence {
+  Home,
+  Away,
+}
+
+/// Ping the phones in the presence settings every minute in a background
+/// thread and keep `state` up to date. Phones sleep and miss pings, so
+/// everyone only counts as away once no phone has answered for
+/// `away_after_min`. Arriving and leaving go on the event bus.
+pub fn spawn(
+  config: SharedConfig,
+  state: SharedState,
+  events: Bus,
+) -> anyhow::Result<()> {
+  std::thread::Builder::new()
+    .name("presence".to_string())
+    .stack_size(6 * 1024)
+    .spawn(move || {
+      let mut pinger = EspPing::default();
+      let mut last_seen: Option<Instant> = None;
+      let mut since = Instant::now();
+      loop {
+        let options = config.lock().unwrap().presence.clone();
+        let phones: Vec<Ipv4Addr> = options
+          .phones
+          .iter()
+          .filter_map(|ip| ip.parse().ok())
+          .collect();
+        let presence = if phones.is_empty() {
+          last_seen = None;
+          since = Instant::now();
+          None
+        } else {
+          if phones.iter().any(|&phone| answers(&mut pinger, phone)) {
+            last_seen = Some(Instant::now());
+          }
+          // until the first answer, the wait starts from when the phones
+          // were set
+          let quiet = last_seen.unwrap_or(since).elapsed();
+          let away_after =
+            Duration::from_secs(u64::from(options.away_after_min) * 60);
+          if last_seen.is_some() && quiet < away_after {
+            Some(Presence::Home)
+          } else if quiet >= away_after {
+            Some(Presence::Away)
+          } else {
+            None
+          }
+        };
+        let previous = {
+          let mut state = state.lock().unwrap();
+          std::mem::replace(&mut state.presence, presence)
+        };
+        match (previous, presence) {
+          (Some(Presence::Away), Some(Presence::Home)) => {
+            log::info!("Someone came home");
+            events.publish(Event::Arrived);
+          }
+          (Some(Presence::Home), Some(Presence::Away)) => {
+            log::info!("Everyone left");
+            events.publish(Event::Left);
+          }
+          _ => {}
+        }
+        FreeRtos::delay_ms(SCAN_INTERVAL.as_millis() as u32);
+      }
+    })?;
+  Ok(())
+}
+
+/// Whether `phone` answers any of a few pings
+fn answers(pinger: &mut EspPing, phone: Ipv4Addr) -> bool {
+  let configuration = ping::Configuration {
+    count: 3,
+    interval: Duration::from_millis(500),
+    timeout: Duration::from_secs(1),
+    ..Default::default()
+  };
+  match pinger.ping(phone, &configuration) {
+    Ok(summary) => summary.received > 0,
+    Err(error) => {
+      log::warn!("Could not ping {}: {:?}", phone, error);
+      false
+    }
+  }
+}
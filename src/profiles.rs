@@ -0,0 +1,221 @@
+use std::collections::BTreeMap;
+
+use esp_idf_hal::delay::FreeRtos;
+use esp_idf_svc::nvs::{EspDefaultNvs, EspDefaultNvsPartition};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  config::{Config, SharedConfig},
+  secrets,
+  wifi::{self, Credentials, SharedWifi},
+};
+
+const NAMESPACE: &str = "profiles";
+const KEY: &str = "profiles";
+
+pub const MAX_PROFILES: usize = 4;
+pub const MAX_NAME_LEN: usize = 12;
+
+/// Settings that belong to a place rather than to the device. Pins, CORS
+/// and the admin token stay as they are when switching profiles.
+const SECTIONS: [&str; 5] = [
+  "location",
+  "refresh",
+  "quiet_hours",
+  "display",
+  "notifications",
+];
+
+/// Settings and WiFi network saved under a name such as "home" or "office"
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Profile {
+  pub name: String,
+  /// `SECTIONS` of the settings, as JSON
+  settings: serde_json::Value,
+  /// SSID to join, its password is kept with the other secrets
+  ssid: Option<String>,
+}
+
+/// Saved profiles and the one applied last
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+  pub list: Vec<Profile>,
+  pub active: Option<String>,
+}
+
+impl Profiles {
+  pub fn load(partition: EspDefaultNvsPartition) -> anyhow::Result<Self> {
+    let nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    let Some(len) = nvs.str_len(KEY)? else {
+      return Ok(Self::default());
+    };
+    let mut buf = vec![0_u8; len];
+    match nvs.get_str(KEY, &mut buf)? {
+      Some(json) => Ok(serde_json::from_str(json)?),
+      None => Ok(Self::default()),
+    }
+  }
+
+  fn save(&self, partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+    let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    nvs.set_str(KEY, &serde_json::to_string(self)?)?;
+    Ok(())
+  }
+
+  pub fn erase(partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+    let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    nvs.remove(KEY)?;
+    Ok(())
+  }
+
+  pub fn names(&self) -> Vec<String> {
+    self
+      .list
+      .iter()
+      .map(|profile| profile.name.clone())
+      .collect()
+  }
+
+  /// Save the current settings and WiFi network as profile `name`,
+  /// replacing a profile of the same name
+  pub fn store(
+    partition: EspDefaultNvsPartition,
+    name: &str,
+    config: &Config,
+  ) -> anyhow::Result<()> {
+    if name.is_empty()
+      || name.len() > MAX_NAME_LEN
+      || !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+      anyhow::bail!(
+        "profile name must be 1-{MAX_NAME_LEN} letters, digits, - or _"
+      );
+    }
+    let mut profiles = Self::load(partition.clone())?;
+    profiles.list.retain(|profile| profile.name != name);
+    if profiles.list.len() >= MAX_PROFILES {
+      anyhow::bail!("at most {MAX_PROFILES} profiles can be saved");
+    }
+    let json = config.without_secrets()?;
+    let settings = SECTIONS
+      .iter()
+      .map(|section| (section.to_string(), json[*section].clone()))
+      .collect::<serde_json::Map<_, _>>();
+    // an enterprise login with its CA certificate is too big to keep per
+    // profile, such networks are left out and stay joined on activation
+    let credentials = Credentials::load(partition.clone())?
+      .filter(|credentials| credentials.enterprise.is_none());
+    let mut passwords = wifi_passwords(partition.clone())?;
+    match &credentials {
+      Some(credentials) => {
+        passwords.insert(name.to_string(), credentials.password.clone())
+      }
+      None => passwords.remove(name),
+    };
+    secrets::set(
+      partition.clone(),
+      secrets::PROFILE_WIFI,
+      &serde_json::to_string(&passwords)?,
+    )?;
+    profiles.list.push(Profile {
+      name: name.to_string(),
+      settings: settings.into(),
+      ssid: credentials.map(|credentials| credentials.ssid),
+    });
+    profiles.active = Some(name.to_string());
+    profiles.save(partition)
+  }
+
+  pub fn remove(
+    partition: EspDefaultNvsPartition,
+    name: &str,
+  ) -> anyhow::Result<bool> {
+    let mut profiles = Self::load(partition.clone())?;
+    let count = profiles.list.len();
+    profiles.list.retain(|profile| profile.name != name);
+    if profiles.list.len() == count {
+      return Ok(false);
+    }
+    if profiles.active.as_deref() == Some(name) {
+      profiles.active = None;
+    }
+    let mut passwords = wifi_passwords(partition.clone())?;
+    passwords.remove(name);
+    secrets::set(
+      partition.clone(),
+      secrets::PROFILE_WIFI,
+      &serde_json::to_string(&passwords)?,
+    )?;
+    profiles.save(partition)?;
+    Ok(true)
+  }
+
+  /// Apply and save the settings of profile `name` and switch to its WiFi
+  /// network in the background, so a web request can still be answered
+  /// over the old one. False when there is no such profile.
+  pub fn activate(
+    partition: EspDefaultNvsPartition,
+    name: &str,
+    config: &SharedConfig,
+    wifi: &SharedWifi,
+  ) -> anyhow::Result<bool> {
+    let mut profiles = Self::load(partition.clone())?;
+    let Some(profile) = profiles.list.iter().find(|p| p.name == name).cloned()
+    else {
+      return Ok(false);
+    };
+
+    let current = config.lock().unwrap().clone();
+    let mut json = current.without_secrets()?;
+    if let Some(settings) = profile.settings.as_object() {
+      for (section, value) in settings {
+        json[section] = value.clone();
+      }
+    }
+    let updated = Config::with_secrets_of(json, &current)?;
+    updated.validate()?;
+    updated.save(partition.clone())?;
+    *config.lock().unwrap() = updated;
+
+    if let Some(ssid) = profile.ssid {
+      let password = wifi_passwords(partition.clone())?
+        .remove(name)
+        .unwrap_or_default();
+      let credentials = Credentials {
+        ssid,
+        password,
+        enterprise: None,
+      };
+      credentials.save(partition.clone())?;
+      let wifi = wifi.clone();
+      std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(move || {
+          FreeRtos::delay_ms(500);
+          if let Err(error) =
+            wifi::apply(&mut wifi.lock().unwrap(), &credentials)
+          {
+            log::error!("Could not join profile network: {:?}", error);
+          }
+        })?;
+    }
+
+    profiles.active = Some(name.to_string());
+    profiles.save(partition)?;
+    log::info!("Profile {} activated", name);
+    Ok(true)
+  }
+}
+
+/// WiFi password of each profile by profile name
+fn wifi_passwords(
+  partition: EspDefaultNvsPartition,
+) -> anyhow::Result<BTreeMap<String, String>> {
+  Ok(match secrets::get(partition, secrets::PROFILE_WIFI)? {
+    Some(json) => serde_json::from_str(&json)?,
+    None => BTreeMap::new(),
+  })
+}
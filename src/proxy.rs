@@ -0,0 +1,230 @@
+use std::{
+  io::{self, Read, Write},
+  net::{TcpStream, ToSocketAddrs},
+  os::fd::{AsRawFd, IntoRawFd},
+  sync::OnceLock,
+};
+
+use anyhow::Context;
+use esp_idf_svc::{
+  http::Method,
+  sys::EspError,
+  tls::{self, EspTls},
+};
+
+use crate::{
+  config,
+  http1::{self, Head},
+  http_client::RequestOptions,
+  tls_pins::{self, Endpoint},
+};
+
+/// The proxy from the settings, set once at boot. Without one requests go
+/// straight out.
+static PROXY: OnceLock<config::Proxy> = OnceLock::new();
+
+/// Sends outgoing requests through `proxy` from now on, unless its host is
+/// empty
+pub fn init(proxy: &config::Proxy) {
+  if proxy.host.is_empty() {
+    return;
+  }
+  log::info!("Outgoing requests go through {}:{}", proxy.host, proxy.port);
+  let _ = PROXY.set(proxy.clone());
+}
+
+pub fn get() -> Option<&'static config::Proxy> {
+  PROXY.get()
+}
+
+/// The proxy answered with something other than 2xx to setting up a tunnel
+#[derive(Debug)]
+pub struct Refused(pub u16);
+
+impl std::fmt::Display for Refused {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self.0 {
+      407 => write!(f, "Proxy wants other credentials"),
+      status => write!(f, "Proxy refused with status: {}", status),
+    }
+  }
+}
+
+impl std::error::Error for Refused {}
+
+/// A request through the proxy, sent like one on an `EspHttpConnection`:
+/// the body is written, then the response is read. HTTPS goes through a
+/// tunnel checked against the CA bundle or the pin of the endpoint, plain
+/// HTTP is handed to the proxy as is. Redirects are not followed.
+pub struct Connection {
+  request: Option<Stream>,
+  response: Option<http1::Response<Stream>>,
+}
+
+impl Connection {
+  /// Connects to `proxy` and sends the `method` request line and `headers`
+  /// for `url`
+  pub fn open(
+    proxy: &config::Proxy,
+    endpoint: Option<Endpoint>,
+    method: Method,
+    url: &str,
+    headers: &[(&str, &str)],
+    options: &RequestOptions,
+  ) -> anyhow::Result<Self> {
+    let url = http1::split_url(url).context("not an http(s) URL")?;
+    let address = (proxy.host.as_str(), proxy.port)
+      .to_socket_addrs()?
+      .next()
+      .context("proxy host not found")?;
+    let mut tcp = TcpStream::connect_timeout(&address, options.timeout)?;
+    tcp.set_read_timeout(Some(options.timeout))?;
+    tcp.set_write_timeout(Some(options.timeout))?;
+    let authorization = (!proxy.username.is_empty())
+      .then(|| http1::basic_auth(&proxy.username, &proxy.password));
+    let authority = format!("{}:{}", url.host, url.port);
+    let mut headers = headers.to_vec();
+
+    let mut stream = if url.https {
+      let mut connect = authorization
+        .iter()
+        .map(|value| ("proxy-authorization", value.as_str()))
+        .collect::<Vec<_>>();
+      connect.push(("user-agent", "pippo"));
+      let head =
+        http1::request_head("CONNECT", &authority, &authority, &connect);
+      tcp.write_all(head.as_bytes())?;
+      let status = Head::read(&mut tcp)?.status;
+      if !(200..=299).contains(&status) {
+        return Err(Refused(status).into());
+      }
+      Stream::Tls(handshake(tcp, endpoint, url.host, options)?)
+    } else {
+      if let Some(value) = &authorization {
+        headers.push(("proxy-authorization", value.as_str()));
+      }
+      Stream::Plain(tcp)
+    };
+
+    // a proxy is given the whole URL, a server behind a tunnel the path
+    let target = match &stream {
+      Stream::Plain(_) => format!("http://{}{}", authority, url.path),
+      Stream::Tls(_) => url.path,
+    };
+    // the variants are named after the methods
+    let method = format!("{method:?}").to_uppercase();
+    let head = http1::request_head(&method, &target, &authority, &headers);
+    stream.write_all(head.as_bytes())?;
+    Ok(Self {
+      request: Some(stream),
+      response: None,
+    })
+  }
+
+  pub fn write_all(&mut self, buf: &[u8]) -> anyhow::Result<()> {
+    let stream = self.request.as_mut().context("request already sent")?;
+    Ok(stream.write_all(buf)?)
+  }
+
+  /// Reads the status and headers of the response
+  pub fn initiate_response(&mut self) -> anyhow::Result<()> {
+    let stream = self.request.take().context("request already sent")?;
+    self.response = Some(http1::Response::read(stream)?);
+    Ok(())
+  }
+
+  /// 0 until [`Connection::initiate_response`]
+  pub fn status(&self) -> u16 {
+    self
+      .response
+      .as_ref()
+      .map_or(0, |response| response.head.status)
+  }
+
+  pub fn header(&self, name: &str) -> Option<&str> {
+    self.response.as_ref()?.head.header(name)
+  }
+
+  pub fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match &mut self.response {
+      Some(response) => response.read(buf),
+      None => Err(io::Error::other("response not read yet")),
+    }
+  }
+}
+
+/// TLS with `host` over the tunnel in `tcp`
+fn handshake(
+  tcp: TcpStream,
+  endpoint: Option<Endpoint>,
+  host: &str,
+  options: &RequestOptions,
+) -> anyhow::Result<EspTls<Socket>> {
+  let mut session = EspTls::adopt(Socket(Some(tcp)))?;
+  let mut negotiate = |pinned: bool| -> anyhow::Result<()> {
+    session.negotiate(
+      host,
+      &tls::Config {
+        common_name: Some(host),
+        timeout_ms: options.timeout.as_millis() as u32,
+        use_global_ca_store: pinned,
+        use_crt_bundle_attach: !pinned,
+        ..tls::Config::new()
+      },
+    )?;
+    Ok(())
+  };
+  match endpoint {
+    Some(endpoint) => tls_pins::with(endpoint, negotiate)?,
+    None => negotiate(false)?,
+  }
+  Ok(session)
+}
+
+/// A TCP connection handed over to esp-tls, which closes it
+struct Socket(Option<TcpStream>);
+
+impl tls::Socket for Socket {
+  fn handle(&self) -> i32 {
+    self.0.as_ref().map_or(-1, AsRawFd::as_raw_fd)
+  }
+
+  fn release(&mut self) -> Result<(), EspError> {
+    // esp-tls closes the descriptor, so the stream must not
+    if let Some(tcp) = self.0.take() {
+      let _ = tcp.into_raw_fd();
+    }
+    Ok(())
+  }
+}
+
+enum Stream {
+  Plain(TcpStream),
+  Tls(EspTls<Socket>),
+}
+
+impl Read for Stream {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Stream::Plain(tcp) => tcp.read(buf),
+      Stream::Tls(tls) => tls.read(buf).map_err(tls_error),
+    }
+  }
+}
+
+impl Write for Stream {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      Stream::Plain(tcp) => tcp.write(buf),
+      Stream::Tls(tls) => tls.write(buf).map_err(tls_error),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+fn tls_error(error: EspError) -> io::Error {
+  io::Error::other(format!("TLS failed: {:?}", error))
+}
@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+
+#[cfg(feature = "psram")]
+use esp_idf_svc::sys::{
+  heap_caps_aligned_alloc, heap_caps_get_free_size, heap_caps_get_total_size,
+  MALLOC_CAP_8BIT, MALLOC_CAP_SPIRAM,
+};
+
+/// Room for `capacity` items in PSRAM when built with the `psram` feature on
+/// a module that has it, in internal RAM otherwise. WiFi and TLS can only
+/// use internal RAM, so the big buffers are better off outside it.
+///
+/// Growing past `capacity` moves the items back to internal RAM, so ask for
+/// as much as the buffer ever holds.
+pub fn vec<T>(capacity: usize) -> Vec<T> {
+  #[cfg(feature = "psram")]
+  if let Some(items) = external(capacity) {
+    return items;
+  }
+  Vec::with_capacity(capacity)
+}
+
+/// [`vec`] for a ring buffer
+pub fn vec_deque<T>(capacity: usize) -> VecDeque<T> {
+  // keeps the buffer of the Vec
+  VecDeque::from(vec(capacity))
+}
+
+/// [`vec`] for text of up to `capacity` bytes
+pub fn string(capacity: usize) -> String {
+  String::from_utf8(vec(capacity)).unwrap_or_default()
+}
+
+#[cfg(feature = "psram")]
+fn external<T>(capacity: usize) -> Option<Vec<T>> {
+  let size = capacity.checked_mul(std::mem::size_of::<T>())?;
+  if size == 0 {
+    return None;
+  }
+  let items = unsafe {
+    heap_caps_aligned_alloc(
+      std::mem::align_of::<T>(),
+      size,
+      MALLOC_CAP_SPIRAM | MALLOC_CAP_8BIT,
+    )
+  } as *mut T;
+  if items.is_null() {
+    return None;
+  }
+  // The std allocator frees with ESP-IDF's free(), which takes memory from
+  // any of its heaps, so dropping the Vec is fine
+  Some(unsafe { Vec::from_raw_parts(items, 0, capacity) })
+}
+
+/// Log how much PSRAM is free, or that the module has none
+#[cfg(feature = "psram")]
+pub fn log_size() {
+  let total = unsafe { heap_caps_get_total_size(MALLOC_CAP_SPIRAM) };
+  if total == 0 {
+    log::warn!("Built for PSRAM but the module has none, using internal RAM");
+    return;
+  }
+  let free = unsafe { heap_caps_get_free_size(MALLOC_CAP_SPIRAM) };
+  log::info!("PSRAM: {} of {} KB free", free / 1024, total / 1024);
+}
@@ -0,0 +1,132 @@
+use std::time::{Duration, Instant};
+
+use chrono::Datelike;
+use embedded_graphics::prelude::*;
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+
+use crate::{
+  clock,
+  config::Config,
+  display::Display,
+  fetch, http_client,
+  persist::Persisted,
+  state::{DailyQuote, SharedState},
+  statusbar,
+  typography::{self, Align, Font},
+};
+
+/// Lines of quote text on the screen, the author goes below them
+const MAX_LINES: usize = 4;
+/// Wait before trying again after a failed fetch, the API allows only a few
+/// requests a minute
+const RETRY: Duration = Duration::from_secs(10 * 60);
+
+/// Fetches the quote of the day once per day and keeps it in `state`. The
+/// last one is cached in NVS, so a restart doesn't fetch it again.
+pub struct Source {
+  state: SharedState,
+  cache: Persisted<DailyQuote>,
+  quote: Option<DailyQuote>,
+  failed_at: Option<Instant>,
+}
+
+impl Source {
+  pub fn new(state: SharedState, nvs: EspDefaultNvsPartition) -> Self {
+    let (cache, quote) = Persisted::load(nvs, "quote");
+    Self {
+      state,
+      cache,
+      quote,
+      failed_at: None,
+    }
+  }
+}
+
+impl fetch::Source for Source {
+  fn poll(&mut self, config: &Config) {
+    if let Some(quote) = &self.quote {
+      self.cache.update(quote);
+    }
+    if !config.quote.enabled {
+      self.state.lock().unwrap().quote = None;
+      return;
+    }
+    self.state.lock().unwrap().quote.clone_from(&self.quote);
+    let today = clock::local_now().date_naive();
+    // the date means nothing until NTP has synced
+    if today.year() < 2024
+      || self.failed_at.is_some_and(|at| at.elapsed() < RETRY)
+    {
+      return;
+    }
+    let today = today.to_string();
+    if self.quote.as_ref().is_some_and(|quote| quote.date == today) {
+      return;
+    }
+    match fetch(&today) {
+      Ok(quote) => {
+        self.failed_at = None;
+        self.quote = Some(quote);
+      }
+      Err(error) => {
+        log::warn!("Quote of the day failed: {:?}", error);
+        self.failed_at = Some(Instant::now());
+      }
+    }
+  }
+}
+
+/// Today's quote from ZenQuotes, which needs no API key
+pub fn fetch(date: &str) -> anyhow::Result<DailyQuote> {
+  log::info!("Fetching the quote of the day");
+  let parsed: serde_json::Value =
+    http_client::get_json("https://zenquotes.io/api/today")?;
+  let quote = &parsed[0];
+  let text = quote["q"]
+    .as_str()
+    .ok_or_else(|| anyhow::anyhow!("no quote in response"))?;
+  Ok(DailyQuote {
+    text: text.trim().to_string(),
+    author: quote["a"].as_str().unwrap_or("").trim().to_string(),
+    date: date.to_string(),
+  })
+}
+
+/// Quote of the day as a page of the Home screen, wrapped over as many
+/// lines as fit and cut short after that
+pub fn draw(display: &mut Display<'_>, quote: &DailyQuote) {
+  let style = Font::Small.style();
+  let top = statusbar::HEIGHT + 2;
+  let mut lines =
+    typography::wrap(&quote.text, typography::PANEL_WIDTH as u32 - 2, &style);
+  if lines.len() > MAX_LINES {
+    lines.truncate(MAX_LINES);
+    let last = &mut lines[MAX_LINES - 1];
+    while typography::width(
+      &typography::line(format_args!("{last}...")),
+      &style,
+    ) > typography::PANEL_WIDTH as u32 - 2
+    {
+      last.pop();
+    }
+    last.push_str("...");
+  }
+  for (row, line) in lines.iter().enumerate() {
+    typography::draw(
+      display,
+      line,
+      Point::new(1, top + 8 * row as i32),
+      Align::Left,
+      style,
+    );
+  }
+  if !quote.author.is_empty() {
+    typography::draw(
+      display,
+      &typography::line(format_args!("- {}", quote.author)),
+      Point::new(typography::PANEL_WIDTH - 1, top + 8 * MAX_LINES as i32 + 2),
+      Align::Right,
+      style,
+    );
+  }
+}
@@ -0,0 +1,65 @@
+use std::{
+  collections::HashMap,
+  net::IpAddr,
+  sync::{Arc, Mutex},
+  time::Instant,
+};
+
+use crate::config::RateLimit;
+
+pub type SharedLimiter = Arc<Mutex<Limiter>>;
+
+/// Buckets kept at most, the one idle the longest makes room for a new one
+const MAX_BUCKETS: usize = 32;
+
+struct Bucket {
+  tokens: f32,
+  updated: Instant,
+}
+
+/// Token bucket per client and route. Each bucket holds up to `burst`
+/// requests and refills at `per_minute`.
+#[derive(Default)]
+pub struct Limiter {
+  buckets: HashMap<(IpAddr, &'static str), Bucket>,
+}
+
+impl Limiter {
+  /// Take a token for a request from `client` to `route`, false when the
+  /// client has none left
+  pub fn allow(
+    &mut self,
+    client: IpAddr,
+    route: &'static str,
+    limit: &RateLimit,
+  ) -> bool {
+    let now = Instant::now();
+    let burst = limit.burst as f32;
+    if !self.buckets.contains_key(&(client, route))
+      && self.buckets.len() >= MAX_BUCKETS
+    {
+      if let Some(idle) = self
+        .buckets
+        .iter()
+        .min_by_key(|(_, bucket)| bucket.updated)
+        .map(|(key, _)| *key)
+      {
+        self.buckets.remove(&idle);
+      }
+    }
+    let bucket = self.buckets.entry((client, route)).or_insert(Bucket {
+      tokens: burst,
+      updated: now,
+    });
+    let refill = now.duration_since(bucket.updated).as_secs_f32()
+      * limit.per_minute as f32
+      / 60.0;
+    bucket.tokens = (bucket.tokens + refill).min(burst);
+    bucket.updated = now;
+    if bucket.tokens < 1.0 {
+      return false;
+    }
+    bucket.tokens -= 1.0;
+    true
+  }
+}
@@ -0,0 +1,289 @@
+use std::{
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  clock::Instant,
+  ui::{ButtonEvent, UiState},
+};
+
+/// Recording or replaying, shared by the console and the main loop
+pub type SharedSession = Arc<Mutex<Session>>;
+
+/// Entries kept in a recording, what happens after is left out
+pub const MAX_ENTRIES: usize = 256;
+/// How late a recorded screen change may come on replay, for screens that
+/// change on a timer rather than on a press
+const SCREEN_SLACK: Duration = Duration::from_secs(1);
+
+/// Something that happened, for a recording
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Input {
+  /// What the debounced button did
+  Button(ButtonEvent),
+  /// The PIR started or stopped seeing motion
+  Motion(bool),
+  /// The screen changed. Not fed back on replay but checked, a different
+  /// screen is the bug being reproduced.
+  Screen(UiState),
+}
+
+/// One line of a recording, e.g. `{"at_ms":1420,"button":"long"}`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+  /// Since the recording started
+  pub at_ms: u64,
+  #[serde(flatten)]
+  pub input: Input,
+}
+
+/// Entries of a recording written one JSON object per line, the lines
+/// of [`to_lines`]. Blank lines and lines starting with `#` are skipped.
+pub fn parse(text: &str) -> Result<Vec<Entry>, String> {
+  text
+    .lines()
+    .enumerate()
+    .filter(|(_, line)| {
+      let line = line.trim();
+      !line.is_empty() && !line.starts_with('#')
+    })
+    .map(|(number, line)| {
+      serde_json::from_str(line)
+        .map_err(|error| format!("line {}: {}", number + 1, error))
+    })
+    .collect()
+}
+
+/// `entries` one JSON object per line, for flash and for test files
+pub fn to_lines(entries: &[Entry]) -> String {
+  entries
+    .iter()
+    .filter_map(|entry| serde_json::to_string(entry).ok())
+    .map(|line| line + "\n")
+    .collect()
+}
+
+/// Collects what happens from when recording started
+#[derive(Clone, Debug)]
+pub struct Recorder {
+  started: Instant,
+  entries: Vec<Entry>,
+  motion: bool,
+  /// The screens still have to start over from Home
+  restart: bool,
+}
+
+impl Recorder {
+  pub fn new(now: Instant) -> Self {
+    Self {
+      started: now,
+      entries: Vec::new(),
+      motion: false,
+      restart: true,
+    }
+  }
+
+  fn push(&mut self, now: Instant, input: Input) {
+    if self.entries.len() < MAX_ENTRIES {
+      self.entries.push(Entry {
+        at_ms: now.duration_since(self.started).as_millis() as u64,
+        input,
+      });
+    }
+  }
+}
+
+/// Feeds a recording back at the times it was recorded, checking the
+/// screens it went through
+#[derive(Clone, Debug)]
+pub struct Player {
+  started: Instant,
+  entries: Vec<Entry>,
+  next: usize,
+  motion: bool,
+  restart: bool,
+  /// The first screen that differed from the recording
+  mismatch: Option<String>,
+}
+
+impl Player {
+  pub fn new(entries: Vec<Entry>, now: Instant) -> Self {
+    Self {
+      started: now,
+      entries,
+      next: 0,
+      motion: false,
+      restart: true,
+      mismatch: None,
+    }
+  }
+
+  fn elapsed_ms(&self, now: Instant) -> u64 {
+    now.duration_since(self.started).as_millis() as u64
+  }
+
+  /// The recorded press due by `now`, at most one a frame as the button
+  /// gives them, after the motion changes due before it
+  fn press(&mut self, now: Instant) -> Option<ButtonEvent> {
+    let elapsed = self.elapsed_ms(now);
+    while let Some(&entry) = self.entries.get(self.next) {
+      match entry.input {
+        _ if entry.at_ms > elapsed => return None,
+        Input::Motion(motion) => self.motion = motion,
+        Input::Button(event) => {
+          self.next += 1;
+          return Some(event);
+        }
+        Input::Screen(screen) => {
+          // waits for the change, unless it is long overdue
+          if entry.at_ms + (SCREEN_SLACK.as_millis() as u64) > elapsed {
+            return None;
+          }
+          self.fail(format!(
+            "at {} ms the screen should have changed to {:?}",
+            entry.at_ms, screen
+          ));
+        }
+      }
+      self.next += 1;
+    }
+    None
+  }
+
+  /// The screen changed to `screen`
+  fn screen(&mut self, screen: UiState, now: Instant) {
+    match self.entries.get(self.next) {
+      Some(Entry {
+        input: Input::Screen(expected),
+        ..
+      }) => {
+        if *expected != screen {
+          self.fail(format!(
+            "at {} ms the screen changed to {:?}, recorded {:?}",
+            self.elapsed_ms(now),
+            screen,
+            expected
+          ));
+        }
+        self.next += 1;
+      }
+      _ => self.fail(format!(
+        "at {} ms the screen changed to {:?}, not in the recording",
+        self.elapsed_ms(now),
+        screen
+      )),
+    }
+  }
+
+  fn fail(&mut self, mismatch: String) {
+    self.mismatch.get_or_insert(mismatch);
+  }
+
+  pub fn is_finished(&self) -> bool {
+    self.next >= self.entries.len()
+  }
+}
+
+/// What the inputs are doing, see [`SharedSession`]
+#[derive(Clone, Debug, Default)]
+pub enum Session {
+  #[default]
+  Idle,
+  Recording(Recorder),
+  Replaying(Player),
+}
+
+impl Session {
+  /// Start recording from the Home screen
+  pub fn record(&mut self, now: Instant) {
+    *self = Self::Recording(Recorder::new(now));
+  }
+
+  /// Start replaying `entries` from the Home screen
+  pub fn replay(&mut self, entries: Vec<Entry>, now: Instant) {
+    *self = Self::Replaying(Player::new(entries, now));
+  }
+
+  /// Stop recording, returning what was recorded
+  pub fn stop(&mut self) -> Option<Vec<Entry>> {
+    match std::mem::take(self) {
+      Self::Recording(recorder) => Some(recorder.entries),
+      _ => None,
+    }
+  }
+
+  /// Recorded inputs are being played back, they are due every frame
+  pub fn is_replaying(&self) -> bool {
+    matches!(self, Self::Replaying(_))
+  }
+
+  /// Whether recording or replay just started, true once. The screens
+  /// start over from Home then, without it counting as a change.
+  pub fn take_restart(&mut self) -> bool {
+    match self {
+      Self::Recording(Recorder { restart, .. })
+      | Self::Replaying(Player { restart, .. }) => std::mem::take(restart),
+      Self::Idle => false,
+    }
+  }
+
+  /// The button's event this frame. While replaying the recorded press
+  /// replaces `pressed`, while recording `pressed` is kept.
+  pub fn press(
+    &mut self,
+    pressed: Option<ButtonEvent>,
+    now: Instant,
+  ) -> Option<ButtonEvent> {
+    match self {
+      Self::Idle => pressed,
+      Self::Recording(recorder) => {
+        if let Some(event) = pressed {
+          recorder.push(now, Input::Button(event));
+        }
+        pressed
+      }
+      Self::Replaying(player) => player.press(now),
+    }
+  }
+
+  /// Whether the PIR sees motion, the recorded reading while replaying
+  pub fn motion(&mut self, motion: bool, now: Instant) -> bool {
+    match self {
+      Self::Idle => motion,
+      Self::Recording(recorder) => {
+        if motion != recorder.motion {
+          recorder.motion = motion;
+          recorder.push(now, Input::Motion(motion));
+        }
+        motion
+      }
+      Self::Replaying(player) => player.motion,
+    }
+  }
+
+  /// The screen changed to `screen`
+  pub fn screen(&mut self, screen: UiState, now: Instant) {
+    match self {
+      Self::Idle => {}
+      Self::Recording(recorder) => recorder.push(now, Input::Screen(screen)),
+      Self::Replaying(player) => player.screen(screen, now),
+    }
+  }
+
+  /// How the replay went once it is over, the first screen that differed
+  /// from the recording as the error. The session is idle again after.
+  pub fn finished(&mut self) -> Option<Result<(), String>> {
+    match self {
+      Self::Replaying(player) if player.is_finished() => {
+        let result = player.mismatch.take().map_or(Ok(()), Err);
+        *self = Self::Idle;
+        Some(result)
+      }
+      _ => None,
+    }
+  }
+}
@@ -0,0 +1,111 @@
+use std::{
+  sync::{Condvar, Mutex},
+  time::Duration,
+};
+
+/// Outgoing requests open at once. Each TLS connection takes tens of KB
+/// of RAM, two leave room for a notification next to a firmware download.
+pub const MAX_OPEN: usize = 2;
+
+static QUEUE: Mutex<Queue> = Mutex::new(Queue::new(MAX_OPEN));
+/// Signalled whenever a request finishes
+static FREED: Condvar = Condvar::new();
+
+/// Which request goes first when several wait for a connection, most
+/// urgent first
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+  /// Webhooks and other messages people wait for
+  Notification,
+  /// Firmware downloads
+  Update,
+  /// Keeping data such as the weather fresh
+  #[default]
+  Refresh,
+}
+
+/// Requests open and waiting. Waiting ones start by priority, and in the
+/// order they came in within one.
+#[derive(Debug)]
+pub struct Queue {
+  limit: usize,
+  open: usize,
+  /// Priority and ticket of each waiting request
+  waiting: Vec<(Priority, u64)>,
+  next_ticket: u64,
+}
+
+impl Queue {
+  pub const fn new(limit: usize) -> Self {
+    Self {
+      limit,
+      open: 0,
+      waiting: Vec::new(),
+      next_ticket: 0,
+    }
+  }
+
+  /// Puts a request in line, returns its ticket
+  pub fn join(&mut self, priority: Priority) -> u64 {
+    let ticket = self.next_ticket;
+    self.next_ticket += 1;
+    self.waiting.push((priority, ticket));
+    ticket
+  }
+
+  /// Starts the request with `ticket` if a connection is free and nothing
+  /// more urgent or older waits for it
+  pub fn try_start(&mut self, ticket: u64) -> bool {
+    let first = self.waiting.iter().min().map(|(_, first)| *first);
+    if self.open >= self.limit || first != Some(ticket) {
+      return false;
+    }
+    self.waiting.retain(|(_, waiting)| *waiting != ticket);
+    self.open += 1;
+    true
+  }
+
+  /// Takes the request with `ticket` out of line without starting it
+  pub fn leave(&mut self, ticket: u64) {
+    self.waiting.retain(|(_, waiting)| *waiting != ticket);
+  }
+
+  pub fn finish(&mut self) {
+    self.open = self.open.saturating_sub(1);
+  }
+
+  pub fn waiting(&self) -> usize {
+    self.waiting.len()
+  }
+}
+
+/// A connection a request may use, given back when dropped
+#[derive(Debug)]
+pub struct Slot(());
+
+impl Drop for Slot {
+  fn drop(&mut self) {
+    QUEUE.lock().unwrap().finish();
+    FREED.notify_all();
+  }
+}
+
+/// Waits up to `timeout` for a connection, `None` if none came free
+pub fn acquire(priority: Priority, timeout: Duration) -> Option<Slot> {
+  let mut queue = QUEUE.lock().unwrap();
+  let ticket = queue.join(priority);
+  let (mut queue, waited) = FREED
+    .wait_timeout_while(queue, timeout, |queue| !queue.try_start(ticket))
+    .unwrap();
+  let started = !waited.timed_out();
+  if !started {
+    queue.leave(ticket);
+  }
+  let next = queue.waiting() > 0;
+  drop(queue);
+  if next {
+    // whoever is next in line may be able to start now
+    FREED.notify_all();
+  }
+  started.then_some(Slot(()))
+}
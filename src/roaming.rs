@@ -0,0 +1,36 @@
+/// An access point of the network the station is on, as a scan or the
+/// driver reports it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessPoint {
+  pub bssid: [u8; 6],
+  pub channel: u8,
+  /// dBm
+  pub rssi: i8,
+}
+
+/// The strongest of `candidates` that beats `current` by at least
+/// `margin_db`, so that two access points of about the same strength don't
+/// pass the station back and forth
+pub fn better(
+  current: &AccessPoint,
+  candidates: &[AccessPoint],
+  margin_db: u8,
+) -> Option<AccessPoint> {
+  candidates
+    .iter()
+    .filter(|candidate| candidate.bssid != current.bssid)
+    .max_by_key(|candidate| candidate.rssi)
+    .filter(|best| {
+      i16::from(best.rssi) >= i16::from(current.rssi) + i16::from(margin_db)
+    })
+    .copied()
+}
+
+/// `aa:bb:cc:dd:ee:ff`
+pub fn format_bssid(bssid: &[u8; 6]) -> String {
+  bssid
+    .iter()
+    .map(|byte| format!("{byte:02x}"))
+    .collect::<Vec<_>>()
+    .join(":")
+}
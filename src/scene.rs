@@ -0,0 +1,137 @@
+use std::sync::{Arc, Mutex};
+
+use embedded_graphics::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "servo")]
+use crate::servo;
+use crate::{
+  automation::Outputs,
+  config::Config,
+  display::Display,
+  events::Event,
+  led, statusbar,
+  typography::{self, Align, Font},
+};
+
+/// What the display shows, as set by the last scene
+pub type SharedDisplayMode = Arc<Mutex<DisplayMode>>;
+
+/// Actuator states set together under a name such as "movie" or "away".
+/// Whatever a scene leaves out stays as it is. For example:
+///
+/// ```json
+/// {"name": "movie", "servo": 90, "relay": false, "display": "dim"}
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+  pub name: String,
+  /// Servo angle, 0-180
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub servo: Option<u32>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub led: Option<led::Pattern>,
+  /// Whether the relay is switched on
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub relay: Option<bool>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub display: Option<DisplayMode>,
+}
+
+#[derive(
+  Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayMode {
+  /// At the brightness in the settings
+  #[default]
+  Normal,
+  /// At the lowest brightness
+  Dim,
+  /// Blank until the button is pressed
+  Off,
+}
+
+/// Set the scene named `name` from the settings. It goes on the event bus,
+/// for the rules and the other units in the group.
+pub fn activate(
+  name: &str,
+  config: &Config,
+  outputs: &Outputs,
+) -> anyhow::Result<()> {
+  let scene = config
+    .automation
+    .scenes
+    .iter()
+    .find(|scene| scene.name == name)
+    .ok_or_else(|| anyhow::anyhow!("no scene named {name:?}"))?;
+  apply(scene, outputs)?;
+  outputs.events.publish(Event::Scene(scene.name.clone()));
+  Ok(())
+}
+
+/// Set the actuators as `scene` says, without raising an event
+pub fn apply(scene: &Scene, outputs: &Outputs) -> anyhow::Result<()> {
+  log::info!("Scene {:?}", scene.name);
+  if let Some(angle) = scene.servo {
+    #[cfg(feature = "servo")]
+    servo::set_angle(&mut outputs.servo.lock().unwrap(), angle)?;
+    #[cfg(not(feature = "servo"))]
+    log::info!("No servo for angle {}", angle);
+  }
+  if let Some(pattern) = scene.led {
+    led::play(&outputs.led, pattern);
+  }
+  if let Some(on) = scene.relay {
+    #[cfg(feature = "relay")]
+    outputs.relay.lock().unwrap().set_level(on.into())?;
+    #[cfg(not(feature = "relay"))]
+    log::info!("No relay to switch {}", if on { "on" } else { "off" });
+  }
+  if let Some(mode) = scene.display {
+    *outputs.display.lock().unwrap() = mode;
+  }
+  outputs.state.lock().unwrap().scene = Some(scene.name.clone());
+  Ok(())
+}
+
+/// The scenes below the status bar, `selected` marked for a long press and
+/// the active one starred
+pub fn draw_list(
+  display: &mut Display<'_>,
+  scenes: &[Scene],
+  selected: usize,
+  active: Option<&str>,
+) {
+  let small_style = Font::Small.style();
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Scenes",
+    Point::new(1, top),
+    Align::Left,
+    small_style,
+  );
+  if scenes.is_empty() {
+    typography::draw_centered(display, "None set up yet", 28, small_style);
+    typography::draw_centered(display, "Add them on the web", 40, small_style);
+    return;
+  }
+  // four rows fit below the title, scrolled to keep the selection in view
+  let first = selected.saturating_sub(3);
+  for (row, scene) in scenes.iter().enumerate().skip(first).take(4) {
+    let cursor = if row == selected { ">" } else { " " };
+    let star = if active == Some(scene.name.as_str()) {
+      " *"
+    } else {
+      ""
+    };
+    typography::draw(
+      display,
+      &typography::line(format_args!("{cursor} {}{star}", scene.name)),
+      Point::new(1, top + 12 + 10 * (row - first) as i32),
+      Align::Left,
+      Font::Medium.style(),
+    );
+  }
+}
@@ -0,0 +1,29 @@
+#[cfg(target_os = "espidf")]
+use chrono::Timelike;
+
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{display::Display, history};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Activity", MenuIcon::Bars, UiState::Activity);
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  let local_now = context.device.local_now;
+  history::draw_occupancy(
+    display,
+    &context
+      .device
+      .history
+      .lock()
+      .unwrap()
+      .occupancy(local_now.naive_local()),
+    local_now.hour(),
+  );
+}
@@ -0,0 +1,16 @@
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{crypto, display::Display};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Crypto", MenuIcon::Coin, UiState::Crypto).paged();
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  crypto::draw(display, &context.device.state.prices, context.pager);
+}
@@ -0,0 +1,53 @@
+use std::fmt;
+
+use embedded_graphics::{
+  mono_font::MonoTextStyle,
+  pixelcolor::BinaryColor,
+  prelude::*,
+  text::{Baseline, Text},
+};
+
+use super::Context;
+use crate::{
+  display::Display,
+  typography::{self, Font},
+  ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState},
+};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Exit", MenuIcon::Back, UiState::Exit)
+    .press(ShortPress::TopOfMenu);
+
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  context
+    .static_screen
+    .draw(display, (UiState::Exit, None), |frame| {
+      draw_screen(frame, Font::Large.style())
+    });
+}
+
+pub fn draw_screen<D>(
+  display: &mut D,
+  text_style: MonoTextStyle<'_, BinaryColor>,
+) where
+  D: DrawTarget<Color = BinaryColor>,
+  D::Error: fmt::Debug,
+{
+  typography::draw_centered(display, "Exit", 11, text_style);
+  Text::with_baseline(
+    "Short: Back",
+    Point::new(10, 26),
+    text_style,
+    Baseline::Top,
+  )
+  .draw(display)
+  .unwrap();
+  Text::with_baseline(
+    "Long: Face",
+    Point::new(10, 34),
+    text_style,
+    Baseline::Top,
+  )
+  .draw(display)
+  .unwrap();
+}
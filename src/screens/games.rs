@@ -0,0 +1,38 @@
+use embedded_graphics::prelude::*;
+
+use super::Context;
+use crate::{
+  display::Display,
+  statusbar,
+  typography::{self, Align, Font},
+  ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState},
+};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Games", MenuIcon::Gamepad, UiState::Games)
+    .press(ShortPress::NextItem);
+
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  draw_screen(display, context.list_offset);
+}
+
+pub fn draw_screen(display: &mut Display<'_>, selected: usize) {
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Games",
+    Point::new(1, top),
+    Align::Left,
+    Font::Small.style(),
+  );
+  for (row, entry) in UiState::Games.entries().enumerate() {
+    let cursor = if row == selected { ">" } else { " " };
+    typography::draw(
+      display,
+      &typography::line(format_args!("{cursor} {}", entry.label)),
+      Point::new(1, top + 12 + 10 * row as i32),
+      Align::Left,
+      Font::Medium.style(),
+    );
+  }
+}
@@ -0,0 +1,28 @@
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{display::Display, feed};
+
+pub const ENTRY: MenuEntry = entry(
+  UiState::Menu,
+  "Headlines",
+  MenuIcon::News,
+  UiState::Headlines,
+)
+.press(ShortPress::NextItemFromStart)
+.animated();
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  let headlines = &context.device.state.headlines;
+  feed::draw(
+    display,
+    headlines,
+    context.list_offset.min(headlines.len().saturating_sub(1)),
+    context.pager.elapsed(),
+  );
+}
@@ -0,0 +1,23 @@
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{display::Display, history};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "History", MenuIcon::Graph, UiState::History);
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  let metric = context.device.history_metric;
+  let history = context.device.history.lock().unwrap();
+  history::draw_graph(
+    display,
+    metric,
+    &history.series(metric, context.device.now),
+    history.latest(metric),
+  );
+}
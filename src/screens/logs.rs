@@ -0,0 +1,72 @@
+#[cfg(target_os = "espidf")]
+use embedded_graphics::{
+  prelude::*,
+  text::{Baseline, Text},
+};
+
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{
+  display::Display,
+  logger, statusbar,
+  typography::{self, Align, Font},
+  ui::LINES_PER_PAGE,
+};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Logs", MenuIcon::List, UiState::Logs)
+    .press(ShortPress::OlderLines);
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  draw_screen(display, context.list_offset);
+}
+
+#[cfg(target_os = "espidf")]
+fn draw_screen(display: &mut Display<'_>, offset: usize) {
+  let small_style = Font::Small.style();
+  let total = logger::len();
+  let entries = logger::recent(offset, LINES_PER_PAGE);
+
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Logs",
+    Point::new(1, top),
+    Align::Left,
+    small_style,
+  );
+  typography::draw(
+    display,
+    &typography::line(format_args!(
+      "{}-{}/{}",
+      offset + 1,
+      offset + entries.len(),
+      total
+    )),
+    Point::new(typography::PANEL_WIDTH - 1, top),
+    Align::Right,
+    small_style,
+  );
+
+  // 25 columns of FONT_5X8 fit on the 128 px wide panel
+  for (row, entry) in entries.iter().enumerate() {
+    let line = typography::first_chars(
+      typography::line(format_args!("{} {}", entry.marker(), entry.message)),
+      25,
+    );
+    Text::with_baseline(
+      line.as_str(),
+      Point::new(1, top + 12 + 8 * row as i32),
+      small_style,
+      Baseline::Top,
+    )
+    .draw(display)
+    .unwrap();
+  }
+}
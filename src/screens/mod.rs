@@ -0,0 +1,309 @@
+#[cfg(target_os = "espidf")]
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::{
+  image::{Image, ImageRaw},
+  mono_font::MonoTextStyle,
+  pixelcolor::BinaryColor,
+  prelude::*,
+};
+
+#[cfg(target_os = "espidf")]
+use chrono::{DateTime, Local};
+
+#[cfg(target_os = "espidf")]
+use crate::{
+  clock::Instant,
+  config::SharedConfig,
+  history::{Metric, SharedHistory},
+  marquee,
+  notify::SharedNotifications,
+  profiles::Profiles,
+  state::DeviceState,
+  typography::Line,
+};
+use crate::{
+  display::Display,
+  pager::Pager,
+  prerender::Prerendered,
+  statusbar,
+  typography::{self, Align, Font},
+  ui::{MenuEntry, MenuIcon, UiState},
+};
+
+pub mod activity;
+pub mod crypto;
+pub mod exit;
+pub mod games;
+pub mod headlines;
+pub mod history;
+pub mod logs;
+pub mod network;
+pub mod notifications;
+pub mod now_playing;
+pub mod profiles;
+pub mod scenes;
+pub mod settings;
+pub mod snake;
+pub mod status;
+pub mod stocks;
+
+/// A screen a menu opens, declared in its own module
+pub struct Screen {
+  pub entry: MenuEntry,
+  pub draw: fn(&mut Display<'_>, &mut Context<'_>),
+}
+
+const fn screen(
+  entry: MenuEntry,
+  draw: fn(&mut Display<'_>, &mut Context<'_>),
+) -> Screen {
+  Screen { entry, draw }
+}
+
+/// Every screen a menu opens, in the order the menus list them. The menus,
+/// what each press does and where a long press goes back to come from the
+/// entries, and the main loop and the simulator draw the screens from here.
+/// A new screen takes its `UiState`, a module with its `ENTRY` and `draw`,
+/// and a line here.
+pub const SCREENS: [Screen; 16] = [
+  screen(settings::ENTRY, settings::draw),
+  screen(profiles::ENTRY, profiles::draw),
+  screen(scenes::ENTRY, scenes::draw),
+  screen(status::ENTRY, status::draw),
+  screen(network::ENTRY, network::draw),
+  screen(history::ENTRY, history::draw),
+  screen(activity::ENTRY, activity::draw),
+  screen(crypto::ENTRY, crypto::draw),
+  screen(stocks::ENTRY, stocks::draw),
+  screen(headlines::ENTRY, headlines::draw),
+  screen(now_playing::ENTRY, now_playing::draw),
+  screen(notifications::ENTRY, notifications::draw),
+  screen(logs::ENTRY, logs::draw),
+  screen(games::ENTRY, games::draw),
+  screen(exit::ENTRY, exit::draw),
+  screen(snake::ENTRY, snake::draw),
+];
+
+/// What the screens are drawn from, gathered by the main loop each frame
+pub struct Context<'a> {
+  /// The screen being drawn
+  pub screen: UiState,
+  /// Date and time as the Status screen shows them
+  pub time: &'a str,
+  /// Entries skipped on Logs and Notifications, selected entry on
+  /// Profiles, Scenes, Games and Headlines
+  pub list_offset: usize,
+  /// On the Settings screen, the edit while there is one
+  pub brightness: u8,
+  pub pager: &'a mut Pager,
+  pub snake: &'a mut crate::snake::Game,
+  /// For the screens that only change with a few values
+  pub static_screen: &'a mut Prerendered<(UiState, Option<u8>)>,
+  #[cfg(target_os = "espidf")]
+  pub device: Device<'a>,
+}
+
+/// What only the device has to draw from, fetched or measured
+#[cfg(target_os = "espidf")]
+pub struct Device<'a> {
+  pub state: &'a DeviceState,
+  pub config: &'a SharedConfig,
+  pub history: &'a SharedHistory,
+  /// Graph shown on the History screen
+  pub history_metric: Metric,
+  pub notifications: &'a SharedNotifications,
+  /// Read on entering Profiles
+  pub profiles: &'a Profiles,
+  /// When the frame started, on the clock the history is kept by
+  pub now: Instant,
+  pub local_now: DateTime<Local>,
+  /// Whether NTP has set the clock
+  pub synced: bool,
+}
+
+/// Draw `context.screen` from its module. Home and the main menu aren't
+/// opened from a menu, their callers draw them.
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  if let Some(screen) = SCREENS
+    .iter()
+    .find(|screen| screen.entry.screen == context.screen)
+  {
+    (screen.draw)(display, context);
+  }
+}
+
+/// Stands in for the screens showing data fetched or measured on the
+/// device
+#[cfg(not(target_os = "espidf"))]
+pub fn only_on_device(display: &mut Display<'_>, context: &mut Context<'_>) {
+  typography::draw_centered(
+    display,
+    &typography::line(format_args!("{:?}", context.screen)),
+    28,
+    Font::Medium.style(),
+  );
+  typography::draw_centered(
+    display,
+    "Only on the device",
+    42,
+    Font::Small.style(),
+  );
+}
+
+/// The three lines under a paged screen's title
+#[cfg(target_os = "espidf")]
+fn draw_page_lines(
+  display: &mut Display<'_>,
+  lines: &[Line],
+  text_style: MonoTextStyle<'_, BinaryColor>,
+  pager: &Pager,
+) {
+  // lines wider than the panel scroll instead of being cut off
+  for (row, line) in lines.iter().enumerate() {
+    marquee::draw(
+      display,
+      line,
+      Rectangle::new(Point::new(10, 26 + 10 * row as i32), Size::new(118, 10)),
+      text_style,
+      pager.elapsed(),
+    );
+  }
+}
+
+/// Menu entries that fit below the status bar, the menu scrolls past these
+const MENU_ROWS: usize = 6;
+/// Height of a menu entry, its icon and a line of small text
+const MENU_ROW_HEIGHT: i32 = 9;
+
+pub fn home_screen(
+  display: &mut Display<'_>,
+  text_style: MonoTextStyle<'_, BinaryColor>,
+  formatted_time: &str,
+) {
+  typography::draw_centered(display, "Welcome!", 24, text_style);
+  typography::draw_centered(display, formatted_time, 42, Font::Medium.style());
+}
+
+/// In place of the clock while the device's own network is up, with what
+/// it takes to join it and choose another WiFi network
+pub fn setup_ap_screen(
+  display: &mut Display<'_>,
+  ssid: &str,
+  password: &str,
+  address: &str,
+) {
+  typography::draw_centered(display, "No WiFi", 22, Font::Medium.style());
+  let lines = [
+    typography::line(format_args!("Join {ssid}")),
+    typography::line(format_args!("Password {password}")),
+    typography::line(format_args!("Open {address}/wifi")),
+  ];
+  for (row, line) in lines.iter().enumerate() {
+    typography::draw_centered(
+      display,
+      line,
+      34 + 10 * row as i32,
+      Font::Small.style(),
+    );
+  }
+}
+
+pub fn menu_screen(display: &mut Display<'_>, selected: usize) {
+  let top = statusbar::HEIGHT + 1;
+  // keep the selected entry on screen
+  let first = (selected + 1).saturating_sub(MENU_ROWS);
+  for (row, (index, entry)) in UiState::Menu
+    .entries()
+    .enumerate()
+    .skip(first)
+    .take(MENU_ROWS)
+    .enumerate()
+  {
+    let y = top + MENU_ROW_HEIGHT * row as i32;
+    if index == selected {
+      typography::draw(
+        display,
+        ">",
+        Point::new(1, y),
+        Align::Left,
+        Font::Small.style(),
+      );
+    }
+    let raw = ImageRaw::<BinaryColor>::new(icon_bitmap(entry.icon), 8);
+    let _ = Image::new(&raw, Point::new(9, y)).draw(display);
+    typography::draw(
+      display,
+      entry.label,
+      Point::new(21, y),
+      Align::Left,
+      Font::Small.style(),
+    );
+  }
+}
+
+/// 8x8, one byte per row with the leftmost pixel in the top bit
+#[rustfmt::skip]
+fn icon_bitmap(icon: MenuIcon) -> &'static [u8; 8] {
+  match icon {
+    MenuIcon::Gear => &[
+      0b00011000, 0b01011010, 0b00111100, 0b11100111,
+      0b11100111, 0b00111100, 0b01011010, 0b00011000,
+    ],
+    MenuIcon::Person => &[
+      0b00011000, 0b00111100, 0b00111100, 0b00011000,
+      0b00000000, 0b01111110, 0b11111111, 0b11111111,
+    ],
+    MenuIcon::Star => &[
+      0b00011000, 0b00011000, 0b11111111, 0b01111110,
+      0b00111100, 0b01111110, 0b01100110, 0b11000011,
+    ],
+    MenuIcon::Info => &[
+      0b00111100, 0b01111110, 0b11100111, 0b11111111,
+      0b11100111, 0b11100111, 0b01100110, 0b00111100,
+    ],
+    MenuIcon::Signal => &[
+      0b00000011, 0b00000011, 0b00001111, 0b00001111,
+      0b00111111, 0b00111111, 0b11111111, 0b11111111,
+    ],
+    MenuIcon::Graph => &[
+      0b10000000, 0b10000001, 0b10000010, 0b10100100,
+      0b11011000, 0b10000000, 0b10000000, 0b11111111,
+    ],
+    MenuIcon::Bars => &[
+      0b00000000, 0b00010000, 0b00010100, 0b01010100,
+      0b01010101, 0b01010101, 0b01010101, 0b11111111,
+    ],
+    MenuIcon::Coin => &[
+      0b00111100, 0b01000010, 0b10011001, 0b10100001,
+      0b10100001, 0b10011001, 0b01000010, 0b00111100,
+    ],
+    MenuIcon::Trend => &[
+      0b00001111, 0b00000011, 0b00000101, 0b00001001,
+      0b01010000, 0b10100000, 0b00000000, 0b11111111,
+    ],
+    MenuIcon::News => &[
+      0b11111110, 0b10000011, 0b10111011, 0b10000011,
+      0b10111011, 0b10000011, 0b10111011, 0b11111110,
+    ],
+    MenuIcon::Note => &[
+      0b00001100, 0b00001110, 0b00001011, 0b00001001,
+      0b00001000, 0b01111000, 0b11111000, 0b01110000,
+    ],
+    MenuIcon::Bell => &[
+      0b00011000, 0b00111100, 0b01111110, 0b01111110,
+      0b01111110, 0b11111111, 0b00000000, 0b00011000,
+    ],
+    MenuIcon::List => &[
+      0b11011111, 0b00000000, 0b11011111, 0b00000000,
+      0b11011111, 0b00000000, 0b11011111, 0b00000000,
+    ],
+    MenuIcon::Gamepad => &[
+      0b00000000, 0b01111110, 0b11011111, 0b10001101,
+      0b11011111, 0b11111111, 0b11000011, 0b00000000,
+    ],
+    MenuIcon::Back => &[
+      0b00010000, 0b00110000, 0b01111111, 0b11111111,
+      0b01111111, 0b00110000, 0b00010000, 0b00000000,
+    ],
+  }
+}
@@ -0,0 +1,110 @@
+#[cfg(target_os = "espidf")]
+use embedded_graphics::{mono_font::MonoTextStyle, pixelcolor::BinaryColor};
+
+#[cfg(target_os = "espidf")]
+use super::{draw_page_lines, Context};
+use crate::ui::{entry, MenuEntry, MenuIcon, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{
+  display::Display,
+  pager::{self, Pager},
+  state::{DeviceState, Reach},
+  typography::{self, Font},
+};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Network", MenuIcon::Signal, UiState::Network).paged();
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  draw_screen(
+    display,
+    Font::Large.style(),
+    context.device.state,
+    context.pager,
+  );
+}
+
+#[cfg(target_os = "espidf")]
+fn draw_screen(
+  display: &mut Display<'_>,
+  text_style: MonoTextStyle<'_, BinaryColor>,
+  snapshot: &DeviceState,
+  pager: &mut Pager,
+) {
+  let unknown = || typography::line(format_args!("--"));
+  let access_point = snapshot.access_point.as_ref();
+  let signal = match (snapshot.rssi, access_point) {
+    (Some(rssi), Some(access_point)) => {
+      typography::line(format_args!("{rssi} dBm ch {}", access_point.channel))
+    }
+    (Some(rssi), None) => typography::line(format_args!("{rssi} dBm")),
+    _ => unknown(),
+  };
+  let reach = |reach: Reach| match reach {
+    Reach::Checking => typography::line(format_args!("checking")),
+    Reach::Answered { ms } => typography::line(format_args!("{ms} ms")),
+    Reach::NoAnswer => typography::line(format_args!("no answer")),
+  };
+  let network = snapshot.network.as_ref();
+  let field = |value: Option<&str>| value.unwrap_or("--");
+  let pages = [
+    (
+      "Link",
+      [
+        typography::line(format_args!(
+          "SSID: {}",
+          field(access_point.map(|access_point| access_point.ssid.as_str()))
+        )),
+        typography::line(format_args!(
+          "AP: {}",
+          field(access_point.map(|access_point| access_point.bssid.as_str()))
+        )),
+        typography::line(format_args!("Signal: {}", signal)),
+      ],
+    ),
+    (
+      "Address",
+      [
+        typography::line(format_args!(
+          "IP: {}",
+          field(network.map(|network| network.address.as_str()))
+        )),
+        typography::line(format_args!(
+          "Gateway: {}",
+          field(network.map(|network| network.gateway.as_str()))
+        )),
+        typography::line(format_args!(
+          "DNS: {}",
+          field(network.and_then(|network| network.dns.as_deref()))
+        )),
+      ],
+    ),
+    (
+      "Checks",
+      [
+        typography::line(format_args!(
+          "MAC: {}",
+          field(network.map(|network| network.mac.as_str()))
+        )),
+        typography::line(format_args!(
+          "Gateway: {}",
+          network.map_or_else(unknown, |network| reach(network.gateway_ping))
+        )),
+        typography::line(format_args!(
+          "Internet: {}",
+          network.map_or_else(unknown, |network| reach(network.internet))
+        )),
+      ],
+    ),
+  ];
+  let page = pager.page(pages.len());
+  let (title, lines) = &pages[page];
+
+  typography::draw_centered(display, title, 11, text_style);
+  draw_page_lines(display, lines, text_style, pager);
+  pager::draw_dots(display, page, pages.len());
+}
@@ -0,0 +1,29 @@
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{display::Display, notify, ui::LINES_PER_PAGE};
+
+pub const ENTRY: MenuEntry = entry(
+  UiState::Menu,
+  "Notifications",
+  MenuIcon::Bell,
+  UiState::Notifications,
+)
+.press(ShortPress::OlderLines);
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  let offset = context.list_offset;
+  let notifications = context.device.notifications.lock().unwrap();
+  let page: Vec<notify::Entry> = notifications
+    .history()
+    .skip(offset)
+    .take(LINES_PER_PAGE)
+    .cloned()
+    .collect();
+  notify::draw_history(display, &page, offset, notifications.history().count());
+}
@@ -0,0 +1,26 @@
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{display::Display, spotify};
+
+pub const ENTRY: MenuEntry = entry(
+  UiState::Menu,
+  "Now playing",
+  MenuIcon::Note,
+  UiState::NowPlaying,
+)
+.press(ShortPress::Restart)
+.animated();
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  spotify::draw(
+    display,
+    context.device.state.now_playing.as_ref(),
+    context.pager.elapsed(),
+  );
+}
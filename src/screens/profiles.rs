@@ -0,0 +1,71 @@
+#[cfg(target_os = "espidf")]
+use embedded_graphics::prelude::*;
+
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{
+  display::Display,
+  profiles::Profiles,
+  statusbar,
+  typography::{self, Align, Font},
+};
+
+pub const ENTRY: MenuEntry = entry(
+  UiState::Menu,
+  "Profiles",
+  MenuIcon::Person,
+  UiState::Profiles,
+)
+.press(ShortPress::NextItem);
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  draw_screen(display, context.device.profiles, context.list_offset);
+}
+
+#[cfg(target_os = "espidf")]
+fn draw_screen(
+  display: &mut Display<'_>,
+  profiles: &Profiles,
+  selected: usize,
+) {
+  let small_style = Font::Small.style();
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Profiles",
+    Point::new(1, top),
+    Align::Left,
+    small_style,
+  );
+  if profiles.list.is_empty() {
+    typography::draw_centered(display, "None saved yet", 28, small_style);
+    typography::draw_centered(
+      display,
+      "Save one from the web",
+      40,
+      small_style,
+    );
+    return;
+  }
+  for (row, profile) in profiles.list.iter().enumerate() {
+    let cursor = if row == selected { ">" } else { " " };
+    let active = if profiles.active.as_deref() == Some(profile.name.as_str()) {
+      " *"
+    } else {
+      ""
+    };
+    typography::draw(
+      display,
+      &typography::line(format_args!("{cursor} {}{active}", profile.name)),
+      Point::new(1, top + 12 + 10 * row as i32),
+      Align::Left,
+      Font::Medium.style(),
+    );
+  }
+}
@@ -0,0 +1,22 @@
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{display::Display, scene};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Scenes", MenuIcon::Star, UiState::Scenes)
+    .press(ShortPress::NextItem);
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  scene::draw_list(
+    display,
+    &context.device.config.lock().unwrap().automation.scenes,
+    context.list_offset,
+    context.device.state.scene.as_deref(),
+  );
+}
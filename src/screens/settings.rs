@@ -0,0 +1,69 @@
+use std::fmt;
+
+use embedded_graphics::{
+  mono_font::MonoTextStyle,
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+};
+
+use super::Context;
+use crate::{
+  display::Display,
+  typography::{self, Align, Font},
+  ui::{entry, MenuEntry, MenuIcon, ShortPress, UiState},
+};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Settings", MenuIcon::Gear, UiState::Settings)
+    .press(ShortPress::Brightness);
+
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  let brightness = context.brightness;
+  context.static_screen.draw(
+    display,
+    (UiState::Settings, Some(brightness)),
+    |frame| draw_screen(frame, Font::Large.style(), brightness),
+  );
+}
+
+pub fn draw_screen<D>(
+  display: &mut D,
+  text_style: MonoTextStyle<'_, BinaryColor>,
+  brightness: u8,
+) where
+  D: DrawTarget<Color = BinaryColor>,
+  D::Error: fmt::Debug,
+{
+  let small_style = Font::Small.style();
+  typography::draw_centered(display, "Settings", 11, text_style);
+  typography::draw(
+    display,
+    "Brightness",
+    Point::new(4, 26),
+    Align::Left,
+    Font::Medium.style(),
+  );
+  typography::draw(
+    display,
+    &typography::line(format_args!("{brightness}")),
+    Point::new(typography::PANEL_WIDTH - 4, 26),
+    Align::Right,
+    Font::Medium.style(),
+  );
+
+  // preview bar, the panel itself already shows the new brightness
+  Rectangle::new(Point::new(4, 38), Size::new(120, 10))
+    .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+    .draw(display)
+    .unwrap();
+  Rectangle::new(
+    Point::new(6, 40),
+    Size::new(u32::from(brightness) * 116 / 255, 6),
+  )
+  .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+  .draw(display)
+  .unwrap();
+
+  typography::draw_centered(display, "Short: +  Long: Save", 54, small_style);
+}
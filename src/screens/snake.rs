@@ -0,0 +1,12 @@
+use super::Context;
+use crate::{
+  display::Display,
+  ui::{entry, MenuEntry, MenuIcon, UiState},
+};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Games, "Snake", MenuIcon::Gamepad, UiState::Snake).animated();
+
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  context.snake.draw(display);
+}
@@ -0,0 +1,157 @@
+#[cfg(target_os = "espidf")]
+use embedded_graphics::{
+  mono_font::MonoTextStyle, pixelcolor::BinaryColor, prelude::*,
+};
+
+#[cfg(target_os = "espidf")]
+use super::{draw_page_lines, Context};
+use crate::ui::{entry, MenuEntry, MenuIcon, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{
+  display::Display,
+  pager::{self, Pager},
+  perf,
+  state::DeviceState,
+  statusbar,
+  typography::{self, Font, Line},
+};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Status", MenuIcon::Info, UiState::Status).paged();
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  draw_screen(
+    display,
+    Font::Large.style(),
+    context.device.state,
+    context.time,
+    context.pager,
+  );
+}
+
+#[cfg(target_os = "espidf")]
+fn draw_screen(
+  display: &mut Display<'_>,
+  text_style: MonoTextStyle<'_, BinaryColor>,
+  snapshot: &DeviceState,
+  formatted: &str,
+  pager: &mut Pager,
+) {
+  // weather is still being fetched right after boot
+  let weather = snapshot.weather.as_ref();
+  let unknown = || typography::line(format_args!("--"));
+  let (temp, weather_condition, humidity) = match weather {
+    Some(weather) => (
+      typography::line(format_args!("{}°C", weather.temp_c)),
+      weather.condition.as_str(),
+      typography::line(format_args!("{}%", weather.humidity)),
+    ),
+    None => (unknown(), "--", unknown()),
+  };
+  let rssi = snapshot
+    .rssi
+    .map_or_else(unknown, |rssi| typography::line(format_args!("{rssi} dBm")));
+  let signal = snapshot.rssi.map_or_else(unknown, |rssi| {
+    typography::line(format_args!("{rssi} dBm, {}/4", statusbar::bars(rssi)))
+  });
+  let access_point = snapshot.access_point.as_ref();
+  let access_point_name = access_point.map_or_else(unknown, |access_point| {
+    typography::line(format_args!(
+      "{} ch {}",
+      access_point.bssid, access_point.channel
+    ))
+  });
+  let pages = [
+    (
+      "Weather",
+      [
+        typography::line(format_args!("Temperature: {}", temp)),
+        typography::line(format_args!("Condition: {}", weather_condition)),
+        typography::line(format_args!("Humidity: {}", humidity)),
+      ],
+    ),
+    (
+      "Device",
+      [
+        typography::line(format_args!("Time: {}", formatted)),
+        typography::line(format_args!("WiFi: {}", rssi)),
+        typography::line(format_args!(
+          "Up: {}h {}m  Heap: {} KB",
+          snapshot.uptime_s / 3600,
+          snapshot.uptime_s / 60 % 60,
+          snapshot.free_heap / 1024
+        )),
+      ],
+    ),
+    (
+      "Network",
+      [
+        typography::line(format_args!(
+          "SSID: {}",
+          access_point.map_or("--", |access_point| &access_point.ssid)
+        )),
+        typography::line(format_args!("Signal: {}", signal)),
+        typography::line(format_args!("AP: {}", access_point_name)),
+      ],
+    ),
+    (
+      "Clock",
+      [
+        typography::line(format_args!(
+          "Synced: {}",
+          if snapshot.ntp_server.is_some() {
+            "yes"
+          } else {
+            "no"
+          }
+        )),
+        typography::line(format_args!("NTP server:")),
+        typography::line(format_args!(
+          "{}",
+          snapshot.ntp_server.as_deref().unwrap_or("--")
+        )),
+      ],
+    ),
+    (
+      "Timing",
+      [
+        typography::line(format_args!("Loop: {} ms", p95_ms(perf::Span::Loop))),
+        typography::line(format_args!(
+          "Draw: {} Flush: {} ms",
+          p95_ms(perf::Span::Render),
+          p95_ms(perf::Span::Flush)
+        )),
+        typography::line(format_args!(
+          "HTTP: {} PIR: {} ms",
+          p95_ms(perf::Span::HttpFetch),
+          p95_ms(perf::Span::SensorPoll)
+        )),
+      ],
+    ),
+  ];
+  let page = pager.page(pages.len());
+  let (title, lines) = &pages[page];
+
+  typography::draw_centered(display, title, 11, text_style);
+  if let Some(icon) = weather
+    .filter(|_| page == 0)
+    .and_then(|weather| typography::Icon::for_condition(&weather.condition))
+  {
+    typography::draw_icon(display, icon, Point::new(110, 13));
+  }
+  draw_page_lines(display, lines, text_style, pager);
+  pager::draw_dots(display, page, pages.len());
+}
+
+#[cfg(target_os = "espidf")]
+/// Slowest of the fastest 95% of `span`'s recent samples, in milliseconds
+fn p95_ms(span: perf::Span) -> Line {
+  typography::line(format_args!(
+    "{:.1}",
+    perf::summary(span).p95_us as f32 / 1000.0
+  ))
+}
@@ -0,0 +1,22 @@
+#[cfg(target_os = "espidf")]
+use super::Context;
+use crate::ui::{entry, MenuEntry, MenuIcon, UiState};
+#[cfg(target_os = "espidf")]
+use crate::{display::Display, stocks};
+
+pub const ENTRY: MenuEntry =
+  entry(UiState::Menu, "Stocks", MenuIcon::Trend, UiState::Stocks).paged();
+
+#[cfg(not(target_os = "espidf"))]
+pub use super::only_on_device as draw;
+
+#[cfg(target_os = "espidf")]
+pub fn draw(display: &mut Display<'_>, context: &mut Context<'_>) {
+  let device = &context.device;
+  stocks::draw(
+    display,
+    &device.state.quotes,
+    device.synced.then(|| device.local_now.timestamp()),
+    context.pager,
+  );
+}
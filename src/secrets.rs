@@ -0,0 +1,203 @@
+use std::sync::OnceLock;
+
+use esp_idf_svc::{
+  nvs::{
+    EspDefaultNvs, EspDefaultNvsPartition, EspEncryptedNvs,
+    EspEncryptedNvsPartition, EspNvs, NvsPartitionId,
+  },
+  sys::{
+    esp_partition_find_first,
+    esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_DATA_NVS_KEYS,
+    esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+  },
+};
+
+use crate::logger;
+
+/// Kept apart from the general settings so they can be reset on their own
+/// and are never written out together with display preferences
+const NAMESPACE: &str = "secrets";
+
+/// Encrypted NVS partition for the secrets and the partition with its keys,
+/// see `partitions.csv`
+const PARTITION: &str = "secure";
+const KEYS_PARTITION: &str = "nvs_keys";
+
+pub const WIFI_PASSWORD: &str = "wifi_password";
+pub const WEATHER_API_KEY: &str = "weather_key";
+pub const ADMIN_TOKEN: &str = "admin_token";
+/// WiFi passwords of the saved profiles, as a JSON object by profile name
+pub const PROFILE_WIFI: &str = "profile_wifi";
+/// Hash of the web login password, never the password itself
+pub const LOGIN_PASSWORD: &str = "login_hash";
+/// Names, scopes and hashes of the API tokens, as a JSON list
+pub const API_TOKENS: &str = "api_tokens";
+/// Refresh token of the linked Spotify account, empty once unlinked
+pub const SPOTIFY_TOKEN: &str = "spotify_token";
+/// Password for the outgoing HTTP proxy
+pub const PROXY_PASSWORD: &str = "proxy_password";
+/// Passwords of the known WiFi networks, as a JSON object by SSID
+pub const KNOWN_WIFI: &str = "known_wifi";
+
+const ALL: [&str; 9] = [
+  WIFI_PASSWORD,
+  WEATHER_API_KEY,
+  ADMIN_TOKEN,
+  PROFILE_WIFI,
+  LOGIN_PASSWORD,
+  API_TOKENS,
+  SPOTIFY_TOKEN,
+  PROXY_PASSWORD,
+  KNOWN_WIFI,
+];
+
+/// Secrets that are single values, such as keys and passwords, masked in
+/// the log once they have been read or set. The rest are lists or hashes.
+const REDACTED: [&str; 5] = [
+  WIFI_PASSWORD,
+  WEATHER_API_KEY,
+  ADMIN_TOKEN,
+  SPOTIFY_TOKEN,
+  PROXY_PASSWORD,
+];
+
+/// The encrypted partition, once `init` has opened it. Until then, and on
+/// a partition table without one, secrets live in the default partition.
+static ENCRYPTED: OnceLock<EspEncryptedNvsPartition> = OnceLock::new();
+
+/// Open the encrypted partition and move the secrets older firmware kept in
+/// plain text in `partition` over to it. Called once at boot, before any
+/// secret is read.
+pub fn init(partition: EspDefaultNvsPartition) {
+  let encrypted =
+    match EspEncryptedNvsPartition::take(PARTITION, Some(KEYS_PARTITION)) {
+      Ok(encrypted) => encrypted,
+      Err(error) => {
+        // e.g. updated over the air, which can't change the partition table
+        log::warn!(
+          "No encrypted NVS partition, secrets stay in plain text: {:?}",
+          error
+        );
+        return;
+      }
+    };
+  if !keys_protected() {
+    log::warn!("Flash encryption is off, the NVS keys are readable in flash");
+  }
+  match migrate(partition, encrypted.clone()) {
+    Ok(0) => {}
+    Ok(moved) => log::info!("Moved {} secrets to encrypted NVS", moved),
+    // whatever wasn't moved is still read from the plain text copy
+    Err(error) => log::error!("Could not move secrets: {:?}", error),
+  }
+  let _ = ENCRYPTED.set(encrypted);
+}
+
+/// Stored value of secret `key`, `None` when it was never set or was reset
+pub fn get(
+  partition: EspDefaultNvsPartition,
+  key: &str,
+) -> anyhow::Result<Option<String>> {
+  let mut value = None;
+  if let Some(encrypted) = ENCRYPTED.get() {
+    let nvs = EspEncryptedNvs::new(encrypted.clone(), NAMESPACE, true)?;
+    value = read(&nvs, key)?;
+  }
+  if value.is_none() {
+    // also the fallback for a secret that failed to move at boot
+    value = read(&EspDefaultNvs::new(partition, NAMESPACE, true)?, key)?;
+  }
+  if let Some(value) = &value {
+    redact(key, value);
+  }
+  Ok(value)
+}
+
+pub fn set(
+  partition: EspDefaultNvsPartition,
+  key: &str,
+  value: &str,
+) -> anyhow::Result<()> {
+  redact(key, value);
+  let Some(encrypted) = ENCRYPTED.get() else {
+    let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    nvs.set_str(key, value)?;
+    return Ok(());
+  };
+  let mut nvs = EspEncryptedNvs::new(encrypted.clone(), NAMESPACE, true)?;
+  nvs.set_str(key, value)?;
+  // a plain text copy left behind would shadow nothing but still leak
+  EspDefaultNvs::new(partition, NAMESPACE, true)?.remove(key)?;
+  Ok(())
+}
+
+/// Forget every secret while keeping the other settings. The build-time
+/// defaults apply again after the next boot.
+pub fn erase(partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+  if let Some(encrypted) = ENCRYPTED.get() {
+    let mut nvs = EspEncryptedNvs::new(encrypted.clone(), NAMESPACE, true)?;
+    for key in ALL {
+      nvs.remove(key)?;
+    }
+  }
+  let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+  for key in ALL {
+    nvs.remove(key)?;
+  }
+  Ok(())
+}
+
+fn redact(key: &str, value: &str) {
+  if REDACTED.contains(&key) {
+    logger::redact(value);
+  }
+}
+
+fn read<T: NvsPartitionId>(
+  nvs: &EspNvs<T>,
+  key: &str,
+) -> anyhow::Result<Option<String>> {
+  let Some(len) = nvs.str_len(key)? else {
+    return Ok(None);
+  };
+  let mut buf = vec![0_u8; len];
+  Ok(nvs.get_str(key, &mut buf)?.map(str::to_string))
+}
+
+/// Copy the plain text secrets into `encrypted` and remove them. A secret
+/// already in `encrypted` is newer and wins. Returns how many were moved.
+fn migrate(
+  partition: EspDefaultNvsPartition,
+  encrypted: EspEncryptedNvsPartition,
+) -> anyhow::Result<usize> {
+  let mut plain = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+  let mut secure = EspEncryptedNvs::new(encrypted, NAMESPACE, true)?;
+  let mut moved = 0;
+  for key in ALL {
+    let Some(value) = read(&plain, key)? else {
+      continue;
+    };
+    if !secure.contains(key)? {
+      secure.set_str(key, &value)?;
+    }
+    plain.remove(key)?;
+    moved += 1;
+  }
+  Ok(moved)
+}
+
+/// Whether flash encryption covers the NVS keys. Without it they sit in
+/// flash next to the data they encrypt.
+fn keys_protected() -> bool {
+  // Safety: only looks the partition up in the partition table
+  let keys = unsafe {
+    esp_partition_find_first(
+      esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+      esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_DATA_NVS_KEYS,
+      c"nvs_keys".as_ptr(),
+    )
+  };
+  // Safety: partitions found are never freed, `encrypted` is only set when
+  // flash encryption is on
+  !keys.is_null() && unsafe { (*keys).encrypted }
+}
@@ -0,0 +1,24 @@
+use esp_idf_hal::ledc::LedcDriver;
+use esp_idf_svc::sys::EspError;
+
+use crate::utils;
+
+/// Largest angle the servo can be driven to
+pub const MAX_ANGLE: u32 = 180;
+
+/// Move the servo to `angle` degrees, clamped to 0..=180. The driver runs at
+/// 50 Hz, so 0.5 ms .. 2.5 ms pulses are 1/40 .. 1/8 of the period.
+pub fn set_angle(
+  driver: &mut LedcDriver<'_>,
+  angle: u32,
+) -> Result<(), EspError> {
+  let max_duty = driver.get_max_duty();
+  let duty = utils::map(
+    angle.min(MAX_ANGLE),
+    0,
+    MAX_ANGLE,
+    max_duty / 40,
+    max_duty / 8,
+  );
+  driver.set_duty(duty)
+}
@@ -0,0 +1,219 @@
+use std::{collections::VecDeque, time::Duration};
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+  clock::Instant,
+  display::Display,
+  statusbar,
+  typography::{self, Font},
+};
+
+/// Pixels per cell, the snake is drawn one pixel smaller to leave a gap
+const CELL: i32 = 4;
+/// Cells inside the wall drawn around the field below the status bar
+const COLUMNS: i32 = 31;
+const ROWS: i32 = 13;
+/// Top left corner of the first cell
+const ORIGIN: Point = Point::new(2, statusbar::HEIGHT + 1);
+/// Time between steps at the start, every apple shortens it down to
+/// `FASTEST`
+const SLOWEST: Duration = Duration::from_millis(250);
+const FASTEST: Duration = Duration::from_millis(90);
+const SPEEDUP_PER_APPLE: Duration = Duration::from_millis(8);
+/// Presses remembered between two steps, so quick presses still turn at
+/// most once per step
+const MAX_PENDING_TURNS: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Heading {
+  Up,
+  Right,
+  Down,
+  Left,
+}
+
+impl Heading {
+  fn clockwise(self) -> Self {
+    match self {
+      Self::Up => Self::Right,
+      Self::Right => Self::Down,
+      Self::Down => Self::Left,
+      Self::Left => Self::Up,
+    }
+  }
+
+  fn offset(self) -> (i32, i32) {
+    match self {
+      Self::Up => (0, -1),
+      Self::Right => (1, 0),
+      Self::Down => (0, 1),
+      Self::Left => (-1, 0),
+    }
+  }
+}
+
+/// One round of Snake. A short press turns clockwise, hitting the wall or
+/// the tail ends the round.
+pub struct Game {
+  /// Cells from head to tail
+  body: VecDeque<(i32, i32)>,
+  heading: Heading,
+  pending_turns: u8,
+  apple: (i32, i32),
+  score: u32,
+  high_score: u32,
+  over: bool,
+  stepped_at: Instant,
+}
+
+impl Game {
+  pub fn new(high_score: u32) -> Self {
+    let (x, y) = (COLUMNS / 4, ROWS / 2);
+    let mut game = Self {
+      body: VecDeque::from([(x, y), (x - 1, y), (x - 2, y)]),
+      heading: Heading::Right,
+      pending_turns: 0,
+      apple: (0, 0),
+      score: 0,
+      high_score,
+      over: false,
+      stepped_at: Instant::now(),
+    };
+    game.place_apple();
+    game
+  }
+
+  pub fn high_score(&self) -> u32 {
+    self.high_score
+  }
+
+  /// Short press: turn, or start over once the round has ended
+  pub fn press(&mut self) {
+    if self.over {
+      *self = Self::new(self.high_score);
+    } else {
+      self.pending_turns = (self.pending_turns + 1).min(MAX_PENDING_TURNS);
+    }
+  }
+
+  /// Called on every frame, moves the snake once its step time has passed
+  pub fn tick(&mut self, now: Instant) {
+    if self.over || now.duration_since(self.stepped_at) < self.step_time() {
+      return;
+    }
+    self.stepped_at = now;
+    if self.pending_turns > 0 {
+      self.pending_turns -= 1;
+      self.heading = self.heading.clockwise();
+    }
+
+    let (x, y) = self.body[0];
+    let (dx, dy) = self.heading.offset();
+    let head = (x + dx, y + dy);
+    let eating = head == self.apple;
+    // the tail moves out of the way unless the snake grows this step
+    let tail = if eating { 0 } else { 1 };
+    let hits_self = self
+      .body
+      .iter()
+      .take(self.body.len() - tail)
+      .any(|&cell| cell == head);
+    if !(0..COLUMNS).contains(&head.0)
+      || !(0..ROWS).contains(&head.1)
+      || hits_self
+    {
+      self.over = true;
+      if self.score > self.high_score {
+        self.high_score = self.score;
+        log::info!("New Snake high score: {}", self.score);
+      }
+      return;
+    }
+
+    self.body.push_front(head);
+    if eating {
+      self.score += 1;
+      self.place_apple();
+    } else {
+      self.body.pop_back();
+    }
+  }
+
+  fn step_time(&self) -> Duration {
+    SLOWEST
+      .saturating_sub(SPEEDUP_PER_APPLE * self.score)
+      .max(FASTEST)
+  }
+
+  /// Puts the apple on a random free cell. With none left the snake has
+  /// filled the field and the round is won.
+  fn place_apple(&mut self) {
+    let free: Vec<(i32, i32)> = (0..ROWS)
+      .flat_map(|y| (0..COLUMNS).map(move |x| (x, y)))
+      .filter(|cell| !self.body.contains(cell))
+      .collect();
+    if free.is_empty() {
+      self.over = true;
+      self.high_score = self.high_score.max(self.score);
+      return;
+    }
+    self.apple = free[rand::random::<u32>() as usize % free.len()];
+  }
+
+  pub fn draw(&self, display: &mut Display<'_>) {
+    let wall = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+    let _ = Rectangle::new(
+      ORIGIN - Point::new(2, 1),
+      Size::new((COLUMNS * CELL + 3) as u32, (ROWS * CELL + 2) as u32),
+    )
+    .into_styled(wall)
+    .draw(display);
+    for &(x, y) in &self.body {
+      let _ = Rectangle::new(cell(x, y), Size::new(3, 3))
+        .into_styled(fill)
+        .draw(display);
+    }
+    let (x, y) = self.apple;
+    let _ = Rectangle::new(cell(x, y) + Point::new(1, 0), Size::new(1, 3))
+      .into_styled(fill)
+      .draw(display);
+    let _ = Rectangle::new(cell(x, y) + Point::new(0, 1), Size::new(3, 1))
+      .into_styled(fill)
+      .draw(display);
+
+    if self.over {
+      let panel = Rectangle::new(Point::new(4, 20), Size::new(120, 38));
+      let _ = panel
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+        .draw(display);
+      let _ = panel.into_styled(wall).draw(display);
+      typography::draw_centered(display, "Game over", 23, Font::Medium.style());
+      typography::draw_centered(
+        display,
+        &typography::line(format_args!(
+          "Score {}  Best {}",
+          self.score, self.high_score
+        )),
+        36,
+        Font::Small.style(),
+      );
+      typography::draw_centered(
+        display,
+        "Short: again  Long: exit",
+        46,
+        Font::Small.style(),
+      );
+    }
+  }
+}
+
+/// Top left pixel of cell (`x`, `y`)
+fn cell(x: i32, y: i32) -> Point {
+  ORIGIN + Point::new(x * CELL, y * CELL)
+}
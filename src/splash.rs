@@ -0,0 +1,260 @@
+use std::{
+  sync::{Arc, Mutex},
+  thread::JoinHandle,
+  time::Duration,
+};
+
+use embedded_graphics::{
+  image::{Image, ImageRaw},
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{Line, PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+  display::{Display, Oled},
+  typography::{self, Align, Font},
+};
+
+const LOGO_SIZE: u32 = 24;
+/// Room right of the logo
+const NAME_WIDTH: u32 = 92;
+
+/// Robot head, 24x24 at one bit per pixel
+#[rustfmt::skip]
+const LOGO: [u8; 72] = [
+  0b00000000, 0b00011000, 0b00000000,
+  0b00000000, 0b00011000, 0b00000000,
+  0b00000000, 0b00111100, 0b00000000,
+  0b00000000, 0b00011000, 0b00000000,
+  0b00000000, 0b00011000, 0b00000000,
+  0b00111111, 0b11111111, 0b11111100,
+  0b01100000, 0b00000000, 0b00000110,
+  0b01000000, 0b00000000, 0b00000010,
+  0b01000000, 0b00000000, 0b00000010,
+  0b01000111, 0b10000001, 0b11100010,
+  0b01001111, 0b11000011, 0b11110010,
+  0b01001111, 0b11000011, 0b11110010,
+  0b01001111, 0b11000011, 0b11110010,
+  0b01000111, 0b10000001, 0b11100010,
+  0b01000000, 0b00000000, 0b00000010,
+  0b01000000, 0b00000000, 0b00000010,
+  0b01000000, 0b01000010, 0b00000010,
+  0b01000000, 0b00111100, 0b00000010,
+  0b01000000, 0b00000000, 0b00000010,
+  0b01100000, 0b00000000, 0b00000110,
+  0b00111111, 0b11111111, 0b11111100,
+  0b00001100, 0b00000000, 0b00110000,
+  0b00001100, 0b00000000, 0b00110000,
+  0b00000000, 0b00000000, 0b00000000,
+];
+
+/// Steps of the boot sequence. A stage starts once the ones it
+/// [needs](Stage::needs) are done and otherwise runs alongside the others,
+/// so bringing up WiFi doesn't wait for the display. Joining WiFi and
+/// setting the clock go on after boot, see `network::spawn`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+  Display,
+  Wifi,
+  Server,
+}
+
+impl Stage {
+  const ALL: [Stage; 3] = [Stage::Display, Stage::Wifi, Stage::Server];
+
+  fn label(self) -> &'static str {
+    match self {
+      Stage::Display => "Display",
+      Stage::Wifi => "WiFi",
+      Stage::Server => "Server",
+    }
+  }
+
+  /// Stages that have to be done before this one starts
+  pub fn needs(self) -> &'static [Stage] {
+    match self {
+      Stage::Display | Stage::Wifi => &[],
+      // the server binds to the network interface the WiFi driver creates
+      Stage::Server => &[Stage::Wifi],
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Status {
+  #[default]
+  Pending,
+  Running,
+  Done,
+  Failed,
+}
+
+/// Boot splash with the logo, a progress bar and a tick or cross per
+/// stage, so a boot that hangs shows which stage it is stuck in. Clones
+/// share the stages, so a stage running in its own thread updates the
+/// splash the main thread draws.
+#[derive(Clone, Default)]
+pub struct Splash {
+  status: Arc<Mutex<[Status; Stage::ALL.len()]>>,
+}
+
+/// A stage running in its own thread, see [`Splash::spawn`]
+pub struct Task<T>(JoinHandle<anyhow::Result<T>>);
+
+impl Splash {
+  pub fn start(&self, stage: Stage) {
+    log::info!("Boot stage {:?} started", stage);
+    let mut status = self.status.lock().unwrap();
+    debug_assert!(
+      stage
+        .needs()
+        .iter()
+        .all(|need| status[*need as usize] == Status::Done),
+      "boot stage {:?} started before the ones it needs",
+      stage
+    );
+    status[stage as usize] = Status::Running;
+  }
+
+  /// Mark `stage` as passed or failed
+  pub fn finish(&self, stage: Stage, ok: bool) {
+    if ok {
+      log::info!("Boot stage {:?} done", stage);
+    } else {
+      log::error!("Boot stage {:?} failed", stage);
+    }
+    self.status.lock().unwrap()[stage as usize] =
+      if ok { Status::Done } else { Status::Failed };
+  }
+
+  /// Run `stage` in its own thread while boot goes on, collected with
+  /// [`Splash::wait`]
+  pub fn spawn<T: Send + 'static>(
+    &self,
+    stage: Stage,
+    run: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+  ) -> anyhow::Result<Task<T>> {
+    self.start(stage);
+    let splash = self.clone();
+    let handle = std::thread::Builder::new()
+      .name(format!("boot-{}", stage.label().to_lowercase()))
+      .stack_size(8 * 1024)
+      .spawn(move || {
+        let result = run();
+        if let Err(error) = &result {
+          log::error!("Boot stage {:?}: {:?}", stage, error);
+        }
+        splash.finish(stage, result.is_ok());
+        result
+      })?;
+    Ok(Task(handle))
+  }
+
+  /// Keep the splash up to date until `task` is done and return what it
+  /// returned
+  pub fn wait<T>(&self, oled: &mut Oled, task: Task<T>) -> anyhow::Result<T> {
+    while !task.0.is_finished() {
+      self.show(oled);
+      std::thread::sleep(Duration::from_millis(50));
+    }
+    self.show(oled);
+    task
+      .0
+      .join()
+      .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+  }
+
+  /// Returns whether the splash reached the panel
+  pub fn show(&self, oled: &mut Oled) -> bool {
+    let status = *self.status.lock().unwrap();
+    oled.render(|display| draw(display, &status))
+  }
+}
+
+fn draw(display: &mut Display<'_>, status: &[Status; Stage::ALL.len()]) {
+  let _ = display.clear(BinaryColor::Off);
+  let raw = ImageRaw::<BinaryColor>::new(&LOGO, LOGO_SIZE);
+  let _ = Image::new(&raw, Point::new(4, 2)).draw(display);
+  // the device name, smaller when a long one would run off the screen
+  let name = crate::hostname::get();
+  let font = [Font::Large, Font::Medium]
+    .into_iter()
+    .find(|font| typography::width(name, &font.style()) <= NAME_WIDTH)
+    .unwrap_or(Font::Small);
+  typography::draw(display, name, Point::new(36, 4), Align::Left, font.style());
+  // stages run side by side, name all of them
+  let running: Vec<&str> = Stage::ALL
+    .iter()
+    .filter(|stage| status[**stage as usize] == Status::Running)
+    .map(|stage| stage.label())
+    .collect();
+  let caption = if running.is_empty() {
+    "booting".to_string()
+  } else {
+    format!("{}...", running.join(", "))
+  };
+  typography::draw(
+    display,
+    &caption,
+    Point::new(36, 17),
+    Align::Left,
+    Font::Small.style(),
+  );
+
+  let finished = status
+    .iter()
+    .filter(|status| matches!(status, Status::Done | Status::Failed))
+    .count() as u32;
+  let _ = Rectangle::new(Point::new(4, 30), Size::new(120, 7))
+    .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+    .draw(display);
+  let _ = Rectangle::new(
+    Point::new(6, 32),
+    Size::new(finished * 116 / Stage::ALL.len() as u32, 3),
+  )
+  .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+  .draw(display);
+
+  // side by side below the bar
+  for (index, stage) in Stage::ALL.iter().enumerate() {
+    let origin =
+      Point::new(4 + 62 * (index as i32 % 2), 42 + 11 * (index as i32 / 2));
+    draw_mark(display, origin, status[*stage as usize]);
+    typography::draw(
+      display,
+      stage.label(),
+      origin + Point::new(11, 0),
+      Align::Left,
+      Font::Small.style(),
+    );
+  }
+}
+
+/// 8x8 box with a tick, a cross or a dot for a running stage
+fn draw_mark(display: &mut Display<'_>, origin: Point, status: Status) {
+  let stroke = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+  let _ = Rectangle::new(origin, Size::new(8, 8))
+    .into_styled(stroke)
+    .draw(display);
+  let lines: &[(Point, Point)] = match status {
+    Status::Pending => &[],
+    Status::Running => &[
+      (Point::new(3, 3), Point::new(4, 3)),
+      (Point::new(3, 4), Point::new(4, 4)),
+    ],
+    Status::Done => &[
+      (Point::new(2, 4), Point::new(3, 5)),
+      (Point::new(3, 5), Point::new(5, 2)),
+    ],
+    Status::Failed => &[
+      (Point::new(2, 2), Point::new(5, 5)),
+      (Point::new(5, 2), Point::new(2, 5)),
+    ],
+  };
+  for (start, end) in lines {
+    let _ = Line::new(origin + *start, origin + *end)
+      .into_styled(stroke)
+      .draw(display);
+  }
+}
@@ -0,0 +1,388 @@
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+};
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+use sha2::{Digest, Sha256};
+
+use crate::{
+  clock::Instant,
+  config::Config,
+  display::Display,
+  fetch,
+  http_client::{self, KeyRejected},
+  marquee, secrets,
+  state::{SharedState, Track},
+  statusbar,
+  typography::{self, Align, Font, Line},
+  url::{self, Url},
+};
+
+/// Where Spotify sends the browser after the user agrees. Nothing listens
+/// there; the user copies the address it ends up on back to the settings
+/// page. Spotify only allows plain HTTP redirects to the loopback address.
+pub const REDIRECT_URI: &str = "http://127.0.0.1:8888/callback";
+const AUTHORIZE_URL: &str = "https://accounts.spotify.com/authorize";
+const TOKEN_URL: &str = "https://accounts.spotify.com/api/token";
+const PLAYING_URL: &str =
+  "https://api.spotify.com/v1/me/player/currently-playing";
+const SCOPES: &str = "user-read-currently-playing user-read-playback-state";
+/// How often the track is checked while the Now Playing screen is open
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub type SharedLink = Arc<Mutex<Option<Link>>>;
+
+/// A link to a Spotify account being set up from the settings page
+pub struct Link {
+  /// PKCE verifier, only its hash is sent with the authorization request
+  verifier: String,
+  state: String,
+  client_id: String,
+  /// Authorization code pasted back by the user, exchanged for tokens by
+  /// the fetch thread
+  code: Option<String>,
+}
+
+impl Link {
+  /// Start linking the account of the app with `client_id`. Returns the
+  /// link and the address to open in a browser.
+  pub fn start(client_id: &str) -> (Self, String) {
+    let verifier: String = (0..64)
+      .map(|_| {
+        const CHARS: &[u8] =
+          b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+        CHARS[rand::random::<u32>() as usize % CHARS.len()] as char
+      })
+      .collect();
+    let state = format!("{:016x}", rand::random::<u64>());
+    let challenge = base64_url(&Sha256::digest(verifier.as_bytes()));
+    let url = Url::new(AUTHORIZE_URL)
+      .query("client_id", client_id)
+      .query("response_type", "code")
+      .query("redirect_uri", REDIRECT_URI)
+      .query("scope", SCOPES)
+      .query("state", &state)
+      .query("code_challenge_method", "S256")
+      .query("code_challenge", &challenge)
+      .to_string();
+    let link = Self {
+      verifier,
+      state,
+      client_id: client_id.to_string(),
+      code: None,
+    };
+    (link, url)
+  }
+
+  /// Takes the code from the address the browser was sent back to. Fails
+  /// if the user declined or the address belongs to another attempt.
+  pub fn complete(&mut self, redirected_to: &str) -> anyhow::Result<()> {
+    let query = redirected_to.split_once('?').map_or("", |(_, query)| query);
+    let param = |name: &str| {
+      query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+    };
+    if let Some(error) = param("error") {
+      anyhow::bail!("Spotify said {error}");
+    }
+    if param("state").as_deref() != Some(self.state.as_str()) {
+      anyhow::bail!("that address is from another attempt, start again");
+    }
+    let Some(code) = param("code") else {
+      anyhow::bail!("no code in that address");
+    };
+    self.code = Some(code);
+    Ok(())
+  }
+}
+
+struct AccessToken {
+  token: String,
+  expires_at: Instant,
+}
+
+/// Keeps the track playing on the linked Spotify account in `state` while
+/// the Now Playing screen is open. Finishes linking an account once the
+/// settings page has handed over a code, and keeps the refresh token with
+/// the other secrets.
+pub struct Source {
+  state: SharedState,
+  link: SharedLink,
+  /// Set by the main loop while the Now Playing screen is shown
+  active: Arc<AtomicBool>,
+  nvs: EspDefaultNvsPartition,
+  access: Option<AccessToken>,
+  polled_at: Option<Instant>,
+}
+
+impl Source {
+  pub fn new(
+    state: SharedState,
+    link: SharedLink,
+    active: Arc<AtomicBool>,
+    nvs: EspDefaultNvsPartition,
+  ) -> Self {
+    Self {
+      state,
+      link,
+      active,
+      nvs,
+      access: None,
+      polled_at: None,
+    }
+  }
+
+  fn finish_link(&mut self) {
+    let mut link = self.link.lock().unwrap();
+    let Some(code) = link.as_mut().and_then(|link| link.code.take()) else {
+      return;
+    };
+    let Some(pending) = link.take() else {
+      return;
+    };
+    drop(link);
+    let form = url::form_encode(&[
+      ("grant_type", "authorization_code"),
+      ("code", &code),
+      ("redirect_uri", REDIRECT_URI),
+      ("client_id", &pending.client_id),
+      ("code_verifier", &pending.verifier),
+    ]);
+    match self.request_token(&form) {
+      Ok(()) => log::info!("Spotify account linked"),
+      Err(error) => log::error!("Could not link Spotify: {:?}", error),
+    }
+  }
+
+  /// A fresh access token, using the stored refresh token when the last
+  /// one has expired. `None` while no account is linked.
+  fn access_token(
+    &mut self,
+    config: &Config,
+  ) -> anyhow::Result<Option<String>> {
+    let refresh_token = secrets::get(self.nvs.clone(), secrets::SPOTIFY_TOKEN)?
+      .filter(|token| !token.is_empty());
+    let Some(refresh_token) = refresh_token else {
+      // unlinked from the settings page
+      self.access = None;
+      return Ok(None);
+    };
+    if let Some(access) = &self.access {
+      if access.expires_at > Instant::now() {
+        return Ok(Some(access.token.clone()));
+      }
+    }
+    let form = url::form_encode(&[
+      ("grant_type", "refresh_token"),
+      ("refresh_token", &refresh_token),
+      ("client_id", &config.spotify.client_id),
+    ]);
+    match self.request_token(&form) {
+      Err(error) if error.is::<KeyRejected>() => {
+        // revoked from the Spotify account page, or the app was removed
+        log::error!("Spotify refused the refresh token, link again");
+        secrets::set(self.nvs.clone(), secrets::SPOTIFY_TOKEN, "")?;
+        Ok(None)
+      }
+      Err(error) => Err(error),
+      Ok(()) => Ok(self.access.as_ref().map(|access| access.token.clone())),
+    }
+  }
+
+  /// Posts a token request and keeps what comes back. Spotify may hand out
+  /// a new refresh token with each one.
+  fn request_token(&mut self, form: &str) -> anyhow::Result<()> {
+    let json = http_client::post_form(TOKEN_URL, form)?;
+    let parsed: serde_json::Value = serde_json::from_str(&json)?;
+    let token = parsed["access_token"]
+      .as_str()
+      .ok_or_else(|| anyhow::anyhow!("no access token in response"))?;
+    let expires_in = parsed["expires_in"].as_u64().unwrap_or(3600);
+    if let Some(refresh_token) = parsed["refresh_token"].as_str() {
+      secrets::set(self.nvs.clone(), secrets::SPOTIFY_TOKEN, refresh_token)?;
+    }
+    self.access = Some(AccessToken {
+      token: token.to_string(),
+      // a minute early, so it doesn't run out on the way
+      expires_at: Instant::now()
+        + Duration::from_secs(expires_in.saturating_sub(60)),
+    });
+    Ok(())
+  }
+}
+
+impl fetch::Source for Source {
+  fn poll(&mut self, config: &Config) {
+    self.finish_link();
+    if !self.active.load(Ordering::Relaxed)
+      || self
+        .polled_at
+        .is_some_and(|at| at.elapsed() < POLL_INTERVAL)
+    {
+      return;
+    }
+    self.polled_at = Some(Instant::now());
+    let token = match self.access_token(config) {
+      Ok(Some(token)) => token,
+      Ok(None) => {
+        self.state.lock().unwrap().now_playing = None;
+        return;
+      }
+      Err(error) => {
+        log::warn!("Spotify token refresh failed: {:?}", error);
+        return;
+      }
+    };
+    match fetch(&token) {
+      Ok(track) => self.state.lock().unwrap().now_playing = track,
+      Err(error) if error.is::<KeyRejected>() => self.access = None,
+      Err(error) => log::warn!("Spotify update failed: {:?}", error),
+    }
+  }
+}
+
+/// Track playing on the account `token` belongs to, `None` when nothing is
+pub fn fetch(token: &str) -> anyhow::Result<Option<Track>> {
+  let authorization = format!("Bearer {token}");
+  let json = http_client::get_with_headers(
+    PLAYING_URL,
+    &[("authorization", authorization.as_str())],
+  )?;
+  // 204 with no body when nothing is playing
+  if json.trim().is_empty() {
+    return Ok(None);
+  }
+  let parsed: serde_json::Value = serde_json::from_str(&json)?;
+  let item = &parsed["item"];
+  let Some(title) = item["name"].as_str() else {
+    // ads and some podcasts come without an item
+    return Ok(None);
+  };
+  let artist = item["artists"]
+    .as_array()
+    .map(|artists| {
+      artists
+        .iter()
+        .filter_map(|artist| artist["name"].as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+    })
+    .unwrap_or_default();
+  Ok(Some(Track {
+    title: title.to_string(),
+    artist,
+    progress_ms: parsed["progress_ms"].as_u64().unwrap_or(0),
+    duration_ms: item["duration_ms"].as_u64().unwrap_or(0),
+    playing: parsed["is_playing"].as_bool().unwrap_or(false),
+    read_at: Instant::now(),
+  }))
+}
+
+/// Now Playing screen: title and artist, scrolling when too long, and a
+/// progress bar with the elapsed and total time
+pub fn draw(
+  display: &mut Display<'_>,
+  track: Option<&Track>,
+  elapsed: Duration,
+) {
+  let small_style = Font::Small.style();
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Now playing",
+    Point::new(1, top),
+    Align::Left,
+    small_style,
+  );
+  let Some(track) = track else {
+    typography::draw_centered(display, "Nothing playing", 28, small_style);
+    typography::draw_centered(
+      display,
+      "Link Spotify in settings",
+      40,
+      small_style,
+    );
+    return;
+  };
+  if !track.playing {
+    typography::draw(
+      display,
+      "Paused",
+      Point::new(typography::PANEL_WIDTH - 1, top),
+      Align::Right,
+      small_style,
+    );
+  }
+  marquee::draw(
+    display,
+    &track.title,
+    Rectangle::new(Point::new(1, top + 11), Size::new(126, 10)),
+    Font::Medium.style(),
+    elapsed,
+  );
+  marquee::draw(
+    display,
+    &track.artist,
+    Rectangle::new(Point::new(1, top + 23), Size::new(126, 8)),
+    small_style,
+    elapsed,
+  );
+
+  let progress = track.position_ms();
+  let bar = Rectangle::new(Point::new(1, top + 34), Size::new(126, 5));
+  let _ = bar
+    .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+    .draw(display);
+  let filled = (progress * 124).checked_div(track.duration_ms).unwrap_or(0);
+  let _ = Rectangle::new(Point::new(2, top + 35), Size::new(filled as u32, 3))
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+    .draw(display);
+  typography::draw(
+    display,
+    &minutes(progress),
+    Point::new(1, top + 42),
+    Align::Left,
+    small_style,
+  );
+  typography::draw(
+    display,
+    &minutes(track.duration_ms),
+    Point::new(typography::PANEL_WIDTH - 1, top + 42),
+    Align::Right,
+    small_style,
+  );
+}
+
+/// `m:ss`
+fn minutes(ms: u64) -> Line {
+  let seconds = ms / 1000;
+  typography::line(format_args!("{}:{:02}", seconds / 60, seconds % 60))
+}
+
+/// Unpadded base64url, as PKCE wants the challenge
+fn base64_url(bytes: &[u8]) -> String {
+  const ALPHABET: &[u8] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+  let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+  for chunk in bytes.chunks(3) {
+    let bits = chunk.iter().enumerate().fold(0_u32, |bits, (i, &byte)| {
+      bits | (u32::from(byte) << (16 - 8 * i))
+    });
+    for i in 0..=chunk.len() {
+      encoded.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+    }
+  }
+  encoded
+}
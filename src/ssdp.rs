@@ -0,0 +1,227 @@
+use std::{
+  fmt::Write,
+  net::{Ipv4Addr, UdpSocket},
+  time::{Duration, Instant},
+};
+
+/// Multicast group and port of SSDP, the discovery part of UPnP
+pub const GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+pub const PORT: u16 = 1900;
+/// Served by the web server
+pub const DESCRIPTION_PATH: &str = "/description.xml";
+pub const DEVICE_TYPE: &str = "urn:schemas-upnp-org:device:Basic:1";
+/// How long others may remember the device without hearing from it again
+const MAX_AGE_S: u64 = 1800;
+/// Announced again at well under [`MAX_AGE_S`], since multicast gets lost
+const NOTIFY_EVERY: Duration = Duration::from_secs(MAX_AGE_S / 3);
+const REQUEST_LEN: usize = 1024;
+
+/// This unit as a UPnP device
+#[derive(Clone, Debug)]
+pub struct Device {
+  pub name: String,
+  pub version: String,
+  /// Same across restarts, so a network browser keeps one entry for it
+  pub uuid: String,
+}
+
+impl Device {
+  pub fn new(name: String, version: &str, mac: [u8; 6]) -> Self {
+    let mut uuid = "9b2c5a42-7e1d-4f36-a0c8-".to_string();
+    for byte in mac {
+      let _ = write!(uuid, "{byte:02x}");
+    }
+    Self {
+      name,
+      version: version.to_string(),
+      uuid,
+    }
+  }
+
+  /// What the device is announced as, each with its own USN
+  pub fn targets(&self) -> [String; 3] {
+    [
+      "upnp:rootdevice".to_string(),
+      format!("uuid:{}", self.uuid),
+      DEVICE_TYPE.to_string(),
+    ]
+  }
+
+  /// The targets answering a search for `st`
+  pub fn answers(&self, st: &str) -> Vec<String> {
+    if st == "ssdp:all" {
+      return self.targets().to_vec();
+    }
+    self
+      .targets()
+      .into_iter()
+      .filter(|target| target == st)
+      .collect()
+  }
+
+  /// Unique service name of `target`
+  pub fn usn(&self, target: &str) -> String {
+    let uuid = format!("uuid:{}", self.uuid);
+    if target == uuid {
+      uuid
+    } else {
+      format!("{uuid}::{target}")
+    }
+  }
+
+  /// Reply to a search, sent to whoever searched
+  pub fn response(&self, target: &str, location: &str) -> String {
+    format!(
+      "HTTP/1.1 200 OK\r\n\
+       CACHE-CONTROL: max-age={MAX_AGE_S}\r\n\
+       EXT:\r\n\
+       LOCATION: {location}\r\n\
+       SERVER: {}\r\n\
+       ST: {target}\r\n\
+       USN: {}\r\n\r\n",
+      self.server(),
+      self.usn(target)
+    )
+  }
+
+  /// Announcement to the multicast group
+  pub fn notify(&self, target: &str, location: &str) -> String {
+    format!(
+      "NOTIFY * HTTP/1.1\r\n\
+       HOST: {GROUP}:{PORT}\r\n\
+       CACHE-CONTROL: max-age={MAX_AGE_S}\r\n\
+       LOCATION: {location}\r\n\
+       NT: {target}\r\n\
+       NTS: ssdp:alive\r\n\
+       SERVER: {}\r\n\
+       USN: {}\r\n\r\n",
+      self.server(),
+      self.usn(target)
+    )
+  }
+
+  /// The device description at [`DESCRIPTION_PATH`], with the web page as
+  /// the page a network browser opens
+  pub fn description(&self) -> String {
+    format!(
+      "<?xml version=\"1.0\"?>\n\
+       <root xmlns=\"urn:schemas-upnp-org:device-1-0\">\n\
+       <specVersion><major>1</major><minor>0</minor></specVersion>\n\
+       <device>\n\
+       <deviceType>{DEVICE_TYPE}</deviceType>\n\
+       <friendlyName>{}</friendlyName>\n\
+       <manufacturer>pippo</manufacturer>\n\
+       <modelName>pippo</modelName>\n\
+       <modelNumber>{}</modelNumber>\n\
+       <UDN>uuid:{}</UDN>\n\
+       <presentationURL>/</presentationURL>\n\
+       </device>\n\
+       </root>\n",
+      escape_xml(&self.name),
+      escape_xml(&self.version),
+      self.uuid
+    )
+  }
+
+  fn server(&self) -> String {
+    format!("esp-idf UPnP/1.1 pippo/{}", self.version)
+  }
+}
+
+/// The search target of an SSDP search, `None` for anything else sent to
+/// the group, such as other devices' announcements
+pub fn parse_search(request: &str) -> Option<&str> {
+  let mut lines = request.lines();
+  if !lines
+    .next()?
+    .trim()
+    .eq_ignore_ascii_case("M-SEARCH * HTTP/1.1")
+  {
+    return None;
+  }
+  let (mut discover, mut st) = (false, None);
+  for line in lines {
+    let Some((name, value)) = line.split_once(':') else {
+      continue;
+    };
+    let value = value.trim();
+    if name.trim().eq_ignore_ascii_case("MAN") {
+      discover = value == "\"ssdp:discover\"";
+    } else if name.trim().eq_ignore_ascii_case("ST") {
+      st = Some(value);
+    }
+  }
+  st.filter(|_| discover)
+}
+
+fn escape_xml(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// Answer searches and announce `device` to the multicast group. Its
+/// description is at `scheme://<address>/description.xml`, the address
+/// coming from `address` each time, and nothing is sent while there is
+/// none.
+pub fn spawn(
+  device: Device,
+  scheme: &'static str,
+  address: impl Fn() -> Option<Ipv4Addr> + Send + 'static,
+) -> anyhow::Result<()> {
+  let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, PORT))?;
+  socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+  // wakes up in time to announce even when nobody searches
+  socket.set_read_timeout(Some(Duration::from_secs(10)))?;
+  let name = device.name.clone();
+  let location =
+    move |ip: Ipv4Addr| format!("{scheme}://{ip}{DESCRIPTION_PATH}");
+  std::thread::Builder::new()
+    .name("ssdp".to_string())
+    .stack_size(6 * 1024)
+    .spawn(move || {
+      let mut buf = [0_u8; REQUEST_LEN];
+      let mut notified: Option<(Instant, Ipv4Addr)> = None;
+      loop {
+        let ip = address();
+        // a new address is announced straight away
+        let due = match notified {
+          Some((at, notified_ip)) => {
+            at.elapsed() >= NOTIFY_EVERY || Some(notified_ip) != ip
+          }
+          None => true,
+        };
+        if let Some(ip) = ip.filter(|_| due) {
+          for target in device.targets() {
+            let notify = device.notify(&target, &location(ip));
+            if let Err(error) = socket.send_to(notify.as_bytes(), (GROUP, PORT))
+            {
+              log::warn!("SSDP announcement failed: {:?}", error);
+            }
+          }
+          notified = Some((Instant::now(), ip));
+        }
+        let (len, sender) = match socket.recv_from(&mut buf) {
+          Ok(received) => received,
+          // the timeout, or no network yet
+          Err(_) => continue,
+        };
+        let Ok(request) = std::str::from_utf8(&buf[..len]) else {
+          continue;
+        };
+        let (Some(st), Some(ip)) = (parse_search(request), ip) else {
+          continue;
+        };
+        for target in device.answers(st) {
+          let response = device.response(&target, &location(ip));
+          if let Err(error) = socket.send_to(response.as_bytes(), sender) {
+            log::warn!("SSDP reply to {} failed: {:?}", sender, error);
+          }
+        }
+      }
+    })?;
+  log::info!("Announcing {} over SSDP", name);
+  Ok(())
+}
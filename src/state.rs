@@ -0,0 +1,178 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::Instant, diagnostics::I2cDevices, presence::Presence};
+
+/// Access point the station is associated with
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AccessPoint {
+  pub ssid: String,
+  /// `aa:bb:cc:dd:ee:ff`, telling apart the access points of a mesh
+  pub bssid: String,
+  pub channel: u8,
+}
+
+/// The device's own network, open while no saved one can be joined
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SetupAp {
+  pub ssid: String,
+  /// Only shown on the display
+  #[serde(skip)]
+  pub password: String,
+  /// Where the WiFi page is on that network
+  pub address: String,
+}
+
+/// Addresses of the station and how far its packets get, for the Network
+/// screen
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NetworkInfo {
+  /// `192.168.1.23/24`
+  pub address: String,
+  pub gateway: String,
+  pub dns: Option<String>,
+  pub mac: String,
+  pub gateway_ping: Reach,
+  /// A connection to a well known site, names looked up included
+  pub internet: Reach,
+}
+
+/// Outcome of a check on the Network screen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reach {
+  #[default]
+  Checking,
+  Answered {
+    ms: u32,
+  },
+  NoAnswer,
+}
+
+/// Current conditions from the weather API
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Weather {
+  pub temp_c: f64,
+  pub condition: String,
+  pub humidity: u64,
+}
+
+/// Price of a coin on the ticker screen
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Price {
+  /// CoinGecko id, such as `bitcoin`
+  pub coin: String,
+  pub price: f64,
+  /// Percent, `None` when the API left it out
+  pub change_24h: Option<f64>,
+}
+
+/// Latest price of a symbol on the stocks screen
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Quote {
+  pub symbol: String,
+  pub price: f64,
+  /// Since the previous close
+  pub change: f64,
+  pub change_percent: f64,
+  pub hours: TradingHours,
+}
+
+/// Start and end, in Unix seconds, of the trading periods of the day the
+/// quote is from
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct TradingHours {
+  pub pre: (i64, i64),
+  pub regular: (i64, i64),
+  pub post: (i64, i64),
+}
+
+/// Track playing on the linked Spotify account
+#[derive(Clone, Debug, Serialize)]
+pub struct Track {
+  pub title: String,
+  /// All of them, comma separated
+  pub artist: String,
+  pub progress_ms: u64,
+  pub duration_ms: u64,
+  pub playing: bool,
+  /// When `progress_ms` was read, so the progress bar moves between polls
+  #[serde(skip)]
+  pub read_at: Instant,
+}
+
+impl Track {
+  /// Position in the track now, counting on from the last poll while it
+  /// plays
+  pub fn position_ms(&self) -> u64 {
+    let moved = if self.playing {
+      self.read_at.elapsed().as_millis() as u64
+    } else {
+      0
+    };
+    (self.progress_ms + moved).min(self.duration_ms)
+  }
+}
+
+/// Quote of the day on the Home screen, cached in NVS
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DailyQuote {
+  pub text: String,
+  pub author: String,
+  /// Local date it was fetched for, as `YYYY-MM-DD`
+  pub date: String,
+}
+
+/// Snapshot of what the device knows, refreshed by the main loop and served
+/// to the web dashboard
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DeviceState {
+  pub time: String,
+  pub weather: Option<Weather>,
+  pub prices: Vec<Price>,
+  pub quotes: Vec<Quote>,
+  /// Newest first, from the feed in the settings
+  pub headlines: Vec<String>,
+  /// Headlines that arrived since the Headlines screen was last open
+  pub unread_headlines: usize,
+  /// Only kept up to date while the Now Playing screen is open
+  pub now_playing: Option<Track>,
+  pub quote: Option<DailyQuote>,
+  /// Names of the thresholds currently crossed
+  pub alerts: Vec<String>,
+  /// `None` while no phones are set, or until it is known
+  pub presence: Option<Presence>,
+  /// Scene set last, until the next boot
+  pub scene: Option<String>,
+  /// Set by a rule's `arm` action, until the next boot
+  pub disarmed: bool,
+  /// `None` without a door switch
+  pub door_open: Option<bool>,
+  pub motion: bool,
+  /// Seconds since the PIR last saw motion
+  pub last_motion_s: Option<u64>,
+  pub rssi: Option<i32>,
+  /// `None` while not connected
+  pub access_point: Option<AccessPoint>,
+  pub setup_ap: Option<SetupAp>,
+  /// Time server the clock was set from, `None` until it is
+  pub ntp_server: Option<String>,
+  /// Only kept up to date while the Network screen is open
+  pub network: Option<NetworkInfo>,
+  pub free_heap: u32,
+  pub uptime_s: u64,
+  /// Parts found on the I2C bus at boot
+  pub i2c: I2cDevices,
+}
+
+pub type SharedState = Arc<Mutex<DeviceState>>;
+
+/// Seconds since boot
+pub fn uptime_s() -> u64 {
+  Instant::now().since_boot().as_secs()
+}
+
+pub fn free_heap() -> u32 {
+  unsafe { esp_idf_svc::sys::esp_get_free_heap_size() }
+}
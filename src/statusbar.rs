@@ -0,0 +1,133 @@
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+  display::Display,
+  titlebar::{self, Title},
+  typography::{self, Align, Font, Line},
+};
+
+/// Rows at the top of the panel taken by the bar; screens draw below it
+pub const HEIGHT: i32 = 10;
+
+/// What the bar shows, gathered by the main loop every frame
+pub struct StatusBar {
+  pub time: Line,
+  /// The clock has been set from NTP
+  pub synced: bool,
+  /// `None` while not connected
+  pub rssi: Option<i32>,
+  /// `None` on boards without a battery monitor
+  pub battery_percent: Option<u8>,
+  pub notifications: usize,
+  /// New headlines not yet seen on the Headlines screen
+  pub unread_headlines: usize,
+  /// Away from Home, on the left with the clock moved right
+  pub title: Option<Title>,
+}
+
+/// Signal strength as 0-4 bars
+pub fn bars(rssi: i32) -> u32 {
+  match rssi {
+    -55.. => 4,
+    -67.. => 3,
+    -78.. => 2,
+    -89.. => 1,
+    _ => 0,
+  }
+}
+
+/// Clock on the left, or the screen's title there and the clock beside the
+/// icons away from Home; unread headlines, notification count, battery and
+/// signal on the right
+pub fn draw(display: &mut Display<'_>, bar: &StatusBar) {
+  let style = Font::Small.style();
+  let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+  let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+  let _ = Rectangle::new(Point::zero(), Size::new(128, HEIGHT as u32))
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+    .draw(display);
+  let _ = Rectangle::new(Point::new(0, HEIGHT - 1), Size::new(128, 1))
+    .into_styled(fill)
+    .draw(display);
+
+  // signal: four bars of growing height, unlit ones drawn as a dot
+  let mut x = 127 - 4 * 3;
+  let lit = bar.rssi.map_or(0, bars);
+  for bar_index in 0..4 {
+    let height = if bar_index < lit {
+      2 + 2 * bar_index
+    } else {
+      1
+    };
+    let top = 8 - height as i32;
+    let _ = Rectangle::new(
+      Point::new(x + 3 * bar_index as i32, top),
+      Size::new(2, height),
+    )
+    .into_styled(fill)
+    .draw(display);
+  }
+  if bar.rssi.is_none() {
+    typography::draw(display, "x", Point::new(x - 1, 0), Align::Right, style);
+    x -= 6;
+  }
+
+  if let Some(percent) = bar.battery_percent {
+    x -= 14;
+    let _ = Rectangle::new(Point::new(x, 1), Size::new(11, 7))
+      .into_styled(outline)
+      .draw(display);
+    let _ = Rectangle::new(Point::new(x + 11, 3), Size::new(1, 3))
+      .into_styled(fill)
+      .draw(display);
+    let level = u32::from(percent.min(100)) * 9 / 100;
+    let _ = Rectangle::new(Point::new(x + 1, 2), Size::new(level, 5))
+      .into_styled(fill)
+      .draw(display);
+  }
+
+  if bar.notifications > 0 {
+    let count = typography::line(format_args!("{}", bar.notifications.min(9)));
+    x -= 10;
+    let _ = Rectangle::new(Point::new(x, 0), Size::new(7, 9))
+      .into_styled(fill)
+      .draw(display);
+    typography::draw(
+      display,
+      &count,
+      Point::new(x + 1, 0),
+      Align::Left,
+      Font::Small.inverted(),
+    );
+  }
+
+  // outlined, unlike the notification count
+  if bar.unread_headlines > 0 {
+    let count =
+      typography::line(format_args!("{}", bar.unread_headlines.min(9)));
+    x -= 10;
+    let _ = Rectangle::new(Point::new(x, 0), Size::new(7, 9))
+      .into_styled(outline)
+      .draw(display);
+    typography::draw(display, &count, Point::new(x + 1, 0), Align::Left, style);
+  }
+
+  // a clock that was never synced still counts from 1970
+  let time = if bar.synced {
+    bar.time.as_str()
+  } else {
+    "--:--"
+  };
+  let Some(title) = &bar.title else {
+    typography::draw(display, time, Point::new(1, 0), Align::Left, style);
+    return;
+  };
+  typography::draw(display, time, Point::new(x - 2, 0), Align::Right, style);
+  // drawn last, the title takes what room the rest leaves
+  let right = x - 2 - typography::width(time, &style) as i32 - 3;
+  titlebar::draw(display, title, right);
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use embedded_graphics::prelude::*;
+
+use crate::{
+  config::{Config, Stocks},
+  display::Display,
+  fetch, http_client,
+  pager::{self, Pager},
+  state::{Quote, SharedState, TradingHours},
+  statusbar,
+  typography::{self, Align, Font},
+  url::Url,
+};
+
+/// Where trading stands for a quote at some moment
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Market {
+  PreMarket,
+  Open,
+  AfterHours,
+  Closed,
+}
+
+impl Market {
+  /// Market state at `now` (Unix seconds) going by the exchange's trading
+  /// periods for the day. Outside all of them, such as on weekends, the
+  /// market is closed.
+  pub fn at(hours: &TradingHours, now: i64) -> Self {
+    let within = |(start, end): (i64, i64)| (start..end).contains(&now);
+    if within(hours.regular) {
+      Self::Open
+    } else if within(hours.pre) {
+      Self::PreMarket
+    } else if within(hours.post) {
+      Self::AfterHours
+    } else {
+      Self::Closed
+    }
+  }
+
+  fn label(self) -> &'static str {
+    match self {
+      Self::PreMarket => "Pre-market",
+      Self::Open => "Open",
+      Self::AfterHours => "After hours",
+      Self::Closed => "Closed",
+    }
+  }
+}
+
+/// Keeps the quotes of the symbols in the stocks settings fresh in `state`.
+/// A change to the list fetches straight away.
+pub struct Source {
+  state: SharedState,
+  fetched: Option<(Instant, Stocks)>,
+}
+
+impl Source {
+  pub fn new(state: SharedState) -> Self {
+    Self {
+      state,
+      fetched: None,
+    }
+  }
+}
+
+impl fetch::Source for Source {
+  fn poll(&mut self, config: &Config) {
+    let interval =
+      Duration::from_secs(u64::from(config.refresh.stocks_min) * 60);
+    let due = match &self.fetched {
+      None => true,
+      Some((at, used)) => at.elapsed() >= interval || *used != config.stocks,
+    };
+    if !due {
+      return;
+    }
+    self.fetched = Some((Instant::now(), config.stocks.clone()));
+    let mut quotes = Vec::new();
+    for symbol in &config.stocks.symbols {
+      match fetch(symbol) {
+        Ok(quote) => quotes.push(quote),
+        Err(error) => log::warn!("Quote for {} failed: {:?}", symbol, error),
+      }
+    }
+    self.state.lock().unwrap().quotes = quotes;
+  }
+}
+
+/// Latest quote for `symbol` from Yahoo Finance's chart API, which needs no
+/// key. Prices during pre-market and after hours trading are the last
+/// regular session's.
+pub fn fetch(symbol: &str) -> anyhow::Result<Quote> {
+  log::info!("Fetching quote for {}", symbol);
+  let url = Url::new("https://query1.finance.yahoo.com/v8/finance/chart")
+    .segment(symbol)
+    .query("range", "1d")
+    .query("interval", "1d");
+  let parsed: serde_json::Value = http_client::get_json(url.as_str())?;
+  let meta = &parsed["chart"]["result"][0]["meta"];
+  let price = meta["regularMarketPrice"]
+    .as_f64()
+    .ok_or_else(|| anyhow::anyhow!("no price in response"))?;
+  let previous_close = meta["chartPreviousClose"].as_f64().unwrap_or(price);
+  let period = |name: &str| {
+    let period = &meta["currentTradingPeriod"][name];
+    (
+      period["start"].as_i64().unwrap_or(0),
+      period["end"].as_i64().unwrap_or(0),
+    )
+  };
+  Ok(Quote {
+    symbol: symbol.to_string(),
+    price,
+    change: price - previous_close,
+    change_percent: if previous_close == 0.0 {
+      0.0
+    } else {
+      (price - previous_close) / previous_close * 100.0
+    },
+    hours: TradingHours {
+      pre: period("pre"),
+      regular: period("regular"),
+      post: period("post"),
+    },
+  })
+}
+
+/// Stocks screen: one symbol per page with its price, the change since the
+/// last close and whether its market is open. `now` is `None` until the
+/// clock has been set, the market state is left out then.
+pub fn draw(
+  display: &mut Display<'_>,
+  quotes: &[Quote],
+  now: Option<i64>,
+  pager: &mut Pager,
+) {
+  let small_style = Font::Small.style();
+  let top = statusbar::HEIGHT + 1;
+  typography::draw(
+    display,
+    "Stocks",
+    Point::new(1, top),
+    Align::Left,
+    small_style,
+  );
+  if quotes.is_empty() {
+    typography::draw_centered(display, "No quotes yet", 28, small_style);
+    typography::draw_centered(
+      display,
+      "Add symbols in settings",
+      40,
+      small_style,
+    );
+    return;
+  }
+
+  let page = pager.page(quotes.len());
+  let quote = &quotes[page];
+  if let Some(now) = now {
+    typography::draw(
+      display,
+      Market::at(&quote.hours, now).label(),
+      Point::new(typography::PANEL_WIDTH - 1, top),
+      Align::Right,
+      small_style,
+    );
+  }
+  let large_style = Font::Large.style();
+  typography::draw(
+    display,
+    &quote.symbol,
+    Point::new(1, top + 11),
+    Align::Left,
+    large_style,
+  );
+  typography::draw(
+    display,
+    &typography::line(format_args!("{:.2}", quote.price)),
+    Point::new(typography::PANEL_WIDTH - 1, top + 11),
+    Align::Right,
+    large_style,
+  );
+  typography::draw_arrow(display, Point::new(1, top + 30), quote.change >= 0.0);
+  typography::draw(
+    display,
+    &typography::line(format_args!(
+      "{:+.2} ({:+.2}%)",
+      quote.change, quote.change_percent
+    )),
+    Point::new(11, top + 28),
+    Align::Left,
+    Font::Medium.style(),
+  );
+  pager::draw_dots(display, page, quotes.len());
+}
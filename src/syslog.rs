@@ -0,0 +1,114 @@
+use std::net::{SocketAddr, UdpSocket};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// `local0`, the usual facility for application logs on a LAN collector
+const FACILITY: u8 = 16;
+/// Datagrams above this size may be dropped by collectors (RFC5424 §6.1)
+const MAX_DATAGRAM_LEN: usize = 480;
+
+/// Forwards log records to a syslog collector over UDP in RFC5424 format
+pub struct Syslog {
+  socket: UdpSocket,
+  collector: SocketAddr,
+  level: LevelFilter,
+  hostname: String,
+}
+
+impl Syslog {
+  pub fn new(
+    collector: &str,
+    level: LevelFilter,
+    hostname: &str,
+  ) -> anyhow::Result<Self> {
+    let collector: SocketAddr = collector.parse()?;
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_nonblocking(true)?;
+    Ok(Self {
+      socket,
+      collector,
+      level,
+      hostname: hostname.to_string(),
+    })
+  }
+}
+
+impl Log for Syslog {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    metadata.level() <= self.level
+  }
+
+  fn log(&self, record: &Record) {
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+    let message = format_rfc5424(
+      record.level(),
+      timestamp(),
+      &self.hostname,
+      record.target(),
+      &record.args().to_string(),
+    );
+    // a lost datagram must never turn into another log record
+    let _ = self.socket.send_to(message.as_bytes(), self.collector);
+  }
+
+  fn flush(&self) {}
+}
+
+/// Wall-clock time, or `None` while the clock hasn't been set by NTP yet
+fn timestamp() -> Option<DateTime<Utc>> {
+  let now: DateTime<Utc> = std::time::SystemTime::now().into();
+  (now.timestamp() > 1_600_000_000).then_some(now)
+}
+
+fn severity(level: Level) -> u8 {
+  match level {
+    Level::Error => 3,
+    Level::Warn => 4,
+    Level::Info => 6,
+    Level::Debug | Level::Trace => 7,
+  }
+}
+
+/// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID SD MSG`
+fn format_rfc5424(
+  level: Level,
+  timestamp: Option<DateTime<Utc>>,
+  hostname: &str,
+  target: &str,
+  message: &str,
+) -> String {
+  let timestamp = timestamp
+    .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true))
+    .unwrap_or_else(|| "-".to_string());
+  // MSGID is limited to 32 printable ASCII characters without spaces
+  let msg_id: String = target
+    .chars()
+    .filter(|c| c.is_ascii_graphic())
+    .take(32)
+    .collect();
+  let msg_id = if msg_id.is_empty() {
+    "-".to_string()
+  } else {
+    msg_id
+  };
+
+  let mut line = format!(
+    "<{}>1 {} {} pippo - {} - {}",
+    FACILITY * 8 + severity(level),
+    timestamp,
+    hostname,
+    msg_id,
+    message
+  );
+  if line.len() > MAX_DATAGRAM_LEN {
+    let mut end = MAX_DATAGRAM_LEN;
+    while !line.is_char_boundary(end) {
+      end -= 1;
+    }
+    line.truncate(end);
+  }
+  line
+}
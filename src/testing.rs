@@ -0,0 +1,120 @@
+use std::{
+  collections::VecDeque,
+  sync::{Arc, Mutex},
+};
+
+use serde::Deserialize;
+
+use crate::{
+  clock,
+  state::{DeviceState, Weather},
+  ui::{ButtonEvent, UiState},
+};
+
+/// Inputs forced over `/api/v1/test`, only in debug builds
+pub type SharedOverrides = Arc<Mutex<Overrides>>;
+
+/// Forced presses waiting for the main loop, more are refused
+const MAX_PRESSES: usize = 16;
+/// How `POST /api/v1/test/time` writes the local time
+pub const TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+/// A button press forced from a test
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Press {
+  Short,
+  Long,
+}
+
+/// Body of `POST /api/v1/test/press`
+#[derive(Deserialize)]
+pub struct PressRequest {
+  pub press: Press,
+}
+
+/// Body of `POST /api/v1/test/sensors`. Each reading left out comes from
+/// the real sensor again.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Sensors {
+  /// The PIR
+  #[serde(default)]
+  pub motion: Option<bool>,
+  /// The weather readings, as the thresholds and the Status screen see them
+  #[serde(default)]
+  pub temp_c: Option<f64>,
+  #[serde(default)]
+  pub humidity: Option<u64>,
+}
+
+/// Body of `POST /api/v1/test/time`, `null` to start the clock again. The
+/// display, the rules and quiet hours all read the time through
+/// [`clock::local_now`], which honours it.
+#[derive(Deserialize)]
+pub struct TimeRequest {
+  /// Local time, e.g. `2026-10-16T22:30:00`
+  pub time: Option<String>,
+}
+
+/// What a test has forced, for end-to-end tests against a flashed board
+#[derive(Debug, Default)]
+pub struct Overrides {
+  presses: VecDeque<Press>,
+  pub sensors: Sensors,
+  /// Screen the main loop is on, for a test to check where presses led
+  pub screen: Option<UiState>,
+}
+
+impl Overrides {
+  /// Queue a press for the next frame. Returns false when too many are
+  /// waiting.
+  pub fn press(&mut self, press: Press) -> bool {
+    if self.presses.len() >= MAX_PRESSES {
+      return false;
+    }
+    self.presses.push_back(press);
+    true
+  }
+
+  /// The next forced press, taken once
+  pub fn take_press(&mut self) -> Option<ButtonEvent> {
+    self.presses.pop_front().map(|press| match press {
+      Press::Short => ButtonEvent::Short,
+      Press::Long => ButtonEvent::Long,
+    })
+  }
+
+  /// Put the fake weather readings over the fetched ones
+  pub fn apply(&self, state: &mut DeviceState) {
+    let Sensors {
+      temp_c, humidity, ..
+    } = self.sensors;
+    if temp_c.is_none() && humidity.is_none() {
+      return;
+    }
+    let weather = state.weather.get_or_insert_with(|| Weather {
+      condition: "Test".to_string(),
+      ..Default::default()
+    });
+    if let Some(temp_c) = temp_c {
+      weather.temp_c = temp_c;
+    }
+    if let Some(humidity) = humidity {
+      weather.humidity = humidity;
+    }
+  }
+
+  pub fn to_json(&self) -> serde_json::Value {
+    serde_json::json!({
+      "pending_presses": self.presses.len(),
+      "sensors": {
+        "motion": self.sensors.motion,
+        "temp_c": self.sensors.temp_c,
+        "humidity": self.sensors.humidity,
+      },
+      "time": clock::frozen()
+        .map(|time| time.format(TIME_FORMAT).to_string()),
+      "screen": self.screen,
+    })
+  }
+}
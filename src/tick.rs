@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use crate::clock::Instant;
+
+/// Time between frames while something on screen moves
+pub const FRAME: Duration = Duration::from_millis(20);
+/// Longest sleep when nothing is due. Presses and motion wake the loop
+/// sooner, what the web API changes shows up within this.
+pub const IDLE: Duration = Duration::from_secs(1);
+/// Shortest sleep, a FreeRTOS tick, so lower priority tasks still get to run
+const MIN_SLEEP: Duration = Duration::from_millis(10);
+
+/// When the main loop has to run again: the soonest deadline of the work
+/// that is pending, so an idle device wakes once a second instead of 50
+/// times
+pub struct Schedule {
+  now: Instant,
+  next: Instant,
+}
+
+impl Schedule {
+  /// Nothing due yet, so the loop would sleep for [`IDLE`]
+  pub fn new(now: Instant) -> Self {
+    Self {
+      now,
+      next: now + IDLE,
+    }
+  }
+
+  /// Something is due at `at`
+  pub fn at(&mut self, at: Instant) {
+    self.next = self.next.min(at);
+  }
+
+  /// Something is due `after` from now
+  pub fn within(&mut self, after: Duration) {
+    self.at(self.now + after);
+  }
+
+  /// Something on screen moves, draw the next frame
+  pub fn frame(&mut self) {
+    self.within(FRAME);
+  }
+
+  /// How long to sleep at `now`, once the work of this pass is done
+  pub fn sleep(&self, now: Instant) -> Duration {
+    self.next.saturating_duration_since(now).max(MIN_SLEEP)
+  }
+}
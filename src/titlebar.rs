@@ -0,0 +1,100 @@
+use std::fmt;
+
+use embedded_graphics::{
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Rectangle},
+};
+
+use crate::{
+  display::Display,
+  typography::{self, Align, Font, Line},
+  ui::{Path, UiState},
+};
+
+/// Room the back arrow takes, with the gap after it
+const ARROW_WIDTH: i32 = 5;
+
+/// Where the screen on show is, `Menu>Games>Snake`, drawn left of the clock
+/// once the button has left Home, see `statusbar::draw`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Title {
+  /// From the main menu down to the screen
+  pub path: Path,
+  /// A long press goes back, rather than opening a menu entry
+  pub back: bool,
+}
+
+impl Title {
+  /// `None` on Home, which shows the clock
+  pub fn for_screen(screen: UiState) -> Option<Self> {
+    let path = screen.path();
+    (!path.is_empty()).then(|| Self {
+      path,
+      back: !screen.is_menu(),
+    })
+  }
+}
+
+/// The crumbs joined by `>`, the first ones left out for `..` while they
+/// are wider than `width`, and the screen's own cut short last of all
+pub fn crumbs(path: &[&str], width: u32) -> Line {
+  let style = Font::Small.style();
+  for skip in 0..path.len() {
+    let prefix = if skip > 0 { "..>" } else { "" };
+    let line =
+      typography::line(format_args!("{prefix}{}", Joined(&path[skip..])));
+    if typography::width(&line, &style) <= width {
+      return line;
+    }
+  }
+  let last =
+    typography::line(format_args!("{}", path.last().copied().unwrap_or("")));
+  (0..=last.chars().count())
+    .rev()
+    .map(|count| typography::first_chars(last.clone(), count))
+    .find(|line| typography::width(line, &style) <= width)
+    .unwrap_or_default()
+}
+
+/// Crumbs with `>` between them, written straight into a [`Line`]
+struct Joined<'a>(&'a [&'a str]);
+
+impl fmt::Display for Joined<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for (index, crumb) in self.0.iter().enumerate() {
+      if index > 0 {
+        f.write_str(">")?;
+      }
+      f.write_str(crumb)?;
+    }
+    Ok(())
+  }
+}
+
+/// The back arrow when a long press goes back, then the crumbs, kept left
+/// of `right`
+pub fn draw(display: &mut Display<'_>, title: &Title, right: i32) {
+  let mut x = 1;
+  if title.back {
+    // a left-pointing triangle, one column at a time
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+    for column in 0..3 {
+      let _ = Rectangle::new(
+        Point::new(x + column, 4 - column),
+        Size::new(1, 1 + 2 * column as u32),
+      )
+      .into_styled(fill)
+      .draw(display);
+    }
+    x += ARROW_WIDTH;
+  }
+  let width = u32::try_from(right - x).unwrap_or(0);
+  typography::draw(
+    display,
+    &crumbs(&title.path, width),
+    Point::new(x, 0),
+    Align::Left,
+    Font::Small.style(),
+  );
+}
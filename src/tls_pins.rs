@@ -0,0 +1,147 @@
+use std::{ffi::CString, sync::Mutex};
+
+use esp_idf_svc::{
+  nvs::{EspCustomNvs, EspCustomNvsPartition},
+  sys::{esp, esp_tls_free_global_ca_store, esp_tls_set_global_ca_store},
+};
+use serde::Deserialize;
+
+/// Kept in the same partition as the HTTPS certificate, see [`crate::certs`]
+const NAMESPACE: &str = "pins";
+/// NVS strings can't be longer than this
+const MAX_PEM_LEN: usize = 4000;
+
+const UNPINNED: Option<CString> = None;
+/// Pinned certificates, set at boot and whenever one is uploaded
+static PINS: Mutex<[Option<CString>; Endpoint::ALL.len()]> =
+  Mutex::new([UNPINNED; Endpoint::ALL.len()]);
+
+/// Outgoing connections whose certificate can be pinned
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Endpoint {
+  /// weatherapi.com
+  Weather,
+  /// Wherever firmware updates are downloaded from
+  Ota,
+}
+
+impl Endpoint {
+  /// In the order of the variants, which index the pins
+  const ALL: [Endpoint; 2] = [Endpoint::Weather, Endpoint::Ota];
+
+  pub fn parse(name: &str) -> Option<Self> {
+    Self::ALL
+      .into_iter()
+      .find(|endpoint| endpoint.key() == name)
+  }
+
+  fn key(self) -> &'static str {
+    match self {
+      Endpoint::Weather => "weather",
+      Endpoint::Ota => "ota",
+    }
+  }
+}
+
+/// A certificate the server of `endpoint` has to present, or have its own
+/// signed by, uploaded from the web. esp-tls checks chains rather than
+/// single keys, so the pin is a certificate: the server's own if it is
+/// self-signed, otherwise the CA or intermediate that issued it. Other CAs
+/// in the bundle are not trusted for that endpoint.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Pin {
+  pub endpoint: Endpoint,
+  pub certificate: String,
+}
+
+impl Pin {
+  pub fn validate(&self) -> anyhow::Result<()> {
+    let pem = &self.certificate;
+    if !pem.trim_start().starts_with("-----BEGIN")
+      || !pem.contains("CERTIFICATE-----")
+    {
+      anyhow::bail!("certificate must be PEM encoded");
+    }
+    if pem.len() > MAX_PEM_LEN || pem.contains('\0') {
+      anyhow::bail!("certificate must be at most {MAX_PEM_LEN} bytes");
+    }
+    Ok(())
+  }
+
+  /// Stores the pin and uses it from the next request on
+  pub fn save(&self, partition: EspCustomNvsPartition) -> anyhow::Result<()> {
+    let mut nvs = EspCustomNvs::new(partition, NAMESPACE, true)?;
+    nvs.set_str(self.endpoint.key(), &self.certificate)?;
+    set(
+      self.endpoint,
+      Some(CString::new(self.certificate.as_str())?),
+    );
+    Ok(())
+  }
+}
+
+/// Forgets the pin of `endpoint`, which goes back to the CA bundle
+pub fn erase(
+  partition: EspCustomNvsPartition,
+  endpoint: Endpoint,
+) -> anyhow::Result<()> {
+  let mut nvs = EspCustomNvs::new(partition, NAMESPACE, true)?;
+  nvs.remove(endpoint.key())?;
+  set(endpoint, None);
+  Ok(())
+}
+
+/// Reads the stored pins, before the first request goes out
+pub fn load(partition: EspCustomNvsPartition) -> anyhow::Result<()> {
+  let nvs = EspCustomNvs::new(partition, NAMESPACE, true)?;
+  let mut buf = vec![0_u8; MAX_PEM_LEN + 1];
+  for endpoint in Endpoint::ALL {
+    if let Some(pem) = nvs.get_str(endpoint.key(), &mut buf)? {
+      log::info!("Certificate pinned for {}", endpoint.key());
+      set(endpoint, Some(CString::new(pem)?));
+    }
+  }
+  Ok(())
+}
+
+/// Names of the endpoints with a pin
+pub fn pinned() -> Vec<&'static str> {
+  let pins = PINS.lock().unwrap();
+  Endpoint::ALL
+    .into_iter()
+    .filter(|endpoint| pins[*endpoint as usize].is_some())
+    .map(Endpoint::key)
+    .collect()
+}
+
+fn set(endpoint: Endpoint, pem: Option<CString>) {
+  PINS.lock().unwrap()[endpoint as usize] = pem;
+}
+
+/// Runs `connect` with the pin of `endpoint` in the global CA store, or
+/// straight away if it has none. `connect` is told which, and has to
+/// finish the TLS handshake before it returns. The store is shared by all
+/// connections, so pinned requests take turns.
+pub fn with<T>(
+  endpoint: Endpoint,
+  connect: impl FnOnce(bool) -> anyhow::Result<T>,
+) -> anyhow::Result<T> {
+  let pins = PINS.lock().unwrap();
+  let Some(pem) = &pins[endpoint as usize] else {
+    drop(pins);
+    return connect(false);
+  };
+  let pem = pem.as_bytes_with_nul();
+  // Safety: the store is only touched with `PINS` locked. It parses its own
+  // copy of the certificate, and its length counts the terminating NUL the
+  // PEM parser needs.
+  unsafe {
+    esp_tls_free_global_ca_store();
+    esp!(esp_tls_set_global_ca_store(pem.as_ptr(), pem.len() as u32))?;
+  }
+  let result = connect(true);
+  // Safety: as above, no other connection is using the store
+  unsafe { esp_tls_free_global_ca_store() };
+  result
+}
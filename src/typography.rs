@@ -0,0 +1,326 @@
+use std::{borrow::Cow, fmt};
+
+use embedded_graphics::{
+  image::{Image, ImageRaw},
+  mono_font::{iso_8859_1, MonoFont, MonoTextStyle},
+  pixelcolor::BinaryColor,
+  prelude::*,
+  primitives::{PrimitiveStyle, Triangle},
+  text::{renderer::TextRenderer, Baseline, Text},
+};
+
+use crate::display::Display;
+
+/// Width of the panel in pixels
+pub const PANEL_WIDTH: i32 = 128;
+/// Bytes in a [`Line`], more than fit across the panel in the smallest font
+pub const LINE_CAPACITY: usize = 64;
+
+/// Text formatted for one line of a screen, kept on the stack so drawing a
+/// frame doesn't allocate. See [`line`].
+pub type Line = heapless::String<LINE_CAPACITY>;
+
+// 8x8 icons, one byte per row
+#[rustfmt::skip]
+const SUN: [u8; 8] = [
+  0b00011000,
+  0b01000010,
+  0b00011000,
+  0b10111101,
+  0b10111101,
+  0b00011000,
+  0b01000010,
+  0b00011000,
+];
+#[rustfmt::skip]
+const CLOUD: [u8; 8] = [
+  0b00000000,
+  0b00111000,
+  0b01000110,
+  0b10000001,
+  0b10000001,
+  0b01111110,
+  0b00000000,
+  0b00000000,
+];
+#[rustfmt::skip]
+const RAIN: [u8; 8] = [
+  0b00111000,
+  0b01000110,
+  0b10000001,
+  0b01111110,
+  0b00000000,
+  0b01001001,
+  0b10010010,
+  0b00000000,
+];
+#[rustfmt::skip]
+const SNOW: [u8; 8] = [
+  0b00111000,
+  0b01000110,
+  0b10000001,
+  0b01111110,
+  0b00000000,
+  0b10010010,
+  0b00000000,
+  0b01001001,
+];
+#[rustfmt::skip]
+const STORM: [u8; 8] = [
+  0b00111000,
+  0b01000110,
+  0b10000001,
+  0b01111110,
+  0b00001000,
+  0b00011000,
+  0b00001000,
+  0b00010000,
+];
+#[rustfmt::skip]
+const FOG: [u8; 8] = [
+  0b00000000,
+  0b11111100,
+  0b00000000,
+  0b00111111,
+  0b00000000,
+  0b11111100,
+  0b00000000,
+  0b00111111,
+];
+
+/// Font sizes used across the screens. All cover Latin-1, so the degree sign
+/// and accented letters in weather conditions and messages draw correctly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Font {
+  /// 5x8, for dense lists such as logs and self-test results
+  Small,
+  /// 6x10, for banners and progress screens
+  Medium,
+  /// 7x13, for titles and the main screens
+  Large,
+}
+
+impl Font {
+  pub fn mono(self) -> &'static MonoFont<'static> {
+    match self {
+      Font::Small => &iso_8859_1::FONT_5X8,
+      Font::Medium => &iso_8859_1::FONT_6X10,
+      Font::Large => &iso_8859_1::FONT_7X13,
+    }
+  }
+
+  /// Lit text on an unlit background
+  pub fn style(self) -> MonoTextStyle<'static, BinaryColor> {
+    MonoTextStyle::new(self.mono(), BinaryColor::On)
+  }
+
+  /// Unlit text, for drawing on a filled background
+  pub fn inverted(self) -> MonoTextStyle<'static, BinaryColor> {
+    MonoTextStyle::new(self.mono(), BinaryColor::Off)
+  }
+}
+
+/// Where the anchor point passed to [`draw`] sits on the text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+  Left,
+  Center,
+  Right,
+}
+
+/// 8x8 weather symbols drawn next to text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Icon {
+  Sun,
+  Cloud,
+  Rain,
+  Snow,
+  Storm,
+  Fog,
+}
+
+impl Icon {
+  /// Symbol for a weather API condition such as "Patchy light rain"
+  pub fn for_condition(condition: &str) -> Option<Self> {
+    let condition = condition.to_lowercase();
+    let has =
+      |words: &[&str]| words.iter().any(|word| condition.contains(word));
+    if has(&["thunder"]) {
+      Some(Icon::Storm)
+    } else if has(&["snow", "sleet", "blizzard", "ice"]) {
+      Some(Icon::Snow)
+    } else if has(&["rain", "drizzle", "shower"]) {
+      Some(Icon::Rain)
+    } else if has(&["fog", "mist", "haze"]) {
+      Some(Icon::Fog)
+    } else if has(&["cloud", "overcast"]) {
+      Some(Icon::Cloud)
+    } else if has(&["sun", "clear"]) {
+      Some(Icon::Sun)
+    } else {
+      None
+    }
+  }
+
+  fn bitmap(self) -> &'static [u8; 8] {
+    match self {
+      Icon::Sun => &SUN,
+      Icon::Cloud => &CLOUD,
+      Icon::Rain => &RAIN,
+      Icon::Snow => &SNOW,
+      Icon::Storm => &STORM,
+      Icon::Fog => &FOG,
+    }
+  }
+}
+
+/// `text` as the Latin-1 fonts can draw it. Typographic punctuation becomes
+/// its ASCII look-alike and any other character outside Latin-1 a `?`.
+pub fn displayable(text: &str) -> Cow<'_, str> {
+  if text.chars().all(|c| u32::from(c) <= 0xFF) {
+    return Cow::Borrowed(text);
+  }
+  let mut out = String::with_capacity(text.len());
+  for c in text.chars() {
+    match c {
+      '\u{2018}' | '\u{2019}' => out.push('\''),
+      '\u{201C}' | '\u{201D}' => out.push('"'),
+      '\u{2013}' | '\u{2014}' => out.push('-'),
+      '\u{2026}' => out.push_str("..."),
+      c if u32::from(c) <= 0xFF => out.push(c),
+      _ => out.push('?'),
+    }
+  }
+  Cow::Owned(out)
+}
+
+/// `args` formatted like `format!` does, into a [`Line`]. Text past its
+/// capacity is cut off at a character boundary.
+pub fn line(args: fmt::Arguments<'_>) -> Line {
+  let mut line = Line::new();
+  // the error only says the rest was cut off
+  let _ = fmt::Write::write_fmt(&mut Truncating(&mut line), args);
+  line
+}
+
+/// The first `count` characters of `line`, for a fixed number of columns
+pub fn first_chars(mut line: Line, count: usize) -> Line {
+  if let Some((end, _)) = line.char_indices().nth(count) {
+    line.truncate(end);
+  }
+  line
+}
+
+/// Writes as much as fits, then stops the formatting
+struct Truncating<'a>(&'a mut Line);
+
+impl fmt::Write for Truncating<'_> {
+  fn write_str(&mut self, text: &str) -> fmt::Result {
+    for c in text.chars() {
+      self.0.push(c).map_err(|()| fmt::Error)?;
+    }
+    Ok(())
+  }
+}
+
+/// Width of `text` in pixels when drawn with `style`
+pub fn width(text: &str, style: &MonoTextStyle<'_, BinaryColor>) -> u32 {
+  style
+    .measure_string(&displayable(text), Point::zero(), Baseline::Top)
+    .bounding_box
+    .size
+    .width
+}
+
+/// Draw `text` with its top edge at `anchor.y`. Depending on `align`,
+/// `anchor.x` is the left edge, the middle or the right edge of the text.
+pub fn draw(
+  display: &mut impl DrawTarget<Color = BinaryColor>,
+  text: &str,
+  anchor: Point,
+  align: Align,
+  style: MonoTextStyle<'_, BinaryColor>,
+) {
+  let width = width(text, &style) as i32;
+  let x = match align {
+    Align::Left => anchor.x,
+    Align::Center => anchor.x - width / 2,
+    Align::Right => anchor.x - width,
+  };
+  let text = displayable(text);
+  let _ =
+    Text::with_baseline(&text, Point::new(x, anchor.y), style, Baseline::Top)
+      .draw(display);
+}
+
+/// `text` broken into lines no wider than `max_width` pixels, at spaces
+/// where it can be and inside a word too long for a line of its own
+pub fn wrap(
+  text: &str,
+  max_width: u32,
+  style: &MonoTextStyle<'_, BinaryColor>,
+) -> Vec<String> {
+  let mut lines = Vec::new();
+  let mut line = String::new();
+  for word in text.split_whitespace() {
+    let candidate = if line.is_empty() {
+      word.to_string()
+    } else {
+      format!("{line} {word}")
+    };
+    if width(&candidate, style) <= max_width {
+      line = candidate;
+      continue;
+    }
+    if !line.is_empty() {
+      lines.push(std::mem::take(&mut line));
+    }
+    for c in word.chars() {
+      line.push(c);
+      if width(&line, style) > max_width {
+        line.pop();
+        lines.push(std::mem::take(&mut line));
+        line.push(c);
+      }
+    }
+  }
+  if !line.is_empty() {
+    lines.push(line);
+  }
+  lines
+}
+
+/// Draw `icon` with its top-left corner at `top_left`
+pub fn draw_icon(display: &mut Display<'_>, icon: Icon, top_left: Point) {
+  let raw = ImageRaw::<BinaryColor>::new(icon.bitmap(), 8);
+  let _ = Image::new(&raw, top_left).draw(display);
+}
+
+/// Draw `text` centred horizontally on the panel with its top edge at `y`
+pub fn draw_centered(
+  display: &mut impl DrawTarget<Color = BinaryColor>,
+  text: &str,
+  y: i32,
+  style: MonoTextStyle<'_, BinaryColor>,
+) {
+  draw(
+    display,
+    text,
+    Point::new(PANEL_WIDTH / 2, y),
+    Align::Center,
+    style,
+  );
+}
+
+/// 7 px wide triangle pointing up for a rise, down for a fall, next to a
+/// price or reading
+pub fn draw_arrow(display: &mut Display<'_>, top_left: Point, up: bool) {
+  let (tip, base) = if up { (0, 6) } else { (6, 0) };
+  let _ = Triangle::new(
+    top_left + Point::new(3, tip),
+    top_left + Point::new(0, base),
+    top_left + Point::new(6, base),
+  )
+  .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+  .draw(display);
+}
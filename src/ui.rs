@@ -0,0 +1,424 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{clock::Instant, screens::SCREENS};
+
+/// Screens, moved between with the button. Nothing here touches the
+/// hardware or the clock, so the simulator runs the same navigation and the
+/// tests in `tests/ui.rs` can script it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UiState {
+  Home,
+  Menu,
+  Settings,
+  Profiles,
+  Scenes,
+  Status,
+  Network,
+  History,
+  Activity,
+  Crypto,
+  Stocks,
+  Headlines,
+  NowPlaying,
+  Notifications,
+  Logs,
+  Games,
+  Snake,
+  Exit,
+}
+
+impl UiState {
+  /// Moves on its own, scrolling text or a game, so it is drawn every frame
+  pub fn is_animated(self) -> bool {
+    self.entry().is_some_and(|entry| entry.animated)
+  }
+
+  /// Split over pages that turn by themselves, see `pager::Pager`
+  pub fn has_pages(self) -> bool {
+    self == UiState::Home || self.entry().is_some_and(|entry| entry.pages)
+  }
+}
+
+/// Symbol drawn next to a menu entry, see `screens::menu_screen`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MenuIcon {
+  Gear,
+  Person,
+  Star,
+  Info,
+  Signal,
+  Graph,
+  Bars,
+  Coin,
+  Trend,
+  News,
+  Note,
+  Bell,
+  List,
+  Gamepad,
+  Back,
+}
+
+/// What a short press does on a screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShortPress {
+  /// Left to the caller, which keeps the state, as for the History graphs
+  /// and the game. Nothing for screens that fit on one page.
+  Caller,
+  /// Show the next page
+  NextPage,
+  /// Page back through older lines, [`LINES_PER_PAGE`] at a time, wrapping
+  /// around
+  OlderLines,
+  /// Select the next one of a list, wrapping around
+  NextItem,
+  /// Select the next one and scroll it from its start
+  NextItemFromStart,
+  /// Scroll from the start again
+  Restart,
+  /// Step the brightness, wrapping to the dimmest
+  Brightness,
+  /// Back to the top of the main menu
+  TopOfMenu,
+}
+
+/// A screen opened from a menu: how that menu lists it and how the screen
+/// takes the button. Each screen's module under `screens` declares its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MenuEntry {
+  /// The menu listing it
+  pub menu: UiState,
+  pub label: &'static str,
+  pub icon: MenuIcon,
+  /// Entered when the entry is chosen
+  pub screen: UiState,
+  pub press: ShortPress,
+  /// See [`UiState::is_animated`]
+  pub animated: bool,
+  /// See [`UiState::has_pages`]
+  pub pages: bool,
+}
+
+pub const fn entry(
+  menu: UiState,
+  label: &'static str,
+  icon: MenuIcon,
+  screen: UiState,
+) -> MenuEntry {
+  MenuEntry {
+    menu,
+    label,
+    icon,
+    screen,
+    press: ShortPress::Caller,
+    animated: false,
+    pages: false,
+  }
+}
+
+impl MenuEntry {
+  pub const fn press(self, press: ShortPress) -> Self {
+    Self { press, ..self }
+  }
+
+  pub const fn animated(self) -> Self {
+    Self {
+      animated: true,
+      ..self
+    }
+  }
+
+  /// Split over pages a short press turns
+  pub const fn paged(self) -> Self {
+    Self {
+      pages: true,
+      press: ShortPress::NextPage,
+      ..self
+    }
+  }
+}
+
+impl UiState {
+  /// The entries of this menu in order, none for a screen that isn't one
+  pub fn entries(self) -> impl Iterator<Item = &'static MenuEntry> {
+    SCREENS
+      .iter()
+      .map(|screen| &screen.entry)
+      .filter(move |entry| entry.menu == self)
+  }
+
+  pub fn menu_len(self) -> usize {
+    self.entries().count()
+  }
+
+  pub fn is_menu(self) -> bool {
+    self.entries().next().is_some()
+  }
+
+  /// How a menu lists this screen, `None` for Home and the main menu
+  pub fn entry(self) -> Option<&'static MenuEntry> {
+    SCREENS
+      .iter()
+      .map(|screen| &screen.entry)
+      .find(|entry| entry.screen == self)
+  }
+
+  /// The labels from the main menu down to this screen, e.g. `["Menu",
+  /// "Games", "Snake"]`, none for Home. Kept on the stack, the status bar
+  /// asks for it every frame.
+  pub fn path(self) -> Path {
+    let mut path = Path::new();
+    let mut screen = self;
+    // a path deeper than PATH_DEPTH loses its top
+    while let Some(entry) = screen.entry() {
+      let _ = path.push(entry.label);
+      screen = entry.menu;
+    }
+    if screen == UiState::Menu {
+      let _ = path.push("Menu");
+    }
+    path.reverse();
+    path
+  }
+
+  /// Where a long press leaves this screen for: the submenu listing it, or
+  /// Home from the main menu's screens, as leaving those is mostly to get
+  /// back to the face
+  pub fn back(self) -> UiState {
+    match self.entry() {
+      Some(entry) if entry.menu != UiState::Menu => entry.menu,
+      _ => UiState::Home,
+    }
+  }
+}
+
+/// Labels in a [`UiState::path`], more than the menus go deep
+pub const PATH_DEPTH: usize = 4;
+/// From [`UiState::path`]
+pub type Path = heapless::Vec<&'static str, PATH_DEPTH>;
+
+/// Lines that fit below the status bar and title on the Logs and
+/// Notifications screens
+pub const LINES_PER_PAGE: usize = 5;
+/// Brightness added by each short press on the Settings screen
+const BRIGHTNESS_STEP: u8 = 32;
+
+/// A reading has to hold this long to count, the contacts bounce
+pub const DEBOUNCE: Duration = Duration::from_millis(30);
+/// Holding the button this long is a long press, fired while still held
+pub const LONG_PRESS: Duration = Duration::from_millis(1600);
+
+/// What the button did, from [`Button::update`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonEvent {
+  /// Pressed down, before it is known how long for
+  Down,
+  /// Held for [`LONG_PRESS`], fired once while still held
+  Long,
+  /// Released before a long press fired
+  Short,
+}
+
+/// Debouncing and press timing for the button, fed raw readings and the
+/// time they were taken
+#[derive(Clone, Debug)]
+pub struct Button {
+  /// Debounced current state
+  down: bool,
+  /// Last raw reading
+  raw_last: bool,
+  changed_at: Instant,
+  pressed_at: Instant,
+  /// The rest of the press does nothing, after a long press or
+  /// [`Button::ignore_press`]
+  done: bool,
+}
+
+impl Button {
+  pub fn new(now: Instant) -> Self {
+    Self {
+      down: false,
+      raw_last: false,
+      changed_at: now,
+      pressed_at: now,
+      done: false,
+    }
+  }
+
+  /// Take a reading, `pressed` true while the button is held, at `now`
+  pub fn update(&mut self, pressed: bool, now: Instant) -> Option<ButtonEvent> {
+    if pressed != self.raw_last {
+      self.raw_last = pressed;
+      self.changed_at = now;
+    }
+    if now.duration_since(self.changed_at) < DEBOUNCE {
+      return None;
+    }
+    if pressed && !self.down {
+      self.down = true;
+      self.pressed_at = now;
+      self.done = false;
+      Some(ButtonEvent::Down)
+    } else if self.down
+      && !self.done
+      && now.duration_since(self.pressed_at) >= LONG_PRESS
+    {
+      self.done = true;
+      Some(ButtonEvent::Long)
+    } else if !pressed && self.down {
+      self.down = false;
+      (!self.done).then_some(ButtonEvent::Short)
+    } else {
+      None
+    }
+  }
+
+  /// Whether the button is held, debounced
+  pub fn is_down(&self) -> bool {
+    self.down
+  }
+
+  /// Let the press that just went down fire neither a short nor a long
+  /// press, e.g. when it only wakes the display
+  pub fn ignore_press(&mut self) {
+    self.done = true;
+  }
+
+  /// When [`Button::update`] has to look again for an event: the end of
+  /// the debounce after a change, or the long press while the button is
+  /// held
+  pub fn deadline(&self) -> Option<Instant> {
+    if self.raw_last != self.down {
+      Some(self.changed_at + DEBOUNCE)
+    } else if self.down && !self.done {
+      Some(self.pressed_at + LONG_PRESS)
+    } else {
+      None
+    }
+  }
+}
+
+/// What a short press asks of the screen's pages, see `pager::Pager`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Paging {
+  Next,
+  /// Scroll from the start again
+  Restart,
+}
+
+pub fn handle_long_press(
+  ui_state: &mut UiState,
+  option_index: u8,
+  list_offset: usize,
+) {
+  match *ui_state {
+    UiState::Home => *ui_state = UiState::Menu, // long press from home opens menu
+    // the main menu's selection is kept across restarts, the others start
+    // at the top
+    menu if menu.is_menu() => {
+      let index = if menu == UiState::Menu {
+        usize::from(option_index)
+      } else {
+        list_offset
+      };
+      if let Some(entry) = menu.entries().nth(index) {
+        *ui_state = entry.screen;
+      }
+    }
+    screen => *ui_state = screen.back(),
+  };
+}
+
+/// Short press on the screens that only move through what they show,
+/// returning what it does to the pages. What it does comes from the
+/// screen's [`MenuEntry`], Home turns its pages and the menu selects the
+/// next entry.
+pub fn handle_short_press(
+  ui_state: &mut UiState,
+  option_index: &mut u8,
+  list_offset: &mut usize,
+  list_len: usize,
+  brightness_draft: &mut Option<u8>,
+) -> Option<Paging> {
+  let press = match *ui_state {
+    UiState::Home => ShortPress::NextPage,
+    UiState::Menu => {
+      *option_index = (*option_index + 1) % UiState::Menu.menu_len() as u8;
+      return None;
+    }
+    screen => screen
+      .entry()
+      .map_or(ShortPress::Caller, |entry| entry.press),
+  };
+  match press {
+    ShortPress::Caller => {}
+    ShortPress::NextPage => return Some(Paging::Next),
+    ShortPress::OlderLines => {
+      *list_offset += LINES_PER_PAGE;
+      if *list_offset >= list_len {
+        *list_offset = 0;
+      }
+    }
+    ShortPress::NextItem => *list_offset = (*list_offset + 1) % list_len.max(1),
+    ShortPress::NextItemFromStart => {
+      *list_offset = (*list_offset + 1) % list_len.max(1);
+      return Some(Paging::Restart);
+    }
+    ShortPress::Restart => return Some(Paging::Restart),
+    ShortPress::Brightness => {
+      if let Some(brightness) = brightness_draft {
+        *brightness = match *brightness {
+          u8::MAX => 0,
+          level => level.saturating_add(BRIGHTNESS_STEP),
+        };
+      }
+    }
+    ShortPress::TopOfMenu => {
+      *option_index = 0;
+      *ui_state = UiState::Menu;
+    }
+  }
+  None
+}
+
+/// Whether the frame on the panel is out of date. Whatever changes what is
+/// on screen marks it, a press, another screen, the clock or new data, and
+/// only a marked frame is drawn and sent to the panel.
+#[derive(Clone, Debug)]
+pub struct Redraw {
+  pending: bool,
+}
+
+impl Default for Redraw {
+  /// Marked, the panel starts out blank
+  fn default() -> Self {
+    Self { pending: true }
+  }
+}
+
+impl Redraw {
+  pub fn mark(&mut self) {
+    self.pending = true;
+  }
+
+  /// Mark it if `value` differs from `last`, keeping `value` in `last`
+  pub fn watch<T: PartialEq + Clone>(&mut self, last: &mut T, value: &T) {
+    if last != value {
+      *last = value.clone();
+      self.mark();
+    }
+  }
+
+  /// Whether the next frame is drawn, without unmarking it
+  pub fn is_pending(&self) -> bool {
+    self.pending
+  }
+
+  /// Whether to draw this frame, unmarking it
+  pub fn take(&mut self) -> bool {
+    std::mem::take(&mut self.pending)
+  }
+}
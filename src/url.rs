@@ -0,0 +1,72 @@
+use std::fmt;
+
+/// A URL put together from a fixed base, path segments and query
+/// parameters. Segments and parameters are percent-encoded, so a city
+/// name with spaces or a symbol such as `^GSPC` can't break it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Url {
+  text: String,
+  has_query: bool,
+}
+
+impl Url {
+  /// Starts from `base`, such as `https://api.example.com/v1`, taken as is
+  pub fn new(base: &str) -> Self {
+    Self {
+      text: base.trim_end_matches('/').to_string(),
+      has_query: base.contains('?'),
+    }
+  }
+
+  /// Adds `/segment` to the path. Only before the first parameter.
+  pub fn segment(mut self, segment: &str) -> Self {
+    debug_assert!(!self.has_query, "path segment after the query");
+    self.text.push('/');
+    self.text.push_str(&percent_encode(segment));
+    self
+  }
+
+  /// Adds `key=value` to the query
+  pub fn query(mut self, key: &str, value: impl fmt::Display) -> Self {
+    self.text.push(if self.has_query { '&' } else { '?' });
+    self.has_query = true;
+    self
+      .text
+      .push_str(&form_encode(&[(key, &value.to_string())]));
+    self
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.text
+  }
+}
+
+impl fmt::Display for Url {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.text)
+  }
+}
+
+/// `application/x-www-form-urlencoded` body or query string
+pub fn form_encode(pairs: &[(&str, &str)]) -> String {
+  pairs
+    .iter()
+    .map(|(key, value)| {
+      format!("{}={}", percent_encode(key), percent_encode(value))
+    })
+    .collect::<Vec<_>>()
+    .join("&")
+}
+
+/// Everything but letters, digits and `-_.~` as `%XX`
+pub fn percent_encode(text: &str) -> String {
+  text
+    .bytes()
+    .map(|byte| match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+        (byte as char).to_string()
+      }
+      _ => format!("%{byte:02X}"),
+    })
+    .collect()
+}
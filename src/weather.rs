@@ -0,0 +1,153 @@
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::nvs::EspDefaultNvsPartition;
+
+use crate::{
+  config::Config,
+  fetch,
+  history::SharedHistory,
+  http_client::{self, KeyRejected, RequestOptions, Validators},
+  notify::{Priority, SharedNotifications},
+  secrets,
+  state::{SharedState, Weather},
+  tls_pins::Endpoint,
+  url::Url,
+};
+
+/// Keeps the weather in `state` fresh. Location and refresh interval are
+/// read from the settings before every fetch, the API key from the secrets
+/// in `nvs`. A change of location or key triggers a fetch straight away.
+/// Every reading is also kept in `history` and, if enabled, announced in
+/// `notifications`. A key the API turns down is announced once, so it can
+/// be replaced. A response the API says is unchanged is not a new reading.
+pub struct Source {
+  state: SharedState,
+  history: SharedHistory,
+  notifications: SharedNotifications,
+  nvs: EspDefaultNvsPartition,
+  fetched: Option<(Instant, Config)>,
+  rejected_key: Option<String>,
+  validators: Validators,
+}
+
+impl Source {
+  pub fn new(
+    state: SharedState,
+    history: SharedHistory,
+    notifications: SharedNotifications,
+    nvs: EspDefaultNvsPartition,
+  ) -> Self {
+    Self {
+      state,
+      history,
+      notifications,
+      nvs,
+      fetched: None,
+      rejected_key: None,
+      validators: Validators::default(),
+    }
+  }
+}
+
+impl fetch::Source for Source {
+  fn poll(&mut self, config: &Config) {
+    let interval =
+      Duration::from_secs(u64::from(config.refresh.weather_min) * 60);
+    let due = match &self.fetched {
+      None => true,
+      Some((at, used)) => {
+        at.elapsed() >= interval
+          || used.location != config.location
+          || used.api_keys != config.api_keys
+      }
+    };
+    if !due {
+      return;
+    }
+    self.fetched = Some((Instant::now(), config.clone()));
+    let key = match api_key(self.nvs.clone()) {
+      Ok(Some(key)) => key,
+      Ok(None) => {
+        log::warn!("No weather API key set, skipping weather update");
+        return;
+      }
+      Err(error) => {
+        log::warn!("Weather API key not read: {:?}", error);
+        return;
+      }
+    };
+    match fetch(config, &key, &mut self.validators) {
+      Ok(None) => log::info!("Weather unchanged"),
+      Ok(Some(weather)) => {
+        self.history.lock().unwrap().record(&weather);
+        if config.notifications.weather {
+          self.notifications.lock().unwrap().push(
+            &format!("{:.1}°C {}", weather.temp_c, weather.condition),
+            config.notifications.duration(),
+            Priority::Low,
+          );
+        }
+        self.state.lock().unwrap().weather = Some(weather);
+      }
+      Err(error) if error.is::<KeyRejected>() => {
+        log::error!("Weather update failed: {}", error);
+        if self.rejected_key.as_ref() != Some(&key) {
+          self.rejected_key = Some(key);
+          self.notifications.lock().unwrap().push(
+            "Weather API key rejected, set a new one from the web",
+            config.notifications.duration(),
+            Priority::High,
+          );
+        }
+      }
+      Err(error) => log::warn!("Weather update failed: {:?}", error),
+    }
+  }
+}
+
+/// The weather API key, `None` while it isn't set. Read when a request is
+/// about to go out rather than kept around, and masked in the log.
+pub fn api_key(nvs: EspDefaultNvsPartition) -> anyhow::Result<Option<String>> {
+  let key = secrets::get(nvs, secrets::WEATHER_API_KEY)?;
+  Ok(key.filter(|key| !key.is_empty()))
+}
+
+/// The current weather at the location in `config`, using `key`. `None` if
+/// it hasn't changed since the response `validators` were taken from. They
+/// are only replaced once a new one has been read in full.
+pub fn fetch(
+  config: &Config,
+  key: &str,
+  validators: &mut Validators,
+) -> anyhow::Result<Option<Weather>> {
+  log::info!("Fetching weather data from API");
+  let mut fresh = validators.clone();
+  let parsed: Option<serde_json::Value> = http_client::get_json_if_modified(
+    Some(Endpoint::Weather),
+    Url::new("https://api.weatherapi.com/v1/current.json")
+      .query("key", key)
+      .query(
+        "q",
+        format!("{},{}", config.location.latitude, config.location.longitude),
+      )
+      .as_str(),
+    &mut fresh,
+    &RequestOptions::default(),
+  )?;
+  let Some(parsed) = parsed else {
+    return Ok(None);
+  };
+  let temp_c = parsed["current"]["temp_c"]
+    .as_f64()
+    .ok_or_else(|| anyhow::anyhow!("no temperature in response"))?;
+  let condition = parsed["current"]["condition"]["text"]
+    .as_str()
+    .unwrap_or("Unknown");
+  let humidity = parsed["current"]["humidity"].as_u64().unwrap_or(0);
+  *validators = fresh;
+  Ok(Some(Weather {
+    temp_c,
+    condition: condition.to_string(),
+    humidity,
+  }))
+}
@@ -0,0 +1,2644 @@
+use std::{
+  net::IpAddr,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+#[cfg(debug_assertions)]
+use chrono::NaiveDateTime;
+use chrono::Timelike;
+use esp_idf_hal::delay::FreeRtos;
+#[cfg(feature = "relay")]
+use esp_idf_hal::gpio::{AnyIOPin, Output, PinDriver};
+#[cfg(feature = "servo")]
+use esp_idf_hal::ledc::LedcDriver;
+use esp_idf_svc::{
+  http::{
+    server::{
+      Configuration as HttpServerConfig, EspHttpConnection, EspHttpServer,
+      Request,
+    },
+    Method,
+  },
+  nvs::{EspCustomNvsPartition, EspDefaultNvsPartition},
+};
+use log::LevelFilter;
+use serde::Deserialize;
+
+#[cfg(feature = "buzzer")]
+use crate::buzzer::{Buzzer, Pattern};
+#[cfg(feature = "servo")]
+use crate::servo;
+#[cfg(debug_assertions)]
+use crate::testing::{
+  self, PressRequest, Sensors, SharedOverrides, TimeRequest,
+};
+use crate::{
+  auth::{self, ApiToken, Confirmations, Scope, Sessions, SharedSessions},
+  automation,
+  bench::SharedBench,
+  certs::Certificate,
+  clock,
+  config::{self, Config, SharedConfig},
+  discovery,
+  events::{Bus, Event},
+  group,
+  history::SharedHistory,
+  hostname,
+  known_networks::KnownNetwork,
+  led::SharedLed,
+  logger,
+  network::Uplink,
+  notify::{Priority, SharedNotifications},
+  ota::{self, SharedProgress},
+  perf,
+  profiles::Profiles,
+  ratelimit::SharedLimiter,
+  scene::{self, SharedDisplayMode},
+  secrets,
+  spotify::{self, SharedLink},
+  ssdp,
+  state::SharedState,
+  tls_pins::{self, Endpoint, Pin},
+  wifi::{self, Credentials, SharedWifi},
+};
+
+#[cfg(feature = "servo")]
+pub type Servo = Arc<Mutex<LedcDriver<'static>>>;
+#[cfg(feature = "relay")]
+pub type Relay = Arc<Mutex<PinDriver<'static, AnyIOPin, Output>>>;
+
+/// Everything the handlers need from the rest of the firmware
+#[derive(Clone)]
+pub struct Context {
+  pub state: SharedState,
+  #[cfg(feature = "buzzer")]
+  pub buzzer: Buzzer,
+  #[cfg(feature = "servo")]
+  pub servo: Servo,
+  pub wifi: SharedWifi,
+  pub uplink: Uplink,
+  pub config: SharedConfig,
+  pub notifications: SharedNotifications,
+  pub ota: SharedProgress,
+  pub nvs: EspDefaultNvsPartition,
+  /// Partition with the HTTPS certificate, `None` on a partition table
+  /// without one
+  pub certs: Option<EspCustomNvsPartition>,
+  /// Spotify account being linked from the settings page
+  pub spotify: SharedLink,
+  pub led: SharedLed,
+  #[cfg(feature = "relay")]
+  pub relay: Relay,
+  pub display_mode: SharedDisplayMode,
+  pub events: Bus,
+  pub history: SharedHistory,
+  pub bench: SharedBench,
+  /// Inputs forced from `/api/v1/test`
+  #[cfg(debug_assertions)]
+  pub overrides: SharedOverrides,
+}
+
+impl Context {
+  /// The actuators, for running rules and setting scenes
+  fn outputs(&self) -> automation::Outputs {
+    automation::Outputs {
+      notifications: self.notifications.clone(),
+      led: self.led.clone(),
+      #[cfg(feature = "buzzer")]
+      buzzer: self.buzzer.clone(),
+      #[cfg(feature = "servo")]
+      servo: self.servo.clone(),
+      #[cfg(feature = "relay")]
+      relay: self.relay.clone(),
+      display: self.display_mode.clone(),
+      state: self.state.clone(),
+      events: self.events.clone(),
+    }
+  }
+}
+
+/// The running servers, they stop when this is dropped
+pub struct Server {
+  _server: EspHttpServer<'static>,
+  /// Plain HTTP server sending browsers over to HTTPS
+  _redirect: Option<EspHttpServer<'static>>,
+  https: bool,
+}
+
+impl Server {
+  /// `https` once a certificate is in use, `http` until then
+  pub fn scheme(&self) -> &'static str {
+    if self.https {
+      "https"
+    } else {
+      "http"
+    }
+  }
+}
+
+/// Body of `POST /api/v1/display/message`
+#[derive(Deserialize)]
+struct DisplayMessage {
+  text: String,
+  #[serde(default = "DisplayMessage::default_duration")]
+  duration_s: u64,
+  #[serde(default)]
+  priority: Priority,
+}
+
+impl DisplayMessage {
+  const MAX_TEXT_LEN: usize = 120;
+  const MAX_DURATION_S: u64 = 300;
+
+  fn default_duration() -> u64 {
+    5
+  }
+}
+
+/// Body of `POST /api/v1/login`
+#[derive(Deserialize)]
+struct Login {
+  password: String,
+}
+
+/// Body of `POST /api/v1/login/password`. `current` can be left out while no
+/// password is set or with the admin token.
+#[derive(Deserialize)]
+struct PasswordChange {
+  #[serde(default)]
+  current: String,
+  password: String,
+}
+
+/// Body of `POST /api/v1/wifi/known/delete`
+#[derive(Deserialize)]
+struct ForgetNetwork {
+  ssid: String,
+}
+
+/// Body of `POST /api/v1/logs/levels`. Without `module` it sets the level of
+/// everything, with `module` and a `null` level the module goes back to it.
+#[derive(Deserialize)]
+struct LogLevelChange {
+  #[serde(default)]
+  module: Option<String>,
+  level: Option<String>,
+}
+
+/// Body of `POST /api/v1/tokens`
+#[derive(Deserialize)]
+struct NewToken {
+  name: String,
+  scope: Scope,
+}
+
+/// Body of `POST /api/v1/spotify/code`
+#[derive(Deserialize)]
+struct SpotifyRedirect {
+  url: String,
+}
+
+/// Body of `POST /api/v1/secrets/weather-key`
+#[derive(Deserialize)]
+struct ApiKey {
+  key: String,
+}
+
+/// Body of `POST /api/v1/ota`
+#[derive(Deserialize)]
+struct FirmwareUpdate {
+  url: String,
+}
+
+/// Largest request body accepted by the JSON endpoints
+const MAX_BODY_LEN: usize = 1024;
+/// Largest certificate upload, a PEM certificate and key or a WiFi CA
+/// certificate with some room
+const MAX_CERTIFICATE_BODY_LEN: usize = 8 * 1024;
+/// How long a scan for other units waits for their replies
+const DISCOVERY_WAIT: Duration = Duration::from_millis(1500);
+
+/// Start the web server with the web pages and the JSON API. It serves HTTPS
+/// once a certificate has been uploaded and plain HTTP until then. The
+/// servers stop when the returned value is dropped.
+pub fn start(context: Context) -> anyhow::Result<Server> {
+  let certificate = context.certs.clone().and_then(|partition| {
+    Certificate::load(partition).unwrap_or_else(|error| {
+      log::warn!("Could not read the HTTPS certificate: {:?}", error);
+      None
+    })
+  });
+  let https_server = certificate.and_then(|certificate| {
+    let started = certificate.x509().and_then(|(certificate, key)| {
+      Ok(EspHttpServer::new(&HttpServerConfig {
+        server_certificate: Some(certificate),
+        private_key: Some(key),
+        ..server_config()
+      })?)
+    });
+    // a broken certificate must not lock everyone out
+    started
+      .map_err(|error| {
+        log::error!("Could not start HTTPS, serving HTTP: {:?}", error)
+      })
+      .ok()
+  });
+  let https = https_server.is_some();
+  let server = match https_server {
+    Some(server) => server,
+    None => EspHttpServer::new(&server_config())?,
+  };
+  log::info!("Web server on {}", if https { "HTTPS" } else { "HTTP" });
+  let password_hash =
+    secrets::get(context.nvs.clone(), secrets::LOGIN_PASSWORD).unwrap_or_else(
+      |error| {
+        log::warn!("Could not read the login password: {:?}", error);
+        None
+      },
+    );
+  let sessions: SharedSessions =
+    Arc::new(Mutex::new(Sessions::new(password_hash)));
+  let mut router = Router {
+    server,
+    routes: Vec::new(),
+    sessions: sessions.clone(),
+    config: context.config.clone(),
+    ota: context.ota.clone(),
+  };
+  // shared by the routes that move or sound something
+  let limiter: SharedLimiter = Arc::default();
+  let preflight_config = context.config.clone();
+  router.route(
+    "/api/*",
+    Method::Options,
+    "CORS preflight for every API route",
+    move |request| -> Result<(), anyhow::Error> {
+      let Some(origin) =
+        allowed_origin(request.header("Origin"), &preflight_config)
+      else {
+        request.into_status_response(403)?;
+        return Ok(());
+      };
+      request.into_response(
+        204,
+        Some(reason(204)),
+        &[
+          ("Access-Control-Allow-Origin", origin.as_str()),
+          ("Access-Control-Allow-Methods", "GET, POST, OPTIONS"),
+          (
+            "Access-Control-Allow-Headers",
+            "Content-Type, Authorization",
+          ),
+          ("Access-Control-Max-Age", "600"),
+          ("Vary", "Origin"),
+        ],
+      )?;
+      Ok(())
+    },
+  )?;
+  router.route(
+    "/",
+    Method::Get,
+    "Dashboard page",
+    |request| -> Result<(), anyhow::Error> { page(request, index_html()) },
+  )?;
+  let description = ssdp::Device::new(
+    hostname::get().to_string(),
+    env!("CARGO_PKG_VERSION"),
+    hostname::mac(),
+  )
+  .description();
+  router.route(
+    ssdp::DESCRIPTION_PATH,
+    Method::Get,
+    "UPnP device description, for network browsers",
+    move |request| -> Result<(), anyhow::Error> {
+      request
+        .into_response(
+          200,
+          Some(reason(200)),
+          &[("Content-Type", "text/xml; charset=utf-8")],
+        )?
+        .write(description.as_bytes())?;
+      Ok(())
+    },
+  )?;
+  router.route(
+    "/logs.html",
+    Method::Get,
+    "Log viewer page",
+    |request| -> Result<(), anyhow::Error> { page(request, logs_html()) },
+  )?;
+  router.route(
+    "/login",
+    Method::Get,
+    "Sign-in page",
+    |request| -> Result<(), anyhow::Error> { page(request, login_html()) },
+  )?;
+  let (login_sessions, login_config) =
+    (sessions.clone(), context.config.clone());
+  let login_limiter = limiter.clone();
+  router.route(
+    LOGIN_URI,
+    Method::Post,
+    "Sign in to the web pages with {\"password\": \"...\"}, sets the \
+     session cookie (rate limited)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      // the same budget as the actuators keeps password guessing slow
+      if !allowed(&mut request, LOGIN_URI, &login_limiter, &login_config) {
+        return too_many_requests(request, &login_config);
+      }
+      let login = match read_body(&mut request).and_then(|body| {
+        serde_json::from_slice::<Login>(&body).map_err(anyhow::Error::from)
+      }) {
+        Ok(login) => login,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &login_config,
+          );
+        }
+      };
+      let mut sessions = login_sessions.lock().unwrap();
+      if !sessions.required() {
+        drop(sessions);
+        return send_json(
+          request,
+          409,
+          &json_error("no login password set"),
+          &login_config,
+        );
+      }
+      let Some(token) = sessions.sign_in(&login.password) else {
+        drop(sessions);
+        log::warn!("Failed sign-in from the web");
+        return send_json(
+          request,
+          401,
+          &json_error("wrong password"),
+          &login_config,
+        );
+      };
+      drop(sessions);
+      log::info!("Signed in from the web");
+      let cookie = format!(
+        "{}={token}; Max-Age={}; Path=/; HttpOnly; SameSite=Strict{}",
+        auth::SESSION_COOKIE,
+        auth::SESSION_TTL.as_secs(),
+        if https { "; Secure" } else { "" },
+      );
+      request
+        .into_response(
+          200,
+          Some(reason(200)),
+          &[("Content-Type", JSON), ("Set-Cookie", cookie.as_str())],
+        )?
+        .write(br#"{"status":"signed in"}"#)?;
+      Ok(())
+    },
+  )?;
+  let logout_sessions = sessions.clone();
+  router.route(
+    LOGOUT_URI,
+    Method::Post,
+    "End the session of the session cookie",
+    move |request| -> Result<(), anyhow::Error> {
+      logout_sessions
+        .lock()
+        .unwrap()
+        .sign_out(request.header("Cookie"));
+      let cookie =
+        format!("{}=; Max-Age=0; Path=/; HttpOnly", auth::SESSION_COOKIE);
+      request
+        .into_response(
+          200,
+          Some(reason(200)),
+          &[("Content-Type", JSON), ("Set-Cookie", cookie.as_str())],
+        )?
+        .write(br#"{"status":"signed out"}"#)?;
+      Ok(())
+    },
+  )?;
+  let (password_sessions, password_config) =
+    (sessions.clone(), context.config.clone());
+  let password_nvs = context.nvs.clone();
+  router.route(
+    "/api/v1/login/password",
+    Method::Post,
+    "Set the login password with {\"current\": \"...\", \"password\": \
+     \"...\"}, signs every session out",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let change = match read_body(&mut request).and_then(|body| {
+        serde_json::from_slice::<PasswordChange>(&body)
+          .map_err(anyhow::Error::from)
+      }) {
+        Ok(change) => change,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &password_config,
+          );
+        }
+      };
+      let admin = auth::is_admin(
+        request.header("Authorization"),
+        &password_config.lock().unwrap(),
+      );
+      let mut sessions = password_sessions.lock().unwrap();
+      // a session left open on a shared computer must not be enough
+      if sessions.required() && !admin && !sessions.verify(&change.current) {
+        drop(sessions);
+        return send_json(
+          request,
+          401,
+          &json_error("current password is wrong"),
+          &password_config,
+        );
+      }
+      let hash = match sessions.set_password(&change.password) {
+        Ok(hash) => hash,
+        Err(error) => {
+          drop(sessions);
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &password_config,
+          );
+        }
+      };
+      drop(sessions);
+      secrets::set(password_nvs.clone(), secrets::LOGIN_PASSWORD, &hash)?;
+      log::warn!("Login password changed from the web");
+      send_json(request, 200, r#"{"status":"changed"}"#, &password_config)
+    },
+  )?;
+  let cors = context.config.clone();
+  router.route(
+    "/api/v1/logs",
+    Method::Get,
+    "Log records after ?since=<seq>, oldest first. ?facility=audit lists \
+     only who changed what",
+    move |request| -> Result<(), anyhow::Error> {
+      let since = query_param(request.uri(), "since")
+        .and_then(|seq| seq.parse::<u32>().ok())
+        .unwrap_or(0);
+      let entries = match query_param(request.uri(), "facility") {
+        Some(logger::AUDIT) => logger::audit_since(since),
+        _ => logger::since(since),
+      };
+      let entries: Vec<serde_json::Value> = entries
+        .iter()
+        .map(|entry| {
+          serde_json::json!({
+            "seq": entry.seq,
+            "timestamp_ms": entry.timestamp_ms,
+            "level": entry.level.as_str(),
+            "target": entry.target,
+            "message": entry.message,
+          })
+        })
+        .collect();
+      let json = serde_json::to_string(&entries)?;
+      send_json(request, 200, &json, &cors)
+    },
+  )?;
+  let cors = context.config.clone();
+  router.route(
+    "/api/v1/logs/levels",
+    Method::Get,
+    "Log level of everything and of the modules set apart",
+    move |request| -> Result<(), anyhow::Error> {
+      let levels = logger::levels();
+      let modules: serde_json::Map<String, serde_json::Value> = levels
+        .modules
+        .iter()
+        .map(|(module, level)| (module.clone(), level.as_str().into()))
+        .collect();
+      let json = serde_json::json!({
+        "default": levels.default.as_str(),
+        "modules": modules,
+      });
+      send_json(request, 200, &json.to_string(), &cors)
+    },
+  )?;
+  let cors = context.config.clone();
+  router.route(
+    "/api/v1/logs/levels",
+    Method::Post,
+    "Set the log level with {\"level\": \"debug\"}, or one module's with \
+     {\"module\": \"weather\", \"level\": ...}, null for the default",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let result = read_body(&mut request)
+        .and_then(|body| {
+          serde_json::from_slice::<LogLevelChange>(&body)
+            .map_err(anyhow::Error::from)
+        })
+        .and_then(|change| {
+          let level = change
+            .level
+            .as_deref()
+            .map(|level| {
+              level.parse::<LevelFilter>().map_err(|_| {
+                anyhow::anyhow!(
+                  "level is off, error, warn, info, debug or trace"
+                )
+              })
+            })
+            .transpose()?;
+          match (change.module, level) {
+            (Some(module), level) => logger::set_module_level(&module, level),
+            (None, Some(level)) => logger::set_level(level),
+            (None, None) => anyhow::bail!("level is needed without a module"),
+          }
+        });
+      if let Err(error) = result {
+        return send_json(request, 400, &json_error(&error.to_string()), &cors);
+      }
+      send_json(request, 200, r#"{"status":"changed"}"#, &cors)
+    },
+  )?;
+  #[cfg(feature = "buzzer")]
+  {
+    let buzzer = context.buzzer.clone();
+    let buzz_config = context.config.clone();
+    let buzz_limiter = limiter.clone();
+    router.route(
+      "/buzz",
+      Method::Get,
+      "Buzzer page",
+      |request| -> Result<(), anyhow::Error> { page(request, buzz_html()) },
+    )?;
+    router.route(
+      BUZZ_URI,
+      Method::Post,
+      "Beep the buzzer for 200 ms (rate limited)",
+      move |mut request| -> Result<(), anyhow::Error> {
+        if !allowed(&mut request, BUZZ_URI, &buzz_limiter, &buzz_config) {
+          return too_many_requests(request, &buzz_config);
+        }
+        if !buzzer.play(Pattern::Beep)? {
+          return send_json(
+            request,
+            200,
+            r#"{"status":"quiet hours"}"#,
+            &buzz_config,
+          );
+        }
+        send_json(request, 200, r#"{"status":"buzzed"}"#, &buzz_config)
+      },
+    )?;
+  }
+  router.route(
+    "/logs",
+    Method::Get,
+    "Log buffer as plain text",
+    |request| -> Result<(), anyhow::Error> {
+      let mut response = request.into_response(
+        200,
+        Some("OK"),
+        &[("Content-Type", "text/plain; charset=utf-8")],
+      )?;
+      response.write(logger::dump().as_bytes())?;
+      Ok(())
+    },
+  )?;
+  let state = context.state.clone();
+  let cors = context.config.clone();
+  router.route(
+    "/api/v1/state",
+    Method::Get,
+    "Device state snapshot",
+    move |request| -> Result<(), anyhow::Error> {
+      let json = serde_json::to_string(&*state.lock().unwrap())?;
+      send_json(request, 200, &json, &cors)
+    },
+  )?;
+  let cors = context.config.clone();
+  router.route(
+    "/api/v1/perf",
+    Method::Get,
+    "Recent durations of the main loop, rendering, HTTP requests and sensor \
+     reads",
+    move |request| -> Result<(), anyhow::Error> {
+      send_json(request, 200, &perf::to_json().to_string(), &cors)
+    },
+  )?;
+  let (bench, cors) = (context.bench.clone(), context.config.clone());
+  router.route(
+    "/api/v1/bench/display",
+    Method::Get,
+    "Flush times of the last display benchmark at each I2C speed, whole \
+     frames and only a changed part",
+    move |request| -> Result<(), anyhow::Error> {
+      let bench = bench.lock().unwrap();
+      let json = serde_json::json!({
+        "running": bench.requested,
+        "results": bench.results,
+      });
+      send_json(request, 200, &json.to_string(), &cors)
+    },
+  )?;
+  let (bench, cors) = (context.bench.clone(), context.config.clone());
+  router.route(
+    "/api/v1/bench/display",
+    Method::Post,
+    "Run the display benchmark, a few seconds with test frames on the panel",
+    move |request| -> Result<(), anyhow::Error> {
+      let mut bench = bench.lock().unwrap();
+      bench.requested = true;
+      bench.results = None;
+      drop(bench);
+      send_json(request, 202, r#"{"status":"started"}"#, &cors)
+    },
+  )?;
+  let (occupancy, cors) = (context.history.clone(), context.config.clone());
+  router.route(
+    "/api/v1/occupancy",
+    Method::Get,
+    "Minutes with motion in each hour of the last 24, midnight first",
+    move |request| -> Result<(), anyhow::Error> {
+      let now = clock::local_now();
+      let json = serde_json::json!({
+        "minutes": occupancy.lock().unwrap().occupancy(now.naive_local()),
+        "current_hour": now.hour(),
+      });
+      send_json(request, 200, &json.to_string(), &cors)
+    },
+  )?;
+  #[cfg(feature = "servo")]
+  {
+    let servo_driver = context.servo.clone();
+    let cors = context.config.clone();
+    let servo_limiter = limiter.clone();
+    router.route(
+      "/api/v1/servo",
+      Method::Post,
+      "Move the servo to ?angle=<0-180> (rate limited)",
+      move |mut request| -> Result<(), anyhow::Error> {
+        if !allowed(&mut request, "/api/v1/servo", &servo_limiter, &cors) {
+          return too_many_requests(request, &cors);
+        }
+        let Some(angle) = query_param(request.uri(), "angle")
+          .and_then(|angle| angle.parse::<u32>().ok())
+          .filter(|angle| *angle <= servo::MAX_ANGLE)
+        else {
+          return send_json(
+            request,
+            400,
+            &json_error(&format!("angle must be 0-{}", servo::MAX_ANGLE)),
+            &cors,
+          );
+        };
+        servo::set_angle(&mut servo_driver.lock().unwrap(), angle)?;
+        log::info!("Servo moved to {} degrees from the web", angle);
+        send_json(request, 200, &format!(r#"{{"angle":{angle}}}"#), &cors)
+      },
+    )?;
+  }
+  router.route(
+    "/wifi",
+    Method::Get,
+    "WiFi configuration page",
+    |request| -> Result<(), anyhow::Error> { page(request, wifi_html()) },
+  )?;
+  let scan_wifi = context.wifi.clone();
+  let cors = context.config.clone();
+  router.route(
+    "/api/v1/wifi/scan",
+    Method::Get,
+    "Nearby WiFi networks, strongest first",
+    move |request| -> Result<(), anyhow::Error> {
+      let networks = wifi::scan(&mut scan_wifi.lock().unwrap())?;
+      let json = serde_json::to_string(&networks)?;
+      send_json(request, 200, &json, &cors)
+    },
+  )?;
+  let (units_uplink, units_config) =
+    (context.uplink.clone(), context.config.clone());
+  router.route(
+    "/api/v1/discovery",
+    Method::Get,
+    "Other pippos on the LAN that answered a broadcast within a second or \
+     two, by name",
+    move |request| -> Result<(), anyhow::Error> {
+      let own = units_uplink.ip();
+      let units = discovery::scan(own, DISCOVERY_WAIT)?;
+      let json = serde_json::to_string(&units)?;
+      send_json(request, 200, &json, &units_config)
+    },
+  )?;
+  let (known_config, known_nvs) = (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/wifi/known",
+    Method::Get,
+    "Known networks and their priorities, passwords left out",
+    move |request| -> Result<(), anyhow::Error> {
+      let json = serde_json::to_string(&wifi::known(known_nvs.clone())?)?;
+      send_json(request, 200, &json, &known_config)
+    },
+  )?;
+  let (remember_config, remember_nvs) =
+    (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/wifi/known",
+    Method::Post,
+    "Add a known network with {\"ssid\", \"password\", \"priority\"}, or \
+     change the one with that SSID. The highest priority in range is joined.",
+    move |mut request| -> Result<(), anyhow::Error> {
+      // a full list is refused the same way as a bad network
+      let remembered = read_body(&mut request)
+        .and_then(|body| {
+          serde_json::from_slice::<KnownNetwork>(&body)
+            .map_err(anyhow::Error::from)
+        })
+        .and_then(|network| {
+          network.validate()?;
+          let ssid = network.ssid.clone();
+          wifi::remember(remember_nvs.clone(), network).map(|()| ssid)
+        });
+      match remembered {
+        Ok(ssid) => {
+          log::info!("Known WiFi network {} saved", ssid);
+          send_json(request, 200, r#"{"status":"saved"}"#, &remember_config)
+        }
+        Err(error) => send_json(
+          request,
+          400,
+          &json_error(&error.to_string()),
+          &remember_config,
+        ),
+      }
+    },
+  )?;
+  let (forget_config, forget_nvs) =
+    (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/wifi/known/delete",
+    Method::Post,
+    "Forget the known network {\"ssid\"}",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let forget = match read_body(&mut request).and_then(|body| {
+        serde_json::from_slice::<ForgetNetwork>(&body)
+          .map_err(anyhow::Error::from)
+      }) {
+        Ok(forget) => forget,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &forget_config,
+          );
+        }
+      };
+      if !wifi::forget(forget_nvs.clone(), &forget.ssid)? {
+        return send_json(
+          request,
+          404,
+          &json_error("no such network"),
+          &forget_config,
+        );
+      }
+      log::info!("Known WiFi network {} forgotten", forget.ssid);
+      send_json(request, 200, r#"{"status":"deleted"}"#, &forget_config)
+    },
+  )?;
+  router.route(
+    "/settings",
+    Method::Get,
+    "Settings page",
+    |request| -> Result<(), anyhow::Error> { page(request, settings_html()) },
+  )?;
+  let get_config = context.config.clone();
+  router.route(
+    "/api/v1/config",
+    Method::Get,
+    "Settings, secrets masked",
+    move |request| -> Result<(), anyhow::Error> {
+      let json = serde_json::to_string(&get_config.lock().unwrap().masked())?;
+      send_json(request, 200, &json, &get_config)
+    },
+  )?;
+  let (set_config, config_nvs) = (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/config",
+    Method::Post,
+    "Validate, save and apply settings",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let body = read_body(&mut request)?;
+      let current = set_config.lock().unwrap().clone();
+      let config = match serde_json::from_slice::<Config>(&body)
+        .map_err(anyhow::Error::from)
+        .map(|config| config.unmasked(&current))
+        .and_then(|config| config.validate().map(|()| config))
+      {
+        Ok(config) => config,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &set_config,
+          );
+        }
+      };
+      if !config.server.allows(client_ip(&mut request)) {
+        return send_json(
+          request,
+          400,
+          &json_error("the allowlist would lock out this client"),
+          &set_config,
+        );
+      }
+      // once an admin token is set, only its holder can change it
+      if config.security != current.security
+        && !current.security.admin_token.is_empty()
+        && !auth::is_admin(request.header("Authorization"), &current)
+      {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &set_config,
+        );
+      }
+      config.save(config_nvs.clone())?;
+      // The main loop and the fetch thread read the shared copy, so the
+      // new settings apply without a reboot
+      *set_config.lock().unwrap() = config.clone();
+      log::info!("Settings saved");
+      send_json(
+        request,
+        200,
+        &serde_json::to_string(&config.masked())?,
+        &set_config,
+      )
+    },
+  )?;
+  let (export_config, export_nvs) =
+    (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/config/export",
+    Method::Get,
+    "Settings for import on another device, ?secrets=true adds the secrets \
+     and WiFi credentials (admin)",
+    move |request| -> Result<(), anyhow::Error> {
+      let config = export_config.lock().unwrap().clone();
+      if query_param(request.uri(), "secrets") != Some("true") {
+        let json = config.without_secrets()?.to_string();
+        return send_json(request, 200, &json, &export_config);
+      }
+      if !auth::is_admin(request.header("Authorization"), &config) {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &export_config,
+        );
+      }
+      let mut json = serde_json::to_value(&config)?;
+      if let Some(credentials) = Credentials::load(export_nvs.clone())? {
+        json["wifi"] = serde_json::to_value(credentials)?;
+      }
+      log::warn!("Settings exported with secrets");
+      send_json(request, 200, &json.to_string(), &export_config)
+    },
+  )?;
+  let (import_config, import_nvs) =
+    (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/config/import",
+    Method::Post,
+    "Replace the settings with an export. Secrets left out are kept, WiFi \
+     credentials apply after a restart",
+    move |mut request| -> Result<(), anyhow::Error> {
+      // room for the CA certificate of an enterprise network
+      let body = read_body_up_to(&mut request, MAX_CERTIFICATE_BODY_LEN)?;
+      let current = import_config.lock().unwrap().clone();
+      let imported = serde_json::from_slice::<serde_json::Value>(&body)
+        .map_err(anyhow::Error::from)
+        .and_then(|mut json| {
+          let credentials = match json.as_object_mut() {
+            Some(object) => object
+              .remove("wifi")
+              .map(serde_json::from_value::<Credentials>)
+              .transpose()?,
+            None => None,
+          };
+          if let Some(credentials) = &credentials {
+            credentials.validate()?;
+          }
+          let config = Config::with_secrets_of(json, &current)?;
+          config.validate()?;
+          Ok((config, credentials))
+        });
+      let (config, credentials) = match imported {
+        Ok(imported) => imported,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &import_config,
+          );
+        }
+      };
+      if !config.server.allows(client_ip(&mut request)) {
+        return send_json(
+          request,
+          400,
+          &json_error("the allowlist would lock out this client"),
+          &import_config,
+        );
+      }
+      // as on /api/v1/config, only the holder of the admin token can
+      // change it
+      if config.security != current.security
+        && !current.security.admin_token.is_empty()
+        && !auth::is_admin(request.header("Authorization"), &current)
+      {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &import_config,
+        );
+      }
+      config.save(import_nvs.clone())?;
+      *import_config.lock().unwrap() = config.clone();
+      log::info!("Settings imported");
+      if let Some(credentials) = &credentials {
+        credentials.save(import_nvs.clone())?;
+        log::info!("WiFi credentials for {} imported", credentials.ssid);
+      }
+      send_json(
+        request,
+        200,
+        &serde_json::to_string(&config.masked())?,
+        &import_config,
+      )
+    },
+  )?;
+  let tokens_config = context.config.clone();
+  router.route(
+    "/api/v1/tokens",
+    Method::Get,
+    "Names and scopes of the API tokens",
+    move |request| -> Result<(), anyhow::Error> {
+      let tokens: Vec<serde_json::Value> = tokens_config
+        .lock()
+        .unwrap()
+        .security
+        .api_tokens
+        .iter()
+        .map(
+          |token| serde_json::json!({"name": token.name, "scope": token.scope}),
+        )
+        .collect();
+      let json = serde_json::to_string(&tokens)?;
+      send_json(request, 200, &json, &tokens_config)
+    },
+  )?;
+  let (create_config, create_nvs) =
+    (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/tokens",
+    Method::Post,
+    "Create an API token with {\"name\": \"...\", \"scope\": \"read\" | \
+     \"actuators\" | \"admin\"}. The token is only shown in this response",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let current = create_config.lock().unwrap().clone();
+      // as with the admin token itself, only its holder can hand out access
+      if !current.security.admin_token.is_empty()
+        && !auth::is_admin(request.header("Authorization"), &current)
+      {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &create_config,
+        );
+      }
+      let mut config = current;
+      let created = read_body(&mut request)
+        .and_then(|body| {
+          serde_json::from_slice::<NewToken>(&body).map_err(anyhow::Error::from)
+        })
+        .and_then(|new| {
+          let (api_token, token) = ApiToken::new(&new.name, new.scope);
+          config.security.api_tokens.push(api_token);
+          config.validate()?;
+          Ok((new, token))
+        });
+      let (new, token) = match created {
+        Ok(created) => created,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &create_config,
+          );
+        }
+      };
+      config.save(create_nvs.clone())?;
+      *create_config.lock().unwrap() = config;
+      log::warn!("API token {:?} created for {:?}", new.name, new.scope);
+      let json = serde_json::json!({
+        "name": new.name,
+        "scope": new.scope,
+        "token": token,
+      });
+      send_json(request, 200, &json.to_string(), &create_config)
+    },
+  )?;
+  let (revoke_config, revoke_nvs) =
+    (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/tokens/revoke",
+    Method::Post,
+    "Revoke API token ?name=<name>",
+    move |request| -> Result<(), anyhow::Error> {
+      let mut config = revoke_config.lock().unwrap().clone();
+      if !config.security.admin_token.is_empty()
+        && !auth::is_admin(request.header("Authorization"), &config)
+      {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &revoke_config,
+        );
+      }
+      let name = query_param(request.uri(), "name").unwrap_or("").to_string();
+      let before = config.security.api_tokens.len();
+      config
+        .security
+        .api_tokens
+        .retain(|token| token.name != name);
+      if config.security.api_tokens.len() == before {
+        return send_json(
+          request,
+          404,
+          &json_error("no such token"),
+          &revoke_config,
+        );
+      }
+      config.save(revoke_nvs.clone())?;
+      *revoke_config.lock().unwrap() = config;
+      log::warn!("API token {:?} revoked", name);
+      send_json(request, 200, r#"{"status":"revoked"}"#, &revoke_config)
+    },
+  )?;
+  let (spotify_nvs, cors) = (context.nvs.clone(), context.config.clone());
+  router.route(
+    "/api/v1/spotify",
+    Method::Get,
+    "Whether a Spotify account is linked for the Now Playing screen",
+    move |request| -> Result<(), anyhow::Error> {
+      let linked = secrets::get(spotify_nvs.clone(), secrets::SPOTIFY_TOKEN)?
+        .is_some_and(|token| !token.is_empty());
+      let json = serde_json::json!({
+        "linked": linked,
+        "redirect_uri": spotify::REDIRECT_URI,
+      });
+      send_json(request, 200, &json.to_string(), &cors)
+    },
+  )?;
+  let (start_link, cors) = (context.spotify.clone(), context.config.clone());
+  router.route(
+    "/api/v1/spotify/link",
+    Method::Post,
+    "Start linking a Spotify account. Returns the address to open, which \
+     ends up on the redirect URI once the account owner agrees",
+    move |request| -> Result<(), anyhow::Error> {
+      let client_id = cors.lock().unwrap().spotify.client_id.clone();
+      if client_id.is_empty() {
+        return send_json(
+          request,
+          409,
+          &json_error("set the Spotify client ID first"),
+          &cors,
+        );
+      }
+      let (link, url) = spotify::Link::start(&client_id);
+      *start_link.lock().unwrap() = Some(link);
+      let json = serde_json::json!({ "url": url });
+      send_json(request, 200, &json.to_string(), &cors)
+    },
+  )?;
+  let (code_link, cors) = (context.spotify.clone(), context.config.clone());
+  router.route(
+    "/api/v1/spotify/code",
+    Method::Post,
+    "Finish linking with {\"url\": \"...\"}, the address the browser was \
+     sent back to",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let completed = read_body(&mut request)
+        .and_then(|body| {
+          serde_json::from_slice::<SpotifyRedirect>(&body)
+            .map_err(anyhow::Error::from)
+        })
+        .and_then(|redirect| match code_link.lock().unwrap().as_mut() {
+          Some(link) => link.complete(&redirect.url),
+          None => Err(anyhow::anyhow!("start linking first")),
+        });
+      if let Err(error) = completed {
+        return send_json(request, 400, &json_error(&error.to_string()), &cors);
+      }
+      // the fetch thread trades the code for tokens, TLS needs its stack
+      send_json(request, 202, r#"{"status":"linking"}"#, &cors)
+    },
+  )?;
+  let (unlink_nvs, unlink_link, cors) = (
+    context.nvs.clone(),
+    context.spotify.clone(),
+    context.config.clone(),
+  );
+  router.route(
+    "/api/v1/spotify/unlink",
+    Method::Post,
+    "Forget the linked Spotify account",
+    move |request| -> Result<(), anyhow::Error> {
+      *unlink_link.lock().unwrap() = None;
+      secrets::set(unlink_nvs.clone(), secrets::SPOTIFY_TOKEN, "")?;
+      log::warn!("Spotify account unlinked");
+      send_json(request, 200, r#"{"status":"unlinked"}"#, &cors)
+    },
+  )?;
+  let rules_config = context.config.clone();
+  router.route(
+    "/api/v1/automation",
+    Method::Get,
+    "Automation rules and webhooks",
+    move |request| -> Result<(), anyhow::Error> {
+      let json =
+        serde_json::to_string(&rules_config.lock().unwrap().automation)?;
+      send_json(request, 200, &json, &rules_config)
+    },
+  )?;
+  let (set_rules, rules_nvs) = (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/automation",
+    Method::Post,
+    "Replace the automation rules and webhooks with {\"rules\": [...], \
+     \"webhooks\": [...]}",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let mut config = set_rules.lock().unwrap().clone();
+      let replaced = read_body(&mut request).and_then(|body| {
+        config.automation = serde_json::from_slice(&body)?;
+        config.validate()
+      });
+      if let Err(error) = replaced {
+        return send_json(
+          request,
+          400,
+          &json_error(&error.to_string()),
+          &set_rules,
+        );
+      }
+      config.save(rules_nvs.clone())?;
+      let json = serde_json::to_string(&config.automation)?;
+      *set_rules.lock().unwrap() = config;
+      log::info!("Automation rules saved");
+      send_json(request, 200, &json, &set_rules)
+    },
+  )?;
+  let (run_config, run_outputs, run_limiter) =
+    (context.config.clone(), context.outputs(), limiter.clone());
+  // rules run by hand that haven't finished, each only runs once at a time
+  let running: Arc<Mutex<Vec<String>>> = Arc::default();
+  router.route(
+    RUN_URI,
+    Method::Post,
+    "Run the actions of rule ?name=<name> now, to try it out (rate limited)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      if !allowed(&mut request, RUN_URI, &run_limiter, &run_config) {
+        return too_many_requests(request, &run_config);
+      }
+      let config = run_config.lock().unwrap().clone();
+      let name = query_param(request.uri(), "name").unwrap_or("");
+      let Some(rule) = config
+        .automation
+        .rules
+        .iter()
+        .find(|rule| rule.name == name)
+      else {
+        return send_json(
+          request,
+          404,
+          &json_error("no such rule"),
+          &run_config,
+        );
+      };
+      let started = {
+        let mut running = running.lock().unwrap();
+        let started = !running.contains(&rule.name);
+        if started {
+          running.push(rule.name.clone());
+        }
+        started
+      };
+      if !started {
+        return send_json(
+          request,
+          409,
+          &json_error("rule is still running"),
+          &run_config,
+        );
+      }
+      let (outputs, rule, done) =
+        (run_outputs.clone(), rule.clone(), running.clone());
+      let name = rule.name.clone();
+      log::info!("Rule {:?} run by hand", name);
+      // the server's stack is too shallow for a webhook's TLS handshake
+      let thread = std::thread::Builder::new().stack_size(12 * 1024);
+      if let Err(error) = thread.spawn(move || {
+        automation::run(&rule, &rule.when, &config, &outputs);
+        done.lock().unwrap().retain(|running| *running != rule.name);
+      }) {
+        running.lock().unwrap().retain(|running| *running != name);
+        return Err(error.into());
+      }
+      // answered first, a webhook can take a while
+      send_json(request, 202, r#"{"status":"running"}"#, &run_config)
+    },
+  )?;
+  let (scenes_config, scenes_state) =
+    (context.config.clone(), context.state.clone());
+  router.route(
+    "/api/v1/scenes",
+    Method::Get,
+    "Scene names and the one set last",
+    move |request| -> Result<(), anyhow::Error> {
+      let names: Vec<String> = scenes_config
+        .lock()
+        .unwrap()
+        .automation
+        .scenes
+        .iter()
+        .map(|scene| scene.name.clone())
+        .collect();
+      let json = serde_json::json!({
+        "active": scenes_state.lock().unwrap().scene,
+        "scenes": names,
+      });
+      send_json(request, 200, &json.to_string(), &scenes_config)
+    },
+  )?;
+  let (set_scene_config, set_scene_outputs, scene_limiter) =
+    (context.config.clone(), context.outputs(), limiter.clone());
+  router.route(
+    SCENE_URI,
+    Method::Post,
+    "Set scene ?name=<name> (rate limited)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      if !allowed(&mut request, SCENE_URI, &scene_limiter, &set_scene_config) {
+        return too_many_requests(request, &set_scene_config);
+      }
+      let config = set_scene_config.lock().unwrap().clone();
+      let name = query_param(request.uri(), "name").unwrap_or("");
+      if !config
+        .automation
+        .scenes
+        .iter()
+        .any(|scene| scene.name == name)
+      {
+        return send_json(
+          request,
+          404,
+          &json_error("no such scene"),
+          &set_scene_config,
+        );
+      }
+      scene::activate(name, &config, &set_scene_outputs)?;
+      send_json(request, 200, r#"{"status":"set"}"#, &set_scene_config)
+    },
+  )?;
+  let (trigger_config, trigger_events, trigger_limiter) = (
+    context.config.clone(),
+    context.events.clone(),
+    limiter.clone(),
+  );
+  router.route(
+    TRIGGER_URI,
+    Method::Post,
+    "Raise event {\"trigger\": \"<name>\"} for the automation rules, from \
+     /api/v1/trigger/<name> (API token required)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      // the other actions also take a signed-in browser, this one is only
+      // for other systems
+      if auth::scope(
+        request.header("Authorization"),
+        &trigger_config.lock().unwrap(),
+      )
+      .is_none()
+      {
+        return send_json(
+          request,
+          401,
+          &json_error("an API token is required"),
+          &trigger_config,
+        );
+      }
+      if !allowed(&mut request, TRIGGER_URI, &trigger_limiter, &trigger_config)
+      {
+        return too_many_requests(request, &trigger_config);
+      }
+      let name = request
+        .uri()
+        .split('?')
+        .next()
+        .and_then(|path| path.strip_prefix("/api/v1/trigger/"))
+        .unwrap_or("")
+        .to_string();
+      if !config::valid_name(&name) {
+        return send_json(
+          request,
+          400,
+          &json_error("trigger must be 1-32 letters, digits, - or _"),
+          &trigger_config,
+        );
+      }
+      let event = Event::Trigger(name);
+      let rules = trigger_config
+        .lock()
+        .unwrap()
+        .automation
+        .rules
+        .iter()
+        .filter(|rule| rule.when == event)
+        .count();
+      log::info!("{:?} from outside, {} rules on it", event, rules);
+      trigger_events.publish(event);
+      let json = serde_json::json!({"status": "triggered", "rules": rules});
+      send_json(request, 202, &json.to_string(), &trigger_config)
+    },
+  )?;
+  let (list_nvs, cors) = (context.nvs.clone(), context.config.clone());
+  router.route(
+    "/api/v1/profiles",
+    Method::Get,
+    "Saved profiles and the active one",
+    move |request| -> Result<(), anyhow::Error> {
+      let profiles = Profiles::load(list_nvs.clone())?;
+      let json = serde_json::json!({
+        "active": profiles.active,
+        "profiles": profiles.names(),
+      });
+      send_json(request, 200, &json.to_string(), &cors)
+    },
+  )?;
+  let (store_nvs, store_config) = (context.nvs.clone(), context.config.clone());
+  router.route(
+    "/api/v1/profiles",
+    Method::Post,
+    "Save the current settings and WiFi network as profile ?name=<name>",
+    move |request| -> Result<(), anyhow::Error> {
+      let name = query_param(request.uri(), "name").unwrap_or("").to_string();
+      let config = store_config.lock().unwrap().clone();
+      if let Err(error) = Profiles::store(store_nvs.clone(), &name, &config) {
+        return send_json(
+          request,
+          400,
+          &json_error(&error.to_string()),
+          &store_config,
+        );
+      }
+      log::info!("Profile {} saved", name);
+      send_json(request, 200, r#"{"status":"saved"}"#, &store_config)
+    },
+  )?;
+  let (activate_nvs, activate_config) =
+    (context.nvs.clone(), context.config.clone());
+  let activate_wifi = context.wifi.clone();
+  router.route(
+    "/api/v1/profiles/activate",
+    Method::Post,
+    "Switch to profile ?name=<name>, joining its WiFi network",
+    move |request| -> Result<(), anyhow::Error> {
+      let name = query_param(request.uri(), "name").unwrap_or("");
+      let activated = Profiles::activate(
+        activate_nvs.clone(),
+        name,
+        &activate_config,
+        &activate_wifi,
+      );
+      match activated {
+        Ok(true) => {
+          send_json(request, 202, r#"{"status":"activated"}"#, &activate_config)
+        }
+        Ok(false) => send_json(
+          request,
+          404,
+          &json_error("no such profile"),
+          &activate_config,
+        ),
+        Err(error) => send_json(
+          request,
+          400,
+          &json_error(&error.to_string()),
+          &activate_config,
+        ),
+      }
+    },
+  )?;
+  let (delete_nvs, cors) = (context.nvs.clone(), context.config.clone());
+  router.route(
+    "/api/v1/profiles/delete",
+    Method::Post,
+    "Delete profile ?name=<name>",
+    move |request| -> Result<(), anyhow::Error> {
+      let name = query_param(request.uri(), "name").unwrap_or("").to_string();
+      if !Profiles::remove(delete_nvs.clone(), &name)? {
+        return send_json(request, 404, &json_error("no such profile"), &cors);
+      }
+      log::info!("Profile {} deleted", name);
+      send_json(request, 200, r#"{"status":"deleted"}"#, &cors)
+    },
+  )?;
+  let notifications = context.notifications.clone();
+  let cors = context.config.clone();
+  let message_limiter = limiter.clone();
+  router.route(
+    "/api/v1/display/message",
+    Method::Post,
+    "Queue a notification on the display (rate limited)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let route = "/api/v1/display/message";
+      if !allowed(&mut request, route, &message_limiter, &cors) {
+        return too_many_requests(request, &cors);
+      }
+      let body = read_body(&mut request)?;
+      let message = match serde_json::from_slice::<DisplayMessage>(&body) {
+        Ok(message)
+          if !message.text.trim().is_empty()
+            && message.text.len() <= DisplayMessage::MAX_TEXT_LEN
+            && (1..=DisplayMessage::MAX_DURATION_S)
+              .contains(&message.duration_s) =>
+        {
+          message
+        }
+        Ok(_) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&format!(
+              "text must be 1-{} bytes and duration_s 1-{}",
+              DisplayMessage::MAX_TEXT_LEN,
+              DisplayMessage::MAX_DURATION_S
+            )),
+            &cors,
+          );
+        }
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &cors,
+          );
+        }
+      };
+      let queued = notifications.lock().unwrap().push(
+        &message.text,
+        Duration::from_secs(message.duration_s),
+        message.priority,
+      );
+      if !queued {
+        return send_json(
+          request,
+          409,
+          &json_error("too many notifications of higher priority waiting"),
+          &cors,
+        );
+      }
+      log::info!("Queued message from the web: {}", message.text);
+      send_json(request, 202, r#"{"status":"queued"}"#, &cors)
+    },
+  )?;
+  let confirmations = Arc::new(Mutex::new(Confirmations::default()));
+  confirmed_action(
+    &mut router,
+    "/api/v1/reboot",
+    "reboot",
+    "Restart the device (admin, two-step confirmation)",
+    confirmations.clone(),
+    || Ok(()),
+  )?;
+  let reset_nvs = context.nvs.clone();
+  confirmed_action(
+    &mut router,
+    "/api/v1/factory-reset",
+    "factory-reset",
+    "Erase settings and WiFi credentials, then restart (admin, two-step \
+     confirmation)",
+    confirmations.clone(),
+    move || {
+      config::factory_reset(reset_nvs.clone())?;
+      log::warn!("Settings and WiFi credentials erased");
+      Ok(())
+    },
+  )?;
+  let (key_config, key_nvs) = (context.config.clone(), context.nvs.clone());
+  router.route(
+    "/api/v1/secrets/weather-key",
+    Method::Post,
+    "Replace the weather API key with {\"key\": \"...\"} (admin)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      if !auth::is_admin(
+        request.header("Authorization"),
+        &key_config.lock().unwrap(),
+      ) {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &key_config,
+        );
+      }
+      let body = read_body(&mut request)?;
+      let mut config = key_config.lock().unwrap().clone();
+      let updated = serde_json::from_slice::<ApiKey>(&body)
+        .map_err(anyhow::Error::from)
+        .and_then(|ApiKey { key }| {
+          if key.is_empty() {
+            anyhow::bail!("key must not be empty");
+          }
+          config.api_keys.weather = key;
+          config.validate()
+        });
+      if let Err(error) = updated {
+        return send_json(
+          request,
+          400,
+          &json_error(&error.to_string()),
+          &key_config,
+        );
+      }
+      config.save(key_nvs.clone())?;
+      // the fetch thread notices the new key and fetches straight away
+      *key_config.lock().unwrap() = config;
+      log::warn!("Weather API key replaced from the web");
+      send_json(request, 200, r#"{"status":"saved"}"#, &key_config)
+    },
+  )?;
+  if let Some(certs) = context.certs.clone() {
+    let (upload_config, upload_certs) = (context.config.clone(), certs.clone());
+    router.route(
+      "/api/v1/tls",
+      Method::Post,
+      "Upload {\"certificate\", \"private_key\"} in PEM for HTTPS, used \
+       after a restart (admin)",
+      move |mut request| -> Result<(), anyhow::Error> {
+        if !auth::is_admin(
+          request.header("Authorization"),
+          &upload_config.lock().unwrap(),
+        ) {
+          return send_json(
+            request,
+            401,
+            &json_error("admin token required"),
+            &upload_config,
+          );
+        }
+        let body = read_body_up_to(&mut request, MAX_CERTIFICATE_BODY_LEN)?;
+        let certificate = match serde_json::from_slice::<Certificate>(&body)
+          .map_err(anyhow::Error::from)
+          .and_then(|certificate| certificate.validate().map(|()| certificate))
+        {
+          Ok(certificate) => certificate,
+          Err(error) => {
+            return send_json(
+              request,
+              400,
+              &json_error(&error.to_string()),
+              &upload_config,
+            );
+          }
+        };
+        certificate.save(upload_certs.clone())?;
+        log::warn!("HTTPS certificate uploaded, used after a restart");
+        send_json(request, 200, r#"{"status":"saved"}"#, &upload_config)
+      },
+    )?;
+    let (delete_config, unpin_certs) = (context.config.clone(), certs.clone());
+    router.route(
+      "/api/v1/tls/delete",
+      Method::Post,
+      "Remove the HTTPS certificate, plain HTTP after a restart (admin)",
+      move |request| -> Result<(), anyhow::Error> {
+        if !auth::is_admin(
+          request.header("Authorization"),
+          &delete_config.lock().unwrap(),
+        ) {
+          return send_json(
+            request,
+            401,
+            &json_error("admin token required"),
+            &delete_config,
+          );
+        }
+        Certificate::erase(certs.clone())?;
+        log::warn!("HTTPS certificate removed, used after a restart");
+        send_json(request, 200, r#"{"status":"deleted"}"#, &delete_config)
+      },
+    )?;
+    let (pin_config, pin_certs) = (context.config.clone(), certs.clone());
+    router.route(
+      "/api/v1/tls/pin",
+      Method::Post,
+      "Trust only the PEM {\"certificate\"} for {\"endpoint\"} (weather or \
+       ota), the server's own or the CA that issued it (admin)",
+      move |mut request| -> Result<(), anyhow::Error> {
+        if !auth::is_admin(
+          request.header("Authorization"),
+          &pin_config.lock().unwrap(),
+        ) {
+          return send_json(
+            request,
+            401,
+            &json_error("admin token required"),
+            &pin_config,
+          );
+        }
+        let body = read_body_up_to(&mut request, MAX_CERTIFICATE_BODY_LEN)?;
+        let pin = match serde_json::from_slice::<Pin>(&body)
+          .map_err(anyhow::Error::from)
+          .and_then(|pin| pin.validate().map(|()| pin))
+        {
+          Ok(pin) => pin,
+          Err(error) => {
+            return send_json(
+              request,
+              400,
+              &json_error(&error.to_string()),
+              &pin_config,
+            );
+          }
+        };
+        pin.save(pin_certs.clone())?;
+        log::warn!("Certificate pinned for {:?} from the web", pin.endpoint);
+        send_json(request, 200, r#"{"status":"saved"}"#, &pin_config)
+      },
+    )?;
+    let unpin_config = context.config.clone();
+    router.route(
+      "/api/v1/tls/pin/delete",
+      Method::Post,
+      "Go back to the CA bundle for ?endpoint=<weather|ota> (admin)",
+      move |request| -> Result<(), anyhow::Error> {
+        if !auth::is_admin(
+          request.header("Authorization"),
+          &unpin_config.lock().unwrap(),
+        ) {
+          return send_json(
+            request,
+            401,
+            &json_error("admin token required"),
+            &unpin_config,
+          );
+        }
+        let Some(endpoint) =
+          query_param(request.uri(), "endpoint").and_then(Endpoint::parse)
+        else {
+          return send_json(
+            request,
+            400,
+            &json_error("endpoint must be weather or ota"),
+            &unpin_config,
+          );
+        };
+        tls_pins::erase(unpin_certs.clone(), endpoint)?;
+        log::warn!("Certificate pin for {:?} removed from the web", endpoint);
+        send_json(request, 200, r#"{"status":"deleted"}"#, &unpin_config)
+      },
+    )?;
+    let pins_config = context.config.clone();
+    router.route(
+      "/api/v1/tls/pins",
+      Method::Get,
+      "Endpoints that only trust a pinned certificate",
+      move |request| -> Result<(), anyhow::Error> {
+        let json = serde_json::json!({ "pinned": tls_pins::pinned() });
+        send_json(request, 200, &json.to_string(), &pins_config)
+      },
+    )?;
+  }
+  let secrets_nvs = context.nvs.clone();
+  confirmed_action(
+    &mut router,
+    "/api/v1/secrets/reset",
+    "reset-secrets",
+    "Erase the WiFi password, weather API key, admin token and login \
+     password but keep the other settings, then restart (admin, two-step \
+     confirmation)",
+    confirmations,
+    move || {
+      secrets::erase(secrets_nvs.clone())?;
+      log::warn!("Secrets erased");
+      Ok(())
+    },
+  )?;
+  router.route(
+    "/ota",
+    Method::Get,
+    "Firmware update page",
+    |request| -> Result<(), anyhow::Error> { page(request, ota_html()) },
+  )?;
+  let status_ota = context.ota.clone();
+  let cors = context.config.clone();
+  router.route(
+    "/api/v1/ota",
+    Method::Get,
+    "Firmware update progress",
+    move |request| -> Result<(), anyhow::Error> {
+      let json = serde_json::to_string(&*status_ota.lock().unwrap())?;
+      send_json(request, 200, &json, &cors)
+    },
+  )?;
+  let (update_config, update_ota) =
+    (context.config.clone(), context.ota.clone());
+  router.route(
+    "/api/v1/ota",
+    Method::Post,
+    "Start a firmware update from a URL (admin)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      if !auth::is_admin(
+        request.header("Authorization"),
+        &update_config.lock().unwrap(),
+      ) {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &update_config,
+        );
+      }
+      let body = read_body(&mut request)?;
+      let url = match serde_json::from_slice::<FirmwareUpdate>(&body) {
+        Ok(update)
+          if update.url.starts_with("http://")
+            || update.url.starts_with("https://") =>
+        {
+          update.url
+        }
+        Ok(_) => {
+          return send_json(
+            request,
+            400,
+            &json_error("url must be http(s)"),
+            &update_config,
+          );
+        }
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &update_config,
+          );
+        }
+      };
+      if let Err(error) = ota::start(url, update_ota.clone()) {
+        return send_json(
+          request,
+          409,
+          &json_error(&error.to_string()),
+          &update_config,
+        );
+      }
+      send_json(request, 202, r#"{"status":"downloading"}"#, &update_config)
+    },
+  )?;
+  #[cfg(debug_assertions)]
+  test_routes(
+    &mut router,
+    context.overrides.clone(),
+    context.config.clone(),
+  )?;
+  let Context {
+    wifi, nvs, config, ..
+  } = context;
+  let cors = config.clone();
+  router.route(
+    "/api/v1/wifi",
+    Method::Post,
+    "Join a WiFi network, saved once joined. An \"enterprise\" object with \
+     identity, username and an optional PEM ca_cert joins a WPA2-Enterprise \
+     network",
+    move |mut request| -> Result<(), anyhow::Error> {
+      // room for the CA certificate of an enterprise network
+      let body = read_body_up_to(&mut request, MAX_CERTIFICATE_BODY_LEN)?;
+      let credentials = match serde_json::from_slice::<Credentials>(&body)
+        .map_err(anyhow::Error::from)
+        .and_then(|credentials| credentials.validate().map(|()| credentials))
+      {
+        Ok(credentials) => credentials,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &cors,
+          );
+        }
+      };
+      // Reconnecting drops the connection this request came in on, so
+      // answer first and switch networks afterwards
+      send_json(request, 202, r#"{"status":"connecting"}"#, &cors)?;
+
+      let (wifi, nvs) = (wifi.clone(), nvs.clone());
+      std::thread::Builder::new()
+        .stack_size(8192)
+        .spawn(move || {
+          FreeRtos::delay_ms(500);
+          if let Err(error) =
+            wifi::apply(&mut wifi.lock().unwrap(), &credentials)
+          {
+            log::error!("Could not apply WiFi credentials: {:?}", error);
+            return;
+          }
+          // only once joined, or the next boot would try the failed
+          // network again
+          match credentials.save(nvs) {
+            Ok(()) => {
+              log::info!("WiFi credentials for {} saved", credentials.ssid)
+            }
+            Err(error) => {
+              log::error!("Could not save WiFi credentials: {:?}", error)
+            }
+          }
+        })?;
+      Ok(())
+    },
+  )?;
+
+  // Registered last: the descriptor lists every route above, and the
+  // catch-alls only see requests no other route matched
+  let descriptor = openapi(&router.routes).to_string();
+  let descriptor_config = config.clone();
+  router.route(
+    "/api/v1/openapi.json",
+    Method::Get,
+    "This descriptor",
+    move |request| -> Result<(), anyhow::Error> {
+      send_json(request, 200, &descriptor, &descriptor_config)
+    },
+  )?;
+  for method in [Method::Get, Method::Post] {
+    let cors = config.clone();
+    router.server.fn_handler(
+      "/api/*",
+      method,
+      move |request| -> Result<(), anyhow::Error> {
+        send_json(
+          request,
+          404,
+          &json_error("no such endpoint, see /api/v1/openapi.json"),
+          &cors,
+        )
+      },
+    )?;
+  }
+
+  let redirect = if https && config.lock().unwrap().security.https_redirect {
+    Some(redirect_server()?)
+  } else {
+    None
+  };
+  Ok(Server {
+    _server: router.server,
+    _redirect: redirect,
+    https,
+  })
+}
+
+/// Handlers the server has room for, one per route in `start` with room to
+/// spare. Past it the server fails to start, `tests/routes.rs` keeps count.
+const MAX_URI_HANDLERS: usize = 96;
+
+/// Settings shared by the HTTP and HTTPS servers
+fn server_config() -> HttpServerConfig {
+  HttpServerConfig {
+    // lets one OPTIONS handler answer preflights for every API route
+    uri_match_wildcard: true,
+    max_uri_handlers: MAX_URI_HANDLERS,
+    ..Default::default()
+  }
+}
+
+/// Plain HTTP server on port 80 sending every request to the same URI over
+/// HTTPS
+fn redirect_server() -> anyhow::Result<EspHttpServer<'static>> {
+  let mut server = EspHttpServer::new(&HttpServerConfig {
+    uri_match_wildcard: true,
+    // the HTTPS server already uses the default control port
+    ctrl_port: 32769,
+    ..Default::default()
+  })?;
+  for method in [Method::Get, Method::Post] {
+    server.fn_handler(
+      "/*",
+      method,
+      |request| -> Result<(), anyhow::Error> {
+        let Some(host) = request
+          .header("Host")
+          .and_then(|host| host.split(':').next())
+          .map(str::to_string)
+        else {
+          request.into_status_response(400)?;
+          return Ok(());
+        };
+        let location = format!("https://{host}{}", request.uri());
+        request.into_response(
+          301,
+          Some(reason(301)),
+          &[("Location", location.as_str())],
+        )?;
+        Ok(())
+      },
+    )?;
+  }
+  Ok(server)
+}
+
+#[cfg(feature = "buzzer")]
+const BUZZ_URI: &str = "/api/v1/buzz";
+const LOGIN_URI: &str = "/api/v1/login";
+const RUN_URI: &str = "/api/v1/automation/run";
+const SCENE_URI: &str = "/api/v1/scenes/activate";
+const TRIGGER_URI: &str = "/api/v1/trigger/*";
+/// Cookie holding the CSRF token the pages send back in `CSRF_HEADER`
+const CSRF_COOKIE: &str = "pippo_csrf";
+const CSRF_HEADER: &str = "X-CSRF-Token";
+const LOGOUT_URI: &str = "/api/v1/logout";
+
+/// Routes an API token with the actuator scope may call
+const ACTUATOR_ROUTES: [&str; 6] = [
+  BUZZ_URI,
+  "/api/v1/servo",
+  "/api/v1/display/message",
+  RUN_URI,
+  SCENE_URI,
+  TRIGGER_URI,
+];
+
+/// Pages that change the device. The other pages only show things and stay
+/// open without signing in.
+const PROTECTED_PAGES: [&str; 4] = ["/settings", "/wifi", "/ota", "/buzz"];
+
+/// Registered routes, kept to describe the API
+struct Router {
+  server: EspHttpServer<'static>,
+  routes: Vec<(Method, &'static str, &'static str)>,
+  sessions: SharedSessions,
+  config: SharedConfig,
+  ota: SharedProgress,
+}
+
+impl Router {
+  /// Register `handler`. Clients missing from the allowlist are turned
+  /// away on every route, as are API tokens without the scope it needs.
+  /// Routes that change the device only run it for signed-in clients once a
+  /// login password is set, and every request to them is noted in the audit
+  /// log. While firmware is being flashed they answer 503 instead.
+  fn route<F>(
+    &mut self,
+    uri: &'static str,
+    method: Method,
+    summary: &'static str,
+    handler: F,
+  ) -> anyhow::Result<&mut Self>
+  where
+    F: for<'r> Fn(Request<&mut EspHttpConnection<'r>>) -> anyhow::Result<()>
+      + Send
+      + 'static,
+  {
+    let (login, action) = (needs_login(method, uri), is_action(method));
+    let (sessions, config) = (self.sessions.clone(), self.config.clone());
+    let ota = self.ota.clone();
+    self.server.fn_handler(
+      uri,
+      method,
+      move |mut request| -> Result<(), anyhow::Error> {
+        let server = config.lock().unwrap().server.clone();
+        if !server.enabled {
+          return send_json(
+            request,
+            503,
+            &json_error("web server disabled"),
+            &config,
+          );
+        }
+        if !server.allows(client_ip(&mut request)) {
+          return send_json(
+            request,
+            403,
+            &json_error("client not on the allowlist"),
+            &config,
+          );
+        }
+        let scope =
+          auth::scope(request.header("Authorization"), &config.lock().unwrap());
+        if scope.is_some_and(|scope| scope < required_scope(method, uri)) {
+          if action {
+            audit(&mut request, false, &sessions, &config);
+          }
+          return send_json(
+            request,
+            403,
+            &json_error("API token lacks the scope for this route"),
+            &config,
+          );
+        }
+        if action && !same_site(&request, &config) {
+          audit(&mut request, false, &sessions, &config);
+          return send_json(
+            request,
+            403,
+            &json_error("cross-site request or CSRF token missing"),
+            &config,
+          );
+        }
+        let allowed =
+          !login || scope.is_some() || signed_in(&request, &sessions, &config);
+        if action {
+          audit(&mut request, allowed, &sessions, &config);
+        }
+        if !allowed {
+          return sign_in_required(request, &config);
+        }
+        if action && ota.lock().unwrap().in_progress() {
+          return flashing(request, &config);
+        }
+        handler(request)
+      },
+    )?;
+    self.routes.push((method, uri, summary));
+    Ok(self)
+  }
+}
+
+/// Scope an API token needs for a route: reading for anything that doesn't
+/// change the device, the actuator scope for the buzzer, servo, scenes,
+/// triggers, rules run by hand and messages, admin for the rest
+fn required_scope(method: Method, uri: &str) -> Scope {
+  if !is_action(method) {
+    Scope::Read
+  } else if ACTUATOR_ROUTES.contains(&uri) {
+    Scope::Actuators
+  } else {
+    Scope::Admin
+  }
+}
+
+/// Whether a route changes the device. Only GET and OPTIONS never do.
+fn is_action(method: Method) -> bool {
+  !matches!(method, Method::Get | Method::Options)
+}
+
+/// Whether an action comes from a page served by this device, a site
+/// allowed in the CORS settings, or a client that isn't a browser
+///
+/// Browsers name the origin of cross-origin POSTs and mark cross-site
+/// requests in `Sec-Fetch-Site`. A browser holding a cookie from these
+/// pages also has to send back the CSRF token the page was served with.
+fn same_site(
+  request: &Request<&mut EspHttpConnection<'_>>,
+  config: &SharedConfig,
+) -> bool {
+  let from_elsewhere = match request.header("Origin") {
+    Some(origin) => {
+      let host = origin.split_once("://").map(|(_, host)| host);
+      host.is_none()
+        || (host != request.header("Host")
+          && allowed_origin(Some(origin), config).is_none())
+    }
+    None => request.header("Sec-Fetch-Site") == Some("cross-site"),
+  };
+  if from_elsewhere {
+    return false;
+  }
+  let cookie = request.header("Cookie");
+  let csrf = auth::cookie(cookie, CSRF_COOKIE);
+  if csrf.is_none() && auth::cookie(cookie, auth::SESSION_COOKIE).is_none() {
+    return true;
+  }
+  match (csrf, request.header(CSRF_HEADER)) {
+    (Some(expected), Some(token)) => {
+      auth::same(token.as_bytes(), expected.as_bytes())
+    }
+    _ => false,
+  }
+}
+
+/// Serve `html` with the CSRF token of this browser in place of
+/// `{{csrf_token}}`, handing out a new token to browsers without one
+fn page(
+  request: Request<&mut EspHttpConnection<'_>>,
+  html: &str,
+) -> anyhow::Result<()> {
+  let token = auth::cookie(request.header("Cookie"), CSRF_COOKIE)
+    .filter(|token| {
+      token.len() == 32 && token.bytes().all(|b| b.is_ascii_hexdigit())
+    })
+    .map_or_else(
+      || format!("{:032x}", rand::random::<u128>()),
+      str::to_string,
+    );
+  let cookie =
+    format!("{CSRF_COOKIE}={token}; Path=/; HttpOnly; SameSite=Strict");
+  let mut response = request.into_response(
+    200,
+    Some(reason(200)),
+    &[
+      ("Content-Type", "text/html; charset=utf-8"),
+      ("Set-Cookie", cookie.as_str()),
+    ],
+  )?;
+  // sent in pieces straight from flash, a copy of the bigger pages takes
+  // more RAM than a TLS connection needs
+  let fields = [
+    ("{{csrf_token}}", token.as_str()),
+    ("{{device_name}}", hostname::get()),
+  ];
+  let mut rest = html;
+  while let Some(start) = rest.find("{{") {
+    response.write(rest[..start].as_bytes())?;
+    rest = &rest[start..];
+    match fields.iter().find(|(field, _)| rest.starts_with(field)) {
+      Some((field, value)) => {
+        response.write(value.as_bytes())?;
+        rest = &rest[field.len()..];
+      }
+      None => {
+        response.write(b"{{")?;
+        rest = &rest[2..];
+      }
+    }
+  }
+  response.write(rest.as_bytes())?;
+  Ok(())
+}
+
+/// Note who asked for an action in the audit log: client address, how it
+/// signed in and the wall-clock time, since the log only counts from boot
+fn audit(
+  request: &mut Request<&mut EspHttpConnection<'_>>,
+  allowed: bool,
+  sessions: &SharedSessions,
+  config: &SharedConfig,
+) {
+  let client = client_ip(request)
+    .map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+  let token = {
+    let (authorization, config) =
+      (request.header("Authorization"), config.lock().unwrap());
+    match auth::api_token(authorization, &config) {
+      Some(token) => Some(format!("token {:?}", token.name)),
+      None => auth::is_admin(authorization, &config)
+        .then(|| "admin token".to_string()),
+    }
+  };
+  let who = token.unwrap_or_else(|| {
+    let signed_in = sessions
+      .lock()
+      .unwrap()
+      .is_signed_in(request.header("Cookie"));
+    if signed_in { "signed in" } else { "anonymous" }.to_string()
+  });
+  let method = format!("{:?}", request.method()).to_uppercase();
+  log::info!(
+    target: logger::AUDIT,
+    "{} {} {} from {} ({}){}",
+    chrono::Local::now().format("%m-%d %H:%M:%S"),
+    method,
+    request.uri(),
+    client,
+    who,
+    if allowed { "" } else { ", denied" },
+  );
+}
+
+fn needs_login(method: Method, uri: &str) -> bool {
+  match method {
+    Method::Get => PROTECTED_PAGES.contains(&uri),
+    Method::Options => false,
+    _ => uri != LOGIN_URI && uri != LOGOUT_URI,
+  }
+}
+
+/// Whether `request` comes from a signed-in browser, or from a client with
+/// the admin token. Always true while no login password is set.
+fn signed_in(
+  request: &Request<&mut EspHttpConnection<'_>>,
+  sessions: &SharedSessions,
+  config: &SharedConfig,
+) -> bool {
+  let sessions = sessions.lock().unwrap();
+  !sessions.required()
+    || sessions.is_signed_in(request.header("Cookie"))
+    || auth::is_admin(request.header("Authorization"), &config.lock().unwrap())
+}
+
+/// 401 for the API, browsers asking for a page go to the sign-in page and
+/// come back afterwards
+fn sign_in_required(
+  request: Request<&mut EspHttpConnection<'_>>,
+  cors: &SharedConfig,
+) -> anyhow::Result<()> {
+  if request.uri().starts_with("/api/") {
+    return send_json(request, 401, &json_error("sign in required"), cors);
+  }
+  let location = format!("/login?next={}", request.uri());
+  request.into_response(
+    303,
+    Some(reason(303)),
+    &[("Location", location.as_str())],
+  )?;
+  Ok(())
+}
+
+/// Minimal OpenAPI 3 document listing the API routes
+fn openapi(routes: &[(Method, &str, &str)]) -> serde_json::Value {
+  let mut paths = serde_json::Map::new();
+  for (method, uri, summary) in routes {
+    if !uri.starts_with("/api/v1/") {
+      continue;
+    }
+    let operations = paths
+      .entry(uri.to_string())
+      .or_insert_with(|| serde_json::json!({}));
+    operations[format!("{method:?}").to_lowercase()] =
+      serde_json::json!({ "summary": summary });
+  }
+  serde_json::json!({
+    "openapi": "3.0.3",
+    "info": { "title": "pippo", "version": env!("CARGO_PKG_VERSION") },
+    "paths": paths,
+  })
+}
+
+const JSON: &str = "application/json";
+
+/// Register an admin-only POST route that reboots the device after running
+/// `action`. The first request returns a confirmation token, the action only
+/// runs when the request is repeated with `?confirm=<token>`.
+fn confirmed_action(
+  router: &mut Router,
+  uri: &'static str,
+  name: &'static str,
+  summary: &'static str,
+  confirmations: Arc<Mutex<Confirmations>>,
+  action: impl Fn() -> anyhow::Result<()> + Send + 'static,
+) -> anyhow::Result<()> {
+  let config = router.config.clone();
+  router.route(
+    uri,
+    Method::Post,
+    summary,
+    move |request| -> Result<(), anyhow::Error> {
+      if !auth::is_admin(
+        request.header("Authorization"),
+        &config.lock().unwrap(),
+      ) {
+        return send_json(
+          request,
+          401,
+          &json_error("admin token required"),
+          &config,
+        );
+      }
+      let Some(token) = query_param(request.uri(), "confirm") else {
+        let token = confirmations.lock().unwrap().issue(name);
+        return send_json(
+          request,
+          200,
+          &serde_json::json!({
+            "confirm": token,
+            "expires_s": auth::CONFIRMATION_TTL.as_secs(),
+          })
+          .to_string(),
+          &config,
+        );
+      };
+      if !confirmations.lock().unwrap().confirm(name, token) {
+        return send_json(
+          request,
+          409,
+          &json_error("confirmation token invalid or expired"),
+          &config,
+        );
+      }
+
+      action()?;
+      log::warn!("{} requested from the web, restarting", name);
+      send_json(request, 202, r#"{"status":"restarting"}"#, &config)?;
+      restart_soon()?;
+      Ok(())
+    },
+  )?;
+  Ok(())
+}
+
+/// Register the routes end-to-end tests drive a flashed board with: forced
+/// presses, fake sensor readings and a stopped clock. Debug builds only.
+#[cfg(debug_assertions)]
+fn test_routes(
+  router: &mut Router,
+  overrides: SharedOverrides,
+  config: SharedConfig,
+) -> anyhow::Result<()> {
+  let (show, cors) = (overrides.clone(), config.clone());
+  router.route(
+    "/api/v1/test",
+    Method::Get,
+    "What the tests have forced, and the screen shown (debug builds)",
+    move |request| -> Result<(), anyhow::Error> {
+      let json = show.lock().unwrap().to_json().to_string();
+      send_json(request, 200, &json, &cors)
+    },
+  )?;
+  let (press, cors) = (overrides.clone(), config.clone());
+  router.route(
+    "/api/v1/test/press",
+    Method::Post,
+    "Press the button, {\"press\": \"short\" | \"long\"} (debug builds)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let body = read_body(&mut request)?;
+      let forced = match serde_json::from_slice::<PressRequest>(&body) {
+        Ok(forced) => forced.press,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &cors,
+          )
+        }
+      };
+      if !press.lock().unwrap().press(forced) {
+        return send_json(
+          request,
+          409,
+          &json_error("too many presses waiting"),
+          &cors,
+        );
+      }
+      log::info!("Test pressed {:?}", forced);
+      send_json(request, 202, r#"{"status":"queued"}"#, &cors)
+    },
+  )?;
+  let (sensors, cors) = (overrides, config.clone());
+  router.route(
+    "/api/v1/test/sensors",
+    Method::Post,
+    "Fake motion, temp_c and humidity, the ones left out read for real \
+     (debug builds)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let body = read_body(&mut request)?;
+      let fake = match serde_json::from_slice::<Sensors>(&body) {
+        Ok(fake) => fake,
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &cors,
+          )
+        }
+      };
+      log::info!("Test sensors {:?}", fake);
+      sensors.lock().unwrap().sensors = fake;
+      send_json(request, 200, r#"{"status":"set"}"#, &cors)
+    },
+  )?;
+  let cors = config;
+  router.route(
+    "/api/v1/test/time",
+    Method::Post,
+    "Stop the clock at {\"time\": \"2026-10-16T22:30:00\"}, or start it \
+     with null (debug builds)",
+    move |mut request| -> Result<(), anyhow::Error> {
+      let body = read_body(&mut request)?;
+      let time = match serde_json::from_slice::<TimeRequest>(&body) {
+        Ok(TimeRequest { time: None }) => None,
+        Ok(TimeRequest { time: Some(time) }) => {
+          match NaiveDateTime::parse_from_str(&time, testing::TIME_FORMAT) {
+            Ok(time) => Some(time),
+            Err(_) => {
+              return send_json(
+                request,
+                400,
+                &json_error("time must look like 2026-10-16T22:30:00"),
+                &cors,
+              )
+            }
+          }
+        }
+        Err(error) => {
+          return send_json(
+            request,
+            400,
+            &json_error(&error.to_string()),
+            &cors,
+          )
+        }
+      };
+      log::info!("Test clock {:?}", time);
+      clock::freeze(time);
+      send_json(request, 200, r#"{"status":"set"}"#, &cors)
+    },
+  )?;
+  Ok(())
+}
+
+/// Whether the client behind `request` may call `route` again under the
+/// configured rate limit. Requests whose source can't be told are let
+/// through.
+fn allowed(
+  request: &mut Request<&mut EspHttpConnection<'_>>,
+  route: &'static str,
+  limiter: &SharedLimiter,
+  config: &SharedConfig,
+) -> bool {
+  let Some(client) = client_ip(request) else {
+    return true;
+  };
+  let limit = config.lock().unwrap().rate_limit.clone();
+  let allowed = limiter.lock().unwrap().allow(client, route, &limit);
+  if !allowed {
+    log::warn!("Rate limited {} on {}", client, route);
+  }
+  allowed
+}
+
+/// Address of the client that sent `request`
+fn client_ip(
+  request: &mut Request<&mut EspHttpConnection<'_>>,
+) -> Option<IpAddr> {
+  let connection = request.connection().raw_connection().ok()?;
+  // the server listens on IPv6, IPv4 clients show up as mapped addresses
+  let ip = connection.source_ipv6().ok()?;
+  Some(ip.to_ipv4_mapped().map_or(IpAddr::V6(ip), IpAddr::V4))
+}
+
+fn too_many_requests(
+  request: Request<&mut EspHttpConnection<'_>>,
+  cors: &SharedConfig,
+) -> anyhow::Result<()> {
+  send_json(
+    request,
+    429,
+    &json_error("too many requests, slow down"),
+    cors,
+  )
+}
+
+/// 503 for requests that would change the device while an update is being
+/// flashed
+fn flashing(
+  request: Request<&mut EspHttpConnection<'_>>,
+  cors: &SharedConfig,
+) -> anyhow::Result<()> {
+  send_json(
+    request,
+    503,
+    &json_error("firmware update in progress"),
+    cors,
+  )
+}
+
+/// Send `json` with `status`. Requests from an origin allowed in the settings
+/// get the header a browser needs to hand the response to a page hosted
+/// elsewhere.
+fn send_json(
+  request: Request<&mut EspHttpConnection<'_>>,
+  status: u16,
+  json: &str,
+  cors: &SharedConfig,
+) -> anyhow::Result<()> {
+  let origin = allowed_origin(request.header("Origin"), cors);
+  let mut headers = vec![("Content-Type", JSON)];
+  if let Some(origin) = origin.as_deref() {
+    headers
+      .extend([("Access-Control-Allow-Origin", origin), ("Vary", "Origin")]);
+  }
+  request
+    .into_response(status, Some(reason(status)), &headers)?
+    .write(json.as_bytes())?;
+  Ok(())
+}
+
+/// Reason phrase for the status codes the API answers with
+fn reason(status: u16) -> &'static str {
+  match status {
+    200 => "OK",
+    202 => "Accepted",
+    204 => "No Content",
+    301 => "Moved Permanently",
+    303 => "See Other",
+    400 => "Bad Request",
+    401 => "Unauthorized",
+    403 => "Forbidden",
+    409 => "Conflict",
+    429 => "Too Many Requests",
+    503 => "Service Unavailable",
+    _ => "",
+  }
+}
+
+/// `origin` if it may read API responses
+fn allowed_origin(origin: Option<&str>, cors: &SharedConfig) -> Option<String> {
+  let origin = origin?;
+  cors
+    .lock()
+    .unwrap()
+    .cors
+    .allows(origin)
+    .then(|| origin.to_string())
+}
+
+/// Restart after the response has had time to go out
+fn restart_soon() -> anyhow::Result<()> {
+  std::thread::Builder::new().stack_size(4096).spawn(|| {
+    FreeRtos::delay_ms(500);
+    esp_idf_hal::reset::restart();
+  })?;
+  Ok(())
+}
+
+fn json_error(message: &str) -> String {
+  serde_json::json!({ "error": message }).to_string()
+}
+
+/// Request body, refused when larger than `MAX_BODY_LEN`
+fn read_body(
+  request: &mut Request<&mut EspHttpConnection<'_>>,
+) -> anyhow::Result<Vec<u8>> {
+  read_body_up_to(request, MAX_BODY_LEN)
+}
+
+/// Request body, refused when larger than `max_len`
+fn read_body_up_to(
+  request: &mut Request<&mut EspHttpConnection<'_>>,
+  max_len: usize,
+) -> anyhow::Result<Vec<u8>> {
+  let len = request
+    .header("Content-Length")
+    .and_then(|len| len.parse::<usize>().ok())
+    .unwrap_or(0);
+  if len > max_len {
+    anyhow::bail!("request body too large: {} bytes", len);
+  }
+  let mut body = vec![0_u8; len];
+  let mut read = 0;
+  while read < len {
+    match request.read(&mut body[read..])? {
+      0 => break,
+      size => read += size,
+    }
+  }
+  body.truncate(read);
+  Ok(body)
+}
+
+/// Value of `name` in the query string of `uri`, not percent-decoded
+fn query_param<'a>(uri: &'a str, name: &str) -> Option<&'a str> {
+  uri
+    .split_once('?')?
+    .1
+    .split('&')
+    .filter_map(|pair| pair.split_once('='))
+    .find(|(key, _)| *key == name)
+    .map(|(_, value)| value)
+}
+
+fn index_html() -> &'static str {
+  include_str!("../web/index.html")
+}
+fn buzz_html() -> &'static str {
+  include_str!("../web/buzz.html")
+}
+fn wifi_html() -> &'static str {
+  include_str!("../web/wifi.html")
+}
+fn logs_html() -> &'static str {
+  include_str!("../web/logs.html")
+}
+fn ota_html() -> &'static str {
+  include_str!("../web/ota.html")
+}
+fn login_html() -> &'static str {
+  include_str!("../web/login.html")
+}
+fn settings_html() -> &'static str {
+  include_str!("../web/settings.html")
+}
@@ -0,0 +1,537 @@
+use std::{
+  collections::BTreeMap,
+  ffi::CString,
+  net::Ipv4Addr,
+  sync::{Arc, Mutex},
+};
+
+use embedded_svc::wifi::{
+  AccessPointConfiguration, AuthMethod, ClientConfiguration, Configuration,
+};
+use esp_idf_svc::{
+  nvs::{EspDefaultNvs, EspDefaultNvsPartition},
+  sys::{
+    esp, esp_eap_client_clear_ca_cert, esp_eap_client_set_ca_cert,
+    esp_eap_client_set_identity, esp_eap_client_set_password,
+    esp_eap_client_set_username, esp_random, esp_wifi_sta_enterprise_disable,
+    esp_wifi_sta_enterprise_enable,
+  },
+  wifi::{BlockingWifi, EspWifi},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  defaults,
+  known_networks::{self, KnownNetwork},
+  logger, secrets,
+  state::SetupAp,
+};
+
+pub type SharedWifi = Arc<Mutex<BlockingWifi<EspWifi<'static>>>>;
+
+const NAMESPACE: &str = "wifi";
+
+/// Longest CA certificate accepted, it has to fit in an NVS string together
+/// with the rest of the enterprise settings
+const MAX_CA_CERT_LEN: usize = 3000;
+/// Longest EAP identity, username or password
+const MAX_EAP_LEN: usize = 128;
+/// Characters of the setup network's password, none that look alike
+const PASSWORD_CHARS: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Station credentials, stored in NVS once set from the web page. The
+/// password is kept with the other [`secrets`].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Credentials {
+  pub ssid: String,
+  /// Network password, or the EAP password on an enterprise network
+  pub password: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub enterprise: Option<Enterprise>,
+}
+
+/// WPA2-Enterprise login, as used by university and office networks.
+/// PEAP and TTLS with MSCHAPv2 inside are both understood.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Enterprise {
+  /// Outer identity sent before the tunnel is up, often
+  /// `anonymous@example.edu`
+  pub identity: String,
+  pub username: String,
+  /// PEM certificate of the CA that signed the network's authentication
+  /// server. Without one any server is trusted.
+  #[serde(default)]
+  pub ca_cert: Option<String>,
+}
+
+impl Credentials {
+  /// Credentials saved in NVS, if any
+  pub fn load(
+    partition: EspDefaultNvsPartition,
+  ) -> anyhow::Result<Option<Self>> {
+    let mut nvs = EspDefaultNvs::new(partition.clone(), NAMESPACE, true)?;
+    let mut ssid_buf = [0_u8; 33];
+    let mut password_buf = [0_u8; 65];
+    let Some(ssid) = nvs.get_str("ssid", &mut ssid_buf)? else {
+      return Ok(None);
+    };
+    let ssid = ssid.to_string();
+    // saved before passwords moved to the secrets namespace
+    let legacy = nvs
+      .get_str("password", &mut password_buf)?
+      .map(str::to_string);
+    let stored = secrets::get(partition.clone(), secrets::WIFI_PASSWORD)?;
+    if let Some(legacy) = &legacy {
+      // move it over, so it is encrypted along with the other secrets
+      if stored.is_none() {
+        secrets::set(partition.clone(), secrets::WIFI_PASSWORD, legacy)?;
+      }
+      nvs.remove("password")?;
+    }
+    // an SSID whose password was reset is no use, the defaults apply instead
+    let Some(password) = stored.or(legacy) else {
+      return Ok(None);
+    };
+    let enterprise = match nvs.str_len("enterprise")? {
+      Some(len) => {
+        let mut buf = vec![0_u8; len];
+        nvs
+          .get_str("enterprise", &mut buf)?
+          .map(serde_json::from_str)
+          .transpose()?
+      }
+      None => None,
+    };
+    Ok(Some(Self {
+      ssid,
+      password,
+      enterprise,
+    }))
+  }
+
+  /// The saved credentials, or the ones built in from cfg.toml
+  pub fn load_or_default(partition: EspDefaultNvsPartition) -> Self {
+    Self::load(partition)
+      .unwrap_or_else(|error| {
+        log::warn!("Could not read saved WiFi credentials: {:?}", error);
+        None
+      })
+      .unwrap_or_else(|| Self {
+        ssid: defaults::DEFAULTS.wifi_ssid.to_string(),
+        password: defaults::DEFAULTS.wifi_password.to_string(),
+        enterprise: None,
+      })
+  }
+
+  pub fn save(&self, partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+    secrets::set(partition.clone(), secrets::WIFI_PASSWORD, &self.password)?;
+    let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    nvs.set_str("ssid", &self.ssid)?;
+    nvs.remove("password")?;
+    match &self.enterprise {
+      Some(enterprise) => {
+        nvs.set_str("enterprise", &serde_json::to_string(enterprise)?)?;
+      }
+      None => {
+        nvs.remove("enterprise")?;
+      }
+    }
+    Ok(())
+  }
+
+  pub fn erase(partition: EspDefaultNvsPartition) -> anyhow::Result<()> {
+    let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+    nvs.remove("ssid")?;
+    nvs.remove("password")?;
+    nvs.remove("enterprise")?;
+    nvs.remove("known")?;
+    Ok(())
+  }
+
+  pub fn validate(&self) -> anyhow::Result<()> {
+    if self.ssid.is_empty() || self.ssid.len() > 32 {
+      anyhow::bail!("SSID must be 1-32 bytes");
+    }
+    let Some(enterprise) = &self.enterprise else {
+      if !self.password.is_empty() && !(8..=64).contains(&self.password.len()) {
+        anyhow::bail!("password must be empty or 8-64 bytes");
+      }
+      return Ok(());
+    };
+    for (name, value) in [
+      ("identity", &enterprise.identity),
+      ("username", &enterprise.username),
+      ("password", &self.password),
+    ] {
+      if value.is_empty() || value.len() > MAX_EAP_LEN || value.contains('\0') {
+        anyhow::bail!("{name} must be 1-{MAX_EAP_LEN} bytes");
+      }
+    }
+    if let Some(ca_cert) = &enterprise.ca_cert {
+      if !ca_cert
+        .trim_start()
+        .starts_with("-----BEGIN CERTIFICATE-----")
+        || ca_cert.len() > MAX_CA_CERT_LEN
+        || ca_cert.contains('\0')
+      {
+        anyhow::bail!(
+          "ca_cert must be a PEM certificate of at most {MAX_CA_CERT_LEN} \
+           bytes"
+        );
+      }
+    }
+    Ok(())
+  }
+
+  pub fn configuration(&self) -> anyhow::Result<Configuration> {
+    let (auth_method, password) = match self.enterprise {
+      // the EAP password goes to the supplicant, not the station config
+      Some(_) => (AuthMethod::WPA2Enterprise, ""),
+      None => (AuthMethod::None, self.password.as_str()),
+    };
+    Ok(Configuration::Client(ClientConfiguration {
+      ssid: self
+        .ssid
+        .as_str()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("SSID too long"))?,
+      bssid: None,
+      auth_method,
+      password: password
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("password too long"))?,
+      channel: None,
+      ..Default::default()
+    }))
+  }
+
+  /// Set up the station to join this network on the next connect. The
+  /// setup network stays up.
+  pub fn configure(
+    &self,
+    wifi: &mut BlockingWifi<EspWifi<'static>>,
+  ) -> anyhow::Result<()> {
+    let configuration = match (self.configuration()?, wifi.get_configuration()?)
+    {
+      (Configuration::Client(client), Configuration::Mixed(_, setup)) => {
+        Configuration::Mixed(client, setup)
+      }
+      (configuration, _) => configuration,
+    };
+    wifi.set_configuration(&configuration)?;
+    match &self.enterprise {
+      Some(enterprise) => enable_enterprise(enterprise, &self.password),
+      None => {
+        // Safety: only clears a flag of the supplicant
+        esp!(unsafe { esp_wifi_sta_enterprise_disable() })?;
+        Ok(())
+      }
+    }
+  }
+}
+
+/// Hand the EAP login to the supplicant and turn WPA2-Enterprise on
+fn enable_enterprise(
+  enterprise: &Enterprise,
+  password: &str,
+) -> anyhow::Result<()> {
+  // Safety: the supplicant copies identity, username and password, the
+  // lengths are checked in `validate`
+  unsafe {
+    esp!(esp_eap_client_set_identity(
+      enterprise.identity.as_ptr(),
+      enterprise.identity.len() as i32,
+    ))?;
+    esp!(esp_eap_client_set_username(
+      enterprise.username.as_ptr(),
+      enterprise.username.len() as i32,
+    ))?;
+    esp!(esp_eap_client_set_password(
+      password.as_ptr(),
+      password.len() as i32,
+    ))?;
+  }
+  match &enterprise.ca_cert {
+    Some(ca_cert) => {
+      // The supplicant keeps the pointer rather than a copy, so the
+      // certificate is leaked. It is only set again when the network
+      // changes.
+      let ca_cert: &'static std::ffi::CStr =
+        Box::leak(CString::new(ca_cert.as_str())?.into_boxed_c_str());
+      let len = ca_cert.to_bytes_with_nul().len();
+      // Safety: `ca_cert` lives until the device restarts and its length
+      // counts the terminating NUL the PEM parser needs
+      esp!(unsafe {
+        esp_eap_client_set_ca_cert(ca_cert.as_ptr().cast(), len as i32)
+      })?;
+    }
+    // Safety: only forgets a certificate set before
+    None => unsafe { esp_eap_client_clear_ca_cert() },
+  }
+  // Safety: the login above is complete
+  esp!(unsafe { esp_wifi_sta_enterprise_enable() })?;
+  Ok(())
+}
+
+/// The known networks, passwords included
+pub fn known(
+  partition: EspDefaultNvsPartition,
+) -> anyhow::Result<Vec<KnownNetwork>> {
+  let nvs = EspDefaultNvs::new(partition.clone(), NAMESPACE, true)?;
+  let mut known: Vec<KnownNetwork> = match nvs.str_len("known")? {
+    Some(len) => {
+      let mut buf = vec![0_u8; len];
+      match nvs.get_str("known", &mut buf)? {
+        Some(json) => serde_json::from_str(json)?,
+        None => Vec::new(),
+      }
+    }
+    None => Vec::new(),
+  };
+  let mut passwords = known_passwords(partition)?;
+  for network in &mut known {
+    network.password = passwords.remove(&network.ssid).unwrap_or_default();
+    if !network.password.is_empty() {
+      logger::redact(&network.password);
+    }
+  }
+  Ok(known)
+}
+
+/// Remember `network`, replacing one with the same SSID
+pub fn remember(
+  partition: EspDefaultNvsPartition,
+  network: KnownNetwork,
+) -> anyhow::Result<()> {
+  let mut known = known(partition.clone())?;
+  known_networks::remember(&mut known, network)?;
+  save_known(partition, &known)
+}
+
+/// Forget the known network `ssid`, `false` if there was none
+pub fn forget(
+  partition: EspDefaultNvsPartition,
+  ssid: &str,
+) -> anyhow::Result<bool> {
+  let mut known = known(partition.clone())?;
+  let count = known.len();
+  known.retain(|network| network.ssid != ssid);
+  if known.len() == count {
+    return Ok(false);
+  }
+  save_known(partition, &known)?;
+  Ok(true)
+}
+
+fn save_known(
+  partition: EspDefaultNvsPartition,
+  known: &[KnownNetwork],
+) -> anyhow::Result<()> {
+  let passwords: BTreeMap<&str, &str> = known
+    .iter()
+    .map(|network| (network.ssid.as_str(), network.password.as_str()))
+    .collect();
+  secrets::set(
+    partition.clone(),
+    secrets::KNOWN_WIFI,
+    &serde_json::to_string(&passwords)?,
+  )?;
+  let mut nvs = EspDefaultNvs::new(partition, NAMESPACE, true)?;
+  // the passwords are skipped, they are with the secrets
+  nvs.set_str("known", &serde_json::to_string(known)?)?;
+  Ok(())
+}
+
+/// Password of each known network by SSID
+fn known_passwords(
+  partition: EspDefaultNvsPartition,
+) -> anyhow::Result<BTreeMap<String, String>> {
+  Ok(match secrets::get(partition, secrets::KNOWN_WIFI)? {
+    Some(json) => serde_json::from_str(&json)?,
+    None => BTreeMap::new(),
+  })
+}
+
+/// Set up the station for the known network with the highest priority in
+/// range, or for the one set on the WiFi page when none is. Without known
+/// networks nothing is scanned and the configuration stays as it is.
+pub fn choose_known(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+  partition: EspDefaultNvsPartition,
+) -> anyhow::Result<()> {
+  let known = known(partition.clone())?;
+  if known.is_empty() {
+    return Ok(());
+  }
+  let access_points = wifi.scan()?;
+  let visible: Vec<_> = access_points
+    .iter()
+    .map(|access_point| {
+      (access_point.ssid.as_str(), access_point.signal_strength)
+    })
+    .collect();
+  let credentials = match known_networks::pick(&known, &visible) {
+    Some(network) => Credentials {
+      ssid: network.ssid.clone(),
+      password: network.password.clone(),
+      enterprise: None,
+    },
+    None => Credentials::load_or_default(partition),
+  };
+  let configured = match wifi.get_configuration()? {
+    Configuration::Client(client) => client.ssid.as_str() == credentials.ssid,
+    _ => false,
+  };
+  // an enterprise login leaks its CA certificate each time it is set
+  if !configured {
+    log::info!("Joining {}", credentials.ssid);
+    credentials.configure(wifi)?;
+  }
+  Ok(())
+}
+
+/// Open the device's own network next to the station, which keeps trying
+/// to join. The WiFi page is served on it as on any other.
+pub fn start_setup_ap(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+) -> anyhow::Result<SetupAp> {
+  let mac = wifi.wifi().sta_netif().get_mac()?;
+  let ssid = format!("pippo-{:02x}{:02x}", mac[4], mac[5]);
+  // a new one each time, it is on the display for anyone to read anyway
+  let password: String = (0..8)
+    .map(|_| {
+      // Safety: only reads the hardware generator, which is random while
+      // the radio is on
+      let random = unsafe { esp_random() } as usize;
+      PASSWORD_CHARS[random % PASSWORD_CHARS.len()] as char
+    })
+    .collect();
+  let client = match wifi.get_configuration()? {
+    Configuration::Client(client) | Configuration::Mixed(client, _) => client,
+    _ => ClientConfiguration::default(),
+  };
+  wifi.set_configuration(&Configuration::Mixed(
+    client,
+    AccessPointConfiguration {
+      ssid: ssid
+        .as_str()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("SSID too long"))?,
+      auth_method: AuthMethod::WPA2Personal,
+      password: password
+        .as_str()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("password too long"))?,
+      max_connections: 2,
+      ..Default::default()
+    },
+  ))?;
+  let address = wifi.wifi().ap_netif().get_ip_info()?.ip.to_string();
+  Ok(SetupAp {
+    ssid,
+    password,
+    address,
+  })
+}
+
+/// Close the device's own network, once the station has joined one
+pub fn stop_setup_ap(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+) -> anyhow::Result<()> {
+  if let Configuration::Mixed(client, _) = wifi.get_configuration()? {
+    wifi.set_configuration(&Configuration::Client(client))?;
+  }
+  Ok(())
+}
+
+pub fn setup_ap_open(wifi: &BlockingWifi<EspWifi<'static>>) -> bool {
+  matches!(wifi.get_configuration(), Ok(Configuration::Mixed(..)))
+}
+
+/// The station's address, `None` without one or while another thread is
+/// switching networks
+pub fn station_ip(wifi: &SharedWifi) -> Option<Ipv4Addr> {
+  let wifi = wifi.try_lock().ok()?;
+  let ip = wifi.wifi().sta_netif().get_ip_info().ok()?.ip;
+  (!ip.is_unspecified()).then_some(ip)
+}
+
+/// Nearby network as listed on the WiFi page
+#[derive(Debug, Serialize)]
+pub struct Network {
+  pub ssid: String,
+  pub rssi: i8,
+  pub channel: u8,
+  pub open: bool,
+  /// Needs a WPA2-Enterprise login
+  pub enterprise: bool,
+}
+
+/// Nearby networks, strongest first, one entry per SSID
+pub fn scan(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+) -> anyhow::Result<Vec<Network>> {
+  let mut networks: Vec<Network> = Vec::new();
+  for access_point in wifi.scan()? {
+    if access_point.ssid.is_empty()
+      || networks
+        .iter()
+        .any(|n| n.ssid == access_point.ssid.as_str())
+    {
+      continue;
+    }
+    networks.push(Network {
+      ssid: access_point.ssid.to_string(),
+      rssi: access_point.signal_strength,
+      channel: access_point.channel,
+      open: matches!(access_point.auth_method, None | Some(AuthMethod::None)),
+      enterprise: access_point.auth_method == Some(AuthMethod::WPA2Enterprise),
+    });
+  }
+  networks.sort_by(|a, b| b.rssi.cmp(&a.rssi));
+  Ok(networks)
+}
+
+/// Switch to new credentials without rebooting. Falls back to the previous
+/// configuration if the new network can't be joined.
+pub fn apply(
+  wifi: &mut BlockingWifi<EspWifi<'static>>,
+  credentials: &Credentials,
+) -> anyhow::Result<()> {
+  let previous = wifi.get_configuration()?;
+  let was_enterprise = matches!(
+    &previous,
+    Configuration::Client(client)
+      if client.auth_method == AuthMethod::WPA2Enterprise
+  );
+  let _ = wifi.disconnect();
+  credentials.configure(wifi)?;
+
+  match wifi.connect().and_then(|()| wifi.wait_netif_up()) {
+    Ok(()) => {
+      log::info!("Connected to {}", credentials.ssid);
+      Ok(())
+    }
+    Err(error) => {
+      log::warn!(
+        "Could not join {}: {:?}, restoring previous network",
+        credentials.ssid,
+        error
+      );
+      let _ = wifi.disconnect();
+      wifi.set_configuration(&previous)?;
+      // The supplicant holds a single login, so this recovers a switch
+      // between a PSK and an enterprise network but not between two
+      // enterprise networks
+      // Safety: only sets a flag of the supplicant
+      if was_enterprise {
+        esp!(unsafe { esp_wifi_sta_enterprise_enable() })?;
+      } else {
+        esp!(unsafe { esp_wifi_sta_enterprise_disable() })?;
+      }
+      wifi.connect()?;
+      wifi.wait_netif_up()?;
+      Err(error.into())
+    }
+  }
+}
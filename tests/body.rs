@@ -0,0 +1,95 @@
+//! Response bodies read the way they arrive from the network, a few bytes
+//! at a time. These run on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test body --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/body.rs"]
+mod body;
+#[path = "../src/clock.rs"]
+mod clock;
+
+use std::{
+  io::{self, Read},
+  time::Duration,
+};
+
+use body::{Body, TooLarge};
+use clock::Instant;
+
+/// Hands out `bytes` at most `chunk` at a time, as a slow connection does
+struct Trickle<'a> {
+  bytes: &'a [u8],
+  chunk: usize,
+}
+
+impl Read for Trickle<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let size = self.chunk.min(buf.len()).min(self.bytes.len());
+    buf[..size].copy_from_slice(&self.bytes[..size]);
+    self.bytes = &self.bytes[size..];
+    Ok(size)
+  }
+}
+
+fn body(bytes: &[u8], chunk: usize, limit: usize) -> Body<Trickle<'_>> {
+  Body::new(Trickle { bytes, chunk }, limit)
+}
+
+#[test]
+fn characters_split_between_chunks() {
+  let text = "12°C, Zürich ☀ 🌧";
+  for chunk in 1..=5 {
+    let read = body(text.as_bytes(), chunk, 1024).text().unwrap();
+    assert_eq!(read, text, "{chunk} bytes at a time");
+  }
+}
+
+#[test]
+fn invalid_utf8_is_an_error() {
+  let error = body(b"caf\xe9 au lait", 3, 1024).text().unwrap_err();
+  assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn body_cut_off_inside_a_character_is_an_error() {
+  let error = body("°".as_bytes()[..1].as_ref(), 1, 1024)
+    .text()
+    .unwrap_err();
+  assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn longer_than_the_limit_is_an_error() {
+  let text = "x".repeat(100);
+  assert_eq!(body(text.as_bytes(), 7, 100).text().unwrap(), text);
+  let error = body(text.as_bytes(), 7, 99).text().unwrap_err();
+  let too_large = error.into_inner().unwrap().downcast::<TooLarge>().unwrap();
+  assert_eq!(too_large.limit, 99);
+}
+
+#[test]
+fn json_parsed_as_it_arrives() {
+  let json = r#"{"current": {"temp_c": 21.5, "condition": {"text": "Sunny"}}}"#;
+  let parsed: serde_json::Value =
+    body(json.as_bytes(), 4, 1024).json().unwrap();
+  assert_eq!(parsed["current"]["temp_c"], 21.5);
+  assert!(body(json.as_bytes(), 4, 20)
+    .json::<serde_json::Value>()
+    .is_err());
+}
+
+#[test]
+fn reads_stop_at_the_deadline() {
+  let soon = Instant::now() + Duration::from_secs(60);
+  let text = body(b"on time", 2, 64).until(soon).text().unwrap();
+  assert_eq!(text, "on time");
+
+  let mut late = body(b"too late", 2, 64).until(Instant::now());
+  let error = late.read(&mut [0; 8]).unwrap_err();
+  assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+}
@@ -0,0 +1,75 @@
+//! Finding the other units on the LAN. These run on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test discovery --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/discovery.rs"]
+mod discovery;
+
+use discovery::Announcement;
+
+fn unit(name: &str, ip: &str) -> Announcement {
+  Announcement {
+    name: name.to_string(),
+    version: "0.1.0".to_string(),
+    ip: ip.to_string(),
+    capabilities: vec!["web".to_string()],
+  }
+}
+
+#[test]
+fn query_allows_a_trailing_newline() {
+  assert!(discovery::is_query(b"PIPPO?"));
+  assert!(discovery::is_query(b"PIPPO?\n"));
+  assert!(discovery::is_query(b"PIPPO?\r\n"));
+  assert!(!discovery::is_query(b"pippo?"));
+  assert!(!discovery::is_query(b"PIPPO? please"));
+  assert!(!discovery::is_query(b""));
+}
+
+#[test]
+fn reply_round_trips() {
+  let sent = unit("pippo-a1b2c3", "192.168.1.23");
+  let json = serde_json::to_vec(&sent).unwrap();
+  assert_eq!(discovery::parse_reply(&json), Some(sent));
+}
+
+#[test]
+fn reply_without_capabilities_is_read() {
+  let reply = discovery::parse_reply(
+    br#"{"name": "pippo-1", "version": "0.1.0", "ip": "10.0.0.2"}"#,
+  )
+  .unwrap();
+  assert!(reply.capabilities.is_empty());
+}
+
+#[test]
+fn other_datagrams_are_not_replies() {
+  assert_eq!(discovery::parse_reply(b"PIPPO?"), None);
+  assert_eq!(discovery::parse_reply(br#"{"name": "pippo-1"}"#), None);
+}
+
+#[test]
+fn merge_orders_by_name() {
+  let mut units = Vec::new();
+  discovery::merge(&mut units, unit("pippo-c", "10.0.0.3"));
+  discovery::merge(&mut units, unit("pippo-a", "10.0.0.1"));
+  discovery::merge(&mut units, unit("pippo-b", "10.0.0.2"));
+  let names: Vec<_> = units.iter().map(|unit| unit.name.as_str()).collect();
+  assert_eq!(names, ["pippo-a", "pippo-b", "pippo-c"]);
+}
+
+#[test]
+fn merge_keeps_the_latest_reply_from_an_address() {
+  let mut units =
+    vec![unit("pippo-a", "10.0.0.1"), unit("pippo-b", "10.0.0.2")];
+  discovery::merge(&mut units, unit("kitchen", "10.0.0.2"));
+  assert_eq!(
+    units,
+    [unit("kitchen", "10.0.0.2"), unit("pippo-a", "10.0.0.1")]
+  );
+}
@@ -0,0 +1,152 @@
+//! Requests and responses as they go through the proxy, the responses
+//! arriving a few bytes at a time. These run on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test http1 --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/http1.rs"]
+mod http1;
+
+use std::io::{self, Read};
+
+use http1::{Response, Url};
+
+/// Hands out `bytes` at most `chunk` at a time, as a slow connection does
+struct Trickle<'a> {
+  bytes: &'a [u8],
+  chunk: usize,
+}
+
+impl Read for Trickle<'_> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let size = self.chunk.min(buf.len()).min(self.bytes.len());
+    buf[..size].copy_from_slice(&self.bytes[..size]);
+    self.bytes = &self.bytes[size..];
+    Ok(size)
+  }
+}
+
+/// Status and body of `response`, read `chunk` bytes at a time
+fn read(response: &str, chunk: usize) -> io::Result<(u16, String)> {
+  let trickle = Trickle {
+    bytes: response.as_bytes(),
+    chunk,
+  };
+  let mut response = Response::read(trickle)?;
+  let mut body = String::new();
+  response.read_to_string(&mut body)?;
+  Ok((response.head.status, body))
+}
+
+#[test]
+fn body_ends_after_its_length() {
+  let response = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello, and more";
+  for chunk in 1..=4 {
+    assert_eq!(read(response, chunk).unwrap(), (200, "hello".to_string()));
+  }
+  let cut_off = "HTTP/1.1 200 OK\r\ncontent-length: 9\r\n\r\nhello";
+  let error = read(cut_off, 2).unwrap_err();
+  assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn chunked_body_put_back_together() {
+  let response = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+    4\r\n{\"a\"\r\n9;ext=1\r\n: [1, 2]}\r\n0\r\nTrailer: x\r\n\r\nafter";
+  for chunk in 1..=5 {
+    let (_, body) = read(response, chunk).unwrap();
+    assert_eq!(body, r#"{"a": [1, 2]}"#, "{chunk} bytes at a time");
+  }
+  let bad_size = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\nzz\r\n";
+  let error = read(bad_size, 3).unwrap_err();
+  assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn body_without_length_ends_with_the_connection() {
+  let response = "HTTP/1.0 200 OK\nContent-Type: text/plain\n\nall of it";
+  assert_eq!(read(response, 3).unwrap(), (200, "all of it".to_string()));
+}
+
+#[test]
+fn not_modified_has_no_body() {
+  let response = "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n";
+  let trickle = Trickle {
+    bytes: response.as_bytes(),
+    chunk: 1,
+  };
+  let mut response = Response::read(trickle).unwrap();
+  assert_eq!(response.head.header("etag"), Some("\"v1\""));
+  assert_eq!(response.read(&mut [0; 8]).unwrap(), 0);
+}
+
+#[test]
+fn not_a_response_is_an_error() {
+  for response in ["SSH-2.0-OpenSSH\r\n", "HTTP/1.1 2x0 OK\r\n\r\n", ""] {
+    let error = read(response, 4).unwrap_err();
+    assert!(
+      matches!(
+        error.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+      ),
+      "{response:?}"
+    );
+  }
+}
+
+#[test]
+fn request_head_closes_the_connection() {
+  let head = http1::request_head(
+    "GET",
+    "http://example.com:80/feed?x=1",
+    "example.com:80",
+    &[("accept", "application/json")],
+  );
+  assert_eq!(
+    head,
+    "GET http://example.com:80/feed?x=1 HTTP/1.1\r\n\
+    host: example.com:80\r\naccept: application/json\r\n\
+    connection: close\r\n\r\n"
+  );
+}
+
+#[test]
+fn basic_auth_is_padded_base64() {
+  assert_eq!(http1::basic_auth("a", "b"), "Basic YTpi");
+  assert_eq!(http1::basic_auth("ab", "c"), "Basic YWI6Yw==");
+  assert_eq!(
+    http1::basic_auth("Aladdin", "open sesame"),
+    "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+  );
+}
+
+#[test]
+fn urls_split_into_their_parts() {
+  let url = http1::split_url("https://api.weatherapi.com/v1/current.json?q=1");
+  assert_eq!(
+    url,
+    Some(Url {
+      https: true,
+      host: "api.weatherapi.com",
+      port: 443,
+      path: "/v1/current.json?q=1".to_string(),
+    })
+  );
+  let url = http1::split_url("http://10.0.0.2:8000?feed").unwrap();
+  assert_eq!(
+    (url.host, url.port, url.path.as_str()),
+    ("10.0.0.2", 8000, "/?feed")
+  );
+  assert_eq!(http1::split_url("http://example.com").unwrap().path, "/");
+  for bad in [
+    "ftp://example.com/",
+    "https://user@example.com/",
+    "http://:80/",
+  ] {
+    assert_eq!(http1::split_url(bad), None, "{bad}");
+  }
+}
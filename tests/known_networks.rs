@@ -0,0 +1,78 @@
+//! Which saved WiFi network to join. These run on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test known_networks --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/known_networks.rs"]
+mod known_networks;
+
+use known_networks::KnownNetwork;
+
+fn network(ssid: &str, priority: u8) -> KnownNetwork {
+  KnownNetwork {
+    ssid: ssid.to_string(),
+    password: "correct horse".to_string(),
+    priority,
+  }
+}
+
+#[test]
+fn highest_priority_in_range_wins() {
+  let known = [
+    network("home", 10),
+    network("office", 5),
+    network("phone", 1),
+  ];
+  let visible = [("phone", -40), ("office", -80), ("cafe", -50)];
+  let picked = known_networks::pick(&known, &visible).unwrap();
+  assert_eq!(picked.ssid, "office");
+}
+
+#[test]
+fn stronger_signal_breaks_a_tie() {
+  let known = [network("upstairs", 5), network("downstairs", 5)];
+  let visible = [("upstairs", -75), ("downstairs", -60), ("upstairs", -70)];
+  let picked = known_networks::pick(&known, &visible).unwrap();
+  assert_eq!(picked.ssid, "downstairs");
+}
+
+#[test]
+fn nothing_known_in_range() {
+  let known = [network("home", 10)];
+  assert_eq!(known_networks::pick(&known, &[("cafe", -50)]), None);
+  assert_eq!(known_networks::pick(&[], &[("home", -50)]), None);
+}
+
+#[test]
+fn remembering_replaces_the_same_ssid() {
+  let mut known = vec![network("home", 10), network("office", 5)];
+  known_networks::remember(&mut known, network("home", 1)).unwrap();
+  assert_eq!(known.len(), 2);
+  assert_eq!(known[1], network("home", 1));
+}
+
+#[test]
+fn list_is_limited() {
+  let mut known: Vec<_> = (0..known_networks::MAX_KNOWN)
+    .map(|n| network(&format!("net{n}"), 0))
+    .collect();
+  let full = known_networks::remember(&mut known, network("one more", 0));
+  assert!(full.is_err());
+  known_networks::remember(&mut known, network("net0", 3)).unwrap();
+  assert_eq!(known.len(), known_networks::MAX_KNOWN);
+}
+
+#[test]
+fn passwords_are_not_listed() {
+  let json = serde_json::to_value(network("home", 10)).unwrap();
+  assert_eq!(json, serde_json::json!({"ssid": "home", "priority": 10}));
+  assert!(network("home", 0).validate().is_ok());
+  assert!(network("", 0).validate().is_err());
+  let mut short = network("home", 0);
+  short.password = "short".to_string();
+  assert!(short.validate().is_err());
+}
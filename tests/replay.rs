@@ -0,0 +1,203 @@
+//! Recordings of button presses played back through the UI state machine,
+//! checking they go through the recorded screens. A recording made on the
+//! device with `record show`, or in the simulator with `--record`, goes in
+//! `tests/recordings` to keep a navigation bug from coming back:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test replay --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/clock.rs"]
+mod clock;
+#[path = "../src/pager.rs"]
+mod pager;
+#[path = "../src/prerender.rs"]
+mod prerender;
+#[path = "../src/recording.rs"]
+mod recording;
+#[path = "../src/screens/mod.rs"]
+mod screens;
+#[path = "../src/snake.rs"]
+mod snake;
+#[path = "../src/statusbar.rs"]
+mod statusbar;
+#[path = "../src/titlebar.rs"]
+mod titlebar;
+#[path = "../src/typography.rs"]
+mod typography;
+#[path = "../src/ui.rs"]
+mod ui;
+
+/// Stands in for the SSD1306 panel in the modules the screens need
+mod display {
+  use embedded_graphics::{mock_display::MockDisplay, pixelcolor::BinaryColor};
+
+  pub type Display<'d> = MockDisplay<BinaryColor>;
+}
+
+use std::time::Duration;
+
+use clock::Instant;
+
+use recording::{Entry, Input, Session};
+use ui::{Button, ButtonEvent, UiState, LONG_PRESS};
+
+/// The main loop reads the button once per frame
+const FRAME: Duration = Duration::from_millis(20);
+/// How long to keep going after the last entry, longer than a screen
+/// change may be late
+const TAIL: Duration = Duration::from_secs(2);
+
+/// The screens as the main loop keeps them, fed by `session` on a clock
+/// that moves a frame at a time
+struct Screens {
+  screen: UiState,
+  option_index: u8,
+  list_offset: usize,
+  brightness_draft: Option<u8>,
+}
+
+impl Screens {
+  fn new() -> Self {
+    Self {
+      screen: UiState::Home,
+      option_index: 0,
+      list_offset: 0,
+      brightness_draft: None,
+    }
+  }
+
+  /// One frame with `pressed` from the button, through `session`
+  fn frame(
+    &mut self,
+    session: &mut Session,
+    pressed: Option<ButtonEvent>,
+    now: Instant,
+  ) {
+    let before = self.screen;
+    match session.press(pressed, now) {
+      Some(ButtonEvent::Long) => ui::handle_long_press(
+        &mut self.screen,
+        self.option_index,
+        self.list_offset,
+      ),
+      Some(ButtonEvent::Short) => {
+        ui::handle_short_press(
+          &mut self.screen,
+          &mut self.option_index,
+          &mut self.list_offset,
+          UiState::Games.menu_len(),
+          &mut self.brightness_draft,
+        );
+      }
+      Some(ButtonEvent::Down) | None => {}
+    }
+    if self.screen != before {
+      session.screen(self.screen, now);
+      self.list_offset = 0;
+      self.brightness_draft = (self.screen == UiState::Settings).then_some(128);
+    }
+  }
+}
+
+/// Play `entries` back, with how it went
+fn replay(entries: Vec<Entry>) -> Result<(), String> {
+  let end =
+    Duration::from_millis(entries.last().map_or(0, |entry| entry.at_ms));
+  let start = Instant::now();
+  let mut session = Session::default();
+  session.replay(entries, start);
+  let mut screens = Screens::new();
+  let mut now = start;
+  while now.duration_since(start) <= end + TAIL {
+    screens.frame(&mut session, None, now);
+    if let Some(result) = session.finished() {
+      return result;
+    }
+    now += FRAME;
+  }
+  Err("the replay didn't finish".to_string())
+}
+
+/// Record the button held down for each of `holds`, with a pause after each
+fn record(holds: &[Duration]) -> Vec<Entry> {
+  let start = Instant::now();
+  let mut button = Button::new(start);
+  let mut session = Session::default();
+  session.record(start);
+  let mut screens = Screens::new();
+  let mut now = start;
+  for &hold in holds {
+    for (pressed, length) in [(true, hold), (false, Duration::from_millis(300))]
+    {
+      let until = now + length;
+      while now < until {
+        now += FRAME;
+        let event = button.update(pressed, now);
+        screens.frame(&mut session, event, now);
+      }
+    }
+  }
+  session.stop().unwrap()
+}
+
+fn long() -> Duration {
+  LONG_PRESS + Duration::from_millis(200)
+}
+
+fn short() -> Duration {
+  Duration::from_millis(150)
+}
+
+#[test]
+fn saved_recording_replays() {
+  let entries =
+    recording::parse(include_str!("recordings/menu_to_snake.jsonl")).unwrap();
+  assert_eq!(replay(entries), Ok(()));
+}
+
+#[test]
+fn recording_replays_the_same_way() {
+  let entries = record(&[long(), short(), short(), long(), long()]);
+  let screens: Vec<Input> = entries
+    .iter()
+    .map(|entry| entry.input)
+    .filter(|input| matches!(input, Input::Screen(_)))
+    .collect();
+  assert_eq!(
+    screens,
+    [
+      Input::Screen(UiState::Menu),
+      Input::Screen(UiState::Scenes),
+      Input::Screen(UiState::Home),
+    ]
+  );
+  let text = recording::to_lines(&entries);
+  assert_eq!(replay(recording::parse(&text).unwrap()), Ok(()));
+}
+
+#[test]
+fn different_screen_is_reported() {
+  let mut entries = record(&[long(), long()]);
+  for entry in &mut entries {
+    if entry.input == Input::Screen(UiState::Settings) {
+      entry.input = Input::Screen(UiState::Status);
+    }
+  }
+  let mismatch = replay(entries).unwrap_err();
+  assert!(mismatch.contains("Settings, recorded Status"), "{mismatch}");
+}
+
+#[test]
+fn missing_screen_change_is_reported() {
+  let mut entries = record(&[long()]);
+  entries.retain(|entry| entry.input != Input::Button(ButtonEvent::Long));
+  let mismatch = replay(entries).unwrap_err();
+  assert!(
+    mismatch.contains("should have changed to Menu"),
+    "{mismatch}"
+  );
+}
@@ -0,0 +1,84 @@
+//! The order outgoing requests get a connection in. These run on the
+//! computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test request_queue --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/request_queue.rs"]
+mod request_queue;
+
+use std::time::Duration;
+
+use request_queue::{Priority, Queue};
+
+#[test]
+fn requests_start_until_the_limit() {
+  let mut queue = Queue::new(2);
+  let first = queue.join(Priority::Refresh);
+  assert!(queue.try_start(first));
+  let second = queue.join(Priority::Refresh);
+  assert!(queue.try_start(second));
+  let third = queue.join(Priority::Refresh);
+  assert!(!queue.try_start(third));
+  queue.finish();
+  assert!(queue.try_start(third));
+  assert_eq!(queue.waiting(), 0);
+}
+
+#[test]
+fn urgent_requests_go_first() {
+  let mut queue = Queue::new(1);
+  let running = queue.join(Priority::Refresh);
+  assert!(queue.try_start(running));
+  let refresh = queue.join(Priority::Refresh);
+  let update = queue.join(Priority::Update);
+  let notification = queue.join(Priority::Notification);
+  queue.finish();
+  assert!(!queue.try_start(refresh));
+  assert!(!queue.try_start(update));
+  assert!(queue.try_start(notification));
+  queue.finish();
+  assert!(!queue.try_start(refresh));
+  assert!(queue.try_start(update));
+  queue.finish();
+  assert!(queue.try_start(refresh));
+}
+
+#[test]
+fn same_priority_in_the_order_they_came() {
+  let mut queue = Queue::new(1);
+  let running = queue.join(Priority::Update);
+  assert!(queue.try_start(running));
+  let older = queue.join(Priority::Refresh);
+  let newer = queue.join(Priority::Refresh);
+  queue.finish();
+  assert!(!queue.try_start(newer));
+  assert!(queue.try_start(older));
+}
+
+#[test]
+fn leaving_lets_the_next_one_start() {
+  let mut queue = Queue::new(1);
+  let running = queue.join(Priority::Refresh);
+  assert!(queue.try_start(running));
+  let gave_up = queue.join(Priority::Notification);
+  let next = queue.join(Priority::Refresh);
+  queue.finish();
+  queue.leave(gave_up);
+  assert!(queue.try_start(next));
+}
+
+#[test]
+fn slots_are_given_back_when_dropped() {
+  let timeout = Duration::from_millis(50);
+  let slots: Vec<_> = (0..request_queue::MAX_OPEN)
+    .map(|_| request_queue::acquire(Priority::Refresh, timeout).unwrap())
+    .collect();
+  assert!(request_queue::acquire(Priority::Notification, timeout).is_none());
+  drop(slots);
+  assert!(request_queue::acquire(Priority::Refresh, timeout).is_some());
+}
@@ -0,0 +1,66 @@
+//! Choosing the access point to roam to. These run on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test roaming --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/roaming.rs"]
+mod roaming;
+
+use roaming::AccessPoint;
+
+fn access_point(last: u8, rssi: i8) -> AccessPoint {
+  AccessPoint {
+    bssid: [0x24, 0x0a, 0xc4, 0, 0, last],
+    channel: last,
+    rssi,
+  }
+}
+
+#[test]
+fn strongest_candidate_wins() {
+  let current = access_point(1, -80);
+  let candidates = [access_point(2, -70), access_point(3, -60)];
+  assert_eq!(
+    roaming::better(&current, &candidates, 8),
+    Some(access_point(3, -60))
+  );
+}
+
+#[test]
+fn candidates_within_the_margin_are_ignored() {
+  let current = access_point(1, -75);
+  assert_eq!(roaming::better(&current, &[access_point(2, -68)], 8), None);
+  assert_eq!(
+    roaming::better(&current, &[access_point(2, -67)], 8),
+    Some(access_point(2, -67))
+  );
+}
+
+#[test]
+fn current_access_point_is_not_a_candidate() {
+  let current = access_point(1, -90);
+  let rescanned = AccessPoint {
+    rssi: -50,
+    ..current
+  };
+  assert_eq!(roaming::better(&current, &[rescanned], 8), None);
+  assert_eq!(roaming::better(&current, &[], 8), None);
+}
+
+#[test]
+fn margin_does_not_overflow() {
+  let current = access_point(1, 120);
+  assert_eq!(roaming::better(&current, &[access_point(2, 127)], 30), None);
+}
+
+#[test]
+fn bssid_as_hex_pairs() {
+  assert_eq!(
+    roaming::format_bssid(&[0x24, 0x0a, 0xc4, 0x12, 0xab, 0xff]),
+    "24:0a:c4:12:ab:ff"
+  );
+}
@@ -0,0 +1,40 @@
+//! The web server has room for a fixed number of handlers and won't start
+//! with more, which stops the boot. This counts the ones `web::start`
+//! registers, the debug-only test routes included, from the source. It runs
+//! on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test routes --target x86_64-unknown-linux-gnu
+//! ```
+
+const WEB: &str = include_str!("../src/web.rs");
+
+/// Left free for the next few routes
+const HEADROOM: usize = 16;
+
+fn count(pattern: &str) -> usize {
+  WEB.matches(pattern).count()
+}
+
+fn limit() -> usize {
+  let (_, rest) = WEB
+    .split_once("const MAX_URI_HANDLERS: usize = ")
+    .expect("web.rs sets MAX_URI_HANDLERS");
+  rest.split_once(';').unwrap().0.parse().unwrap()
+}
+
+#[test]
+fn routes_fit_the_handler_limit() {
+  // `confirmed_action` goes through `router.route` once per call, and each
+  // `/api/*` catch-all is registered for GET and POST
+  let confirmed = count("confirmed_action(\n    &mut router,");
+  let routes = count("router.route(") - 1
+    + confirmed
+    + 2 * count("router.server.fn_handler(");
+  assert!(confirmed > 0 && routes > 60, "{routes} routes, miscounted?");
+  assert!(
+    routes + HEADROOM <= limit(),
+    "{routes} routes leave too little room below {}",
+    limit()
+  );
+}
@@ -0,0 +1,377 @@
+//! Screens drawn with fixed inputs into a frame buffer in memory and
+//! compared with the bitmaps in `tests/golden`, so a change to a font or a
+//! widget that moves things around shows up here. These run on the
+//! computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test screens --target x86_64-unknown-linux-gnu
+//! ```
+//!
+//! After a deliberate layout change, write the new bitmaps with
+//! `UPDATE_GOLDEN=1` and look at them (they are plain PBM images) before
+//! committing.
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/clock.rs"]
+mod clock;
+#[path = "../src/marquee.rs"]
+mod marquee;
+#[path = "../src/notify.rs"]
+mod notify;
+#[path = "../src/overlay.rs"]
+mod overlay;
+#[path = "../src/pager.rs"]
+mod pager;
+#[path = "../src/prerender.rs"]
+mod prerender;
+#[path = "../src/screens/mod.rs"]
+mod screens;
+#[path = "../src/snake.rs"]
+mod snake;
+#[path = "../src/statusbar.rs"]
+mod statusbar;
+#[path = "../src/titlebar.rs"]
+mod titlebar;
+#[path = "../src/typography.rs"]
+mod typography;
+#[path = "../src/ui.rs"]
+mod ui;
+
+/// Stands in for the SSD1306 panel in the shared modules
+mod display {
+  use std::convert::Infallible;
+
+  use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+
+  pub const WIDTH: usize = 128;
+  pub const HEIGHT: usize = 64;
+
+  /// The panel's pixels, lit or not
+  pub struct Framebuffer {
+    pub pixels: [[bool; WIDTH]; HEIGHT],
+  }
+
+  impl Framebuffer {
+    pub fn new() -> Self {
+      Self {
+        pixels: [[false; WIDTH]; HEIGHT],
+      }
+    }
+  }
+
+  impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+      I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+      for Pixel(point, color) in pixels {
+        // drawing off the panel is clipped, as on the device
+        if let (Ok(x @ 0..WIDTH), Ok(y @ 0..HEIGHT)) =
+          (usize::try_from(point.x), usize::try_from(point.y))
+        {
+          self.pixels[y][x] = color.is_on();
+        }
+      }
+      Ok(())
+    }
+  }
+
+  impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+      Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+  }
+
+  pub type Display<'d> = Framebuffer;
+}
+
+use std::{path::PathBuf, time::Duration};
+
+use clock::Instant;
+use display::{Display, Framebuffer, HEIGHT, WIDTH};
+use prerender::Prerendered;
+use statusbar::StatusBar;
+use typography::Font;
+
+/// `draw` on a blank panel
+fn render(draw: impl FnOnce(&mut Display<'_>)) -> Framebuffer {
+  let mut display = Framebuffer::new();
+  draw(&mut display);
+  display
+}
+
+/// Plain PBM, one row of `0` and `1` per line, which image viewers open
+fn to_pbm(frame: &Framebuffer) -> String {
+  let mut pbm = format!("P1\n{WIDTH} {HEIGHT}\n");
+  for row in &frame.pixels {
+    pbm.extend(row.iter().map(|&lit| if lit { '1' } else { '0' }));
+    pbm.push('\n');
+  }
+  pbm
+}
+
+/// The rows of `actual` that differ from `expected`, drawn with `#` for
+/// lit pixels
+fn row_diff(expected: &str, actual: &str) -> String {
+  let mut diff = String::new();
+  let rows = |pbm: &str| -> Vec<String> {
+    pbm.lines().skip(2).map(str::to_string).collect()
+  };
+  let draw = |row: &str| row.replace('1', "#").replace('0', ".");
+  let (expected, actual) = (rows(expected), rows(actual));
+  for y in 0..expected.len().max(actual.len()) {
+    let (want, got) = (
+      expected.get(y).map_or("", String::as_str),
+      actual.get(y).map_or("", String::as_str),
+    );
+    if want != got {
+      diff.push_str(&format!(
+        "row {y:>2} want {}\n       got  {}\n",
+        draw(want),
+        draw(got)
+      ));
+    }
+  }
+  diff
+}
+
+/// Compare `frame` with `tests/golden/<name>.pbm`, or write it there with
+/// `UPDATE_GOLDEN` set
+fn assert_golden(name: &str, frame: &Framebuffer) {
+  // the tests are built by host-tests, next to tests/
+  let golden = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+    .join("../tests/golden")
+    .join(format!("{name}.pbm"));
+  let actual = to_pbm(frame);
+  if std::env::var_os("UPDATE_GOLDEN").is_some() {
+    std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+    std::fs::write(&golden, &actual).unwrap();
+    return;
+  }
+  let Ok(expected) = std::fs::read_to_string(&golden) else {
+    panic!("no {}, write it with UPDATE_GOLDEN=1", golden.display());
+  };
+  if expected != actual {
+    let written =
+      PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.pbm"));
+    std::fs::write(&written, &actual).unwrap();
+    panic!(
+      "{name} differs from {}, drawn to {}:\n{}",
+      golden.display(),
+      written.display(),
+      row_diff(&expected, &actual)
+    );
+  }
+}
+
+fn status_bar() -> StatusBar {
+  StatusBar {
+    time: "09:41".try_into().unwrap(),
+    synced: true,
+    rssi: Some(-60),
+    battery_percent: Some(80),
+    notifications: 2,
+    unread_headlines: 0,
+    title: None,
+  }
+}
+
+#[test]
+fn home() {
+  let frame = render(|display| {
+    screens::home_screen(display, Font::Large.style(), "16/10 09:41");
+    statusbar::draw(display, &status_bar());
+  });
+  assert_golden("home", &frame);
+}
+
+#[test]
+fn setup_ap() {
+  let frame = render(|display| {
+    screens::setup_ap_screen(display, "pippo-3f2a", "k7qmx4ph", "192.168.71.1");
+    statusbar::draw(
+      display,
+      &StatusBar {
+        rssi: None,
+        ..status_bar()
+      },
+    );
+  });
+  assert_golden("setup_ap", &frame);
+}
+
+#[test]
+fn menu_first_entry() {
+  let frame = render(|display| {
+    screens::menu_screen(display, 0);
+    statusbar::draw(display, &status_bar());
+  });
+  assert_golden("menu_first_entry", &frame);
+}
+
+#[test]
+fn menu_scrolled_to_the_end() {
+  let frame = render(|display| {
+    screens::menu_screen(display, ui::UiState::Menu.menu_len() - 1);
+    statusbar::draw(display, &status_bar());
+  });
+  assert_golden("menu_scrolled_to_the_end", &frame);
+}
+
+#[test]
+fn settings() {
+  let frame = render(|display| {
+    screens::settings::draw_screen(display, Font::Large.style(), 160);
+  });
+  assert_golden("settings", &frame);
+}
+
+#[test]
+fn games() {
+  let frame = render(|display| {
+    screens::games::draw_screen(display, 0);
+    statusbar::draw(display, &status_bar());
+  });
+  assert_golden("games", &frame);
+}
+
+#[test]
+fn exit() {
+  let frame = render(|display| {
+    screens::exit::draw_screen(display, Font::Large.style());
+  });
+  assert_golden("exit", &frame);
+}
+
+#[test]
+fn prerendered_screen_matches_drawing_it() {
+  let mut cached = Prerendered::<u8>::default();
+  let mut drawn = 0;
+  for brightness in [160, 160, 96, 160] {
+    let frame = render(|display| {
+      cached.draw(display, brightness, |frame| {
+        drawn += 1;
+        screens::settings::draw_screen(frame, Font::Large.style(), brightness);
+      });
+    });
+    if brightness == 160 {
+      assert_golden("settings", &frame);
+    }
+  }
+  // drawn again only when the brightness differs from the last frame's
+  assert_eq!(drawn, 3);
+}
+
+#[test]
+fn status_bar_offline() {
+  let frame = render(|display| {
+    statusbar::draw(
+      display,
+      &StatusBar {
+        time: "12:00".try_into().unwrap(),
+        synced: false,
+        rssi: None,
+        battery_percent: None,
+        notifications: 0,
+        unread_headlines: 3,
+        title: None,
+      },
+    );
+  });
+  assert_golden("status_bar_offline", &frame);
+}
+
+#[test]
+fn title_bar_in_a_game() {
+  let frame = render(|display| {
+    statusbar::draw(
+      display,
+      &StatusBar {
+        title: titlebar::Title::for_screen(ui::UiState::Snake),
+        ..status_bar()
+      },
+    );
+  });
+  assert_golden("title_bar_in_a_game", &frame);
+}
+
+#[test]
+fn crumbs_leave_out_the_top_first() {
+  let path = ui::UiState::Snake.path();
+  assert_eq!(path, ["Menu", "Games", "Snake"]);
+  assert_eq!(titlebar::crumbs(&path, 128).as_str(), "Menu>Games>Snake");
+  let width = |text: &str| typography::width(text, &Font::Small.style());
+  assert_eq!(
+    titlebar::crumbs(&path, width("..>Games>Snake")).as_str(),
+    "..>Games>Snake"
+  );
+  assert_eq!(titlebar::crumbs(&path, width("Sna")).as_str(), "Sna");
+  // the menus themselves offer no way back
+  assert!(
+    !titlebar::Title::for_screen(ui::UiState::Games)
+      .unwrap()
+      .back
+  );
+  assert_eq!(titlebar::Title::for_screen(ui::UiState::Home), None);
+}
+
+#[test]
+fn notification_banner() {
+  let frame = render(|display| {
+    screens::home_screen(display, Font::Large.style(), "16/10 09:41");
+    notify::draw_banner(
+      display,
+      &notify::Message {
+        text: "Door left open for 10 minutes".to_string(),
+        priority: notify::Priority::High,
+        duration: Duration::from_secs(5),
+        shown_at: Instant::now(),
+      },
+    );
+  });
+  assert_golden("notification_banner", &frame);
+}
+
+#[test]
+fn notification_history() {
+  let entry = |text: &str, priority| notify::Entry {
+    text: text.to_string(),
+    priority,
+    at: Instant::now(),
+  };
+  let frame = render(|display| {
+    notify::draw_history(
+      display,
+      &[
+        entry("Motion detected", notify::Priority::Normal),
+        entry("Door left open", notify::Priority::High),
+      ],
+      0,
+      2,
+    );
+    statusbar::draw(display, &status_bar());
+  });
+  assert_golden("notification_history", &frame);
+}
+
+#[test]
+fn debug_overlay() {
+  let frame = render(|display| {
+    screens::home_screen(display, Font::Large.style(), "16/10 09:41");
+    overlay::draw(
+      display,
+      &overlay::FrameStats {
+        frame: Duration::from_millis(25),
+        draw: Duration::from_millis(3),
+        flush: Duration::from_millis(19),
+        free_heap: 151 * 1024,
+      },
+    );
+  });
+  assert_golden("debug_overlay", &frame);
+}
@@ -0,0 +1,95 @@
+//! Showing up in UPnP network browsers. These run on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test ssdp --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/ssdp.rs"]
+mod ssdp;
+
+use ssdp::Device;
+
+const UUID: &str = "uuid:9b2c5a42-7e1d-4f36-a0c8-240ac4a1b2c3";
+
+fn device() -> Device {
+  Device::new(
+    "pippo-a1b2c3".to_string(),
+    "0.1.0",
+    [0x24, 0x0a, 0xc4, 0xa1, 0xb2, 0xc3],
+  )
+}
+
+fn search(st: &str) -> String {
+  format!(
+    "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\n\
+     MAN: \"ssdp:discover\"\r\nMX: 2\r\nST: {st}\r\n\r\n"
+  )
+}
+
+#[test]
+fn uuid_comes_from_the_mac() {
+  assert_eq!(format!("uuid:{}", device().uuid), UUID);
+}
+
+#[test]
+fn search_target_is_read() {
+  assert_eq!(ssdp::parse_search(&search("ssdp:all")), Some("ssdp:all"));
+  let lower = "m-search * http/1.1\r\nman: \"ssdp:discover\"\r\nst: \
+               upnp:rootdevice\r\n\r\n";
+  assert_eq!(ssdp::parse_search(lower), Some("upnp:rootdevice"));
+}
+
+#[test]
+fn announcements_are_not_searches() {
+  let notify = device().notify("upnp:rootdevice", "http://10.0.0.2/");
+  assert_eq!(ssdp::parse_search(&notify), None);
+  let no_man = "M-SEARCH * HTTP/1.1\r\nST: ssdp:all\r\n\r\n";
+  assert_eq!(ssdp::parse_search(no_man), None);
+}
+
+#[test]
+fn all_answers_every_target() {
+  let device = device();
+  assert_eq!(device.answers("ssdp:all").len(), 3);
+  assert_eq!(device.answers("upnp:rootdevice"), ["upnp:rootdevice"]);
+  assert_eq!(device.answers(UUID), [UUID]);
+  assert_eq!(device.answers(ssdp::DEVICE_TYPE), [ssdp::DEVICE_TYPE]);
+  assert!(device
+    .answers("urn:schemas-upnp-org:device:Printer:1")
+    .is_empty());
+}
+
+#[test]
+fn usn_names_the_target_after_the_uuid() {
+  let device = device();
+  assert_eq!(device.usn(UUID), UUID);
+  assert_eq!(
+    device.usn("upnp:rootdevice"),
+    format!("{UUID}::upnp:rootdevice")
+  );
+}
+
+#[test]
+fn response_points_at_the_description() {
+  let response =
+    device().response("upnp:rootdevice", "http://10.0.0.2/description.xml");
+  assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  assert!(
+    response.contains("\r\nLOCATION: http://10.0.0.2/description.xml\r\n")
+  );
+  assert!(response.contains("\r\nST: upnp:rootdevice\r\n"));
+  assert!(response.ends_with("\r\n\r\n"));
+}
+
+#[test]
+fn description_escapes_the_name() {
+  let mut device = device();
+  device.name = "Tom & Jerry's <desk>".to_string();
+  let description = device.description();
+  assert!(description
+    .contains("<friendlyName>Tom &amp; Jerry's &lt;desk&gt;</friendlyName>"));
+  assert!(description.contains(&format!("<UDN>{UUID}</UDN>")));
+}
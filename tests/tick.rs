@@ -0,0 +1,57 @@
+//! Deadlines of the main loop's sleep. These run on the computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test tick --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/clock.rs"]
+mod clock;
+#[path = "../src/tick.rs"]
+mod tick;
+
+use std::time::Duration;
+
+use clock::Instant;
+
+use tick::{Schedule, FRAME, IDLE};
+
+#[test]
+fn idle_sleeps_the_longest() {
+  let now = Instant::now();
+  assert_eq!(Schedule::new(now).sleep(now), IDLE);
+}
+
+#[test]
+fn soonest_deadline_wins() {
+  let now = Instant::now();
+  let mut schedule = Schedule::new(now);
+  schedule.within(Duration::from_millis(300));
+  schedule.at(now + Duration::from_millis(120));
+  schedule.within(Duration::from_millis(500));
+  assert_eq!(schedule.sleep(now), Duration::from_millis(120));
+  schedule.frame();
+  assert_eq!(schedule.sleep(now), FRAME);
+}
+
+#[test]
+fn time_spent_working_comes_off_the_sleep() {
+  let now = Instant::now();
+  let mut schedule = Schedule::new(now);
+  schedule.frame();
+  assert_eq!(
+    schedule.sleep(now + Duration::from_millis(5)),
+    Duration::from_millis(15)
+  );
+}
+
+#[test]
+fn overdue_work_still_yields() {
+  let now = Instant::now();
+  let mut schedule = Schedule::new(now);
+  schedule.at(now);
+  let sleep = schedule.sleep(now + Duration::from_millis(50));
+  assert!(sleep > Duration::ZERO && sleep <= FRAME);
+}
@@ -0,0 +1,402 @@
+//! Scripted button presses through the UI state machine. These run on the
+//! computer, not the board:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test ui --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/clock.rs"]
+mod clock;
+#[path = "../src/pager.rs"]
+mod pager;
+#[path = "../src/prerender.rs"]
+mod prerender;
+#[path = "../src/screens/mod.rs"]
+mod screens;
+#[path = "../src/snake.rs"]
+mod snake;
+#[path = "../src/statusbar.rs"]
+mod statusbar;
+#[path = "../src/titlebar.rs"]
+mod titlebar;
+#[path = "../src/typography.rs"]
+mod typography;
+#[path = "../src/ui.rs"]
+mod ui;
+
+/// Stands in for the SSD1306 panel in the modules the screens need
+mod display {
+  use embedded_graphics::{mock_display::MockDisplay, pixelcolor::BinaryColor};
+
+  pub type Display<'d> = MockDisplay<BinaryColor>;
+}
+
+use std::time::Duration;
+
+use clock::Instant;
+
+use screens::SCREENS;
+use ui::{Button, ButtonEvent, Paging, Redraw, UiState, DEBOUNCE, LONG_PRESS};
+
+/// The main loop reads the button once per frame
+const FRAME_MS: u64 = 20;
+
+/// The button and the screens as the main loop keeps them, driven by a
+/// clock that only moves when the script says so
+struct Rig {
+  button: Button,
+  start: Instant,
+  at_ms: u64,
+  screen: UiState,
+  option_index: u8,
+  list_offset: usize,
+  list_len: usize,
+  brightness_draft: Option<u8>,
+  events: Vec<ButtonEvent>,
+  paging: Vec<Paging>,
+}
+
+impl Rig {
+  fn new() -> Self {
+    let start = Instant::now();
+    Self {
+      button: Button::new(start),
+      start,
+      at_ms: 0,
+      screen: UiState::Home,
+      option_index: 0,
+      list_offset: 0,
+      list_len: 0,
+      brightness_draft: None,
+      events: Vec::new(),
+      paging: Vec::new(),
+    }
+  }
+
+  /// Read `pressed` every frame for `ms`
+  fn read(&mut self, pressed: bool, ms: u64) -> &mut Self {
+    let end = self.at_ms + ms;
+    while self.at_ms < end {
+      self.at_ms += FRAME_MS;
+      let now = self.start + Duration::from_millis(self.at_ms);
+      if let Some(event) = self.button.update(pressed, now) {
+        self.events.push(event);
+        self.apply(event);
+      }
+    }
+    self
+  }
+
+  fn short(&mut self) -> &mut Self {
+    self.read(true, 200).read(false, 200)
+  }
+
+  fn long(&mut self) -> &mut Self {
+    self
+      .read(true, LONG_PRESS.as_millis() as u64 + 200)
+      .read(false, 200)
+  }
+
+  fn apply(&mut self, event: ButtonEvent) {
+    let before = self.screen;
+    match event {
+      ButtonEvent::Down => {}
+      ButtonEvent::Long => ui::handle_long_press(
+        &mut self.screen,
+        self.option_index,
+        self.list_offset,
+      ),
+      ButtonEvent::Short => {
+        let paging = ui::handle_short_press(
+          &mut self.screen,
+          &mut self.option_index,
+          &mut self.list_offset,
+          self.list_len,
+          &mut self.brightness_draft,
+        );
+        self.paging.extend(paging);
+      }
+    }
+    // entering a screen starts it over, as in the main loop
+    if self.screen != before {
+      self.list_offset = 0;
+      self.brightness_draft = (self.screen == UiState::Settings).then_some(128);
+    }
+  }
+
+  /// Open the menu entry named `label` from Home
+  fn open(&mut self, label: &str) -> &mut Self {
+    let index = UiState::Menu
+      .entries()
+      .position(|entry| entry.label == label)
+      .unwrap();
+    self.long();
+    for _ in 0..index {
+      self.short();
+    }
+    self.long()
+  }
+}
+
+#[test]
+fn bounce_is_not_a_press() {
+  let start = Instant::now();
+  let mut button = Button::new(start);
+  for (ms, pressed) in [(5, true), (12, false), (18, true), (24, false)] {
+    let now = start + Duration::from_millis(ms);
+    assert_eq!(button.update(pressed, now), None);
+  }
+  assert_eq!(
+    button.update(false, start + Duration::from_millis(500)),
+    None
+  );
+  assert!(!button.is_down());
+}
+
+#[test]
+fn press_goes_down_after_the_debounce() {
+  let start = Instant::now();
+  let mut button = Button::new(start);
+  assert_eq!(button.update(true, start + Duration::from_millis(10)), None);
+  assert_eq!(
+    button.update(true, start + Duration::from_millis(40)),
+    Some(ButtonEvent::Down)
+  );
+  assert!(button.is_down());
+}
+
+#[test]
+fn button_wakes_the_loop_when_an_event_can_fire() {
+  let start = Instant::now();
+  let mut button = Button::new(start);
+  assert_eq!(button.deadline(), None);
+  let pressed_at = start + Duration::from_millis(10);
+  assert_eq!(button.update(true, pressed_at), None);
+  assert_eq!(button.deadline(), Some(pressed_at + DEBOUNCE));
+  let down_at = pressed_at + DEBOUNCE;
+  assert_eq!(button.update(true, down_at), Some(ButtonEvent::Down));
+  assert_eq!(button.deadline(), Some(down_at + LONG_PRESS));
+  // a press that does nothing has nothing to wait for
+  button.ignore_press();
+  assert_eq!(button.deadline(), None);
+}
+
+#[test]
+fn short_press_fires_on_release() {
+  let mut rig = Rig::new();
+  rig.read(true, 200);
+  assert_eq!(rig.events, [ButtonEvent::Down]);
+  rig.read(false, 200);
+  assert_eq!(rig.events, [ButtonEvent::Down, ButtonEvent::Short]);
+}
+
+#[test]
+fn long_press_fires_once_while_held() {
+  let mut rig = Rig::new();
+  rig.read(true, 5000);
+  assert_eq!(rig.events, [ButtonEvent::Down, ButtonEvent::Long]);
+  rig.read(false, 200);
+  assert_eq!(rig.events, [ButtonEvent::Down, ButtonEvent::Long]);
+}
+
+#[test]
+fn just_short_of_long_is_short() {
+  let mut rig = Rig::new();
+  rig.read(true, LONG_PRESS.as_millis() as u64 - 100);
+  rig.read(false, 200);
+  assert_eq!(rig.events, [ButtonEvent::Down, ButtonEvent::Short]);
+}
+
+#[test]
+fn ignored_press_does_nothing() {
+  let start = Instant::now();
+  let mut button = Button::new(start);
+  let at = |ms| start + Duration::from_millis(ms);
+  assert_eq!(button.update(true, at(10)), None);
+  assert_eq!(button.update(true, at(40)), Some(ButtonEvent::Down));
+  button.ignore_press();
+  assert_eq!(button.update(true, at(3000)), None);
+  assert_eq!(button.update(false, at(3010)), None);
+  assert_eq!(button.update(false, at(3050)), None);
+  // the next press counts again
+  assert_eq!(button.update(true, at(4000)), None);
+  assert_eq!(button.update(true, at(4040)), Some(ButtonEvent::Down));
+  assert_eq!(button.update(false, at(4100)), None);
+  assert_eq!(button.update(false, at(4140)), Some(ButtonEvent::Short));
+}
+
+#[test]
+fn long_press_on_home_opens_the_menu() {
+  let mut rig = Rig::new();
+  rig.long();
+  assert_eq!(rig.screen, UiState::Menu);
+  assert_eq!(rig.option_index, 0);
+}
+
+#[test]
+fn menu_opens_the_selected_entry() {
+  let mut rig = Rig::new();
+  rig.open("Status");
+  assert_eq!(rig.screen, UiState::Status);
+  rig.long();
+  assert_eq!(rig.screen, UiState::Home);
+}
+
+#[test]
+fn menu_selection_wraps_around() {
+  let mut rig = Rig::new();
+  rig.long();
+  for _ in 0..UiState::Menu.menu_len() + 1 {
+    rig.short();
+  }
+  assert_eq!(rig.option_index, 1);
+}
+
+#[test]
+fn exit_goes_back_to_the_top_of_the_menu() {
+  let mut rig = Rig::new();
+  rig.open("Exit");
+  assert_eq!(rig.screen, UiState::Exit);
+  rig.short();
+  assert_eq!(rig.screen, UiState::Menu);
+  assert_eq!(rig.option_index, 0);
+}
+
+#[test]
+fn game_returns_to_the_games_menu() {
+  let mut rig = Rig::new();
+  rig.open("Games");
+  assert_eq!(rig.screen, UiState::Games);
+  rig.long();
+  assert_eq!(rig.screen, UiState::Snake);
+  // short presses turn the snake, which is the caller's
+  rig.short();
+  assert_eq!(rig.screen, UiState::Snake);
+  rig.long();
+  assert_eq!(rig.screen, UiState::Games);
+}
+
+#[test]
+fn every_screen_is_listed_once() {
+  for (index, screen) in SCREENS.iter().enumerate() {
+    let entry = &screen.entry;
+    assert!(entry.menu.is_menu(), "{:?}", entry.menu);
+    assert!(
+      SCREENS[..index]
+        .iter()
+        .all(|other| other.entry.screen != entry.screen),
+      "{:?} is listed twice",
+      entry.screen
+    );
+  }
+  assert!(!UiState::Home.is_menu());
+  assert_eq!(UiState::Games.menu_len(), 1);
+}
+
+#[test]
+fn screens_take_paging_and_animation_from_the_registry() {
+  assert!(UiState::Home.has_pages());
+  assert!(UiState::Network.has_pages());
+  assert!(!UiState::Logs.has_pages());
+  assert!(UiState::Snake.is_animated());
+  assert!(!UiState::Menu.is_animated());
+  // the histogram fits on one page, a short press leaves it be
+  let mut rig = Rig::new();
+  rig.open("Activity");
+  rig.short();
+  assert_eq!(rig.screen, UiState::Activity);
+  assert!(rig.paging.is_empty());
+}
+
+#[test]
+fn long_press_backs_out_of_submenus_only() {
+  assert_eq!(UiState::Snake.back(), UiState::Games);
+  assert_eq!(UiState::Games.back(), UiState::Home);
+  assert_eq!(UiState::Status.back(), UiState::Home);
+}
+
+#[test]
+fn settings_brightness_wraps_to_the_dimmest() {
+  let mut rig = Rig::new();
+  rig.open("Settings");
+  assert_eq!(rig.brightness_draft, Some(128));
+  rig.short().short().short().short();
+  assert_eq!(rig.brightness_draft, Some(u8::MAX));
+  rig.short();
+  assert_eq!(rig.brightness_draft, Some(0));
+  rig.short();
+  assert_eq!(rig.brightness_draft, Some(32));
+}
+
+#[test]
+fn logs_page_back_and_wrap() {
+  let mut rig = Rig::new();
+  rig.open("Logs");
+  rig.list_len = 12;
+  rig.short();
+  assert_eq!(rig.list_offset, 5);
+  rig.short();
+  assert_eq!(rig.list_offset, 10);
+  rig.short();
+  assert_eq!(rig.list_offset, 0);
+}
+
+#[test]
+fn short_press_turns_pages() {
+  let mut rig = Rig::new();
+  rig.short();
+  assert_eq!(rig.paging, [Paging::Next]);
+  assert_eq!(rig.screen, UiState::Home);
+
+  let mut rig = Rig::new();
+  rig.open("Headlines");
+  rig.list_len = 3;
+  rig.short();
+  assert_eq!(rig.list_offset, 1);
+  assert_eq!(rig.paging, [Paging::Restart]);
+}
+
+#[test]
+fn empty_list_stays_put() {
+  let mut rig = Rig::new();
+  rig.open("Scenes");
+  rig.short();
+  assert_eq!(rig.list_offset, 0);
+  assert_eq!(rig.screen, UiState::Scenes);
+}
+
+#[test]
+fn redraw_only_after_a_change() {
+  let mut redraw = Redraw::default();
+  // the first frame is drawn on a blank panel
+  assert!(redraw.take());
+  assert!(!redraw.take());
+
+  let mut shown = String::from("09:41");
+  redraw.watch(&mut shown, &"09:41".to_string());
+  assert!(!redraw.take());
+  redraw.watch(&mut shown, &"09:42".to_string());
+  assert!(redraw.take());
+  assert_eq!(shown, "09:42");
+  assert!(!redraw.take());
+
+  redraw.mark();
+  redraw.mark();
+  assert!(redraw.is_pending());
+  assert!(redraw.take());
+  assert!(!redraw.take());
+}
+
+#[test]
+fn screens_that_change_on_their_own() {
+  assert!(UiState::Snake.is_animated());
+  assert!(UiState::Headlines.is_animated());
+  assert!(!UiState::Home.is_animated());
+  assert!(!UiState::Menu.is_animated());
+  // Home turns to the quote of the day, the Menu waits for a press
+  assert!(UiState::Home.has_pages());
+  assert!(!UiState::Menu.has_pages());
+}
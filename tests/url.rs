@@ -0,0 +1,64 @@
+//! URLs for the data sources put together from settings. These run on the
+//! computer:
+//!
+//! ```sh
+//! cargo test -p pippo-host-tests --test url --target x86_64-unknown-linux-gnu
+//! ```
+
+// the shared modules have more in them than the tests use
+#![allow(dead_code)]
+
+#[path = "../src/url.rs"]
+mod url;
+
+use url::Url;
+
+#[test]
+fn parameters_are_percent_encoded() {
+  let url = Url::new("https://api.weatherapi.com/v1/current.json")
+    .query("key", "abc123")
+    .query("q", "São Paulo, BR");
+  assert_eq!(
+    url.as_str(),
+    "https://api.weatherapi.com/v1/current.json\
+     ?key=abc123&q=S%C3%A3o%20Paulo%2C%20BR"
+  );
+}
+
+#[test]
+fn numbers_and_flags_as_parameters() {
+  let url = Url::new("https://example.com/price")
+    .query("lat", 47.37)
+    .query("change", true);
+  assert_eq!(
+    url.to_string(),
+    "https://example.com/price?lat=47.37&change=true"
+  );
+}
+
+#[test]
+fn segments_are_encoded_before_the_query() {
+  let url = Url::new("https://query1.finance.yahoo.com/v8/finance/chart/")
+    .segment("^GSPC")
+    .segment("a/b")
+    .query("range", "1d");
+  assert_eq!(
+    url.as_str(),
+    "https://query1.finance.yahoo.com/v8/finance/chart/%5EGSPC/a%2Fb?range=1d"
+  );
+}
+
+#[test]
+fn base_with_a_query_is_continued() {
+  let url = Url::new("https://example.com/feed?lang=en").query("n", "&=?#");
+  assert_eq!(
+    url.as_str(),
+    "https://example.com/feed?lang=en&n=%26%3D%3F%23"
+  );
+}
+
+#[test]
+fn form_encoding_keeps_unreserved_characters() {
+  let form = url::form_encode(&[("grant_type", "code"), ("x y", "a-b_c.d~e")]);
+  assert_eq!(form, "grant_type=code&x%20y=a-b_c.d~e");
+}